@@ -0,0 +1,94 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Benchmarks of the library execution: running the bytecode, which is decoded on each run,
+//! against running the [prepared](aluvm::CompiledLib::prepare) instructions, and decoding of the
+//! control-flow instructions.
+
+extern crate alloc;
+
+use aluvm::isa::Instr;
+use aluvm::{aluasm, CompiledLib, ExecError, LibId, PreparedLib, Vm};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+/// Number of steps each benchmarked run takes before being halted by the step limit.
+const STEPS: u64 = 10_000;
+
+const MAIN: u16 = 0;
+
+fn control_flow_lib() -> CompiledLib {
+    const LOOP: u16 = 1;
+    const FUNC: u16 = 2;
+    let code = aluasm! {
+        .routine    :MAIN   ;
+        call        :LOOP   ;
+        stop                ;
+        .routine    :LOOP   ;
+        call        :FUNC   ;
+        not         CO      ;
+        jif         CO, :LOOP ;
+        jmp         :LOOP   ;
+        .routine    :FUNC   ;
+        not         CO      ;
+        not         CO      ;
+        ret                 ;
+    };
+    CompiledLib::compile(code, &[]).unwrap()
+}
+
+fn exec(c: &mut Criterion) {
+    let lib = control_flow_lib();
+    let prepared: PreparedLib<Instr<LibId>> = lib.prepare().unwrap();
+    let run = |vm: &mut Vm<Instr<LibId>>| vm.run(lib.routine(MAIN), &(), |_| Some(lib.as_lib()));
+    let mut vm = Vm::<Instr<LibId>>::new();
+    vm.set_step_limit(Some(STEPS));
+    assert!(matches!(run(&mut vm), Err(ExecError::StepLimitExceeded(_))));
+
+    let mut group = c.benchmark_group("exec");
+    group.bench_function("bytecode", |b| {
+        b.iter(|| {
+            let mut vm = Vm::<Instr<LibId>>::new();
+            vm.set_step_limit(Some(STEPS));
+            black_box(run(&mut vm))
+        })
+    });
+    group.bench_function("prepared", |b| {
+        b.iter(|| {
+            let mut vm = Vm::<Instr<LibId>>::new();
+            vm.set_step_limit(Some(STEPS));
+            black_box(vm.run_prepared(lib.routine(MAIN), &(), |_| Some(&prepared)))
+        })
+    });
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let lib = control_flow_lib();
+    c.bench_function("decode/ctrl", |b| {
+        b.iter(|| black_box(lib.prepare::<Instr<LibId>>().unwrap()))
+    });
+}
+
+criterion_group!(benches, exec, decode);
+criterion_main!(benches);
@@ -26,7 +26,7 @@ use core::fmt::{self, Debug, Formatter};
 
 use amplify::confinement::ConfinedVec;
 
-use super::{Site, SiteId, Status};
+use super::{OverflowMode, Site, SiteId, Status};
 use crate::{Register, LIB_NAME_ALUVM};
 
 /// Maximal size of call stack.
@@ -48,6 +48,15 @@ pub trait CoreExt: Clone + Debug {
     fn put(&mut self, reg: Self::Reg, val: Option<<Self::Reg as Register>::Value>);
 
     fn reset(&mut self);
+
+    /// Overwrites the values of all registers with zeros, such that they don't remain in memory.
+    ///
+    /// Called when the [`Core`] containing the extension is zeroized or dropped. Unlike
+    /// [`Self::reset`], the memory holding the register values must be overwritten with writes
+    /// which can't be optimized out, i.e. using [`zeroize::Zeroize`] or other means of volatile
+    /// writes.
+    #[cfg(feature = "zeroize")]
+    fn zeroize_regs(&mut self);
 }
 
 pub trait Supercore<Subcore> {
@@ -56,7 +65,8 @@ pub trait Supercore<Subcore> {
 }
 
 /// Registers of a single CPU/VM core.
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Core<
     Id: SiteId,
     Cx: CoreExt,
@@ -112,6 +122,13 @@ pub struct Core<
     /// stop program execution setting `CK` to a failure.
     pub(super) cl: Option<u64>,
 
+    /// Overflow mode, defining how integer arithmetic operations handle overflows.
+    ///
+    /// Defaults to [`OverflowMode::Trap`] when absent from the serialized data, which precedes
+    /// the introduction of the register.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(super) om: OverflowMode,
+
     /// Call stack.
     ///
     /// # See also
@@ -176,6 +193,7 @@ impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> Core<Id, Cx, CALL_ST
             cy: 0,
             ca: 0,
             cl: config.complexity_lim,
+            om: OverflowMode::default(),
             cs: ConfinedVec::with_capacity(CALL_STACK_SIZE),
             cx: Cx::with(cx_config),
         }
@@ -185,11 +203,30 @@ impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> Core<Id, Cx, CALL_ST
         let mut new = Self::new();
         new.ch = self.ch;
         new.cl = self.cl;
+        new.om = self.om;
         new.cx.reset();
         *self = new;
     }
+
+    /// Captures the state of all the core registers, including the core extension registers.
+    pub fn snapshot(&self) -> RegisterSnapshot<Id, Cx, CALL_STACK_SIZE> {
+        RegisterSnapshot(self.clone())
+    }
+
+    /// Restores the state of all the core registers from a snapshot.
+    pub fn restore(&mut self, snapshot: &RegisterSnapshot<Id, Cx, CALL_STACK_SIZE>) {
+        *self = snapshot.0.clone();
+    }
 }
 
+/// Snapshot of the state of all registers of a [`Core`], taken with [`Core::snapshot`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RegisterSnapshot<
+    Id: SiteId,
+    Cx: CoreExt,
+    const CALL_STACK_SIZE: usize = { CALL_STACK_SIZE_MAX as usize },
+>(Core<Id, Cx, CALL_STACK_SIZE>);
+
 impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> Debug
     for Core<Id, Cx, CALL_STACK_SIZE>
 {
@@ -212,6 +249,7 @@ impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> Debug
             .map(|v| v.to_string())
             .unwrap_or_else(|| "~".to_string());
         write!(f, "{reg}CL{reset} {val}{cl}{reset}, ")?;
+        write!(f, "{reg}OM{reset} {val}{}{reset}, ", self.om)?;
         write!(f, "{reg}CP{reset} {val}{}{reset}, ", self.cp())?;
         write!(f, "\n{reg}CS{reset} {val}{reset}")?;
         for item in &self.cs {
@@ -235,12 +273,14 @@ impl<Id: SiteId, Cx: CoreExt + Supercore<Cx2>, Cx2: CoreExt, const CALL_STACK_SI
             cy: self.cy,
             ca: self.ca,
             cl: self.cl,
+            om: self.om,
             cs: self.cs.clone(),
             cx: self.cx.subcore(),
         }
     }
 
-    fn merge_subcore(&mut self, subcore: Core<Id, Cx2, CALL_STACK_SIZE>) {
+    #[cfg_attr(not(feature = "zeroize"), allow(unused_mut))]
+    fn merge_subcore(&mut self, mut subcore: Core<Id, Cx2, CALL_STACK_SIZE>) {
         assert_eq!(self.ch, subcore.ch);
         self.ck = subcore.ck;
         self.co = subcore.co;
@@ -248,7 +288,94 @@ impl<Id: SiteId, Cx: CoreExt + Supercore<Cx2>, Cx2: CoreExt, const CALL_STACK_SI
         self.cy = subcore.cy;
         self.ca = subcore.ca;
         assert_eq!(self.cl, subcore.cl);
-        self.cs = subcore.cs;
-        self.cx.merge_subcore(subcore.cx);
+        assert_eq!(self.om, subcore.om);
+        // With zeroization, the subcore implements `Drop`, and its fields can't be moved out;
+        // thus the call stack and the extension registers are swapped with the empty ones.
+        #[cfg(feature = "zeroize")]
+        {
+            self.cs = core::mem::take(&mut subcore.cs);
+            let cx = core::mem::replace(&mut subcore.cx, Cx2::with(default!()));
+            self.cx.merge_subcore(cx);
+        }
+        #[cfg(not(feature = "zeroize"))]
+        {
+            self.cs = subcore.cs;
+            self.cx.merge_subcore(subcore.cx);
+        }
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> zeroize::Zeroize
+    for Core<Id, Cx, CALL_STACK_SIZE>
+{
+    /// Zeroizes the control registers, setting `CK` and `CO` to [`Status::Ok`], and the memory
+    /// taken by the call stack, with volatile writes. The extension registers are wiped with
+    /// [`CoreExt::zeroize_regs`]. The complexity limit (`CL`) and the overflow mode (`OM`) are
+    /// configuration rather than secrets and are kept.
+    ///
+    /// The copies of the core made with [`Clone`] (including the [`RegisterSnapshot`]s, and the
+    /// VM states and dumps) are zeroized independently once they are dropped. Values which were
+    /// read from the registers or serialized are not tracked.
+    fn zeroize(&mut self) {
+        self.ch.zeroize();
+        self.cf.zeroize();
+        self.cy.zeroize();
+        self.ca.zeroize();
+        // SAFETY: the pointers are derived from the mutable references, which are valid and
+        // aligned, and the written value is a valid `Status`.
+        unsafe {
+            core::ptr::write_volatile(&mut self.ck, Status::Ok);
+            core::ptr::write_volatile(&mut self.co, Status::Ok);
+        }
+        let mut cs = core::mem::take(&mut self.cs).release();
+        cs.clear();
+        cs.spare_capacity_mut().zeroize();
+        self.cx.zeroize_regs();
+        core::sync::atomic::compiler_fence(core::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> Drop for Core<Id, Cx, CALL_STACK_SIZE> {
+    fn drop(&mut self) { zeroize::Zeroize::zeroize(self) }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> zeroize::ZeroizeOnDrop
+    for Core<Id, Cx, CALL_STACK_SIZE>
+{
+}
+
+#[cfg(feature = "zeroize")]
+impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> zeroize::Zeroize
+    for RegisterSnapshot<Id, Cx, CALL_STACK_SIZE>
+{
+    fn zeroize(&mut self) { self.0.zeroize() }
+}
+
+/// The snapshot is zeroized on drop by the [`Core`] it contains.
+#[cfg(feature = "zeroize")]
+impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> zeroize::ZeroizeOnDrop
+    for RegisterSnapshot<Id, Cx, CALL_STACK_SIZE>
+{
+}
+
+#[cfg(test)]
+mod test {
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_default_om() {
+        use super::*;
+        use crate::{LibId, NoExt};
+
+        let mut core = Core::<LibId, NoExt>::new();
+        core.om = OverflowMode::Wrap;
+        let mut json = serde_json::to_value(&core).unwrap();
+        assert_eq!(serde_json::from_value::<Core<LibId, NoExt>>(json.clone()).unwrap(), core);
+
+        json.as_object_mut().unwrap().remove("om").unwrap();
+        let decoded = serde_json::from_value::<Core<LibId, NoExt>>(json).unwrap();
+        assert_eq!(decoded.om, OverflowMode::Trap);
     }
 }
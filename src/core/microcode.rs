@@ -22,7 +22,7 @@
 // or implied. See the License for the specific language governing permissions and limitations under
 // the License.
 
-use crate::core::{Core, CoreExt, SiteId, Status};
+use crate::core::{Core, CoreExt, OverflowMode, SiteId, Status};
 use crate::{Register, Site};
 
 /// Microcode for flag registers.
@@ -68,6 +68,9 @@ impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> Core<Id, Cx, CALL_ST
         Some(self.cp())
     }
 
+    /// Returns the call stack, starting from the bottom.
+    pub fn cs(&self) -> &[Site<Id>] { self.cs.as_slice() }
+
     /// Pops a call stack item.
     pub fn pop_cs(&mut self) -> Option<Site<Id>> { self.cs.pop() }
 
@@ -84,5 +87,38 @@ impl<Id: SiteId, Cx: CoreExt, const CALL_STACK_SIZE: usize> Core<Id, Cx, CALL_ST
         self.cl().map(|lim| self.ca < lim).unwrap_or(true)
     }
 
+    /// Return overflow mode used by integer arithmetic operations.
+    pub fn om(&self) -> OverflowMode { self.om }
+
+    /// Set overflow mode used by integer arithmetic operations.
+    pub fn set_om(&mut self, om: OverflowMode) { self.om = om; }
+
+    /// Resolve the result of an integer operation which has overflowed, according to the
+    /// overflow mode.
+    ///
+    /// In wrapping and saturating modes sets `CO` to a failed state and returns the `wrapped` or
+    /// `saturated` value, correspondingly. In trapping mode returns `None`: the instruction must
+    /// set the destination register to an undefined state and return
+    /// [`crate::isa::ExecStep::Fail`], which sets `CK` to a failed state.
+    pub fn overflow<T>(&mut self, wrapped: T, saturated: T) -> Option<T> {
+        match self.om {
+            OverflowMode::Wrap => {
+                self.co = Status::Fail;
+                Some(wrapped)
+            }
+            OverflowMode::Saturate => {
+                self.co = Status::Fail;
+                Some(saturated)
+            }
+            OverflowMode::Trap => None,
+        }
+    }
+
     pub fn get(&self, reg: Cx::Reg) -> Option<<Cx::Reg as Register>::Value> { self.cx.get(reg) }
+
+    /// Set register of the core extension to a value.
+    pub fn set(&mut self, reg: Cx::Reg, val: <Cx::Reg as Register>::Value) { self.cx.set(reg, val) }
+
+    /// Clear register of the core extension, setting it to an undefined state.
+    pub fn clr(&mut self, reg: Cx::Reg) { self.cx.clr(reg) }
 }
@@ -62,6 +62,7 @@ impl Register for NoRegs {
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
 #[repr(i8)]
 pub enum Status {
     #[display("ok")]
@@ -86,10 +87,29 @@ impl Not for Status {
     }
 }
 
+/// Behavior of integer arithmetic operations on overflow.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default, Display)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub enum OverflowMode {
+    /// The result is wrapped modulo the maximum register value; `CO` is set to failure.
+    #[display("wrap")]
+    Wrap,
+
+    /// The result is clamped to the minimal or maximal register value; `CO` is set to failure.
+    #[display("saturate")]
+    Saturate,
+
+    /// The destination register is set to an undefined state and `CK` is set to failure.
+    #[default]
+    #[display("trap")]
+    Trap,
+}
+
 pub trait SiteId: Copy + Ord + Debug + Display + FromStr {}
 
 /// Location inside the instruction sequence which can be executed by the core.
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
 pub struct Site<Id: SiteId> {
     pub prog_id: Id,
     pub offset: u16,
@@ -107,6 +127,7 @@ impl<Id: SiteId> Display for Site<Id> {
 }
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct NoExt;
 
 impl CoreExt for NoExt {
@@ -122,4 +143,7 @@ impl CoreExt for NoExt {
     fn put(&mut self, _reg: Self::Reg, _val: Option<u8>) { unreachable!() }
 
     fn reset(&mut self) {}
+
+    #[cfg(feature = "zeroize")]
+    fn zeroize_regs(&mut self) {}
 }
@@ -0,0 +1,449 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Textual assembly format for AluVM instructions.
+//!
+//! The syntax follows the one of [`aluasm!`](crate::aluasm) macro, with each instruction placed on
+//! a separate line. Everything after `;` is a comment. Routines are started with `.routine :name`
+//! (or `.proc`, `.label`, `.loop`) directives, or with `name:` lines, which produce `nop` goto
+//! targets. Goto instructions reference routines by their names as `:name`, which are resolved
+//! into the routine indices expected by [`CompiledLib::compile`](crate::CompiledLib::compile).
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::str::FromStr;
+
+use super::{CtrlInstr, Instruction};
+use crate::{LibId, Site};
+
+/// Error parsing assembly text, indicating the location of the problem.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display("{line}:{col}: {kind}")]
+pub struct ParseError {
+    /// Line number, starting from 1.
+    pub line: usize,
+    /// Column number, starting from 1.
+    pub col: usize,
+    /// Details of the error.
+    pub kind: ParseErrorKind,
+}
+
+/// Kinds of errors parsing assembly text.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum ParseErrorKind {
+    /// unknown instruction mnemonic `{0}`.
+    UnknownMnemonic(String),
+
+    /// invalid operands for `{0}` instruction.
+    InvalidOperands(String),
+
+    /// invalid literal `{0}`.
+    InvalidLiteral(String),
+
+    /// invalid library site `{0}`.
+    InvalidSite(String),
+
+    /// invalid routine label `{0}`.
+    InvalidLabel(String),
+
+    /// routine label `{0}` is not defined.
+    UnknownLabel(String),
+
+    /// routine label `{0}` is defined more than once.
+    RepeatedLabel(String),
+
+    /// too many routines in the code.
+    TooManyRoutines,
+}
+
+/// Instruction operand token, keeping its column.
+#[derive(Copy, Clone)]
+struct Token<'s> {
+    col: usize,
+    text: &'s str,
+}
+
+/// Parsed line of the assembly code.
+struct Line<'s> {
+    no: usize,
+    mnemonic: Token<'s>,
+    operands: Vec<Token<'s>>,
+}
+
+impl<'s> Line<'s> {
+    fn err(&self, token: Token, kind: ParseErrorKind) -> ParseError {
+        ParseError { line: self.no, col: token.col, kind }
+    }
+
+    fn invalid_operands(&self) -> ParseError {
+        self.err(self.mnemonic, ParseErrorKind::InvalidOperands(self.mnemonic.text.to_string()))
+    }
+}
+
+/// Directives starting a new routine.
+const DIRECTIVES: [&str; 4] = [".routine", ".proc", ".label", ".loop"];
+
+/// Control flow instructions, covering each of the [mnemonics](Instruction::mnemonic) recognized
+/// by the parser.
+const MNEMONIC_INSTRS: [CtrlInstr<LibId>; 13] = [
+    CtrlInstr::Nop,
+    CtrlInstr::ChkCo,
+    CtrlInstr::NotCo,
+    CtrlInstr::FailCk,
+    CtrlInstr::RsetCk,
+    CtrlInstr::Ret,
+    CtrlInstr::Stop,
+    CtrlInstr::Trap { code: 0 },
+    CtrlInstr::Jmp { pos: 0 },
+    CtrlInstr::JiOvfl { pos: 0 },
+    CtrlInstr::Fn { pos: 0 },
+    CtrlInstr::JmpRel { pos: 0 },
+    CtrlInstr::FnRel { pos: 0 },
+];
+
+/// Parses assembly text into a sequence of instructions, ready for the compilation with
+/// [`CompiledLib::compile`](crate::CompiledLib::compile).
+///
+/// Routine labels are resolved into routine indices, i.e. the number of goto targets preceding the
+/// labeled instruction. Thus, labels may be referenced before they are defined.
+pub fn parse_asm<Isa>(src: &str) -> Result<Vec<Isa>, ParseError>
+where Isa: From<CtrlInstr<LibId>> {
+    let mut lines = Vec::new();
+    let mut labels = BTreeMap::<&str, u16>::new();
+    let mut routines = 0usize;
+    for (no, text) in src.lines().enumerate() {
+        let text = text.split(';').next().unwrap_or_default();
+        let Some(line) = tokenize(no + 1, text) else {
+            continue;
+        };
+
+        let label = if let Some(name) = line.mnemonic.text.strip_suffix(':') {
+            if !line.operands.is_empty() {
+                return Err(line.invalid_operands());
+            }
+            Some(Token { col: line.mnemonic.col, text: name })
+        } else if DIRECTIVES.contains(&line.mnemonic.text) {
+            let [operand] = line.operands[..] else {
+                return Err(line.invalid_operands());
+            };
+            let Some(name) = operand.text.strip_prefix(':') else {
+                return Err(
+                    line.err(operand, ParseErrorKind::InvalidLabel(operand.text.to_string()))
+                );
+            };
+            Some(Token { col: operand.col, text: name })
+        } else {
+            None
+        };
+
+        match label {
+            Some(label) => {
+                if !is_ident(label.text) {
+                    return Err(
+                        line.err(label, ParseErrorKind::InvalidLabel(label.text.to_string()))
+                    );
+                }
+                let Ok(no) = u16::try_from(routines) else {
+                    return Err(line.err(label, ParseErrorKind::TooManyRoutines));
+                };
+                if labels.insert(label.text, no).is_some() {
+                    return Err(
+                        line.err(label, ParseErrorKind::RepeatedLabel(label.text.to_string()))
+                    );
+                }
+                routines += 1;
+                lines.push((line, true));
+            }
+            None => {
+                if line.mnemonic.text == "nop" {
+                    routines += 1;
+                }
+                lines.push((line, false));
+            }
+        }
+    }
+
+    lines
+        .into_iter()
+        .map(|(line, is_label)| {
+            if is_label {
+                return Ok(CtrlInstr::Nop.into());
+            }
+            parse_instr(&line, &labels).map(Isa::from)
+        })
+        .collect()
+}
+
+/// Formats a sequence of instructions as assembly text, one instruction per line, with the
+/// operands aligned after the [mnemonics](Instruction::mnemonic).
+///
+/// The text can be parsed back with [`parse_asm`].
+pub fn to_asm<Isa: Instruction<LibId>>(code: &[Isa]) -> String {
+    let mut asm = String::new();
+    for instr in code {
+        let text = instr.to_string();
+        let mnemonic = instr.mnemonic();
+        match text.strip_prefix(mnemonic).map(str::trim) {
+            Some("") => asm.push_str(mnemonic),
+            Some(operands) => asm.push_str(&format!("{mnemonic:<7} {operands}")),
+            None => asm.push_str(&text),
+        }
+        asm.push('\n');
+    }
+    asm
+}
+
+fn tokenize(no: usize, text: &str) -> Option<Line<'_>> {
+    let col = |s: &str| text[..text.len() - s.len()].chars().count() + 1;
+
+    let text_start = text.trim_start();
+    if text_start.is_empty() {
+        return None;
+    }
+    let (mnemonic, rest) = text_start.split_at(
+        text_start
+            .find(char::is_whitespace)
+            .unwrap_or(text_start.len()),
+    );
+    let mnemonic = Token { col: col(text_start), text: mnemonic };
+    let mut operands = Vec::new();
+    if !rest.trim().is_empty() {
+        let mut rest = rest;
+        loop {
+            let (operand, tail) = rest.split_once(',').unwrap_or((rest, ""));
+            let trimmed = operand.trim_start();
+            operands.push(Token { col: col(trimmed), text: trimmed.trim_end() });
+            if operand.len() == rest.len() {
+                break;
+            }
+            rest = tail;
+        }
+    }
+    Some(Line { no, mnemonic, operands })
+}
+
+/// Operand of a goto instruction.
+enum Target {
+    Pos(u16),
+    Shift(i8),
+    Site(Site<LibId>),
+}
+
+fn parse_instr(line: &Line, labels: &BTreeMap<&str, u16>) -> Result<CtrlInstr<LibId>, ParseError> {
+    let operands = line
+        .operands
+        .iter()
+        .map(|token| token.text)
+        .collect::<Vec<_>>();
+    let target = |token: Token| parse_target(line, token, labels);
+    Ok(match (line.mnemonic.text, &operands[..], &line.operands[..]) {
+        ("nop", [], _) => CtrlInstr::Nop,
+        ("chk", ["CO"], _) => CtrlInstr::ChkCo,
+        ("chk", ["CK"], _) => CtrlInstr::ChkCk,
+        ("not", ["CO"], _) => CtrlInstr::NotCo,
+        ("fail", ["CK"], _) => CtrlInstr::FailCk,
+        ("mov", ["CO", "CK"], _) => CtrlInstr::RsetCk,
+        ("ret", [], _) => CtrlInstr::Ret,
+        ("stop", [], _) => CtrlInstr::Stop,
+        ("trap", [code], [token]) => CtrlInstr::Trap {
+            code: parse_u16(code).ok_or_else(|| {
+                line.err(*token, ParseErrorKind::InvalidLiteral(code.to_string()))
+            })?,
+        },
+        ("jmp", [_], [token]) => match target(*token)? {
+            Target::Pos(pos) => CtrlInstr::Jmp { pos },
+            Target::Shift(shift) => CtrlInstr::Sh { shift },
+            Target::Site(site) => CtrlInstr::Exec { site },
+        },
+        ("jif", ["CO", _], [_, token]) => match target(*token)? {
+            Target::Pos(pos) => CtrlInstr::JiOvfl { pos },
+            Target::Shift(shift) => CtrlInstr::ShOvfl { shift },
+            Target::Site(_) => return Err(line.invalid_operands()),
+        },
+        ("jif", ["CK", _], [_, token]) => match target(*token)? {
+            Target::Pos(pos) => CtrlInstr::JiFail { pos },
+            Target::Shift(shift) => CtrlInstr::ShFail { shift },
+            Target::Site(_) => return Err(line.invalid_operands()),
+        },
+        ("call", [_], [token]) => match target(*token)? {
+            Target::Pos(pos) => CtrlInstr::Fn { pos },
+            Target::Site(site) => CtrlInstr::Call { site },
+            Target::Shift(_) => return Err(line.invalid_operands()),
+        },
+        ("rjmp", [_], [token]) => match target(*token)? {
+            Target::Pos(pos) => CtrlInstr::JmpRel { pos },
+            Target::Shift(_) | Target::Site(_) => return Err(line.invalid_operands()),
+        },
+        ("rcall", [_], [token]) => match target(*token)? {
+            Target::Pos(pos) => CtrlInstr::FnRel { pos },
+            Target::Shift(_) | Target::Site(_) => return Err(line.invalid_operands()),
+        },
+        (mnemonic, _, _)
+            if MNEMONIC_INSTRS
+                .iter()
+                .any(|instr| instr.mnemonic() == mnemonic) =>
+        {
+            return Err(line.invalid_operands())
+        }
+        (mnemonic, _, _) => {
+            return Err(
+                line.err(line.mnemonic, ParseErrorKind::UnknownMnemonic(mnemonic.to_string()))
+            )
+        }
+    })
+}
+
+fn parse_target(
+    line: &Line,
+    token: Token,
+    labels: &BTreeMap<&str, u16>,
+) -> Result<Target, ParseError> {
+    let text = token.text;
+    if let Some(label) = text.strip_prefix(':') {
+        return labels
+            .get(label)
+            .copied()
+            .map(Target::Pos)
+            .ok_or_else(|| line.err(token, ParseErrorKind::UnknownLabel(label.to_string())));
+    }
+    if let Some((lib, pos)) = text.rsplit_once('@') {
+        let invalid = || line.err(token, ParseErrorKind::InvalidSite(text.to_string()));
+        let lib_id = LibId::from_str(lib).map_err(|_| invalid())?;
+        let offset = parse_u16(pos).ok_or_else(invalid)?;
+        return Ok(Target::Site(Site::new(lib_id, offset)));
+    }
+    let invalid = || line.err(token, ParseErrorKind::InvalidLiteral(text.to_string()));
+    if let Some(shift) = text.strip_prefix('+') {
+        return parse_i8(shift).map(Target::Shift).ok_or_else(invalid);
+    }
+    if let Some(shift) = text.strip_prefix('-') {
+        return parse_i8(shift)
+            .and_then(i8::checked_neg)
+            .map(Target::Shift)
+            .ok_or_else(invalid);
+    }
+    parse_u16(text).map(Target::Pos).ok_or_else(invalid)
+}
+
+/// Parses decimal or hexadecimal (with `#h` suffix) 16-bit number.
+fn parse_u16(s: &str) -> Option<u16> {
+    match s.strip_suffix("#h") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Parses unsigned shift value, which can be either decimal or hexadecimal (with `#h` suffix).
+///
+/// Hexadecimal values represent the byte of the shift encoding, such that `FE#h` stands for `-2`.
+fn parse_i8(s: &str) -> Option<i8> {
+    match s.strip_suffix("#h") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok().map(|byte| byte as i8),
+        None => s.parse().ok(),
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{Instr, Instruction};
+    use crate::regs::Status;
+    use crate::{aluasm, CompiledLib, Vm};
+
+    const SRC: &str = "
+        ; entry point
+        .routine :main
+            call    :func       ; forward reference
+            jif     CK, -1
+            stop
+        func:
+            not     CO
+            mov     CO, CK
+            ret
+    ";
+
+    #[test]
+    fn parse() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 1;
+        let code = parse_asm::<Instr<LibId>>(SRC).unwrap();
+        assert_eq!(code, aluasm! {
+            .routine    :MAIN   ;
+            call        :FUNC   ;
+            jif         CK, -1  ;
+            stop                ;
+            .routine    :FUNC   ;
+            not         CO      ;
+            mov         CO, CK  ;
+            ret                 ;
+        });
+
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.exec(lib.routine(MAIN), &(), |_| Some(lib.as_lib())), Status::Ok);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let lib_id = LibId::from([0xA5; 32]);
+        let mut code = parse_asm::<Instr<LibId>>(SRC).unwrap();
+        code.push(CtrlInstr::Call { site: Site::new(lib_id, 0x1F) }.into());
+        code.push(CtrlInstr::ShFail { shift: -0x70 }.into());
+        code.push(CtrlInstr::Sh { shift: 0x12 }.into());
+        code.push(CtrlInstr::Trap { code: 0xBEEF }.into());
+        code.push(CtrlInstr::JmpRel { pos: 1 }.into());
+        code.push(CtrlInstr::FnRel { pos: 0 }.into());
+        let asm = to_asm(&code);
+        assert_eq!(parse_asm::<Instr<LibId>>(&asm).unwrap(), code);
+        for (line, instr) in asm.lines().zip(&code) {
+            assert_eq!(line, instr.to_string());
+            assert_eq!(line.split_whitespace().next(), Some(instr.mnemonic()));
+        }
+    }
+
+    #[test]
+    fn errors() {
+        let err = |src| parse_asm::<Instr<LibId>>(src).unwrap_err();
+        assert_eq!(err("nop\n  foo CO").to_string(), "2:3: unknown instruction mnemonic `foo`.");
+        assert_eq!(err("jmp   :bar").to_string(), "1:7: routine label `bar` is not defined.");
+        assert_eq!(err("chk CO, CK").to_string(), "1:1: invalid operands for `chk` instruction.");
+        assert_eq!(
+            err("a:\n   a:").to_string(),
+            "2:4: routine label `a` is defined more than once."
+        );
+        assert_eq!(err("jif CK, +x").to_string(), "1:9: invalid literal `+x`.");
+        assert_eq!(err("call foo@1").to_string(), "1:6: invalid library site `foo@1`.");
+        assert_eq!(err("trap  -1").to_string(), "1:7: invalid literal `-1`.");
+        assert_eq!(err("trap").to_string(), "1:1: invalid operands for `trap` instruction.");
+        assert_eq!(err("rjmp +1").to_string(), "1:1: invalid operands for `rjmp` instruction.");
+    }
+}
@@ -29,6 +29,7 @@ use amplify::confinement::SmallBlob;
 use amplify::num::{u1, u2, u3, u4, u5, u6, u7};
 
 use crate::core::SiteId;
+use crate::EncodingVersion;
 
 /// Non-failing byte encoding for the instruction set.
 ///
@@ -49,6 +50,13 @@ pub trait Bytecode<Id: SiteId> {
     /// If the instruction call or references any external program, returns a reference to it.
     fn external_ref(&self) -> Option<Id>;
 
+    /// Returns the earliest version of the bytecode encoding which defines the instruction.
+    ///
+    /// Instructions added by the later encoding versions take the opcodes which were decoded as
+    /// reserved instructions before, thus they are rejected in the libraries using earlier
+    /// versions.
+    fn encoding_version(&self) -> EncodingVersion { EncodingVersion::V0 }
+
     /// Write an instruction as bytecode.
     fn encode_instr<W>(&self, writer: &mut W) -> Result<(), W::Error>
     where W: BytecodeWrite<Id> {
@@ -87,6 +95,14 @@ pub trait Bytecode<Id: SiteId> {
 pub struct CodeEofError;
 
 /// Reader from a bytecode for instruction deserialization.
+///
+/// # Byte order
+///
+/// The bytecode is a bit stream: values are stored starting from their least significant bit, and
+/// each byte of the code segment is filled starting from its least significant bit. Thus, words
+/// and all other multi-byte values are little-endian, and bit fields shorter than a byte are
+/// packed starting from the lowest bit of a byte. The order is fixed, since it defines the library
+/// identifiers; implementations must not depend on the byte order of the host platform.
 pub trait BytecodeRead<Id: SiteId> {
     /// Return current byte offset of the cursor. Does not account for bits.
     /// If the position is exactly at EOF, returns `None`.
@@ -157,6 +173,8 @@ pub trait BytecodeRead<Id: SiteId> {
 }
 
 /// Writer converting instructions into a bytecode.
+///
+/// Values must be written in the bit and byte order described in [`BytecodeRead`].
 pub trait BytecodeWrite<Id: SiteId> {
     type Error: Debug;
 
@@ -0,0 +1,178 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use super::CtrlInstr;
+use crate::core::SiteId;
+use crate::Site;
+
+/// Constructors for the control flow instructions, available for any instruction set which
+/// includes them (i.e. implements `From<CtrlInstr>`).
+///
+/// Goto and call instructions are constructed with the routine numbers as their targets, which is
+/// the form expected by the [compiler](crate::CompiledLib::compile): it replaces them with the code
+/// offsets of the routine entry points. Routines are started with [`Self::routine`].
+pub trait InstrBuilder<Id: SiteId>: From<CtrlInstr<Id>> {
+    /// Goto target starting a new routine.
+    fn routine() -> Self { CtrlInstr::Nop.into() }
+
+    /// Unconditional jump to the local routine `no`.
+    fn goto_routine(no: u16) -> Self { CtrlInstr::Jmp { pos: no }.into() }
+
+    /// Jump to the local routine `no` if `CO` is true.
+    fn goto_routine_if_co(no: u16) -> Self { CtrlInstr::JiOvfl { pos: no }.into() }
+
+    /// Jump to the local routine `no` if `CK` is in a failed state.
+    fn goto_routine_if_fail(no: u16) -> Self { CtrlInstr::JiFail { pos: no }.into() }
+
+    /// Call of the local routine `no`.
+    fn call_routine(no: u16) -> Self { CtrlInstr::Fn { pos: no }.into() }
+
+    /// Position-independent jump to the local routine `no`, which is compiled into an offset
+    /// relative to the jump instruction.
+    fn goto_routine_rel(no: u16) -> Self { CtrlInstr::JmpRel { pos: no }.into() }
+
+    /// Position-independent call of the local routine `no`, which is compiled into an offset
+    /// relative to the call instruction.
+    fn call_routine_rel(no: u16) -> Self { CtrlInstr::FnRel { pos: no }.into() }
+
+    /// Call of the routine `no` of the external library `lib`.
+    fn call(lib: Id, no: u16) -> Self { CtrlInstr::Call { site: Site::new(lib, no) }.into() }
+
+    /// Jump to the routine `no` of the external library `lib`.
+    fn exec(lib: Id, no: u16) -> Self { CtrlInstr::Exec { site: Site::new(lib, no) }.into() }
+
+    /// Return from a subroutine, or program completion.
+    fn ret() -> Self { CtrlInstr::Ret.into() }
+
+    /// Program stop.
+    fn stop() -> Self { CtrlInstr::Stop.into() }
+
+    /// Program abort with the trap `code`.
+    fn trap(code: u16) -> Self { CtrlInstr::Trap { code }.into() }
+}
+
+impl<Id: SiteId, Isa: From<CtrlInstr<Id>>> InstrBuilder<Id> for Isa {}
+
+/// Constructors for the conditional branches on register comparison, available for the instruction
+/// sets which provide a comparison instruction setting `CO` (see [`Self::cmp_eq`]).
+///
+/// Each of the branches is a comparison followed by a conditional jump to a local routine, which is
+/// given by its number in the same way as for [`InstrBuilder::goto_routine_if_co`].
+pub trait BranchBuilder<Id: SiteId>: InstrBuilder<Id> {
+    /// Register type which values are compared.
+    type Reg;
+
+    /// Comparison setting `CO` to true if the values of registers `a` and `b` are equal, and to
+    /// false otherwise.
+    fn cmp_eq(a: Self::Reg, b: Self::Reg) -> Self;
+
+    /// Jump to the local routine `no` if the values of registers `a` and `b` are equal.
+    fn branch_if_eq(a: Self::Reg, b: Self::Reg, no: u16) -> [Self; 2] {
+        [Self::cmp_eq(a, b), Self::goto_routine_if_co(no)]
+    }
+
+    /// Jump to the local routine `no` if the values of registers `a` and `b` are not equal.
+    fn branch_if_ne(a: Self::Reg, b: Self::Reg, no: u16) -> [Self; 3] {
+        [Self::cmp_eq(a, b), CtrlInstr::NotCo.into(), Self::goto_routine_if_co(no)]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{Either, Instr};
+    use crate::{aluasm, CompiledLib, LibId};
+
+    type Isa = Instr<LibId>;
+
+    #[test]
+    fn build() {
+        let dep = CompiledLib::compile(aluasm! { nop; ret; }, &[]).unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        let code = vec![
+            Isa::routine(),
+            Isa::call_routine(1),
+            Isa::goto_routine_if_fail(2),
+            Isa::goto_routine_if_co(2),
+            Isa::call(dep_id, 0),
+            Isa::goto_routine(2),
+            Isa::routine(),
+            Isa::ret(),
+            Isa::routine(),
+            Isa::exec(dep_id, 0),
+            Isa::stop(),
+        ];
+        let expected: Vec<Isa> = aluasm! {
+            nop                 ;
+            call    1           ;
+            jif     CK, 2       ;
+            jif     CO, 2       ;
+            call    dep_id, 0   ;
+            jmp     2           ;
+            nop                 ;
+            ret                 ;
+            nop                 ;
+            jmp     dep_id, 0   ;
+            stop                ;
+        };
+        assert_eq!(code, expected);
+
+        let lib = CompiledLib::compile(code, &[&dep]).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 17, 19]);
+    }
+
+    #[test]
+    fn branch() {
+        use crate::testing::isa::XReg;
+
+        #[derive(Clone, PartialEq, Eq, Debug)]
+        enum CmpIsa {
+            Ctrl(CtrlInstr<LibId>),
+            Eq(XReg, XReg),
+        }
+        impl From<CtrlInstr<LibId>> for CmpIsa {
+            fn from(instr: CtrlInstr<LibId>) -> Self { CmpIsa::Ctrl(instr) }
+        }
+        impl BranchBuilder<LibId> for CmpIsa {
+            type Reg = XReg;
+            fn cmp_eq(a: XReg, b: XReg) -> Self { CmpIsa::Eq(a, b) }
+        }
+
+        assert_eq!(CmpIsa::branch_if_eq(XReg::A, XReg::B, 3), [
+            CmpIsa::Eq(XReg::A, XReg::B),
+            CmpIsa::Ctrl(CtrlInstr::JiOvfl { pos: 3 }),
+        ]);
+        assert_eq!(CmpIsa::branch_if_ne(XReg::B, XReg::A, 1), [
+            CmpIsa::Eq(XReg::B, XReg::A),
+            CmpIsa::Ctrl(CtrlInstr::NotCo),
+            CmpIsa::Ctrl(CtrlInstr::JiOvfl { pos: 1 }),
+        ]);
+    }
+
+    #[test]
+    fn either() {
+        type Ext = Either<Isa, Isa>;
+        assert_eq!(Ext::goto_routine(1), Either::Base(CtrlInstr::Jmp { pos: 1 }.into()));
+    }
+}
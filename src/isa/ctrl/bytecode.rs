@@ -28,7 +28,7 @@ use super::CtrlInstr;
 use crate::core::SiteId;
 use crate::isa::bytecode::CodeEofError;
 use crate::isa::{Bytecode, BytecodeRead, BytecodeWrite, Instr, ReservedInstr};
-use crate::Site;
+use crate::{EncodingVersion, Site};
 
 impl<Id: SiteId> Bytecode<Id> for Instr<Id> {
     fn op_range() -> RangeInclusive<u8> { 0..=0xFF }
@@ -54,6 +54,13 @@ impl<Id: SiteId> Bytecode<Id> for Instr<Id> {
         }
     }
 
+    fn encoding_version(&self) -> EncodingVersion {
+        match self {
+            Instr::Ctrl(instr) => instr.encoding_version(),
+            Instr::Reserved(instr) => Bytecode::<Id>::encoding_version(instr),
+        }
+    }
+
     fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
     where W: BytecodeWrite<Id> {
         match self {
@@ -101,7 +108,7 @@ impl<Id: SiteId> Bytecode<Id> for ReservedInstr {
 
 impl<Id: SiteId> CtrlInstr<Id> {
     const START: u8 = 0;
-    const END: u8 = Self::START + Self::STOP;
+    const END: u8 = Self::START + Self::FNREL;
 
     const NOP: u8 = 0;
     const NOCO: u8 = 1;
@@ -120,6 +127,9 @@ impl<Id: SiteId> CtrlInstr<Id> {
     const CALL: u8 = 14;
     const RET: u8 = 15;
     const STOP: u8 = 16;
+    const TRAP: u8 = 17;
+    const JMPREL: u8 = 18;
+    const FNREL: u8 = 19;
 }
 
 impl<Id: SiteId> Bytecode<Id> for CtrlInstr<Id> {
@@ -144,6 +154,9 @@ impl<Id: SiteId> Bytecode<Id> for CtrlInstr<Id> {
             CtrlInstr::Call { .. } => Self::CALL,
             CtrlInstr::Ret => Self::RET,
             CtrlInstr::Stop => Self::STOP,
+            CtrlInstr::Trap { .. } => Self::TRAP,
+            CtrlInstr::JmpRel { .. } => Self::JMPREL,
+            CtrlInstr::FnRel { .. } => Self::FNREL,
         }
     }
 
@@ -158,7 +171,10 @@ impl<Id: SiteId> Bytecode<Id> for CtrlInstr<Id> {
             CtrlInstr::Jmp { pos: _ }
             | CtrlInstr::JiOvfl { pos: _ }
             | CtrlInstr::JiFail { pos: _ }
-            | CtrlInstr::Fn { pos: _ } => 2,
+            | CtrlInstr::Fn { pos: _ }
+            | CtrlInstr::JmpRel { pos: _ }
+            | CtrlInstr::FnRel { pos: _ }
+            | CtrlInstr::Trap { code: _ } => 2,
             CtrlInstr::Sh { shift: _ }
             | CtrlInstr::ShOvfl { shift: _ }
             | CtrlInstr::ShFail { shift: _ } => 1,
@@ -177,12 +193,15 @@ impl<Id: SiteId> Bytecode<Id> for CtrlInstr<Id> {
             | CtrlInstr::RsetCk
             | CtrlInstr::NotCo
             | CtrlInstr::Ret
-            | CtrlInstr::Stop => None,
+            | CtrlInstr::Stop
+            | CtrlInstr::Trap { code: _ } => None,
 
             CtrlInstr::Jmp { pos: _ }
             | CtrlInstr::JiOvfl { pos: _ }
             | CtrlInstr::JiFail { pos: _ }
-            | CtrlInstr::Fn { pos: _ } => None,
+            | CtrlInstr::Fn { pos: _ }
+            | CtrlInstr::JmpRel { pos: _ }
+            | CtrlInstr::FnRel { pos: _ } => None,
             CtrlInstr::Sh { shift: _ }
             | CtrlInstr::ShOvfl { shift: _ }
             | CtrlInstr::ShFail { shift: _ } => None,
@@ -190,6 +209,15 @@ impl<Id: SiteId> Bytecode<Id> for CtrlInstr<Id> {
         }
     }
 
+    fn encoding_version(&self) -> EncodingVersion {
+        match self {
+            CtrlInstr::Trap { .. } | CtrlInstr::JmpRel { .. } | CtrlInstr::FnRel { .. } => {
+                EncodingVersion::V1
+            }
+            _ => EncodingVersion::V0,
+        }
+    }
+
     fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
     where W: BytecodeWrite<Id> {
         match *self {
@@ -205,7 +233,10 @@ impl<Id: SiteId> Bytecode<Id> for CtrlInstr<Id> {
             CtrlInstr::Jmp { pos }
             | CtrlInstr::JiOvfl { pos }
             | CtrlInstr::JiFail { pos }
-            | CtrlInstr::Fn { pos } => writer.write_word(pos)?,
+            | CtrlInstr::Fn { pos }
+            | CtrlInstr::JmpRel { pos }
+            | CtrlInstr::FnRel { pos }
+            | CtrlInstr::Trap { code: pos } => writer.write_word(pos)?,
             CtrlInstr::Sh { shift } | CtrlInstr::ShOvfl { shift } | CtrlInstr::ShFail { shift } => {
                 writer.write_byte(shift.to_le_bytes()[0])?
             }
@@ -223,40 +254,37 @@ impl<Id: SiteId> Bytecode<Id> for CtrlInstr<Id> {
         Self: Sized,
         R: BytecodeRead<Id>,
     {
-        Ok(match opcode {
-            Self::NOP => Self::Nop,
-            Self::CHCO => Self::ChkCo,
-            Self::CHCK => Self::ChkCk,
-            Self::FAIL => Self::FailCk,
-            Self::RSET => Self::RsetCk,
-            Self::NOCO => Self::NotCo,
-            Self::RET => Self::Ret,
-            Self::STOP => Self::Stop,
-
-            Self::JMP => CtrlInstr::Jmp { pos: reader.read_word()? },
-            Self::JINE => CtrlInstr::JiOvfl { pos: reader.read_word()? },
-            Self::JIFAIL => CtrlInstr::JiFail { pos: reader.read_word()? },
-            Self::FN => CtrlInstr::Fn { pos: reader.read_word()? },
-
-            Self::SH => CtrlInstr::Sh { shift: i8::from_le_bytes([reader.read_byte()?]) },
-            Self::SHNE => CtrlInstr::ShOvfl { shift: i8::from_le_bytes([reader.read_byte()?]) },
-            Self::SHFAIL => CtrlInstr::ShFail { shift: i8::from_le_bytes([reader.read_byte()?]) },
-
-            Self::CALL => {
-                let prog_id = reader.read_ref()?;
-                let offset = reader.read_word()?;
-                let site = Site::new(prog_id, offset);
-                CtrlInstr::Call { site }
-            }
-            Self::EXEC => {
-                let prog_id = reader.read_ref()?;
-                let offset = reader.read_word()?;
-                let site = Site::new(prog_id, offset);
-                CtrlInstr::Exec { site }
-            }
-
-            _ => unreachable!(),
-        })
+        fn site<Id: SiteId, R: BytecodeRead<Id>>(reader: &mut R) -> Result<Site<Id>, CodeEofError> {
+            let prog_id = reader.read_ref()?;
+            let offset = reader.read_word()?;
+            Ok(Site::new(prog_id, offset))
+        }
+        // Decoders indexed by the opcode: a dense table of function pointers is dispatched faster
+        // than a match over the opcode constants.
+        type Decoder<I, R> = fn(&mut R) -> Result<I, CodeEofError>;
+        let table: [Decoder<Self, R>; 20] = [
+            |_| Ok(Self::Nop),
+            |_| Ok(Self::NotCo),
+            |_| Ok(Self::ChkCo),
+            |_| Ok(Self::ChkCk),
+            |_| Ok(Self::FailCk),
+            |_| Ok(Self::RsetCk),
+            |r| Ok(CtrlInstr::Jmp { pos: r.read_word()? }),
+            |r| Ok(CtrlInstr::JiOvfl { pos: r.read_word()? }),
+            |r| Ok(CtrlInstr::JiFail { pos: r.read_word()? }),
+            |r| Ok(CtrlInstr::Sh { shift: i8::from_le_bytes([r.read_byte()?]) }),
+            |r| Ok(CtrlInstr::ShOvfl { shift: i8::from_le_bytes([r.read_byte()?]) }),
+            |r| Ok(CtrlInstr::ShFail { shift: i8::from_le_bytes([r.read_byte()?]) }),
+            |r| Ok(CtrlInstr::Exec { site: site(r)? }),
+            |r| Ok(CtrlInstr::Fn { pos: r.read_word()? }),
+            |r| Ok(CtrlInstr::Call { site: site(r)? }),
+            |_| Ok(Self::Ret),
+            |_| Ok(Self::Stop),
+            |r| Ok(CtrlInstr::Trap { code: r.read_word()? }),
+            |r| Ok(CtrlInstr::JmpRel { pos: r.read_word()? }),
+            |r| Ok(CtrlInstr::FnRel { pos: r.read_word()? }),
+        ];
+        table[(opcode - Self::START) as usize](reader)
     }
 }
 
@@ -346,4 +374,16 @@ mod test {
     fn ret() { roundtrip(CtrlInstr::Ret, [CtrlInstr::<LibId>::RET]); }
     #[test]
     fn stop() { roundtrip(CtrlInstr::Stop, [CtrlInstr::<LibId>::STOP]); }
+    #[test]
+    fn trap() {
+        roundtrip(CtrlInstr::Trap { code: 0x75AE }, [CtrlInstr::<LibId>::TRAP, 0xAE, 0x75]);
+    }
+    #[test]
+    fn jmp_rel() {
+        roundtrip(CtrlInstr::JmpRel { pos: 0xFFFB }, [CtrlInstr::<LibId>::JMPREL, 0xFB, 0xFF]);
+    }
+    #[test]
+    fn func_rel() {
+        roundtrip(CtrlInstr::FnRel { pos: 0x0105 }, [CtrlInstr::<LibId>::FNREL, 0x05, 0x01]);
+    }
 }
@@ -26,7 +26,7 @@ use alloc::collections::BTreeSet;
 
 use super::CtrlInstr;
 use crate::core::{Core, NoExt, NoRegs, Site, SiteId, Status};
-use crate::isa::{ExecStep, Instr, Instruction, ReservedInstr};
+use crate::isa::{ExecStep, Instr, Instruction, Operand, Operands, RegAccess, ReservedInstr};
 
 impl<Id: SiteId> Instruction<Id> for Instr<Id> {
     const ISA_EXT: &'static [&'static str] = &[];
@@ -34,6 +34,13 @@ impl<Id: SiteId> Instruction<Id> for Instr<Id> {
     type Core = NoExt;
     type Context<'ctx> = ();
 
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Instr::Ctrl(instr) => instr.mnemonic(),
+            Instr::Reserved(instr) => Instruction::<Id>::mnemonic(instr),
+        }
+    }
+
     fn is_goto_target(&self) -> bool {
         match self {
             Instr::Ctrl(instr) => instr.is_goto_target(),
@@ -55,6 +62,93 @@ impl<Id: SiteId> Instruction<Id> for Instr<Id> {
         }
     }
 
+    fn local_goto_table(&mut self) -> &mut [u16] {
+        match self {
+            Instr::Ctrl(instr) => instr.local_goto_table(),
+            Instr::Reserved(instr) => Instruction::<Id>::local_goto_table(instr),
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        match self {
+            Instr::Ctrl(instr) => instr.is_terminal(),
+            Instr::Reserved(instr) => Instruction::<Id>::is_terminal(instr),
+        }
+    }
+
+    fn is_relative_jump(&self) -> bool {
+        match self {
+            Instr::Ctrl(instr) => instr.is_relative_jump(),
+            Instr::Reserved(instr) => Instruction::<Id>::is_relative_jump(instr),
+        }
+    }
+
+    fn relative_jump_shift(&self) -> Option<i16> {
+        match self {
+            Instr::Ctrl(instr) => instr.relative_jump_shift(),
+            Instr::Reserved(instr) => Instruction::<Id>::relative_jump_shift(instr),
+        }
+    }
+
+    fn is_goto_relative(&self) -> bool {
+        match self {
+            Instr::Ctrl(instr) => instr.is_goto_relative(),
+            Instr::Reserved(instr) => Instruction::<Id>::is_goto_relative(instr),
+        }
+    }
+
+    fn is_nop(&self) -> bool {
+        match self {
+            Instr::Ctrl(instr) => instr.is_nop(),
+            Instr::Reserved(instr) => Instruction::<Id>::is_nop(instr),
+        }
+    }
+
+    fn is_local_call(&self) -> bool {
+        match self {
+            Instr::Ctrl(instr) => instr.is_local_call(),
+            Instr::Reserved(instr) => Instruction::<Id>::is_local_call(instr),
+        }
+    }
+
+    fn is_return(&self) -> bool {
+        match self {
+            Instr::Ctrl(instr) => instr.is_return(),
+            Instr::Reserved(instr) => Instruction::<Id>::is_return(instr),
+        }
+    }
+
+    fn is_call(&self) -> bool {
+        match self {
+            Instr::Ctrl(instr) => instr.is_call(),
+            Instr::Reserved(instr) => Instruction::<Id>::is_call(instr),
+        }
+    }
+
+    fn try_const_eval(&self, next: &Self) -> Option<Self> {
+        match (self, next) {
+            (Instr::Ctrl(instr), Instr::Ctrl(next)) => instr.try_const_eval(next).map(Instr::Ctrl),
+            (Instr::Reserved(instr), Instr::Reserved(next)) => {
+                Instruction::<Id>::try_const_eval(instr, next).map(Instr::Reserved)
+            }
+            _ => None,
+        }
+    }
+
+    fn reg_access(&self) -> RegAccess<NoRegs> {
+        match self {
+            Instr::Ctrl(instr) => instr.reg_access(),
+            Instr::Reserved(instr) => Instruction::<Id>::reg_access(instr),
+        }
+    }
+
+    fn operands(&self) -> Operands<Id, NoRegs> {
+        match self {
+            Instr::Ctrl(instr) => instr.operands(),
+            Instr::Reserved(instr) => Instruction::<Id>::operands(instr),
+        }
+    }
+
     fn src_regs(&self) -> BTreeSet<NoRegs> {
         match self {
             Instr::Ctrl(instr) => instr.src_regs(),
@@ -102,12 +196,16 @@ impl<Id: SiteId> Instruction<Id> for ReservedInstr {
     type Core = NoExt;
     type Context<'ctx> = ();
 
+    fn mnemonic(&self) -> &'static str { "halt" }
+
     fn is_goto_target(&self) -> bool { false }
 
     fn local_goto_pos(&mut self) -> Option<&mut u16> { None }
 
     fn remote_goto_pos(&mut self) -> Option<&mut Site<Id>> { None }
 
+    fn operands(&self) -> Operands<Id, NoRegs> { Operands::new() }
+
     fn src_regs(&self) -> BTreeSet<NoRegs> { none!() }
 
     fn dst_regs(&self) -> BTreeSet<NoRegs> { none!() }
@@ -134,6 +232,27 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
     type Core = NoExt;
     type Context<'ctx> = ();
 
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            CtrlInstr::Nop => "nop",
+            CtrlInstr::ChkCo | CtrlInstr::ChkCk => "chk",
+            CtrlInstr::NotCo => "not",
+            CtrlInstr::FailCk => "fail",
+            CtrlInstr::RsetCk => "mov",
+            CtrlInstr::Jmp { .. } | CtrlInstr::Sh { .. } | CtrlInstr::Exec { .. } => "jmp",
+            CtrlInstr::JiOvfl { .. }
+            | CtrlInstr::JiFail { .. }
+            | CtrlInstr::ShOvfl { .. }
+            | CtrlInstr::ShFail { .. } => "jif",
+            CtrlInstr::Fn { .. } | CtrlInstr::Call { .. } => "call",
+            CtrlInstr::Ret => "ret",
+            CtrlInstr::Stop => "stop",
+            CtrlInstr::Trap { .. } => "trap",
+            CtrlInstr::JmpRel { .. } => "rjmp",
+            CtrlInstr::FnRel { .. } => "rcall",
+        }
+    }
+
     fn is_goto_target(&self) -> bool {
         match self {
             CtrlInstr::Nop => true,
@@ -145,7 +264,8 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
             CtrlInstr::Jmp { .. } | CtrlInstr::JiOvfl { .. } | CtrlInstr::JiFail { .. } => false,
             CtrlInstr::Sh { .. } | CtrlInstr::ShOvfl { .. } | CtrlInstr::ShFail { .. } => false,
             CtrlInstr::Exec { .. } | CtrlInstr::Fn { .. } | CtrlInstr::Call { .. } => false,
-            CtrlInstr::Ret | CtrlInstr::Stop => false,
+            CtrlInstr::JmpRel { .. } | CtrlInstr::FnRel { .. } => false,
+            CtrlInstr::Ret | CtrlInstr::Stop | CtrlInstr::Trap { .. } => false,
         }
     }
 
@@ -160,12 +280,14 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
             CtrlInstr::Jmp { pos }
             | CtrlInstr::JiOvfl { pos }
             | CtrlInstr::JiFail { pos }
-            | CtrlInstr::Fn { pos } => Some(pos),
+            | CtrlInstr::Fn { pos }
+            | CtrlInstr::JmpRel { pos }
+            | CtrlInstr::FnRel { pos } => Some(pos),
             CtrlInstr::Sh { shift: _ }
             | CtrlInstr::ShOvfl { shift: _ }
             | CtrlInstr::ShFail { shift: _ } => None,
             CtrlInstr::Exec { site: _ } | CtrlInstr::Call { site: _ } => None,
-            CtrlInstr::Ret | CtrlInstr::Stop => None,
+            CtrlInstr::Ret | CtrlInstr::Stop | CtrlInstr::Trap { code: _ } => None,
         }
     }
 
@@ -180,15 +302,88 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
             CtrlInstr::Jmp { pos: _ }
             | CtrlInstr::JiOvfl { pos: _ }
             | CtrlInstr::JiFail { pos: _ }
-            | CtrlInstr::Fn { pos: _ } => None,
+            | CtrlInstr::Fn { pos: _ }
+            | CtrlInstr::JmpRel { pos: _ }
+            | CtrlInstr::FnRel { pos: _ } => None,
             CtrlInstr::Sh { shift: _ }
             | CtrlInstr::ShOvfl { shift: _ }
             | CtrlInstr::ShFail { shift: _ } => None,
             CtrlInstr::Exec { site } | CtrlInstr::Call { site } => Some(site),
-            CtrlInstr::Ret | CtrlInstr::Stop => None,
+            CtrlInstr::Ret | CtrlInstr::Stop | CtrlInstr::Trap { code: _ } => None,
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        match self {
+            CtrlInstr::Nop
+            | CtrlInstr::ChkCo
+            | CtrlInstr::ChkCk
+            | CtrlInstr::NotCo
+            | CtrlInstr::FailCk
+            | CtrlInstr::RsetCk => false,
+            CtrlInstr::Jmp { .. } => true,
+            CtrlInstr::JiOvfl { .. } | CtrlInstr::JiFail { .. } => false,
+            CtrlInstr::Sh { .. } | CtrlInstr::ShOvfl { .. } | CtrlInstr::ShFail { .. } => false,
+            CtrlInstr::Exec { .. } => true,
+            CtrlInstr::Fn { .. } | CtrlInstr::Call { .. } => false,
+            CtrlInstr::JmpRel { .. } => true,
+            CtrlInstr::FnRel { .. } => false,
+            CtrlInstr::Ret | CtrlInstr::Stop | CtrlInstr::Trap { .. } => true,
         }
     }
 
+    fn is_relative_jump(&self) -> bool {
+        matches!(self, CtrlInstr::Sh { .. } | CtrlInstr::ShOvfl { .. } | CtrlInstr::ShFail { .. })
+    }
+
+    fn relative_jump_shift(&self) -> Option<i16> {
+        match *self {
+            CtrlInstr::Sh { shift } | CtrlInstr::ShOvfl { shift } | CtrlInstr::ShFail { shift } => {
+                Some(shift as i16)
+            }
+            _ => None,
+        }
+    }
+
+    fn is_nop(&self) -> bool { *self == CtrlInstr::Nop }
+
+    fn is_goto_relative(&self) -> bool {
+        matches!(self, CtrlInstr::JmpRel { .. } | CtrlInstr::FnRel { .. })
+    }
+
+    fn is_local_call(&self) -> bool {
+        matches!(self, CtrlInstr::Fn { .. } | CtrlInstr::FnRel { .. })
+    }
+
+    fn is_return(&self) -> bool { *self == CtrlInstr::Ret }
+
+    fn is_call(&self) -> bool {
+        matches!(self, CtrlInstr::Fn { .. } | CtrlInstr::FnRel { .. } | CtrlInstr::Call { .. })
+    }
+
+    fn operands(&self) -> Operands<Id, NoRegs> {
+        Operands::from(match *self {
+            CtrlInstr::Nop => vec![],
+            CtrlInstr::ChkCo | CtrlInstr::NotCo => vec![Operand::Co],
+            CtrlInstr::ChkCk | CtrlInstr::FailCk => vec![Operand::Ck],
+            CtrlInstr::RsetCk => vec![Operand::Co, Operand::Ck],
+            CtrlInstr::Jmp { pos }
+            | CtrlInstr::Fn { pos }
+            | CtrlInstr::JmpRel { pos }
+            | CtrlInstr::FnRel { pos } => vec![Operand::Pos(pos)],
+            CtrlInstr::JiOvfl { pos } => vec![Operand::Co, Operand::Pos(pos)],
+            CtrlInstr::JiFail { pos } => vec![Operand::Ck, Operand::Pos(pos)],
+            CtrlInstr::Sh { shift } => vec![Operand::Shift(shift as i16)],
+            CtrlInstr::ShOvfl { shift } => vec![Operand::Co, Operand::Shift(shift as i16)],
+            CtrlInstr::ShFail { shift } => vec![Operand::Ck, Operand::Shift(shift as i16)],
+            CtrlInstr::Exec { site } | CtrlInstr::Call { site } => vec![Operand::Site(site)],
+            CtrlInstr::Ret | CtrlInstr::Stop => vec![],
+            CtrlInstr::Trap { code } => {
+                vec![Operand::Ck, Operand::Imm(code.to_le_bytes().to_vec())]
+            }
+        })
+    }
+
     fn src_regs(&self) -> BTreeSet<NoRegs> { none!() }
 
     fn dst_regs(&self) -> BTreeSet<NoRegs> { none!() }
@@ -206,7 +401,9 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
             CtrlInstr::Exec { .. } => 2,
             CtrlInstr::Fn { .. } => 2,
             CtrlInstr::Call { .. } => 2,
+            CtrlInstr::JmpRel { .. } | CtrlInstr::FnRel { .. } => 2,
             CtrlInstr::Ret | CtrlInstr::Stop => 0,
+            CtrlInstr::Trap { .. } => 2,
         }
     }
 
@@ -223,7 +420,8 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
             CtrlInstr::Exec { .. } => 32,
             CtrlInstr::Fn { .. } => 0,
             CtrlInstr::Call { .. } => 32,
-            CtrlInstr::Ret | CtrlInstr::Stop => 0,
+            CtrlInstr::JmpRel { .. } | CtrlInstr::FnRel { .. } => 0,
+            CtrlInstr::Ret | CtrlInstr::Stop | CtrlInstr::Trap { .. } => 0,
         }
     }
 
@@ -233,8 +431,8 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
         core: &mut Core<Id, Self::Core>,
         _: &Self::Context<'_>,
     ) -> ExecStep<Site<Id>> {
-        let shift_jump = |shift: i8| {
-            let Some(pos) = cursor.offset.checked_add_signed(shift as i16) else {
+        let shift_jump = |shift: i16| {
+            let Some(pos) = cursor.offset.checked_add_signed(shift) else {
                 return ExecStep::Fail;
             };
             ExecStep::Jump(pos)
@@ -274,16 +472,16 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
                 }
             }
             CtrlInstr::Sh { shift } => {
-                return shift_jump(shift);
+                return shift_jump(shift as i16);
             }
             CtrlInstr::ShOvfl { shift } => {
                 if core.co() == Status::Fail {
-                    return shift_jump(shift);
+                    return shift_jump(shift as i16);
                 }
             }
             CtrlInstr::ShFail { shift } => {
                 if core.ck() == Status::Fail {
-                    return shift_jump(shift);
+                    return shift_jump(shift as i16);
                 }
             }
             CtrlInstr::Exec { site } => return ExecStep::Call(site),
@@ -306,6 +504,17 @@ impl<Id: SiteId> Instruction<Id> for CtrlInstr<Id> {
                 }
             }
             CtrlInstr::Stop => return ExecStep::Stop,
+            CtrlInstr::Trap { code } => return ExecStep::Trap(code),
+            CtrlInstr::JmpRel { pos } => return shift_jump(pos as i16),
+            CtrlInstr::FnRel { pos } => {
+                let Some(pos) = cursor.offset.checked_add_signed(pos as i16) else {
+                    return ExecStep::Fail;
+                };
+                return match core.push_cs(cursor) {
+                    Some(_) => ExecStep::Jump(pos),
+                    None => ExecStep::Fail,
+                };
+            }
         }
         ExecStep::Next
     }
@@ -96,4 +96,16 @@ pub enum CtrlInstr<Id: SiteId> {
     /// Stop the program.
     #[display("stop")]
     Stop,
+
+    /// Abort the program with a trap code, setting `CK` to a failed state.
+    #[display("trap    {code:04X}#h")]
+    Trap { code: u16 },
+
+    /// Position-independent jump to location, encoded relative to the instruction offset.
+    #[display("rjmp    {pos:04X}#h")]
+    JmpRel { pos: u16 },
+
+    /// Position-independent subroutine call, encoded relative to the instruction offset.
+    #[display("rcall   {pos:04X}#h")]
+    FnRel { pos: u16 },
 }
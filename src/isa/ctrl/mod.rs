@@ -27,5 +27,7 @@
 mod bytecode;
 mod instr;
 mod exec;
+mod builder;
 
+pub use builder::{BranchBuilder, InstrBuilder};
 pub use instr::CtrlInstr;
@@ -0,0 +1,545 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::BTreeSet;
+use core::fmt::{self, Display, Formatter};
+use core::ops::RangeInclusive;
+
+use amplify::confinement::TinyOrdSet;
+
+use super::{
+    Bytecode, BytecodeRead, BytecodeWrite, CodeEofError, CtrlInstr, ExecStep, Instruction,
+    Operands, RegAccess,
+};
+use crate::core::{Core, CoreExt, Site, SiteId};
+use crate::{EncodingVersion, IsaId};
+
+/// Range of opcodes reserved for the instructions provided by ISA extensions.
+pub const EXT_OP_RANGE: RangeInclusive<u8> = 0x80..=0xFF;
+
+/// Instruction set composed of a base ISA and an ISA extension.
+///
+/// Opcodes falling into the extension [`Bytecode::op_range`] (which must be a part of
+/// [`EXT_OP_RANGE`]) are decoded as extension instructions; the rest of opcodes are decoded with
+/// the base ISA. Thus, the extension instructions shadow the base ISA instructions (normally
+/// [`super::ReservedInstr`]) in the range.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Either<Base, Ext> {
+    /// Instruction from the base ISA.
+    Base(Base),
+
+    /// Instruction from the ISA extension.
+    Ext(Ext),
+}
+
+impl<Base: Display, Ext: Display> Display for Either<Base, Ext> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Either::Base(instr) => Display::fmt(instr, f),
+            Either::Ext(instr) => Display::fmt(instr, f),
+        }
+    }
+}
+
+impl<Id: SiteId, Base: From<CtrlInstr<Id>>, Ext> From<CtrlInstr<Id>> for Either<Base, Ext> {
+    fn from(instr: CtrlInstr<Id>) -> Self { Either::Base(instr.into()) }
+}
+
+impl<Id, Base, Ext> Bytecode<Id> for Either<Base, Ext>
+where
+    Id: SiteId,
+    Base: Bytecode<Id>,
+    Ext: Bytecode<Id>,
+{
+    fn op_range() -> RangeInclusive<u8> { 0..=0xFF }
+
+    fn opcode_byte(&self) -> u8 {
+        match self {
+            Either::Base(instr) => instr.opcode_byte(),
+            Either::Ext(instr) => instr.opcode_byte(),
+        }
+    }
+
+    fn code_byte_len(&self) -> u16 {
+        match self {
+            Either::Base(instr) => instr.code_byte_len(),
+            Either::Ext(instr) => instr.code_byte_len(),
+        }
+    }
+
+    fn external_ref(&self) -> Option<Id> {
+        match self {
+            Either::Base(instr) => instr.external_ref(),
+            Either::Ext(instr) => instr.external_ref(),
+        }
+    }
+
+    fn encoding_version(&self) -> EncodingVersion {
+        match self {
+            Either::Base(instr) => instr.encoding_version(),
+            Either::Ext(instr) => instr.encoding_version(),
+        }
+    }
+
+    fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where W: BytecodeWrite<Id> {
+        match self {
+            Either::Base(instr) => instr.encode_operands(writer),
+            Either::Ext(instr) => instr.encode_operands(writer),
+        }
+    }
+
+    fn decode_operands<R>(reader: &mut R, opcode: u8) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: BytecodeRead<Id>,
+    {
+        debug_assert!(
+            EXT_OP_RANGE.contains(Ext::op_range().start())
+                && EXT_OP_RANGE.contains(Ext::op_range().end()),
+            "ISA extension opcodes must be in the reserved range"
+        );
+        match opcode {
+            op if Ext::op_range().contains(&op) => Ext::decode_operands(reader, op).map(Self::Ext),
+            _ => Base::decode_operands(reader, opcode).map(Self::Base),
+        }
+    }
+}
+
+impl<Id, Base, Ext> Instruction<Id> for Either<Base, Ext>
+where
+    Id: SiteId,
+    Base: Instruction<Id>,
+    Ext: for<'ctx> Instruction<Id, Core = Base::Core, Context<'ctx> = Base::Context<'ctx>>,
+{
+    /// Extensions of the ISA extension; use [`Instruction::isa_ext`] to get the extensions of both
+    /// the base ISA and the ISA extension.
+    const ISA_EXT: &'static [&'static str] = Ext::ISA_EXT;
+
+    const GOTO_TARGET_MNEMONIC: &'static str = Base::GOTO_TARGET_MNEMONIC;
+
+    type Core = Base::Core;
+    type Context<'ctx> = Base::Context<'ctx>;
+
+    fn isa_ext() -> TinyOrdSet<IsaId> {
+        let iter = Base::ISA_EXT
+            .iter()
+            .chain(Ext::ISA_EXT)
+            .copied()
+            .map(IsaId::from);
+        TinyOrdSet::from_iter_checked(iter)
+    }
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Either::Base(instr) => instr.mnemonic(),
+            Either::Ext(instr) => instr.mnemonic(),
+        }
+    }
+
+    fn is_goto_target(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_goto_target(),
+            Either::Ext(instr) => instr.is_goto_target(),
+        }
+    }
+
+    fn local_goto_pos(&mut self) -> Option<&mut u16> {
+        match self {
+            Either::Base(instr) => instr.local_goto_pos(),
+            Either::Ext(instr) => instr.local_goto_pos(),
+        }
+    }
+
+    fn remote_goto_pos(&mut self) -> Option<&mut Site<Id>> {
+        match self {
+            Either::Base(instr) => instr.remote_goto_pos(),
+            Either::Ext(instr) => instr.remote_goto_pos(),
+        }
+    }
+
+    fn local_goto_table(&mut self) -> &mut [u16] {
+        match self {
+            Either::Base(instr) => instr.local_goto_table(),
+            Either::Ext(instr) => instr.local_goto_table(),
+        }
+    }
+
+    fn is_terminal(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_terminal(),
+            Either::Ext(instr) => instr.is_terminal(),
+        }
+    }
+
+    fn is_relative_jump(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_relative_jump(),
+            Either::Ext(instr) => instr.is_relative_jump(),
+        }
+    }
+
+    fn relative_jump_shift(&self) -> Option<i16> {
+        match self {
+            Either::Base(instr) => instr.relative_jump_shift(),
+            Either::Ext(instr) => instr.relative_jump_shift(),
+        }
+    }
+
+    fn is_goto_relative(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_goto_relative(),
+            Either::Ext(instr) => instr.is_goto_relative(),
+        }
+    }
+
+    fn is_nop(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_nop(),
+            Either::Ext(instr) => instr.is_nop(),
+        }
+    }
+
+    fn is_local_call(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_local_call(),
+            Either::Ext(instr) => instr.is_local_call(),
+        }
+    }
+
+    fn is_return(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_return(),
+            Either::Ext(instr) => instr.is_return(),
+        }
+    }
+
+    fn is_call(&self) -> bool {
+        match self {
+            Either::Base(instr) => instr.is_call(),
+            Either::Ext(instr) => instr.is_call(),
+        }
+    }
+
+    fn try_const_eval(&self, next: &Self) -> Option<Self> {
+        match (self, next) {
+            (Either::Base(instr), Either::Base(next)) => instr.try_const_eval(next).map(Self::Base),
+            (Either::Ext(instr), Either::Ext(next)) => instr.try_const_eval(next).map(Self::Ext),
+            _ => None,
+        }
+    }
+
+    fn reg_access(&self) -> RegAccess<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Either::Base(instr) => instr.reg_access(),
+            Either::Ext(instr) => instr.reg_access(),
+        }
+    }
+
+    fn operands(&self) -> Operands<Id, <Self::Core as CoreExt>::Reg> {
+        match self {
+            Either::Base(instr) => instr.operands(),
+            Either::Ext(instr) => instr.operands(),
+        }
+    }
+
+    fn src_regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Either::Base(instr) => instr.src_regs(),
+            Either::Ext(instr) => instr.src_regs(),
+        }
+    }
+
+    fn dst_regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Either::Base(instr) => instr.dst_regs(),
+            Either::Ext(instr) => instr.dst_regs(),
+        }
+    }
+
+    fn op_data_bytes(&self) -> u16 {
+        match self {
+            Either::Base(instr) => instr.op_data_bytes(),
+            Either::Ext(instr) => instr.op_data_bytes(),
+        }
+    }
+
+    fn ext_data_bytes(&self) -> u16 {
+        match self {
+            Either::Base(instr) => instr.ext_data_bytes(),
+            Either::Ext(instr) => instr.ext_data_bytes(),
+        }
+    }
+
+    fn complexity(&self) -> u64 {
+        match self {
+            Either::Base(instr) => instr.complexity(),
+            Either::Ext(instr) => instr.complexity(),
+        }
+    }
+
+    fn exec(
+        &self,
+        site: Site<Id>,
+        core: &mut Core<Id, Self::Core>,
+        context: &Self::Context<'_>,
+    ) -> ExecStep<Site<Id>> {
+        match self {
+            Either::Base(instr) => instr.exec(site, core, context),
+            Either::Ext(instr) => instr.exec(site, core, context),
+        }
+    }
+}
+
+/// Implements the methods of [`Instruction`] for an instruction set wrapping an inner ISA into its
+/// `Isa` variant and adding extension instructions, which are neither goto targets nor jumps and
+/// can't be evaluated at compile time.
+///
+/// The methods are forwarded to the inner ISA, returning the neutral values for the extension
+/// instructions.
+macro_rules! forward_inner_isa {
+    ($wrapper:ident) => {
+        const GOTO_TARGET_MNEMONIC: &'static str = Isa::GOTO_TARGET_MNEMONIC;
+
+        fn isa_ext() -> TinyOrdSet<IsaId> {
+            let iter = Isa::isa_ext()
+                .into_iter()
+                .chain(Self::ISA_EXT.iter().copied().map(IsaId::from));
+            TinyOrdSet::from_iter_checked(iter)
+        }
+
+        fn is_goto_target(&self) -> bool {
+            matches!(self, $wrapper::Isa(instr) if instr.is_goto_target())
+        }
+
+        fn local_goto_pos(&mut self) -> Option<&mut u16> {
+            match self {
+                $wrapper::Isa(instr) => instr.local_goto_pos(),
+                _ => None,
+            }
+        }
+
+        fn remote_goto_pos(&mut self) -> Option<&mut Site<Id>> {
+            match self {
+                $wrapper::Isa(instr) => instr.remote_goto_pos(),
+                _ => None,
+            }
+        }
+
+        fn local_goto_table(&mut self) -> &mut [u16] {
+            match self {
+                $wrapper::Isa(instr) => instr.local_goto_table(),
+                _ => &mut [],
+            }
+        }
+
+        fn is_terminal(&self) -> bool {
+            matches!(self, $wrapper::Isa(instr) if instr.is_terminal())
+        }
+
+        fn is_relative_jump(&self) -> bool {
+            matches!(self, $wrapper::Isa(instr) if instr.is_relative_jump())
+        }
+
+        fn relative_jump_shift(&self) -> Option<i16> {
+            match self {
+                $wrapper::Isa(instr) => instr.relative_jump_shift(),
+                _ => None,
+            }
+        }
+
+        fn is_goto_relative(&self) -> bool {
+            matches!(self, $wrapper::Isa(instr) if instr.is_goto_relative())
+        }
+
+        fn is_nop(&self) -> bool { matches!(self, $wrapper::Isa(instr) if instr.is_nop()) }
+
+        fn is_local_call(&self) -> bool {
+            matches!(self, $wrapper::Isa(instr) if instr.is_local_call())
+        }
+
+        fn is_return(&self) -> bool { matches!(self, $wrapper::Isa(instr) if instr.is_return()) }
+
+        fn is_call(&self) -> bool { matches!(self, $wrapper::Isa(instr) if instr.is_call()) }
+
+        fn try_const_eval(&self, next: &Self) -> Option<Self> {
+            match (self, next) {
+                ($wrapper::Isa(instr), $wrapper::Isa(next)) => {
+                    instr.try_const_eval(next).map($wrapper::Isa)
+                }
+                _ => None,
+            }
+        }
+    };
+}
+pub(super) use forward_inner_isa;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{CtrlInstr, Instr, Operand};
+    use crate::regs::Status;
+    use crate::testing::isa::XSwitch;
+    use crate::{CompiledLib, CompilerError, LibId, NoExt, NoRegs, Vm};
+
+    /// Extension instruction setting `CK` to a failed state.
+    #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+    #[display("xfail")]
+    struct XFail;
+
+    impl Bytecode<LibId> for XFail {
+        fn op_range() -> RangeInclusive<u8> { 0x80..=0x80 }
+
+        fn opcode_byte(&self) -> u8 { 0x80 }
+
+        fn code_byte_len(&self) -> u16 { 1 }
+
+        fn external_ref(&self) -> Option<LibId> { None }
+
+        fn encode_operands<W>(&self, _writer: &mut W) -> Result<(), W::Error>
+        where W: BytecodeWrite<LibId> {
+            Ok(())
+        }
+
+        fn decode_operands<R>(_reader: &mut R, _opcode: u8) -> Result<Self, CodeEofError>
+        where
+            Self: Sized,
+            R: BytecodeRead<LibId>,
+        {
+            Ok(XFail)
+        }
+    }
+
+    impl Instruction<LibId> for XFail {
+        const ISA_EXT: &'static [&'static str] = &["XFAIL"];
+
+        type Core = NoExt;
+        type Context<'ctx> = ();
+
+        fn mnemonic(&self) -> &'static str { "xfail" }
+
+        fn is_goto_target(&self) -> bool { false }
+
+        fn local_goto_pos(&mut self) -> Option<&mut u16> { None }
+
+        fn remote_goto_pos(&mut self) -> Option<&mut Site<LibId>> { None }
+
+        fn src_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+        fn dst_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+        fn op_data_bytes(&self) -> u16 { none!() }
+
+        fn ext_data_bytes(&self) -> u16 { none!() }
+
+        fn exec(
+            &self,
+            _: Site<LibId>,
+            _: &mut Core<LibId, NoExt>,
+            _: &Self::Context<'_>,
+        ) -> ExecStep<Site<LibId>> {
+            ExecStep::Fail
+        }
+    }
+
+    type Isa = Either<Instr<LibId>, XFail>;
+
+    #[test]
+    fn extension() {
+        let code: Vec<Isa> = vec![
+            Either::Base(CtrlInstr::Nop.into()),
+            Either::Ext(XFail),
+            Either::Base(CtrlInstr::Stop.into()),
+        ];
+        assert_eq!(
+            Isa::isa_ext()
+                .iter()
+                .map(IsaId::to_string)
+                .collect::<Vec<_>>(),
+            vec!["XFAIL"]
+        );
+
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(lib.as_lib().code.as_slice(), &[0x00, 0x80, 0x10]);
+        assert_eq!(lib.as_lib().disassemble::<Isa>().unwrap(), code);
+
+        let mut vm = Vm::<Isa>::new();
+        assert_eq!(vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib())), Status::Fail);
+    }
+
+    #[test]
+    fn jump_table() {
+        type Isa = Either<Instr<LibId>, XSwitch>;
+        const OK: u16 = 1;
+        const FAIL: u16 = 2;
+        let run = |select: bool, table: Vec<u16>| {
+            let mut code: Vec<Isa> = vec![Either::Base(CtrlInstr::Nop.into())];
+            if select {
+                code.push(Either::Base(CtrlInstr::NotCo.into()));
+            }
+            code.extend([
+                Either::Ext(XSwitch(table)),
+                Either::Base(CtrlInstr::Stop.into()),
+                Either::Base(CtrlInstr::Nop.into()),
+                Either::Base(CtrlInstr::Stop.into()),
+                Either::Base(CtrlInstr::Nop.into()),
+                Either::Base(CtrlInstr::FailCk.into()),
+                Either::Base(CtrlInstr::Stop.into()),
+            ]);
+            let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+            assert_eq!(lib.disassemble::<Isa>(&[]).unwrap(), code);
+            let mut vm = Vm::<Isa>::new();
+            vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib()))
+        };
+        assert_eq!(run(false, vec![OK, FAIL]), Status::Ok);
+        assert_eq!(run(true, vec![OK, FAIL]), Status::Fail);
+        assert_eq!(run(false, vec![FAIL]), Status::Fail);
+        assert_eq!(run(true, vec![OK]), Status::Fail);
+
+        let code: Vec<Isa> =
+            vec![Either::Base(CtrlInstr::Nop.into()), Either::Ext(XSwitch(vec![1]))];
+        assert!(matches!(
+            CompiledLib::compile(code, &[]).unwrap_err(),
+            CompilerError::InvalidRef(_, 1, 1, "nop", _)
+        ));
+    }
+
+    #[test]
+    fn operands() {
+        type Isa = Either<Instr<LibId>, XSwitch>;
+        let site = Site::new(LibId::RELOC, 5);
+        let instr: Isa = Either::Base(CtrlInstr::JiOvfl { pos: 4 }.into());
+        assert_eq!(instr.operands(), Operands::from(vec![Operand::Co, Operand::Pos(4)]));
+        let instr: Isa = Either::Base(CtrlInstr::ShFail { shift: -3 }.into());
+        assert_eq!(instr.operands(), Operands::from(vec![Operand::Ck, Operand::Shift(-3)]));
+        let instr: Isa = Either::Base(CtrlInstr::Call { site }.into());
+        assert_eq!(instr.operands().sites().collect::<Vec<_>>(), vec![site]);
+        let instr: Isa = Either::Base(CtrlInstr::Stop.into());
+        assert!(instr.operands().is_empty());
+
+        // Operands reported by default
+        let instr: Isa = Either::Ext(XSwitch(vec![1, 2]));
+        assert_eq!(instr.operands().positions().collect::<Vec<_>>(), vec![1, 2]);
+    }
+}
@@ -0,0 +1,507 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Host function calls, allowing programs to call back into the host environment running the VM.
+
+use alloc::collections::BTreeSet;
+use core::cell::{Cell, RefCell};
+use core::fmt::{self, Display, Formatter};
+use core::ops::RangeInclusive;
+
+use amplify::confinement::TinyOrdSet;
+
+use super::ext::forward_inner_isa;
+use super::{
+    Bytecode, BytecodeRead, BytecodeWrite, CodeEofError, CtrlInstr, ExecStep, Instruction, Operand,
+    Operands, RegAccess,
+};
+use crate::core::{Core, CoreExt, NoExt, NoRegs, Site, SiteId};
+use crate::{EncodingVersion, IsaId};
+
+/// Opcode of the host function call instruction, which lies in the [`super::EXT_OP_RANGE`].
+pub const HOST_CALL_OPCODE: u8 = 0xFF;
+
+/// Errors returned by the host functions.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum HostError {
+    /// host function {0:#06x} is not registered.
+    UnknownCall(u16),
+
+    /// host function {0:#06x} has failed.
+    Failed(u16),
+}
+
+/// Host environment providing functions which can be called by a program with the
+/// [`Hosted::ExtCall`] instruction.
+pub trait HostEnv<Cx: CoreExt> {
+    /// Dispatches host function call, providing the function with an access to the core extension
+    /// registers.
+    ///
+    /// Must return [`HostError::UnknownCall`] for the call ids which are not registered. The host
+    /// function must access only the registers declared by the [`HostRegs`] implementation of the
+    /// core extension.
+    fn dispatch(&mut self, call_id: u16, regs: &mut Cx) -> Result<(), HostError>;
+}
+
+/// Host environment without any registered functions.
+impl<Cx: CoreExt> HostEnv<Cx> for () {
+    fn dispatch(&mut self, call_id: u16, _: &mut Cx) -> Result<(), HostError> {
+        Err(HostError::UnknownCall(call_id))
+    }
+}
+
+/// Core extension registers accessed by the host functions, which are reported as the registers
+/// read and written by the [`Hosted::ExtCall`] instructions.
+///
+/// The register sets are used by the compiler and the optimizer in the data-flow analysis; thus an
+/// implementation must include all the registers which may be accessed by any of the host
+/// environments used with the core.
+pub trait HostRegs: CoreExt {
+    /// Returns the registers which may be read by the host function with the given call id.
+    fn host_reads(call_id: u16) -> BTreeSet<Self::Reg>;
+
+    /// Returns the registers which may be written by the host function with the given call id.
+    fn host_writes(call_id: u16) -> BTreeSet<Self::Reg>;
+}
+
+impl HostRegs for NoExt {
+    fn host_reads(_: u16) -> BTreeSet<NoRegs> { none!() }
+
+    fn host_writes(_: u16) -> BTreeSet<NoRegs> { none!() }
+}
+
+/// Counter of the nested host calls, limiting the reentrancy of the VM from the host functions.
+///
+/// A host function may run another program in a nested VM (for instance, to execute a callback),
+/// which in its turn may call the host functions. To bound the nesting, the same guard must be
+/// provided to the contexts of all the nested VMs (see [`HostContext::with_guard`]). A host call,
+/// executed while more than `max_reentrancy` other host calls are in progress, halts the program
+/// with [`crate::ExecError::ReentrancyLimit`].
+///
+/// The depth is restored once the host call completes, even if the host function panics.
+#[derive(Eq, PartialEq, Debug)]
+pub struct ReentrancyGuard {
+    depth: Cell<u16>,
+    max_reentrancy: u16,
+}
+
+impl ReentrancyGuard {
+    /// Constructs a guard allowing up to `max_reentrancy` levels of the nested host calls.
+    ///
+    /// With zero `max_reentrancy`, the programs run by the host functions can't do host calls.
+    pub fn new(max_reentrancy: u16) -> Self { Self { depth: Cell::new(0), max_reentrancy } }
+
+    /// Returns the maximal number of the nested host calls allowed by the guard.
+    pub fn max_reentrancy(&self) -> u16 { self.max_reentrancy }
+
+    /// Returns the number of the host calls which are currently in progress.
+    pub fn depth(&self) -> u16 { self.depth.get() }
+
+    /// Registers a host call, returning `None` if the limit is exceeded.
+    fn enter(&self) -> Option<ReentrancyScope<'_>> {
+        let depth = self.depth.get();
+        if depth > self.max_reentrancy {
+            return None;
+        }
+        self.depth.set(depth + 1);
+        Some(ReentrancyScope(self))
+    }
+}
+
+/// Host call registered with a [`ReentrancyGuard`], which is left once the scope is dropped.
+struct ReentrancyScope<'guard>(&'guard ReentrancyGuard);
+
+impl Drop for ReentrancyScope<'_> {
+    fn drop(&mut self) { self.0.depth.set(self.0.depth.get() - 1); }
+}
+
+/// Execution context of the [`Hosted`] instruction set, combining the context of the inner ISA
+/// with the host environment.
+pub struct HostContext<'ctx, Cx: CoreExt, Ctx> {
+    /// Context of the inner ISA.
+    pub isa: Ctx,
+    env: RefCell<&'ctx mut dyn HostEnv<Cx>>,
+    error: Cell<Option<HostError>>,
+    guard: Option<&'ctx ReentrancyGuard>,
+}
+
+impl<'ctx, Cx: CoreExt, Ctx> HostContext<'ctx, Cx, Ctx> {
+    /// Constructs context from the inner ISA context and the host environment.
+    pub fn new(isa: Ctx, env: &'ctx mut dyn HostEnv<Cx>) -> Self {
+        Self {
+            isa,
+            env: RefCell::new(env),
+            error: Cell::new(None),
+            guard: None,
+        }
+    }
+
+    /// Limits the nested host calls with the reentrancy guard, which must be shared with the
+    /// contexts of the nested VMs run by the host functions.
+    pub fn with_guard(mut self, guard: &'ctx ReentrancyGuard) -> Self {
+        self.guard = Some(guard);
+        self
+    }
+
+    /// Returns the error of the last failed host function call, if any.
+    pub fn last_error(&self) -> Option<HostError> { self.error.get() }
+
+    fn dispatch(&self, call_id: u16, regs: &mut Cx) -> Result<(), HostError> {
+        let Ok(mut env) = self.env.try_borrow_mut() else {
+            return Err(HostError::Failed(call_id));
+        };
+        env.dispatch(call_id, regs)
+    }
+}
+
+/// Instruction set extending an ISA with the host function calls.
+///
+/// The host function call instruction uses [`HOST_CALL_OPCODE`], shadowing the instruction of the
+/// inner ISA with the same opcode (if any). When executed, the call is routed to the [`HostEnv`]
+/// provided in the [`HostContext`]. If the host function fails (including the case of the call id
+/// not being registered), `CK` is set to a failed state, and the error is recorded in the context
+/// (see [`HostContext::last_error`]). The registers accessed by the host functions are declared
+/// by the [`HostRegs`] implementation of the core extension.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Hosted<Isa> {
+    /// Instruction of the inner ISA.
+    Isa(Isa),
+
+    /// Host function call.
+    ExtCall(u16),
+}
+
+impl<Isa: Display> Display for Hosted<Isa> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Hosted::Isa(instr) => Display::fmt(instr, f),
+            Hosted::ExtCall(call_id) => write!(f, "ext_call {call_id:04X}#h"),
+        }
+    }
+}
+
+impl<Id: SiteId, Isa: From<CtrlInstr<Id>>> From<CtrlInstr<Id>> for Hosted<Isa> {
+    fn from(instr: CtrlInstr<Id>) -> Self { Hosted::Isa(instr.into()) }
+}
+
+impl<Id: SiteId, Isa: Bytecode<Id>> Bytecode<Id> for Hosted<Isa> {
+    fn op_range() -> RangeInclusive<u8> { 0..=0xFF }
+
+    fn opcode_byte(&self) -> u8 {
+        match self {
+            Hosted::Isa(instr) => instr.opcode_byte(),
+            Hosted::ExtCall(_) => HOST_CALL_OPCODE,
+        }
+    }
+
+    fn code_byte_len(&self) -> u16 {
+        match self {
+            Hosted::Isa(instr) => instr.code_byte_len(),
+            Hosted::ExtCall(_) => 3,
+        }
+    }
+
+    fn external_ref(&self) -> Option<Id> {
+        match self {
+            Hosted::Isa(instr) => instr.external_ref(),
+            Hosted::ExtCall(_) => None,
+        }
+    }
+
+    fn encoding_version(&self) -> EncodingVersion {
+        match self {
+            Hosted::Isa(instr) => instr.encoding_version(),
+            Hosted::ExtCall(_) => EncodingVersion::V0,
+        }
+    }
+
+    fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where W: BytecodeWrite<Id> {
+        match self {
+            Hosted::Isa(instr) => instr.encode_operands(writer),
+            Hosted::ExtCall(call_id) => writer.write_word(*call_id),
+        }
+    }
+
+    fn decode_operands<R>(reader: &mut R, opcode: u8) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: BytecodeRead<Id>,
+    {
+        match opcode {
+            HOST_CALL_OPCODE => reader.read_word().map(Hosted::ExtCall),
+            _ => Isa::decode_operands(reader, opcode).map(Hosted::Isa),
+        }
+    }
+}
+
+impl<Id, Isa> Instruction<Id> for Hosted<Isa>
+where
+    Id: SiteId,
+    Isa: Instruction<Id>,
+    Isa::Core: HostRegs + 'static,
+{
+    /// Extensions added to the inner ISA; use [`Instruction::isa_ext`] to get all the extensions.
+    const ISA_EXT: &'static [&'static str] = &["HOST"];
+
+    type Core = Isa::Core;
+    type Context<'ctx> = HostContext<'ctx, Isa::Core, Isa::Context<'ctx>>;
+
+    forward_inner_isa!(Hosted);
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Hosted::Isa(instr) => instr.mnemonic(),
+            Hosted::ExtCall(_) => "ext_call",
+        }
+    }
+
+    fn reg_access(&self) -> RegAccess<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Hosted::Isa(instr) => instr.reg_access(),
+            Hosted::ExtCall(_) => {
+                RegAccess { reads: self.src_regs(), writes: self.dst_regs(), pure: false }
+            }
+        }
+    }
+
+    fn operands(&self) -> Operands<Id, <Self::Core as CoreExt>::Reg> {
+        match self {
+            Hosted::Isa(instr) => instr.operands(),
+            Hosted::ExtCall(call_id) => {
+                Operands::from(vec![Operand::Imm(call_id.to_le_bytes().to_vec())])
+            }
+        }
+    }
+
+    fn src_regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Hosted::Isa(instr) => instr.src_regs(),
+            Hosted::ExtCall(call_id) => Isa::Core::host_reads(*call_id),
+        }
+    }
+
+    fn dst_regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Hosted::Isa(instr) => instr.dst_regs(),
+            Hosted::ExtCall(call_id) => Isa::Core::host_writes(*call_id),
+        }
+    }
+
+    fn op_data_bytes(&self) -> u16 {
+        match self {
+            Hosted::Isa(instr) => instr.op_data_bytes(),
+            Hosted::ExtCall(_) => 2,
+        }
+    }
+
+    fn ext_data_bytes(&self) -> u16 {
+        match self {
+            Hosted::Isa(instr) => instr.ext_data_bytes(),
+            Hosted::ExtCall(_) => 0,
+        }
+    }
+
+    fn complexity(&self) -> u64 {
+        match self {
+            Hosted::Isa(instr) => instr.complexity(),
+            Hosted::ExtCall(_) => self.base_complexity(),
+        }
+    }
+
+    fn exec(
+        &self,
+        site: Site<Id>,
+        core: &mut Core<Id, Self::Core>,
+        context: &Self::Context<'_>,
+    ) -> ExecStep<Site<Id>> {
+        match self {
+            Hosted::Isa(instr) => instr.exec(site, core, &context.isa),
+            Hosted::ExtCall(call_id) => {
+                let _scope = match context.guard.map(ReentrancyGuard::enter) {
+                    Some(None) => return ExecStep::ReentrancyLimit,
+                    scope => scope,
+                };
+                match context.dispatch(*call_id, &mut core.cx) {
+                    Ok(()) => ExecStep::Next,
+                    Err(err) => {
+                        context.error.set(Some(err));
+                        ExecStep::Fail
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::Instr;
+    use crate::regs::Status;
+    use crate::testing::isa::{RegInstr, XCore, XReg};
+    use crate::{CompiledLib, ExecError, LibId, LibSite, NoExt, Vm};
+
+    /// Host environment providing a clock, which puts the current time into the register `A`.
+    struct Clock(u8);
+
+    impl HostRegs for XCore {
+        fn host_reads(_: u16) -> BTreeSet<XReg> { none!() }
+
+        fn host_writes(call_id: u16) -> BTreeSet<XReg> {
+            match call_id {
+                0 => bset![XReg::A],
+                _ => none!(),
+            }
+        }
+    }
+
+    impl HostEnv<XCore> for Clock {
+        fn dispatch(&mut self, call_id: u16, regs: &mut XCore) -> Result<(), HostError> {
+            match call_id {
+                0 => {
+                    regs.put(XReg::A, Some(self.0));
+                    self.0 += 1;
+                    Ok(())
+                }
+                _ => Err(HostError::UnknownCall(call_id)),
+            }
+        }
+    }
+
+    #[test]
+    fn host_call() {
+        type Isa = Hosted<RegInstr>;
+        let code: Vec<Isa> = vec![
+            Hosted::Isa(RegInstr::Nop),
+            Hosted::ExtCall(0),
+            Hosted::Isa(RegInstr::Mov(XReg::B, XReg::A)),
+            Hosted::ExtCall(0),
+        ];
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(lib.as_lib().code.as_slice(), &[0x00, 0xFF, 0, 0, 0x02, 1, 0, 0xFF, 0, 0]);
+        assert_eq!(lib.as_lib().disassemble::<Isa>().unwrap(), code);
+        assert_eq!(code[1].to_string(), "ext_call 0000#h");
+        assert_eq!(Instruction::<LibId>::src_regs(&code[1]), none!());
+        assert_eq!(Instruction::<LibId>::dst_regs(&code[1]), bset![XReg::A]);
+
+        let mut clock = Clock(7);
+        let context = HostContext::new((), &mut clock);
+        let mut vm = Vm::<Isa>::new();
+        assert_eq!(vm.exec(lib.routine(0), &context, |_| Some(lib.as_lib())), Status::Ok);
+        assert_eq!(vm.core.get(XReg::A), Some(8));
+        assert_eq!(vm.core.get(XReg::B), Some(7));
+        assert_eq!(context.last_error(), None);
+        assert_eq!(clock.0, 9);
+    }
+
+    #[test]
+    fn unknown_call() {
+        type Isa = Hosted<Instr<LibId>>;
+        let code: Vec<Isa> =
+            vec![CtrlInstr::Nop.into(), Hosted::ExtCall(0x1234), CtrlInstr::Stop.into()];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        assert_eq!(
+            Isa::isa_ext()
+                .iter()
+                .map(IsaId::to_string)
+                .collect::<Vec<_>>(),
+            vec!["HOST"]
+        );
+
+        let mut env = ();
+        let context = HostContext::<NoExt, ()>::new((), &mut env);
+        let mut vm = Vm::<Isa>::new();
+        assert_eq!(vm.exec(lib.routine(0), &context, |_| Some(lib.as_lib())), Status::Fail);
+        assert_eq!(context.last_error(), Some(HostError::UnknownCall(0x1234)));
+    }
+
+    /// Host environment which runs the library in a nested VM on each host call.
+    struct Reenter<'a> {
+        lib: &'a CompiledLib,
+        guard: &'a ReentrancyGuard,
+        fault: &'a Cell<Option<ExecError>>,
+    }
+
+    impl HostEnv<NoExt> for Reenter<'_> {
+        fn dispatch(&mut self, call_id: u16, _: &mut NoExt) -> Result<(), HostError> {
+            let mut env = Reenter { lib: self.lib, guard: self.guard, fault: self.fault };
+            let context = HostContext::new((), &mut env).with_guard(self.guard);
+            let mut vm = Vm::<Hosted<Instr<LibId>>>::new();
+            match vm.run(self.lib.routine(0), &context, |_| Some(self.lib.as_lib())) {
+                Ok(Status::Ok) => Ok(()),
+                Ok(Status::Fail) => Err(HostError::Failed(call_id)),
+                Err(err) => {
+                    self.fault.set(Some(err));
+                    Err(HostError::Failed(call_id))
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reentrancy_limit() {
+        type Isa = Hosted<Instr<LibId>>;
+        let code: Vec<Isa> =
+            vec![CtrlInstr::Nop.into(), Hosted::ExtCall(0), CtrlInstr::Stop.into()];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let guard = ReentrancyGuard::new(2);
+        let fault = Cell::new(None);
+        let mut env = Reenter { lib: &lib, guard: &guard, fault: &fault };
+        let context = HostContext::new((), &mut env).with_guard(&guard);
+        let mut vm = Vm::<Isa>::new();
+        assert_eq!(vm.exec(lib.routine(0), &context, |_| Some(lib.as_lib())), Status::Fail);
+        let site = LibSite::new(lib.as_lib().lib_id(), 1);
+        assert_eq!(fault.get(), Some(ExecError::ReentrancyLimit(site)));
+        assert_eq!(context.last_error(), Some(HostError::Failed(0)));
+        assert_eq!(guard.depth(), 0);
+        assert_eq!(guard.max_reentrancy(), 2);
+    }
+
+    struct Panic;
+
+    impl HostEnv<NoExt> for Panic {
+        fn dispatch(&mut self, _: u16, _: &mut NoExt) -> Result<(), HostError> {
+            panic!("host function failure")
+        }
+    }
+
+    #[test]
+    fn reentrancy_unwind() {
+        type Isa = Hosted<Instr<LibId>>;
+        let code: Vec<Isa> =
+            vec![CtrlInstr::Nop.into(), Hosted::ExtCall(0), CtrlInstr::Stop.into()];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let guard = ReentrancyGuard::new(0);
+        let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            let mut env = Panic;
+            let context = HostContext::new((), &mut env).with_guard(&guard);
+            let mut vm = Vm::<Isa>::new();
+            vm.exec(lib.routine(0), &context, |_| Some(lib.as_lib()))
+        }));
+        assert!(res.is_err());
+        assert_eq!(guard.depth(), 0);
+    }
+}
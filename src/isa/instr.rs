@@ -23,6 +23,7 @@
 // the License.
 
 use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
 use core::fmt::{Debug, Display};
 
 use amplify::confinement::TinyOrdSet;
@@ -33,6 +34,7 @@ use crate::{CoreExt, IsaId};
 
 /// Turing machine movement after instruction execution
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[non_exhaustive]
 pub enum ExecStep<Site> {
     /// Stop program execution.
     Stop,
@@ -51,12 +53,153 @@ pub enum ExecStep<Site> {
 
     /// Return to the next instruction after the original caller position.
     Ret(Site),
+
+    /// Abort program execution with a trap code, setting `CK` to a failed state.
+    Trap(u16),
+
+    /// Halt program execution, since the instruction exceeds the limit of the nested host calls
+    /// (see [`crate::isa::ReentrancyGuard`]).
+    ReentrancyLimit,
+}
+
+/// Registers accessed by an instruction, as reported by [`Instruction::reg_access`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RegAccess<Reg: Register> {
+    /// Registers which value is read by the instruction.
+    pub reads: BTreeSet<Reg>,
+
+    /// Registers which value is fully overwritten by the instruction.
+    pub writes: BTreeSet<Reg>,
+
+    /// Whether the instruction has no effects other than writing [`Self::writes`] registers.
+    ///
+    /// Pure instructions don't change control registers, don't read or write external data and
+    /// always pass the control flow to the next instruction.
+    pub pure: bool,
+}
+
+/// Operand of an instruction, as reported by [`Instruction::operands`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Operand<Id: SiteId, Reg: Register> {
+    /// Register which value is taken into the account by the instruction.
+    Src(Reg),
+
+    /// Register which value may be changed by the instruction.
+    Dst(Reg),
+
+    /// Control register `CO`, which is read or modified by the instruction.
+    Co,
+
+    /// Control register `CK`, which is read or modified by the instruction.
+    Ck,
+
+    /// Immediate value, in the little-endian byte order.
+    Imm(Vec<u8>),
+
+    /// Code offset within the same library, which is a goto target or a called routine.
+    Pos(u16),
+
+    /// Jump target offset relative to the position of the instruction.
+    Shift(i16),
+
+    /// Code location in another library.
+    Site(Site<Id>),
+}
+
+/// Operands of an instruction, as reported by [`Instruction::operands`], in the order they appear
+/// in the assembly.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Operands<Id: SiteId, Reg: Register>(Vec<Operand<Id, Reg>>);
+
+impl<Id: SiteId, Reg: Register> Default for Operands<Id, Reg> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<Id: SiteId, Reg: Register> Operands<Id, Reg> {
+    /// Constructs an empty list of operands.
+    pub const fn new() -> Self { Self(Vec::new()) }
+
+    /// Adds an operand to the end of the list.
+    pub fn push(&mut self, operand: Operand<Id, Reg>) { self.0.push(operand); }
+
+    /// Returns the number of the operands.
+    pub fn len(&self) -> usize { self.0.len() }
+
+    /// Checks whether the instruction has no operands.
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+
+    /// Iterates over the operands.
+    pub fn iter(&self) -> core::slice::Iter<'_, Operand<Id, Reg>> { self.0.iter() }
+
+    /// Iterates over the registers which values are taken into the account by the instruction.
+    pub fn src_regs(&self) -> impl Iterator<Item = Reg> + '_ {
+        self.iter().filter_map(|op| match op {
+            Operand::Src(reg) => Some(*reg),
+            _ => None,
+        })
+    }
+
+    /// Iterates over the registers which values may be changed by the instruction.
+    pub fn dst_regs(&self) -> impl Iterator<Item = Reg> + '_ {
+        self.iter().filter_map(|op| match op {
+            Operand::Dst(reg) => Some(*reg),
+            _ => None,
+        })
+    }
+
+    /// Iterates over the code offsets within the same library referenced by the instruction.
+    pub fn positions(&self) -> impl Iterator<Item = u16> + '_ {
+        self.iter().filter_map(|op| match op {
+            Operand::Pos(pos) => Some(*pos),
+            _ => None,
+        })
+    }
+
+    /// Iterates over the code locations in other libraries referenced by the instruction.
+    pub fn sites(&self) -> impl Iterator<Item = Site<Id>> + '_ {
+        self.iter().filter_map(|op| match op {
+            Operand::Site(site) => Some(*site),
+            _ => None,
+        })
+    }
+}
+
+impl<Id: SiteId, Reg: Register> From<Vec<Operand<Id, Reg>>> for Operands<Id, Reg> {
+    fn from(operands: Vec<Operand<Id, Reg>>) -> Self { Self(operands) }
+}
+
+impl<Id: SiteId, Reg: Register> FromIterator<Operand<Id, Reg>> for Operands<Id, Reg> {
+    fn from_iter<I: IntoIterator<Item = Operand<Id, Reg>>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
+}
+
+impl<Id: SiteId, Reg: Register> Extend<Operand<Id, Reg>> for Operands<Id, Reg> {
+    fn extend<I: IntoIterator<Item = Operand<Id, Reg>>>(&mut self, iter: I) { self.0.extend(iter) }
+}
+
+impl<Id: SiteId, Reg: Register> IntoIterator for Operands<Id, Reg> {
+    type Item = Operand<Id, Reg>;
+    type IntoIter = alloc::vec::IntoIter<Operand<Id, Reg>>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
+}
+
+impl<'a, Id: SiteId, Reg: Register> IntoIterator for &'a Operands<Id, Reg> {
+    type Item = &'a Operand<Id, Reg>;
+    type IntoIter = core::slice::Iter<'a, Operand<Id, Reg>>;
+
+    fn into_iter(self) -> Self::IntoIter { self.0.iter() }
 }
 
 /// Trait for instructions
 pub trait Instruction<Id: SiteId>: Display + Debug + Bytecode<Id> + Clone + Eq {
     const ISA_EXT: &'static [&'static str];
 
+    /// Mnemonic of the instruction marking goto targets (see [`Self::is_goto_target`]), which is
+    /// used in the compiler diagnostics.
+    const GOTO_TARGET_MNEMONIC: &'static str = "nop";
+
     type Core: CoreExt;
     /// Context: external data which are accessible to the ISA.
     type Context<'ctx>;
@@ -66,12 +209,113 @@ pub trait Instruction<Id: SiteId>: Display + Debug + Bytecode<Id> + Clone + Eq {
         TinyOrdSet::from_iter_checked(iter)
     }
 
+    /// Returns the mnemonic of the instruction, which is the first word of its assembly
+    /// representation (i.e. its [`Display`]).
+    ///
+    /// Instruction variants may share a mnemonic and differ only in their operands, like `chk CO`
+    /// and `chk CK`.
+    fn mnemonic(&self) -> &'static str;
+
     fn is_goto_target(&self) -> bool;
 
     fn local_goto_pos(&mut self) -> Option<&mut u16>;
 
     fn remote_goto_pos(&mut self) -> Option<&mut Site<Id>>;
 
+    /// Returns jump table of an indirect goto instruction, which selects the goto target from the
+    /// table at runtime.
+    ///
+    /// Like with [`Self::local_goto_pos`], the table entries are provided to the compiler as
+    /// routine numbers, and are replaced by it with the code offsets of the routine entry points.
+    /// The instruction, when executed, must bounds-check the selected table index and return
+    /// [`ExecStep::Fail`] if it lies outside the table.
+    fn local_goto_table(&mut self) -> &mut [u16] { &mut [] }
+
+    /// Checks whether the instruction never passes the control flow to the instruction following
+    /// it.
+    ///
+    /// Conditional and relative jumps, as well as subroutine calls, must return `false`.
+    fn is_terminal(&self) -> bool { false }
+
+    /// Checks whether the instruction jumps to an offset relative to its own position.
+    ///
+    /// The target of such jumps depends on the byte length of the code between the instruction and
+    /// the target, thus code transformations which change the code length must preserve it.
+    fn is_relative_jump(&self) -> bool { false }
+
+    /// Returns the byte shift of the [relative jump](Self::is_relative_jump) target from the
+    /// offset of the instruction, or `None` if the instruction is not a relative jump or its shift
+    /// is unknown.
+    ///
+    /// Code transformations use the shift to find the code spanned by the jump. Relative jumps
+    /// with an unknown shift prevent the transformations from changing the code length anywhere
+    /// in the code.
+    fn relative_jump_shift(&self) -> Option<i16> { None }
+
+    /// Checks whether the [local goto position](Self::local_goto_pos) is encoded in the compiled
+    /// code relative to the instruction offset, making the code position-independent.
+    ///
+    /// Like the other local gotos, the position is provided to the compiler as a routine number;
+    /// the compiler replaces it with the signed (two's complement) 16-bit delta between the routine
+    /// entry point and the instruction offset. Unlike [relative jumps](Self::is_relative_jump),
+    /// the delta is computed by the compiler, thus the code may be transformed freely before the
+    /// compilation.
+    fn is_goto_relative(&self) -> bool { false }
+
+    /// Returns the code offset of the [local goto](Self::local_goto_pos) target of the compiled
+    /// instruction located at the given `offset`, resolving [relative](Self::is_goto_relative)
+    /// gotos.
+    ///
+    /// Returns `None` if the instruction doesn't have a local goto, or if a relative goto points
+    /// outside the addressable code range.
+    fn local_goto_target(&mut self, offset: u16) -> Option<u16> {
+        let relative = self.is_goto_relative();
+        let pos = *self.local_goto_pos()?;
+        if relative {
+            offset.checked_add_signed(pos as i16)
+        } else {
+            Some(pos)
+        }
+    }
+
+    /// Checks whether the instruction has no effect when executed, besides passing the control
+    /// flow to the instruction following it.
+    ///
+    /// Goto targets which are no-ops may be omitted from the bytecode by the compiler (see
+    /// [`crate::CompileOpts::strip_markers`]).
+    fn is_nop(&self) -> bool { false }
+
+    /// Checks whether the instruction is a call of a subroutine from the same library, located at
+    /// [`Self::local_goto_pos`], which passes the control flow to the instruction following the
+    /// call once the subroutine returns.
+    ///
+    /// Used by the [`crate::inline_routines`] pass to detect the call sites.
+    fn is_local_call(&self) -> bool { false }
+
+    /// Checks whether the instruction returns from a subroutine to its caller.
+    ///
+    /// Used by the [`crate::inline_routines`] pass to detect the routines which may be inlined.
+    fn is_return(&self) -> bool { false }
+
+    /// Checks whether the instruction calls a subroutine, either local or external one, pushing
+    /// the return address to the call stack.
+    ///
+    /// Used by the [`crate::Vm`] to enforce the [call depth limit](crate::Vm::set_max_call_depth)
+    /// before the call is performed. Defaults to [`Self::is_local_call`].
+    fn is_call(&self) -> bool { self.is_local_call() }
+
+    /// Tries to evaluate the instruction together with the instruction following it at compile
+    /// time, returning a single instruction having the same effect as both of them (for instance,
+    /// a single constant load replacing a sequence of arithmetic operations over constants).
+    ///
+    /// Used by the [`crate::optimize`] pass. Implementations must return `None` unless the
+    /// returned instruction leaves the VM in exactly the same state as the execution of the two
+    /// instructions would do.
+    fn try_const_eval(&self, next: &Self) -> Option<Self> {
+        let _ = next;
+        None
+    }
+
     /// Lists all registers which are used by the instruction.
     fn regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg> {
         let mut regs = self.src_regs();
@@ -79,6 +323,36 @@ pub trait Instruction<Id: SiteId>: Display + Debug + Bytecode<Id> + Clone + Eq {
         regs
     }
 
+    /// Describes the register access by the instruction, which is used by the optimization passes
+    /// (see [`crate::eliminate_dead_stores`]).
+    ///
+    /// By default, reports [`Self::src_regs`] and [`Self::dst_regs`] and assumes that the
+    /// instruction is not pure.
+    fn reg_access(&self) -> RegAccess<<Self::Core as CoreExt>::Reg> {
+        RegAccess { reads: self.src_regs(), writes: self.dst_regs(), pure: false }
+    }
+
+    /// Describes the operands of the instruction, allowing to analyze the code without matching
+    /// over the concrete instruction set.
+    ///
+    /// By default, reports [`Self::src_regs`], [`Self::dst_regs`] and the goto targets (see
+    /// [`Self::local_goto_pos`], [`Self::local_goto_table`] and [`Self::remote_goto_pos`]), but no
+    /// immediate values or control registers.
+    fn operands(&self) -> Operands<Id, <Self::Core as CoreExt>::Reg> {
+        let mut operands = Operands::new();
+        operands.extend(self.src_regs().into_iter().map(Operand::Src));
+        operands.extend(self.dst_regs().into_iter().map(Operand::Dst));
+        let mut instr = self.clone();
+        if let Some(pos) = instr.local_goto_pos() {
+            operands.push(Operand::Pos(*pos));
+        }
+        operands.extend(instr.local_goto_table().iter().copied().map(Operand::Pos));
+        if let Some(site) = instr.remote_goto_pos() {
+            operands.push(Operand::Site(*site));
+        }
+        operands
+    }
+
     /// List of registers which value is taken into the account by the instruction.
     fn src_regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg>;
 
@@ -171,6 +171,9 @@ macro_rules! instr {
     (stop) => {
         $crate::isa::CtrlInstr::Stop.into()
     };
+    (trap $code:literal) => {
+        $crate::isa::CtrlInstr::Trap { code: $code }.into()
+    };
 
     // Jumps
     (jmp $pos:literal) => {
@@ -224,4 +227,18 @@ macro_rules! instr {
     (call: $pos:ident) => {
         $crate::isa::CtrlInstr::Fn { pos: $pos }.into()
     };
+
+    // Position-independent gotos
+    (rjmp $pos:literal) => {
+        $crate::isa::CtrlInstr::JmpRel { pos: $pos }.into()
+    };
+    (rjmp: $pos:ident) => {
+        $crate::isa::CtrlInstr::JmpRel { pos: $pos }.into()
+    };
+    (rcall $pos:literal) => {
+        $crate::isa::CtrlInstr::FnRel { pos: $pos }.into()
+    };
+    (rcall: $pos:ident) => {
+        $crate::isa::CtrlInstr::FnRel { pos: $pos }.into()
+    };
 }
@@ -30,8 +30,18 @@ mod arch;
 
 mod ctrl;
 mod masm;
+mod asm;
+pub(crate) mod ext;
+mod host;
+mod rand;
 
 pub use arch::{Instr, IsaId, ReservedInstr, ISA_ID_MAX_LEN};
+pub use asm::{parse_asm, to_asm, ParseError, ParseErrorKind};
 pub use bytecode::{Bytecode, BytecodeRead, BytecodeWrite, CodeEofError};
-pub use ctrl::CtrlInstr;
-pub use instr::{ExecStep, Instruction};
+pub use ctrl::{BranchBuilder, CtrlInstr, InstrBuilder};
+pub use ext::{Either, EXT_OP_RANGE};
+pub use host::{
+    HostContext, HostEnv, HostError, HostRegs, Hosted, ReentrancyGuard, HOST_CALL_OPCODE,
+};
+pub use instr::{ExecStep, Instruction, Operand, Operands, RegAccess};
+pub use rand::{RandRegs, Seeded, RAND_OPCODE};
@@ -0,0 +1,333 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Deterministic pseudo-random numbers, which sequence is fully defined by a seed taken from the
+//! registers.
+
+use alloc::collections::BTreeSet;
+use core::fmt::{self, Display, Formatter};
+use core::ops::RangeInclusive;
+
+use amplify::confinement::TinyOrdSet;
+
+use super::ext::forward_inner_isa;
+use super::{
+    Bytecode, BytecodeRead, BytecodeWrite, CodeEofError, CtrlInstr, ExecStep, Instruction, Operand,
+    Operands, RegAccess,
+};
+use crate::core::{Core, CoreExt, Register, Site, SiteId};
+use crate::{EncodingVersion, IsaId};
+
+/// Opcode of the pseudo-random number instruction, which lies in the [`super::EXT_OP_RANGE`].
+pub const RAND_OPCODE: u8 = 0xFE;
+
+/// Core extension registers which can be used by the [`Seeded`] instruction set.
+pub trait RandRegs: CoreExt {
+    /// Returns the register with the given index, as encoded in the [`Seeded::Rand`] instruction,
+    /// or `None` if the register can't receive pseudo-random values.
+    fn rand_reg(index: u8) -> Option<Self::Reg>;
+
+    /// Returns the register keeping the state of the pseudo-random number generator, which is
+    /// initially set to the seed.
+    fn rand_state_reg() -> Self::Reg;
+
+    /// Returns the state of the pseudo-random number generator taken from the
+    /// [`RandRegs::rand_state_reg`] register, or `None` if the register is not set.
+    fn rand_state(&self) -> Option<u64>;
+
+    /// Writes the advanced state of the pseudo-random number generator into the
+    /// [`RandRegs::rand_state_reg`] register, truncating it if the register is smaller than 64
+    /// bits.
+    fn set_rand_state(&mut self, state: u64);
+
+    /// Converts a pseudo-random number into a value of a given register, truncating it if the
+    /// register is smaller than 64 bits.
+    fn rand_value(reg: Self::Reg, random: u64) -> <Self::Reg as Register>::Value;
+}
+
+/// Advances the SplitMix64 generator state, returning the next number.
+fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Instruction set extending an ISA with a deterministic pseudo-random number generator.
+///
+/// The generator instruction uses [`RAND_OPCODE`], shadowing the instruction of the inner ISA with
+/// the same opcode (if any). Each of the instructions advances the SplitMix64 generator, which
+/// state is kept in the [`RandRegs::rand_state_reg`] register, and no host entropy is involved, so
+/// the sequence of the numbers depends only on the seed initially put into that register. Thus,
+/// the generator state is a part of the core state, and it is saved, restored and logged together
+/// with the rest of the registers. If the state register is not set, or the instruction references
+/// an unknown register, `CK` is set to a failed state.
+///
+/// The destination register of the instruction should differ from the state register: otherwise
+/// the state gets overwritten with the produced number.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Seeded<Isa> {
+    /// Instruction of the inner ISA.
+    Isa(Isa),
+
+    /// Write of the next pseudo-random number into the register with the given index (see
+    /// [`RandRegs::rand_reg`]).
+    Rand(u8),
+}
+
+impl<Isa: Display> Display for Seeded<Isa> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Seeded::Isa(instr) => Display::fmt(instr, f),
+            Seeded::Rand(index) => write!(f, "rand    {index}"),
+        }
+    }
+}
+
+impl<Id: SiteId, Isa: From<CtrlInstr<Id>>> From<CtrlInstr<Id>> for Seeded<Isa> {
+    fn from(instr: CtrlInstr<Id>) -> Self { Seeded::Isa(instr.into()) }
+}
+
+impl<Id: SiteId, Isa: Bytecode<Id>> Bytecode<Id> for Seeded<Isa> {
+    fn op_range() -> RangeInclusive<u8> { 0..=0xFF }
+
+    fn opcode_byte(&self) -> u8 {
+        match self {
+            Seeded::Isa(instr) => instr.opcode_byte(),
+            Seeded::Rand(_) => RAND_OPCODE,
+        }
+    }
+
+    fn code_byte_len(&self) -> u16 {
+        match self {
+            Seeded::Isa(instr) => instr.code_byte_len(),
+            Seeded::Rand(_) => 2,
+        }
+    }
+
+    fn external_ref(&self) -> Option<Id> {
+        match self {
+            Seeded::Isa(instr) => instr.external_ref(),
+            Seeded::Rand(_) => None,
+        }
+    }
+
+    fn encoding_version(&self) -> EncodingVersion {
+        match self {
+            Seeded::Isa(instr) => instr.encoding_version(),
+            Seeded::Rand(_) => EncodingVersion::V0,
+        }
+    }
+
+    fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where W: BytecodeWrite<Id> {
+        match self {
+            Seeded::Isa(instr) => instr.encode_operands(writer),
+            Seeded::Rand(index) => writer.write_byte(*index),
+        }
+    }
+
+    fn decode_operands<R>(reader: &mut R, opcode: u8) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: BytecodeRead<Id>,
+    {
+        match opcode {
+            RAND_OPCODE => reader.read_byte().map(Seeded::Rand),
+            _ => Isa::decode_operands(reader, opcode).map(Seeded::Isa),
+        }
+    }
+}
+
+impl<Id, Isa> Instruction<Id> for Seeded<Isa>
+where
+    Id: SiteId,
+    Isa: Instruction<Id>,
+    Isa::Core: RandRegs,
+{
+    /// Extensions added to the inner ISA; use [`Instruction::isa_ext`] to get all the extensions.
+    const ISA_EXT: &'static [&'static str] = &["RAND"];
+
+    type Core = Isa::Core;
+    type Context<'ctx> = Isa::Context<'ctx>;
+
+    forward_inner_isa!(Seeded);
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            Seeded::Isa(instr) => instr.mnemonic(),
+            Seeded::Rand(_) => "rand",
+        }
+    }
+
+    fn reg_access(&self) -> RegAccess<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Seeded::Isa(instr) => instr.reg_access(),
+            // The produced value is defined by the generator state, however the instruction is
+            // excluded from the constant folding
+            Seeded::Rand(_) => {
+                RegAccess { reads: self.src_regs(), writes: self.dst_regs(), pure: false }
+            }
+        }
+    }
+
+    fn operands(&self) -> Operands<Id, <Self::Core as CoreExt>::Reg> {
+        match self {
+            Seeded::Isa(instr) => instr.operands(),
+            Seeded::Rand(index) => Isa::Core::rand_reg(*index)
+                .map(Operand::Dst)
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn src_regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Seeded::Isa(instr) => instr.src_regs(),
+            Seeded::Rand(_) => bset![Isa::Core::rand_state_reg()],
+        }
+    }
+
+    fn dst_regs(&self) -> BTreeSet<<Self::Core as CoreExt>::Reg> {
+        match self {
+            Seeded::Isa(instr) => instr.dst_regs(),
+            Seeded::Rand(index) => Isa::Core::rand_reg(*index)
+                .into_iter()
+                .chain([Isa::Core::rand_state_reg()])
+                .collect(),
+        }
+    }
+
+    fn op_data_bytes(&self) -> u16 {
+        match self {
+            Seeded::Isa(instr) => instr.op_data_bytes(),
+            Seeded::Rand(_) => 1,
+        }
+    }
+
+    fn ext_data_bytes(&self) -> u16 {
+        match self {
+            Seeded::Isa(instr) => instr.ext_data_bytes(),
+            Seeded::Rand(_) => 0,
+        }
+    }
+
+    fn complexity(&self) -> u64 {
+        match self {
+            Seeded::Isa(instr) => instr.complexity(),
+            Seeded::Rand(_) => self.base_complexity(),
+        }
+    }
+
+    fn exec(
+        &self,
+        site: Site<Id>,
+        core: &mut Core<Id, Self::Core>,
+        context: &Self::Context<'_>,
+    ) -> ExecStep<Site<Id>> {
+        match self {
+            Seeded::Isa(instr) => instr.exec(site, core, context),
+            Seeded::Rand(index) => {
+                let Some(reg) = Isa::Core::rand_reg(*index) else {
+                    return ExecStep::Fail;
+                };
+                let Some(mut state) = core.cx.rand_state() else {
+                    return ExecStep::Fail;
+                };
+                let random = splitmix64(&mut state);
+                core.cx.set_rand_state(state);
+                core.cx.set(reg, Isa::Core::rand_value(reg, random));
+                ExecStep::Next
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::regs::Status;
+    use crate::testing::isa::{RegInstr, XCore, XReg};
+    use crate::{CompiledLib, LibId, LibSite, Vm};
+
+    impl RandRegs for XCore {
+        fn rand_reg(index: u8) -> Option<XReg> {
+            match index {
+                0 => Some(XReg::A),
+                1 => Some(XReg::B),
+                _ => None,
+            }
+        }
+
+        fn rand_state_reg() -> XReg { XReg::A }
+
+        fn rand_state(&self) -> Option<u64> { self.get(XReg::A).map(u64::from) }
+
+        fn set_rand_state(&mut self, state: u64) { self.set(XReg::A, state as u8); }
+
+        fn rand_value(_: XReg, random: u64) -> u8 { random as u8 }
+    }
+
+    type Isa = Seeded<RegInstr>;
+
+    fn run(lib: &CompiledLib, seed: Option<u8>) -> (Status, Option<u8>, Option<u8>) {
+        let mut vm = Vm::<Isa>::new();
+        if let Some(seed) = seed {
+            vm.set_reg(XReg::A, seed);
+        }
+        let entry = LibSite::new(lib.as_lib().lib_id(), 0);
+        let status = vm.exec(entry, &(), |_| Some(lib.as_lib()));
+        (status, vm.get_reg(XReg::A), vm.get_reg(XReg::B))
+    }
+
+    #[test]
+    fn rand() {
+        let code: Vec<Isa> = vec![
+            Seeded::Isa(RegInstr::Nop),
+            Seeded::Rand(1),
+            Seeded::Isa(RegInstr::Put(XReg::B, 0)),
+            Seeded::Rand(1),
+        ];
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(lib.as_lib().code.as_slice(), &[0x00, 0xFE, 1, 0x01, 1, 0, 0xFE, 1]);
+        assert_eq!(lib.as_lib().disassemble::<Isa>().unwrap(), code);
+        assert_eq!(code[1].to_string(), "rand    1");
+        assert_eq!(Instruction::<LibId>::src_regs(&code[1]), bset![XReg::A]);
+        assert_eq!(Instruction::<LibId>::dst_regs(&code[1]), bset![XReg::A, XReg::B]);
+
+        let mut state = 7;
+        splitmix64(&mut state);
+        // The state is truncated by the 8-bit register
+        state = state as u8 as u64;
+        let second = splitmix64(&mut state) as u8;
+        assert_eq!(run(&lib, Some(7)), (Status::Ok, Some(state as u8), Some(second)));
+        assert_eq!(run(&lib, Some(7)), run(&lib, Some(7)));
+        assert_ne!(run(&lib, Some(8)), run(&lib, Some(7)));
+        assert_eq!(run(&lib, None), (Status::Fail, None, None));
+
+        let lib = CompiledLib::compile(vec![Isa::Rand(2)], &[]).unwrap();
+        assert_eq!(run(&lib, Some(7)).0, Status::Fail);
+    }
+}
@@ -154,6 +154,8 @@ mod library;
 mod vm;
 #[cfg(feature = "stl")]
 pub mod stl;
+#[cfg(any(test, feature = "tests"))]
+pub mod testing;
 
 pub mod regs {
     pub use crate::core::{Status, CALL_STACK_SIZE_MAX};
@@ -163,13 +165,34 @@ pub use isa::{ExecStep, IsaId, ISA_ID_MAX_LEN};
 #[cfg(feature = "armor")]
 pub use library::armor::LibArmorError;
 pub use library::{
-    AssemblerError, CompiledLib, CompilerError, Lib, LibId, LibSite, LibsSeg, MarshallError,
-    Marshaller,
+    canonicalize, eliminate_dead_stores, inline_routines, optimize, run_bytes, Arity,
+    AssemblerError, BasicBlock, Cfg, CfgEdge, CompileOpts, CompilePlan, CompileReport, CompiledLib,
+    CompilerError, CyclicDeps, DataPool, DecodeError, DisassembleError, EdgeKind, EncodingVersion,
+    Export, FromPartsError, GlobalRoutineId, GotoMode, GotoTargets, HaltReason, Inlined, Lib,
+    LibBuilder, LibDiff, LibId, LibIdMismatch, LibLoadError, LibPatch, LibSet, LibSetError,
+    LibSigner, LibSite, LibStats, LibView, LibViewError, LibsSeg, LinkError, MarshallError,
+    Marshaller, PatchApplyError, PatchError, PreparedLib, RegCodec, Reloc, RoutineId, RoutineRef,
+    RunBytesError, SegmentDelta, SigScheme, SignatureError, SignedLib, UnlinkedRef, UnresolvedRef,
+    VerifyError, VerifyLimits, STUB_OFFSET,
 };
+#[cfg(feature = "fuzz")]
+pub use library::{fuzz_execute, FUZZ_STEP_LIMIT};
+#[cfg(feature = "std")]
+pub use library::{BundleError, InstructionReader, LibBundle, StreamError};
+#[cfg(feature = "compress")]
+pub use library::{DecompressError, COMPRESSED_MAGIC, DECOMPRESSED_MAX_LEN};
 #[doc(hidden)]
 pub use paste::paste;
-pub use vm::Vm;
+#[cfg(feature = "std")]
+pub use vm::DEADLINE_CHECK_INTERVAL;
+pub use vm::{
+    CallConv, CallError, Coverage, ExecError, ExecLog, LogStep, RegOf, ReplayError, RunOutcome,
+    Stepper, Vm, VmDump, VmState,
+};
 
-pub use self::core::{Core, CoreConfig, CoreExt, NoExt, NoRegs, Register, Site, SiteId, Supercore};
+pub use self::core::{
+    Core, CoreConfig, CoreExt, NoExt, NoRegs, OverflowMode, Register, RegisterSnapshot, Site,
+    SiteId, Supercore,
+};
 
 pub const LIB_NAME_ALUVM: &str = "AluVM";
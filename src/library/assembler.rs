@@ -24,32 +24,92 @@
 
 use amplify::confinement::{self, TinyOrdSet};
 
-use super::{Lib, LibId, MarshallError, Marshaller};
-use crate::isa::{BytecodeRead, CodeEofError, Instruction};
+use super::{EncodingVersion, Lib, LibId, MarshallError, Marshaller};
+use crate::isa::{BytecodeRead, Instruction};
 
 /// Errors while assembling lib-old from the instruction set.
 #[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Display, Error, From)]
-#[display(inner)]
+#[display(doc_comments)]
 pub enum AssemblerError {
-    /// Error assembling code and data segments.
-    #[from]
-    Bytecode(MarshallError),
+    /// unable to encode instruction number {0} (offset {1:#x}) into code and data segments: {2}
+    Bytecode(usize, u16, MarshallError),
+
+    /// instruction number {0} (offset {1:#x}) is declared to take {2} bytes, but it has encoded
+    /// {3} bytes.
+    LengthMismatch(usize, u16, u16, u16),
 
-    /// Error assembling library segment.
+    /// {0}
     #[from]
     LibSegOverflow(confinement::Error),
 }
 
+/// Errors while disassembling library into a set of instructions.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DisassembleError {
+    /// unable to decode instruction at offset {0:#x}.
+    Decode(u16),
+
+    /// instruction number {0} (offset {1:#x}) references offset {2:#x} which is not an entry point
+    /// of a routine.
+    InvalidRef(usize, u16, u16),
+
+    /// instruction number {0} (offset {1:#x}) references library which is not a dependency (lib
+    /// id {2}).
+    InvalidLib(usize, u16, LibId),
+
+    /// bytecode encoding version {0} is not supported.
+    UnsupportedVersion(EncodingVersion),
+
+    /// instruction at offset {0:#x} is not defined by the bytecode encoding version {1}.
+    UnsupportedInstr(u16, EncodingVersion),
+
+    /// entry point of routine {0} doesn't contain a goto target (the library may be compiled with
+    /// stripped goto target markers).
+    MissingMarker(u16),
+}
+
+/// Errors while decoding a single instruction from the library code.
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum DecodeError {
+    /// offset {0:#x} lies outside of the code segment.
+    OutOfCode(u16),
+
+    /// offset {0:#x} is not an instruction boundary.
+    NotBoundary(u16),
+
+    /// unable to decode instruction at offset {0:#x}.
+    Decode(u16),
+}
+
 impl Lib {
     /// Assembles library from the provided instructions by encoding them into bytecode.
+    ///
+    /// Instruction operands are encoded in the little-endian bit and byte order described in
+    /// [`BytecodeRead`](crate::isa::BytecodeRead), independently of the host platform.
+    ///
+    /// # Errors
+    ///
+    /// If an instruction encodes into a number of bytes different from its
+    /// [`Bytecode::code_byte_len`](crate::isa::Bytecode::code_byte_len), returns
+    /// [`AssemblerError::LengthMismatch`] with the number of the instruction, its declared and
+    /// actual lengths, both in debug and release builds.
     pub fn assemble<Isa>(code: &[Isa]) -> Result<Lib, AssemblerError>
     where Isa: Instruction<LibId> {
         let call_sites = code.iter().filter_map(|instr| instr.external_ref());
         let libs_segment = TinyOrdSet::try_from_iter(call_sites)?;
 
         let mut writer = Marshaller::new(&libs_segment);
-        for instr in code.iter() {
-            instr.encode_instr(&mut writer)?;
+        for (no, instr) in code.iter().enumerate() {
+            let offset = writer.offset().0;
+            instr
+                .encode_instr(&mut writer)
+                .map_err(|err| AssemblerError::Bytecode(no, offset, err))?;
+            let len = writer.offset().0 - offset;
+            if len != instr.code_byte_len() {
+                return Err(AssemblerError::LengthMismatch(no, offset, instr.code_byte_len(), len));
+            }
         }
         let (code_segment, data_segment) = writer.finish();
 
@@ -62,16 +122,49 @@ impl Lib {
     }
 
     /// Disassembles library into a set of instructions.
-    pub fn disassemble<Isa>(&self) -> Result<Vec<Isa>, CodeEofError>
+    ///
+    /// The bytecode is decoded using the same bit and byte order as in [`Self::assemble`].
+    ///
+    /// Goto positions in the returned instructions are kept as byte offsets; use
+    /// [`super::CompiledLib::disassemble`] to get the code which can be compiled back.
+    pub fn disassemble<Isa>(&self) -> Result<Vec<Isa>, DisassembleError>
     where Isa: Instruction<LibId> {
         let mut code = Vec::new();
         let mut reader = Marshaller::with(&self.code, &self.data, &self.libs);
         while !reader.is_eof() {
-            code.push(Isa::decode_instr(&mut reader)?);
+            let pos = reader.pos();
+            code.push(Isa::decode_instr(&mut reader).map_err(|_| DisassembleError::Decode(pos))?);
         }
         Ok(code)
     }
 
+    /// Decodes a single instruction located at the provided byte offset of the code segment.
+    ///
+    /// Since the instruction length is variable, the code preceding the offset is scanned to
+    /// ensure the offset is an instruction boundary; the scan doesn't allocate and stops at the
+    /// requested offset.
+    ///
+    /// # Returns
+    ///
+    /// Decoded instruction and its length in bytes.
+    pub fn instruction_at<Isa>(&self, offset: u16) -> Result<(Isa, u16), DecodeError>
+    where Isa: Instruction<LibId> {
+        if offset as usize >= self.code.len() {
+            return Err(DecodeError::OutOfCode(offset));
+        }
+        let mut reader = Marshaller::with(&self.code, &self.data, &self.libs);
+        loop {
+            let pos = reader.pos();
+            if pos > offset {
+                return Err(DecodeError::NotBoundary(offset));
+            }
+            let instr = Isa::decode_instr(&mut reader).map_err(|_| DecodeError::Decode(pos))?;
+            if pos == offset {
+                return Ok((instr, reader.pos() - pos));
+            }
+        }
+    }
+
     /// Disassembles library into a set of instructions and offsets and prints it to the writer.
     pub fn print_disassemble<Isa>(
         &self,
@@ -92,3 +185,135 @@ impl Lib {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use alloc::collections::BTreeSet;
+    use core::ops::RangeInclusive;
+
+    use super::*;
+    use crate::isa::{Bytecode, BytecodeWrite, CodeEofError, CtrlInstr, Either, ExecStep, Instr};
+    use crate::{Core, NoExt, NoRegs, Site};
+
+    /// Instruction which declares a length shorter than its encoding.
+    #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+    #[display("wrong {0}")]
+    struct WrongLen(u8);
+
+    impl Bytecode<LibId> for WrongLen {
+        fn op_range() -> RangeInclusive<u8> { 0x80..=0x80 }
+
+        fn opcode_byte(&self) -> u8 { 0x80 }
+
+        fn code_byte_len(&self) -> u16 { 1 }
+
+        fn external_ref(&self) -> Option<LibId> { None }
+
+        fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
+        where W: BytecodeWrite<LibId> {
+            writer.write_byte(self.0)
+        }
+
+        fn decode_operands<R>(reader: &mut R, _opcode: u8) -> Result<Self, CodeEofError>
+        where
+            Self: Sized,
+            R: BytecodeRead<LibId>,
+        {
+            reader.read_byte().map(WrongLen)
+        }
+    }
+
+    impl Instruction<LibId> for WrongLen {
+        const ISA_EXT: &'static [&'static str] = &["WRONG"];
+
+        type Core = NoExt;
+        type Context<'ctx> = ();
+
+        fn mnemonic(&self) -> &'static str { "wrong" }
+
+        fn is_goto_target(&self) -> bool { false }
+
+        fn local_goto_pos(&mut self) -> Option<&mut u16> { None }
+
+        fn remote_goto_pos(&mut self) -> Option<&mut Site<LibId>> { None }
+
+        fn src_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+        fn dst_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+        fn op_data_bytes(&self) -> u16 { 1 }
+
+        fn ext_data_bytes(&self) -> u16 { none!() }
+
+        fn exec(
+            &self,
+            _: Site<LibId>,
+            _: &mut Core<LibId, NoExt>,
+            _: &Self::Context<'_>,
+        ) -> ExecStep<Site<LibId>> {
+            ExecStep::Next
+        }
+    }
+
+    #[test]
+    fn length_mismatch() {
+        assert_eq!(
+            Lib::assemble(&[WrongLen(1), WrongLen(2)]),
+            Err(AssemblerError::LengthMismatch(0, 0, 1, 2))
+        );
+        let code = [Either::Base(CtrlInstr::Nop.into()), Either::Ext(WrongLen(1))];
+        let err = Lib::assemble::<Either<Instr<LibId>, WrongLen>>(&code).unwrap_err();
+        assert_eq!(err, AssemblerError::LengthMismatch(1, 1, 1, 2));
+        assert_eq!(
+            err.to_string(),
+            "instruction number 1 (offset 0x1) is declared to take 1 bytes, but it has encoded 2 \
+             bytes."
+        );
+    }
+
+    #[test]
+    fn code_overflow() {
+        let code = vec![Instr::<LibId>::from(CtrlInstr::Nop); 0x10000];
+        assert_eq!(
+            Lib::assemble(&code),
+            Err(AssemblerError::Bytecode(0xFFFF, 0xFFFF, MarshallError::CodeNotFittingSegment))
+        );
+        assert_eq!(
+            Lib::assemble(&code).unwrap_err().to_string(),
+            "unable to encode instruction number 65535 (offset 0xffff) into code and data \
+             segments: attempt to read or write outside of code segment (i.e. at position > 0xFF)."
+        );
+    }
+
+    #[test]
+    fn immediate_encoding() {
+        let site = Site::new(LibId::from([0xAB; 32]), 0x1234);
+        let vectors = [
+            (CtrlInstr::Jmp { pos: 0x1234 }, vec![0x06, 0x34, 0x12]),
+            (CtrlInstr::Jmp { pos: 0xFF00 }, vec![0x06, 0x00, 0xFF]),
+            (CtrlInstr::Sh { shift: 1 }, vec![0x09, 0x01]),
+            (CtrlInstr::Sh { shift: -2 }, vec![0x09, 0xFE]),
+            (CtrlInstr::Call { site }, vec![0x0E, 0x00, 0x34, 0x12]),
+        ];
+        for (instr, bytes) in vectors {
+            let lib = Lib::assemble(&[Instr::<LibId>::from(instr)]).unwrap();
+            assert_eq!(lib.code.as_slice(), bytes.as_slice(), "encoding of `{instr}`");
+            assert_eq!(lib.disassemble::<Instr<LibId>>().unwrap(), vec![Instr::from(instr)]);
+        }
+    }
+
+    #[test]
+    fn instruction_at() {
+        let code = [
+            Instr::<LibId>::from(CtrlInstr::Nop),
+            Instr::from(CtrlInstr::Jmp { pos: 0 }),
+            Instr::from(CtrlInstr::Stop),
+        ];
+        let lib = Lib::assemble(&code).unwrap();
+        assert_eq!(lib.instruction_at::<Instr<LibId>>(0), Ok((code[0], 1)));
+        assert_eq!(lib.instruction_at::<Instr<LibId>>(1), Ok((code[1], 3)));
+        assert_eq!(lib.instruction_at::<Instr<LibId>>(4), Ok((code[2], 1)));
+        assert_eq!(lib.instruction_at::<Instr<LibId>>(2), Err(DecodeError::NotBoundary(2)));
+        assert_eq!(lib.instruction_at::<Instr<LibId>>(5), Err(DecodeError::OutOfCode(5)));
+    }
+}
@@ -0,0 +1,380 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+use amplify::confinement::SmallBlob;
+
+use super::{CompiledLib, CompilerError, LibId, LibSite};
+use crate::isa::Instruction;
+
+/// Pool of the distinct data segments used by a set of libraries.
+///
+/// Since the data segment is committed to by the library id, each of the libraries still keeps its
+/// own copy of the segment; the pool allows to store (or deploy) each of the distinct data segments
+/// just once, referencing it from the libraries by the index in the pool.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct DataPool {
+    segments: Vec<SmallBlob>,
+    refs: Vec<usize>,
+    bytes_saved: usize,
+}
+
+impl DataPool {
+    /// Constructs data pool for the provided libraries, deduplicating their data segments by the
+    /// raw data bytes.
+    pub fn with<'lib>(libs: impl IntoIterator<Item = &'lib CompiledLib>) -> Self {
+        let mut pool = DataPool::default();
+        let mut index = BTreeMap::<&[u8], usize>::new();
+        for lib in libs {
+            let data = lib.lib.data.as_slice();
+            let no = match index.get(data) {
+                Some(no) => {
+                    pool.bytes_saved += data.len();
+                    *no
+                }
+                None => {
+                    let no = pool.segments.len();
+                    pool.segments.push(lib.lib.data.clone());
+                    index.insert(data, no);
+                    no
+                }
+            };
+            pool.refs.push(no);
+        }
+        pool
+    }
+
+    /// Returns the distinct data segments.
+    pub fn segments(&self) -> &[SmallBlob] { &self.segments }
+
+    /// Returns an index in [`Self::segments`] of the data segment used by a library at the
+    /// provided position in the batch.
+    ///
+    /// # Panics
+    ///
+    /// If the position is beyond the number of libraries in the pool.
+    pub fn segment_no(&self, lib_no: usize) -> usize { self.refs[lib_no] }
+
+    /// Returns the number of bytes saved by storing each of the distinct data segments once.
+    pub fn bytes_saved(&self) -> usize { self.bytes_saved }
+}
+
+impl CompiledLib {
+    /// Compiles a batch of libraries, constructing a pool of their distinct data segments.
+    ///
+    /// Libraries are compiled in the provided order; each of them may reference the libraries
+    /// preceding it in the batch.
+    pub fn compile_batch<Isa>(
+        libs: &mut [Vec<Isa>],
+    ) -> Result<(Vec<CompiledLib>, DataPool), CompilerError<Isa>>
+    where Isa: Instruction<LibId> {
+        let mut compiled = Vec::with_capacity(libs.len());
+        for code in libs {
+            let deps = compiled.iter().collect::<Vec<_>>();
+            let lib = CompiledLib::compile(code, &deps)?;
+            compiled.push(lib);
+        }
+        let pool = DataPool::with(&compiled);
+        Ok((compiled, pool))
+    }
+}
+
+/// Libraries forming a cycle of dependencies, listed in the order of their references (the last
+/// library references the first one).
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Error)]
+pub struct CyclicDeps(pub Vec<LibId>);
+
+impl Display for CyclicDeps {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("cyclic dependency between libraries ")?;
+        for id in &self.0 {
+            write!(f, "{id} -> ")?;
+        }
+        match self.0.first() {
+            Some(id) => write!(f, "{id}"),
+            None => f.write_str("~"),
+        }
+    }
+}
+
+/// Routine identified unambiguously across a set of libraries by the id of the library and the
+/// number of the routine within it.
+///
+/// The library id may be either a placeholder id of a [`LibSet`] library, or the id of a compiled
+/// library (see [`LibSet::resolve`]).
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{0}#{1}")]
+pub struct GlobalRoutineId(pub LibId, pub u16);
+
+/// Errors compiling a [`LibSet`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum LibSetError<Isa: Instruction<LibId>> {
+    /// {0}
+    #[from]
+    Cycle(CyclicDeps),
+
+    /// unable to compile library {0}: {1}
+    Compile(LibId, CompilerError<Isa>),
+}
+
+/// Set of libraries which may reference each other, compiled in the order of their dependencies.
+///
+/// Since the id of a library is known only after its compilation, the libraries in the set are
+/// identified by arbitrary placeholder ids. Remote gotos using a placeholder id are replaced with
+/// the id of the compiled library.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct LibSet<Isa: Instruction<LibId>> {
+    libs: BTreeMap<LibId, Vec<Isa>>,
+}
+
+impl<Isa: Instruction<LibId>> Default for LibSet<Isa> {
+    fn default() -> Self { Self::new() }
+}
+
+impl<Isa: Instruction<LibId>> LibSet<Isa> {
+    /// Constructs an empty set of libraries.
+    pub fn new() -> Self { Self { libs: BTreeMap::new() } }
+
+    /// Adds library code to the set under a placeholder id, returning the code previously added
+    /// under the same id, if any.
+    pub fn insert(&mut self, id: LibId, code: Vec<Isa>) -> Option<Vec<Isa>> {
+        self.libs.insert(id, code)
+    }
+
+    /// Returns placeholder ids of the libraries from the set which are referenced by the library,
+    /// in ascending order.
+    pub fn deps(&self, id: LibId) -> Vec<LibId> {
+        let Some(code) = self.libs.get(&id) else {
+            return vec![];
+        };
+        let mut deps = BTreeSet::new();
+        for instr in code {
+            let mut instr = instr.clone();
+            if let Some(site) = instr.remote_goto_pos() {
+                if self.libs.contains_key(&site.prog_id) {
+                    deps.insert(site.prog_id);
+                }
+            }
+        }
+        deps.into_iter().collect()
+    }
+
+    /// Orders the libraries such that each library follows all the libraries it depends on.
+    ///
+    /// # Errors
+    ///
+    /// If the libraries have cyclic dependencies (including a library referencing itself).
+    pub fn order(&self) -> Result<Vec<LibId>, CyclicDeps> {
+        let deps = self
+            .libs
+            .keys()
+            .map(|id| (*id, self.deps(*id)))
+            .collect::<BTreeMap<_, _>>();
+        let mut order = Vec::with_capacity(self.libs.len());
+        let mut done = BTreeSet::new();
+        for root in self.libs.keys() {
+            // Depth-first traversal keeping the current path on the stack
+            let mut path = vec![(*root, 0usize)];
+            while let Some((id, next)) = path.last_mut() {
+                if done.contains(id) {
+                    path.pop();
+                    continue;
+                }
+                let Some(dep) = deps[id].get(*next).copied() else {
+                    done.insert(*id);
+                    order.push(*id);
+                    path.pop();
+                    continue;
+                };
+                *next += 1;
+                if let Some(pos) = path.iter().position(|(id, _)| *id == dep) {
+                    return Err(CyclicDeps(path[pos..].iter().map(|(id, _)| *id).collect()));
+                }
+                path.push((dep, 0));
+            }
+        }
+        Ok(order)
+    }
+
+    /// Compiles all libraries from the set in the order of their dependencies (see
+    /// [`Self::order`]).
+    ///
+    /// The libraries may reference the libraries from the set (by their placeholder ids) and the
+    /// libraries from `deps`.
+    ///
+    /// # Returns
+    ///
+    /// The compiled libraries, together with the map from the placeholder ids to the ids of the
+    /// compiled libraries, which is used by [`Self::resolve`].
+    #[allow(clippy::type_complexity)]
+    pub fn compile(
+        &self,
+        deps: &[&CompiledLib],
+    ) -> Result<(Vec<CompiledLib>, BTreeMap<LibId, LibId>), LibSetError<Isa>> {
+        let order = self.order()?;
+        let mut ids = BTreeMap::new();
+        let mut compiled = Vec::<CompiledLib>::with_capacity(order.len());
+        for id in order {
+            let mut code = self.libs[&id].clone();
+            for instr in &mut code {
+                if let Some(site) = instr.remote_goto_pos() {
+                    if let Some(lib_id) = ids.get(&site.prog_id) {
+                        site.prog_id = *lib_id;
+                    }
+                }
+            }
+            let all_deps = deps.iter().copied().chain(&compiled).collect::<Vec<_>>();
+            let lib = CompiledLib::compile(code, &all_deps)
+                .map_err(|err| LibSetError::Compile(id, err))?;
+            ids.insert(id, lib.id);
+            compiled.push(lib);
+        }
+        Ok((compiled, ids))
+    }
+
+    /// Resolves a routine into its entry point in one of the `compiled` libraries, using the map
+    /// of the placeholder ids `ids`; both must be the result of [`Self::compile`] for this set.
+    ///
+    /// The routine may reference a library either by its placeholder id in the set, or by the id
+    /// of the compiled library.
+    ///
+    /// Returns `None` if neither of the compiled libraries matches the library id, or if the
+    /// library doesn't define the routine.
+    pub fn resolve(
+        &self,
+        compiled: &[CompiledLib],
+        ids: &BTreeMap<LibId, LibId>,
+        routine: GlobalRoutineId,
+    ) -> Option<LibSite> {
+        let GlobalRoutineId(id, no) = routine;
+        let id = match ids.get(&id) {
+            Some(lib_id) if self.libs.contains_key(&id) => *lib_id,
+            _ => id,
+        };
+        compiled.iter().find(|lib| lib.id == id)?.try_routine(no)
+    }
+
+    /// Returns the entry point of the routine `no` of the library `lib_id` in the same way as
+    /// [`Self::resolve`], which is the set-level analogue of [`CompiledLib::try_routine`].
+    pub fn routine(
+        &self,
+        compiled: &[CompiledLib],
+        ids: &BTreeMap<LibId, LibId>,
+        lib_id: LibId,
+        no: u16,
+    ) -> Option<LibSite> {
+        self.resolve(compiled, ids, GlobalRoutineId(lib_id, no))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{CtrlInstr, Instr};
+    use crate::{aluasm, Site};
+
+    #[test]
+    fn batch() {
+        let dep = aluasm! {
+            nop         ;
+            ret         ;
+        };
+        let (dep_lib, _) = CompiledLib::compile_batch(&mut [dep.clone()]).unwrap();
+        let dep_id = dep_lib[0].as_lib().lib_id();
+        let code: Vec<Instr<LibId>> =
+            vec![CtrlInstr::Call { site: Site::new(dep_id, 0) }.into(), CtrlInstr::Stop.into()];
+
+        let (mut libs, pool) = CompiledLib::compile_batch(&mut [dep, code]).unwrap();
+        assert_eq!(libs[0], dep_lib[0]);
+        libs[1].link::<Instr<LibId>>(&[&libs[0]]).unwrap();
+        assert_eq!(pool.segments().len(), 1);
+        assert_eq!(pool.bytes_saved(), 0);
+
+        libs[0].lib.data = SmallBlob::try_from(vec![1, 2, 3]).unwrap();
+        libs[1].lib.data = SmallBlob::try_from(vec![1, 2, 3]).unwrap();
+        libs.push(libs[0].clone());
+        libs[2].lib.data = SmallBlob::try_from(vec![4, 5]).unwrap();
+        let pool = DataPool::with(&libs);
+        assert_eq!(pool.segments(), &[
+            SmallBlob::try_from(vec![1, 2, 3]).unwrap(),
+            SmallBlob::try_from(vec![4, 5]).unwrap()
+        ]);
+        assert_eq!(pool.segment_no(1), 0);
+        assert_eq!(pool.segment_no(2), 1);
+        assert_eq!(pool.bytes_saved(), 3);
+    }
+
+    #[test]
+    fn lib_set() {
+        let main = LibId::from([1u8; 32]);
+        let util = LibId::from([2u8; 32]);
+        let core = LibId::from([3u8; 32]);
+        let call = |id| -> Instr<LibId> { CtrlInstr::Call { site: Site::new(id, 0) }.into() };
+
+        let mut set = LibSet::<Instr<LibId>>::new();
+        set.insert(main, vec![
+            CtrlInstr::Nop.into(),
+            call(util),
+            call(core),
+            CtrlInstr::Stop.into(),
+        ]);
+        set.insert(util, vec![CtrlInstr::Nop.into(), call(core), CtrlInstr::Ret.into()]);
+        set.insert(core, vec![CtrlInstr::Nop.into(), CtrlInstr::Ret.into()]);
+        assert_eq!(set.deps(main), vec![util, core]);
+        assert_eq!(set.order(), Ok(vec![core, util, main]));
+
+        let (libs, ids) = set.compile(&[]).unwrap();
+        assert_eq!(libs.len(), 3);
+        assert_eq!(ids[&util], libs[1].as_lib().lib_id());
+        libs[1].link::<Instr<LibId>>(&[&libs[0]]).unwrap();
+        libs[2].link::<Instr<LibId>>(&[&libs[0], &libs[1]]).unwrap();
+        assert!(libs[2].as_lib().libs.contains(&libs[1].as_lib().lib_id()));
+
+        let util_id = libs[1].as_lib().lib_id();
+        let resolve =
+            |libs: &[CompiledLib], id, no| set.resolve(libs, &ids, GlobalRoutineId(id, no));
+        assert_eq!(resolve(&libs, util, 0), Some(LibSite::new(util_id, 0)));
+        assert_eq!(resolve(&libs, util_id, 0), Some(LibSite::new(util_id, 0)));
+        assert_eq!(resolve(&libs, util, 1), None);
+        assert_eq!(set.routine(&libs, &ids, util, 0), Some(LibSite::new(util_id, 0)));
+        assert_eq!(set.routine(&libs, &ids, util_id, 1), None);
+        assert_eq!(set.routine(&libs, &ids, LibId::from([0xAA; 32]), 0), None);
+        assert_eq!(resolve(&libs, LibId::from([0xAA; 32]), 0), None);
+        assert_eq!(resolve(&libs[..1], util, 0), None);
+        // Subsets of the compiled libraries are resolved by the ids rather than by the positions
+        assert_eq!(resolve(&libs[1..], util, 0), Some(LibSite::new(util_id, 0)));
+        assert_eq!(GlobalRoutineId(util_id, 2).to_string(), format!("{util_id}#2"));
+
+        set.insert(core, vec![CtrlInstr::Nop.into(), call(main), CtrlInstr::Ret.into()]);
+        assert_eq!(set.order(), Err(CyclicDeps(vec![main, util, core])));
+        assert!(matches!(set.compile(&[]), Err(LibSetError::Cycle(_))));
+
+        set.insert(core, vec![CtrlInstr::Nop.into(), call(core), CtrlInstr::Ret.into()]);
+        assert_eq!(set.order(), Err(CyclicDeps(vec![core])));
+    }
+}
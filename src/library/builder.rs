@@ -0,0 +1,215 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+use super::{CompiledLib, CompilerError};
+use crate::isa::{CtrlInstr, InstrBuilder, Instruction};
+use crate::LibId;
+
+/// Reference to a routine of the library being built with [`LibBuilder`].
+///
+/// The reference may be obtained before the routine is marked (see [`LibBuilder::routine`]),
+/// allowing forward gotos.
+#[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+pub struct RoutineRef(usize);
+
+/// Incremental assembler of a library, which tracks the routine markers and resolves the gotos
+/// into routine numbers, such that there is no need to do the routine bookkeeping manually.
+///
+/// The library is compiled with [`CompiledLib::compile_labeled`] once [`Self::finish`] is called;
+/// the names of the routines are used as their labels.
+#[derive(Clone, Debug)]
+pub struct LibBuilder<Isa: Instruction<LibId>> {
+    code: Vec<Isa>,
+    labels: Vec<Option<String>>,
+    // Names of the routines and the positions of their markers in the code, if already marked.
+    routines: Vec<(String, Option<usize>)>,
+    names: BTreeMap<String, RoutineRef>,
+    // Positions of the gotos in the code and the routines they reference.
+    gotos: Vec<(usize, RoutineRef)>,
+}
+
+impl<Isa: Instruction<LibId>> Default for LibBuilder<Isa> {
+    fn default() -> Self {
+        Self {
+            code: none!(),
+            labels: none!(),
+            routines: none!(),
+            names: none!(),
+            gotos: none!(),
+        }
+    }
+}
+
+impl<Isa> LibBuilder<Isa>
+where Isa: Instruction<LibId> + From<CtrlInstr<LibId>>
+{
+    /// Constructs a builder with no code.
+    pub fn new() -> Self { Self::default() }
+
+    /// Returns the instructions pushed to the builder so far, with the gotos still unresolved.
+    pub fn code(&self) -> &[Isa] { &self.code }
+
+    /// Appends an instruction to the code.
+    ///
+    /// Local gotos of the instruction are kept as they are; use [`Self::goto_with`] for the gotos
+    /// which must reference a routine of the builder.
+    pub fn push(&mut self, instr: Isa) -> &mut Self {
+        self.code.push(instr);
+        self.labels.push(None);
+        self
+    }
+
+    /// Returns reference to the routine with a given name, which may be marked later.
+    pub fn routine(&mut self, name: &str) -> RoutineRef {
+        if let Some(routine) = self.names.get(name) {
+            return *routine;
+        }
+        let routine = RoutineRef(self.routines.len());
+        self.routines.push((name.to_string(), None));
+        self.names.insert(name.to_string(), routine);
+        routine
+    }
+
+    /// Starts a new routine with a given name by appending a goto target (see
+    /// [`InstrBuilder::routine`]) to the code.
+    ///
+    /// If the routine with the same name was already marked, [`Self::finish`] fails with
+    /// [`CompilerError::RepeatedLabel`].
+    pub fn mark_routine(&mut self, name: &str) -> RoutineRef {
+        let routine = self.routine(name);
+        let pos = self.code.len();
+        self.routines[routine.0].1.get_or_insert(pos);
+        self.code.push(Isa::routine());
+        self.labels.push(Some(name.to_string()));
+        routine
+    }
+
+    /// Appends an unconditional jump to the `routine`.
+    pub fn goto(&mut self, routine: RoutineRef) -> &mut Self {
+        self.goto_with(Isa::goto_routine(0), routine)
+    }
+
+    /// Appends a call of the `routine`.
+    pub fn call(&mut self, routine: RoutineRef) -> &mut Self {
+        self.goto_with(Isa::call_routine(0), routine)
+    }
+
+    /// Appends a local goto instruction (see [`Instruction::local_goto_pos`]), which target is
+    /// replaced with the `routine` number once the library is finished.
+    ///
+    /// # Panics
+    ///
+    /// If the instruction is not a local goto.
+    pub fn goto_with(&mut self, mut instr: Isa, routine: RoutineRef) -> &mut Self {
+        assert!(instr.local_goto_pos().is_some(), "instruction `{instr}` is not a local goto");
+        self.gotos.push((self.code.len(), routine));
+        self.push(instr)
+    }
+
+    /// Resolves the gotos into the routine numbers and compiles the library.
+    ///
+    /// Fails with [`CompilerError::UndefinedRoutine`] if some of the referenced routines were
+    /// never marked, and with any of the errors of [`CompiledLib::compile_labeled`].
+    pub fn finish(self, deps: &[&CompiledLib]) -> Result<CompiledLib, CompilerError<Isa>> {
+        let Self { mut code, labels, routines, gotos, .. } = self;
+        let mut routine_nos = BTreeMap::new();
+        let mut count = 0usize;
+        for (pos, instr) in code.iter().enumerate() {
+            if instr.is_goto_target() {
+                routine_nos.insert(pos, count);
+                count += 1;
+            }
+        }
+        for (pos, routine) in gotos {
+            let (name, marker) = &routines[routine.0];
+            let marker = marker.ok_or_else(|| CompilerError::UndefinedRoutine(name.clone()))?;
+            let no = u16::try_from(routine_nos[&marker])
+                .map_err(|_| CompilerError::TooManyRoutines(marker))?;
+            *code[pos]
+                .local_goto_pos()
+                .expect("checked when the goto was pushed") = no;
+        }
+        let labels = labels.iter().map(Option::as_deref).collect::<Vec<_>>();
+        CompiledLib::compile_labeled(code, &labels, deps)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::Instr;
+    use crate::regs::Status;
+    use crate::{aluasm, Vm};
+
+    #[test]
+    fn forward_goto() {
+        let mut builder = LibBuilder::<Instr<LibId>>::new();
+        let hash = builder.routine("hash");
+        builder.mark_routine("main");
+        builder.call(hash).push(Instr::stop());
+        assert_eq!(builder.mark_routine("hash"), hash);
+        builder.push(CtrlInstr::NotCo.into()).push(Instr::ret());
+        let lib = builder.finish(&[]).unwrap();
+
+        const MAIN: u16 = 0;
+        const HASH: u16 = 1;
+        let expected = CompiledLib::compile(
+            aluasm! {
+                .routine    :MAIN   ;
+                call        :HASH   ;
+                stop                ;
+                .routine    :HASH   ;
+                not         CO      ;
+                ret                 ;
+            },
+            &[],
+        )
+        .unwrap();
+        assert_eq!(lib.as_lib(), expected.as_lib());
+        assert_eq!(lib.routine_by_name("hash"), Some(lib.routine(HASH)));
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.exec(lib.routine(MAIN), &(), |_| Some(lib.as_lib())), Status::Ok);
+    }
+
+    #[test]
+    fn routine_errors() {
+        let mut builder = LibBuilder::<Instr<LibId>>::new();
+        builder.mark_routine("main");
+        let absent = builder.routine("absent");
+        builder.goto(absent);
+        assert_eq!(builder.finish(&[]).unwrap_err(), CompilerError::UndefinedRoutine(s!("absent")));
+
+        let mut builder = LibBuilder::<Instr<LibId>>::new();
+        let main = builder.mark_routine("main");
+        builder.push(Instr::stop());
+        assert_eq!(builder.mark_routine("main"), main);
+        builder.push(Instr::stop());
+        assert_eq!(builder.finish(&[]).unwrap_err(), CompilerError::RepeatedLabel(s!("main")));
+    }
+}
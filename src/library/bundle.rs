@@ -0,0 +1,333 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::vec::Vec;
+
+use amplify::confinement::Confined;
+use strict_encoding::{StrictDeserialize, StrictSerialize};
+
+use super::lib::LIB_MAX_LEN;
+use super::{Lib, LibId, LibIdMismatch};
+
+/// Errors reading a [`LibBundle`].
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BundleError {
+    /// unable to read the library bundle: {0}
+    #[from]
+    Io(io::Error),
+
+    /// the data are not a library bundle (invalid magic bytes).
+    InvalidMagic,
+
+    /// library bundle uses format version {0}, which is not supported.
+    UnsupportedVersion(u16),
+
+    /// library bundle contains too many libraries.
+    TooManyLibs,
+
+    /// library bundle table is not canonical: the entries must be ordered by the library ids and
+    /// must follow each other without gaps.
+    NonCanonical,
+
+    /// library {0} in the bundle has length of {1} bytes, exceeding the maximal library length.
+    LibTooLong(LibId, u32),
+
+    /// library {0} in the bundle is not a valid strict serialization of a library.
+    InvalidLib(LibId),
+
+    /// {0}
+    #[from]
+    IdMismatch(LibIdMismatch),
+}
+
+/// Container for distributing a set of libraries (like a program with all of its dependencies) as
+/// a single file.
+///
+/// The bundle is serialized in the following format, with all integers being little-endian:
+///
+/// ```text
+/// magic     8 bytes   "ALUVMBDL"
+/// version   u16       1
+/// count     u16       number of the libraries
+/// table     count * (LibId: 32 bytes, offset: u64, length: u32)
+/// data      strict-serialized libraries, concatenated
+/// ```
+///
+/// Library offsets are counted from the start of the data. The table is ordered by the library
+/// ids, and the libraries follow each other in the same order, such that each set of libraries has
+/// a single serialization.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct LibBundle {
+    libs: BTreeMap<LibId, Lib>,
+}
+
+impl LibBundle {
+    /// Magic bytes starting a serialized bundle.
+    pub const MAGIC: [u8; 8] = *b"ALUVMBDL";
+
+    /// Version of the bundle format.
+    pub const VERSION: u16 = 1;
+
+    /// Maximal number of the libraries in a bundle.
+    pub const MAX_LIBS: usize = u16::MAX as usize;
+
+    /// Constructs an empty bundle.
+    pub fn new() -> Self { Self::default() }
+
+    /// Adds library to the bundle.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the library is already present in the bundle.
+    ///
+    /// # Errors
+    ///
+    /// If the bundle already contains [`Self::MAX_LIBS`] libraries.
+    pub fn insert(&mut self, lib: Lib) -> Result<bool, BundleError> {
+        let id = lib.lib_id();
+        if self.libs.contains_key(&id) {
+            return Ok(false);
+        }
+        if self.libs.len() >= Self::MAX_LIBS {
+            return Err(BundleError::TooManyLibs);
+        }
+        self.libs.insert(id, lib);
+        Ok(true)
+    }
+
+    /// Returns library with the given id, if it is present in the bundle.
+    ///
+    /// The method can be used as a library resolver for the [`crate::Vm`].
+    pub fn get(&self, id: LibId) -> Option<&Lib> { self.libs.get(&id) }
+
+    /// Returns iterator over the libraries in the bundle, ordered by their ids.
+    pub fn libs(&self) -> impl Iterator<Item = &Lib> { self.libs.values() }
+
+    /// Returns number of the libraries in the bundle.
+    pub fn len(&self) -> usize { self.libs.len() }
+
+    /// Checks whether the bundle has no libraries.
+    pub fn is_empty(&self) -> bool { self.libs.is_empty() }
+
+    /// Writes serialized bundle.
+    pub fn write(&self, mut w: impl Write) -> io::Result<()> {
+        let data = self
+            .libs
+            .values()
+            .map(|lib| {
+                lib.to_strict_serialized::<LIB_MAX_LEN>()
+                    .expect("library length is always below the maximum")
+                    .release()
+            })
+            .collect::<Vec<_>>();
+
+        w.write_all(&Self::MAGIC)?;
+        w.write_all(&Self::VERSION.to_le_bytes())?;
+        w.write_all(&(self.libs.len() as u16).to_le_bytes())?;
+        let mut offset = 0u64;
+        for (id, data) in self.libs.keys().zip(&data) {
+            w.write_all(id.as_slice())?;
+            w.write_all(&offset.to_le_bytes())?;
+            w.write_all(&(data.len() as u32).to_le_bytes())?;
+            offset += data.len() as u64;
+        }
+        for data in data {
+            w.write_all(&data)?;
+        }
+        Ok(())
+    }
+
+    /// Reads serialized bundle, verifying that each library matches its id in the table.
+    ///
+    /// The reader is not consumed beyond the end of the bundle data.
+    pub fn read(mut r: impl Read) -> Result<Self, BundleError> {
+        if read_array(&mut r)? != Self::MAGIC {
+            return Err(BundleError::InvalidMagic);
+        }
+        let version = u16::from_le_bytes(read_array(&mut r)?);
+        if version != Self::VERSION {
+            return Err(BundleError::UnsupportedVersion(version));
+        }
+        let count = u16::from_le_bytes(read_array(&mut r)?);
+
+        let mut table = Vec::with_capacity(count as usize);
+        let mut next = 0u64;
+        for _ in 0..count {
+            let id = LibId::from(read_array::<32>(&mut r)?);
+            let offset = u64::from_le_bytes(read_array(&mut r)?);
+            let len = u32::from_le_bytes(read_array(&mut r)?);
+            if offset != next || table.last().is_some_and(|(prev, _)| *prev >= id) {
+                return Err(BundleError::NonCanonical);
+            }
+            if len as usize > LIB_MAX_LEN {
+                return Err(BundleError::LibTooLong(id, len));
+            }
+            next += len as u64;
+            table.push((id, len));
+        }
+
+        let mut libs = BTreeMap::new();
+        for (id, len) in table {
+            let mut data = vec![0u8; len as usize];
+            r.read_exact(&mut data)?;
+            let data = Confined::try_from(data).expect("length is checked against the maximum");
+            let lib = Lib::from_strict_serialized::<LIB_MAX_LEN>(data)
+                .map_err(|_| BundleError::InvalidLib(id))?;
+            let expected = lib.lib_id();
+            if expected != id {
+                return Err(LibIdMismatch { expected, found: id }.into());
+            }
+            libs.insert(id, lib);
+        }
+        Ok(Self { libs })
+    }
+}
+
+fn read_array<const N: usize>(r: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0u8; N];
+    r.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{CtrlInstr, Instr};
+    use crate::{aluasm, CompiledLib, HaltReason, Site, Vm};
+
+    fn libs() -> (CompiledLib, CompiledLib) {
+        let dep = CompiledLib::compile(aluasm! { nop; ret; }, &[]).unwrap();
+        let code: Vec<Instr<LibId>> = vec![
+            CtrlInstr::Nop.into(),
+            CtrlInstr::Call { site: Site::new(dep.as_lib().lib_id(), 0) }.into(),
+            CtrlInstr::Stop.into(),
+        ];
+        let main = CompiledLib::compile(code, &[&dep]).unwrap();
+        (main, dep)
+    }
+
+    fn bundle() -> LibBundle {
+        let (main, dep) = libs();
+        let mut bundle = LibBundle::new();
+        assert!(bundle.insert(main.as_lib().clone()).unwrap());
+        assert!(bundle.insert(dep.as_lib().clone()).unwrap());
+        assert!(!bundle.insert(dep.as_lib().clone()).unwrap());
+        bundle
+    }
+
+    fn serialize(bundle: &LibBundle) -> Vec<u8> {
+        let mut data = vec![];
+        bundle.write(&mut data).unwrap();
+        data
+    }
+
+    #[test]
+    fn roundtrip() {
+        let bundle = bundle();
+        assert_eq!(bundle.len(), 2);
+        let data = serialize(&bundle);
+        assert_eq!(&data[..12], b"ALUVMBDL\x01\x00\x02\x00");
+
+        let mut reader = data.as_slice();
+        assert_eq!(LibBundle::read(&mut reader).unwrap(), bundle);
+        assert!(reader.is_empty());
+        assert_eq!(
+            LibBundle::read(serialize(&LibBundle::new()).as_slice())
+                .unwrap()
+                .len(),
+            0
+        );
+
+        // Serialization doesn't depend on the order of the libraries added
+        let (main, dep) = libs();
+        let mut other = LibBundle::new();
+        other.insert(dep.as_lib().clone()).unwrap();
+        other.insert(main.as_lib().clone()).unwrap();
+        assert_eq!(serialize(&other), data);
+    }
+
+    #[test]
+    fn run() {
+        let bundle = LibBundle::read(serialize(&bundle()).as_slice()).unwrap();
+        let (main, dep) = libs();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.run_reported(main.routine(0), &(), |id| bundle.get(id)), HaltReason::Stop);
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let resolver = |id| (id == main.as_lib().lib_id()).then_some(main.as_lib());
+        assert_eq!(
+            vm.run_reported(main.routine(0), &(), resolver),
+            HaltReason::UnknownLib(dep.as_lib().lib_id())
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let bundle = bundle();
+        let data = serialize(&bundle);
+        let read = |data: &[u8]| LibBundle::read(data).unwrap_err();
+        let ids = bundle.libs().map(Lib::lib_id).collect::<Vec<_>>();
+        let entry = |no: usize| 12 + no * 44;
+        let lib = entry(2);
+
+        assert!(matches!(read(b"ALUVMLIB\x01\x00\x00\x00"), BundleError::InvalidMagic));
+        assert!(matches!(read(b"ALUVMBDL\x02\x00\x00\x00"), BundleError::UnsupportedVersion(2)));
+        assert!(matches!(read(&data[..data.len() - 1]), BundleError::Io(_)));
+
+        let mut swapped = data.clone();
+        swapped[entry(0)..entry(0) + 32].copy_from_slice(ids[1].as_slice());
+        swapped[entry(1)..entry(1) + 32].copy_from_slice(ids[0].as_slice());
+        assert!(matches!(read(&swapped), BundleError::NonCanonical));
+
+        let mut gap = data.clone();
+        gap[entry(1) + 32] += 1;
+        assert!(matches!(read(&gap), BundleError::NonCanonical));
+
+        let mut long = data.clone();
+        long[entry(1) + 42] = 0x10;
+        assert!(matches!(read(&long), BundleError::LibTooLong(id, _) if id == ids[1]));
+
+        let mut invalid = data.clone();
+        invalid[lib] = 0xFF;
+        assert!(matches!(read(&invalid), BundleError::InvalidLib(id) if id == ids[0]));
+
+        let (main, _) = libs();
+        let mut single = LibBundle::new();
+        single.insert(main.as_lib().clone()).unwrap();
+        let mut tampered = serialize(&single);
+        tampered[entry(0)..entry(0) + 32].copy_from_slice(&[0xAA; 32]);
+        match read(&tampered) {
+            BundleError::IdMismatch(err) => {
+                assert_eq!(err, LibIdMismatch {
+                    expected: main.as_lib().lib_id(),
+                    found: LibId::from([0xAA; 32])
+                })
+            }
+            err => panic!("unexpected error {err}"),
+        }
+    }
+}
@@ -0,0 +1,159 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::vec::Vec;
+
+use amplify::confinement::Confined;
+use strict_encoding::StrictDeserialize;
+
+use super::lib::LIB_MAX_LEN;
+use super::{CompiledLib, HaltReason, Lib, LibId, VerifyError};
+use crate::core::{CoreExt, NoExt};
+use crate::isa::Instruction;
+use crate::Vm;
+
+/// Byte encoding of the core extension registers, used by [`run_bytes`] to pass the registers
+/// in and out of the VM.
+pub trait RegCodec: CoreExt {
+    /// Sets the registers from their byte encoding.
+    ///
+    /// # Returns
+    ///
+    /// `false` if the bytes are not a valid encoding of the registers.
+    fn load(&mut self, input: &[u8]) -> bool;
+
+    /// Returns the byte encoding of the registers.
+    fn store(&self) -> Vec<u8>;
+}
+
+impl RegCodec for NoExt {
+    fn load(&mut self, input: &[u8]) -> bool { input.is_empty() }
+
+    fn store(&self) -> Vec<u8> { vec![] }
+}
+
+/// Errors of the library execution with [`run_bytes`].
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum RunBytesError {
+    /// the provided bytes are not a valid strict serialization of a library.
+    InvalidLib,
+
+    /// the provided bytes are not a valid encoding of the input registers.
+    InvalidInput,
+
+    #[from]
+    #[display(inner)]
+    Verify(VerifyError),
+
+    /// program execution has halted with {0}.
+    Halted(HaltReason),
+}
+
+/// Decodes, verifies and runs a library provided as bytes, passing the registers in and out as
+/// bytes.
+///
+/// The function is intended to be exposed to the environments lacking threads, a wall clock and a
+/// filesystem (like WebAssembly in the browser, with `wasm-bindgen`), thus it verifies the code
+/// sequentially and limits the execution by the number of steps only.
+///
+/// The library must be strict-serialized [`Lib`]; its code is run from the beginning, and calls
+/// into other libraries halt the program with [`HaltReason::UnknownLib`]. The registers of the
+/// core extension are set from `input` (see [`RegCodec`]).
+///
+/// # Returns
+///
+/// On the program completion, the resulting registers: the byte values of the `CK` and `CO`
+/// registers (`0` for success and `0xFF` for failure), followed by the [encoding of the core
+/// extension registers](RegCodec::store).
+pub fn run_bytes<Isa>(
+    lib: &[u8],
+    input: &[u8],
+    step_limit: u64,
+    context: &Isa::Context<'_>,
+) -> Result<Vec<u8>, RunBytesError>
+where
+    Isa: Instruction<LibId>,
+    Isa::Core: RegCodec,
+{
+    let data = Confined::<Vec<u8>, 0, LIB_MAX_LEN>::try_from(lib.to_vec())
+        .map_err(|_| RunBytesError::InvalidLib)?;
+    let lib =
+        Lib::from_strict_serialized::<LIB_MAX_LEN>(data).map_err(|_| RunBytesError::InvalidLib)?;
+    let lib = CompiledLib::with_code_entry(lib);
+    lib.verify::<Isa>()?;
+
+    let mut vm = Vm::<Isa>::new();
+    vm.set_step_limit(Some(step_limit));
+    if !vm.core.cx.load(input) {
+        return Err(RunBytesError::InvalidInput);
+    }
+    let entry = lib.routine(0);
+    let reason = vm.run_reported(entry, context, |id| (id == lib.id).then_some(&lib.lib));
+    if !reason.is_completion() {
+        return Err(RunBytesError::Halted(reason));
+    }
+    let mut output = vec![vm.core.ck() as i8 as u8, vm.core.co() as i8 as u8];
+    output.extend(vm.core.cx.store());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictSerialize;
+
+    use super::*;
+    use crate::aluasm;
+    use crate::isa::Instr;
+    use crate::vm::ExecError;
+
+    fn serialize(lib: &CompiledLib) -> Vec<u8> {
+        lib.as_lib()
+            .to_strict_serialized::<LIB_MAX_LEN>()
+            .unwrap()
+            .release()
+    }
+
+    #[test]
+    fn run() {
+        let lib = CompiledLib::compile(aluasm! { not CO; stop; }, &[]).unwrap();
+        let data = serialize(&lib);
+        assert_eq!(run_bytes::<Instr<LibId>>(&data, &[], 0x10, &()), Ok(vec![0x00, 0xFF]));
+        assert_eq!(
+            run_bytes::<Instr<LibId>>(&data, &[0x01], 0x10, &()),
+            Err(RunBytesError::InvalidInput)
+        );
+        assert_eq!(
+            run_bytes::<Instr<LibId>>(&data[1..], &[], 0x10, &()),
+            Err(RunBytesError::InvalidLib)
+        );
+
+        let lib = CompiledLib::compile(aluasm! { nop; jmp 0; }, &[]).unwrap();
+        let site = lib.routine(0);
+        assert_eq!(
+            run_bytes::<Instr<LibId>>(&serialize(&lib), &[], 0x10, &()),
+            Err(RunBytesError::Halted(HaltReason::Fault(ExecError::StepLimitExceeded(site))))
+        );
+    }
+}
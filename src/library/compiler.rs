@@ -22,12 +22,24 @@
 // or implied. See the License for the specific language governing permissions and limitations under
 // the License.
 
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
-use std::collections::BTreeMap;
+use core::fmt::{self, Display, Formatter};
+use core::ops::Range;
 
-use crate::isa::Instruction;
-use crate::library::assembler::AssemblerError;
-use crate::{Lib, LibId, LibSite};
+use amplify::Bytes32;
+use commit_verify::{Digest, Sha256};
+
+use super::optimizer::relative_spans;
+use super::verifier::verify_code;
+use super::{Arity, Reloc, VerifyError};
+use crate::isa::{BytecodeRead, Instruction};
+use crate::library::assembler::{AssemblerError, DecodeError, DisassembleError};
+use crate::library::Marshaller;
+use crate::vm::{CallConv, RegOf};
+use crate::{Lib, LibId, LibSite, RoutineId, Site};
 
 #[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
 #[display(doc_comments)]
@@ -36,86 +48,3443 @@ pub enum CompilerError<Isa: Instruction<LibId>> {
     #[display(inner)]
     Assemble(AssemblerError),
 
+    #[from]
+    #[display(inner)]
+    Disassemble(DisassembleError),
+
     /// instruction number {1} `{0}` (offset {2:#x}) references goto target absent in the code. Use
-    /// `nop` instruction to mark the goto target.
+    /// `{3}` instruction to mark the goto target.
     ///
-    /// The known goto target offsets are: {3:#x?}
-    InvalidRef(Isa, usize, u16, Vec<u16>),
+    /// The known goto targets are: {4}
+    InvalidRef(Isa, usize, u16, &'static str, Box<GotoTargets>),
+
+    /// instruction number {1} `{0}` (offset {2:#x}) references routine {3}, while the code defines
+    /// only {4} routines; this is likely a bug in the code generator.
+    GotoOutOfRange(Isa, usize, u16, u16, usize),
 
     /// instruction number {1} `{0}` (offset {2:#x}) references library which is not a dependency
     /// (lib id {3}).
     InvalidLib(Isa, usize, u16, LibId),
+
+    /// instruction number {1} `{0}` (offset {2:#x}) references routine {4} absent in the library
+    /// {3}.
+    InvalidRemoteRef(Isa, usize, u16, LibId, u16),
+
+    /// instruction number {1} `{0}` (offset {2:#x}) references routine {4} of the library {3},
+    /// which is not exported.
+    PrivateRemoteRef(Isa, usize, u16, LibId, u16),
+
+    /// instruction number {1} `{0}` is labeled as `{2}`, but it is not a goto target.
+    LabelNotTarget(Isa, usize, String),
+
+    /// instruction number {1} `{0}` is marked as relocation `{2}`, but it is not an external call.
+    RelocNotCall(Isa, usize, String),
+
+    /// label `{0}` is used for more than one routine.
+    RepeatedLabel(String),
+
+    /// routine `{0}` is exported, but no routine has such label.
+    UnknownExport(String),
+
+    /// routine {0} is not defined in the library.
+    UnknownRoutine(u16),
+
+    /// bytecode encoding version {0} is not supported.
+    UnsupportedVersion(EncodingVersion),
+
+    /// instruction number {1} `{0}` requires bytecode encoding version {2}, while the library is
+    /// compiled with version {3}.
+    UnsupportedInstr(Isa, usize, EncodingVersion, EncodingVersion),
+
+    /// routine {0} doesn't end with a terminating instruction, and its execution may fall through
+    /// to the next routine or past the end of the code.
+    UnterminatedRoutine(u16),
+
+    /// instruction number {2} `{0}` of the code chunk {1} references routine {3} which is not
+    /// defined in that chunk.
+    InvalidChunkRef(Isa, usize, usize, u16),
+
+    /// replacement code for routine {0} must start with a goto target and must not contain other
+    /// goto targets.
+    RoutineMismatch(u16),
+
+    /// instruction number {1} `{0}` (offset {2:#x}) references offset {3:#x}, which is not an
+    /// instruction boundary. When compiling with byte offset gotos, all gotos must use byte
+    /// offsets and not routine numbers.
+    InvalidOffsetRef(Isa, usize, u16, u16),
+
+    /// instruction number {at_instruction} (offset {overflow_at:#x}) doesn't fit into the maximal
+    /// code segment size.
+    CodeTooLarge {
+        at_instruction: usize,
+        overflow_at: u16,
+    },
+
+    /// goto target at instruction number {0} exceeds the maximal number of routines.
+    TooManyRoutines(usize),
+
+    /// goto target at instruction number {0} immediately follows another goto target, leaving
+    /// routine {1} empty.
+    EmptyRoutine(usize, u16),
+
+    /// code defines {found} routines, while {expected} routines were expected.
+    RoutineCountMismatch { expected: u16, found: usize },
+
+    /// routine {0} takes {1} bytes, exceeding the limit of {2} bytes.
+    RoutineTooLarge(u16, u16, u16),
+
+    /// instruction number {1} `{0}` (offset {2:#x}) references offset {3:#x}, which is too far
+    /// to be encoded as a relative goto.
+    RelativeGotoOutOfRange(Isa, usize, u16, u16),
+
+    /// instruction number {1} `{0}` of routine {2} reads register {3}, which is not written on
+    /// some of the code paths leading to the instruction.
+    UninitRead(Isa, usize, u16, String),
+
+    /// routine `{0}` is referenced by a goto, but it is never marked.
+    UndefinedRoutine(String),
+}
+
+/// Offsets of the goto targets known to the compiler, together with their labels (if any).
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct GotoTargets {
+    offsets: Vec<u16>,
+    labels: BTreeMap<u16, String>,
+}
+
+impl GotoTargets {
+    /// Returns byte offsets of the goto targets, ordered by their routine number.
+    pub fn offsets(&self) -> &[u16] { &self.offsets }
+
+    /// Returns the label of a routine with a given number, if the routine was labeled.
+    pub fn label(&self, routine_no: u16) -> Option<&str> {
+        self.labels.get(&routine_no).map(String::as_str)
+    }
 }
 
+impl Display for GotoTargets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.offsets.is_empty() {
+            return f.write_str("~");
+        }
+        for (no, offset) in self.offsets.iter().enumerate() {
+            if no > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{no}@{offset:#x}")?;
+            if let Some(label) = self.label(no as u16) {
+                write!(f, " `{label}`")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "camelCase", try_from = "CompiledLibUnchecked")
+)]
 pub struct CompiledLib {
+    pub(super) id: LibId,
+    pub(super) lib: Lib,
+    pub(super) routines: Vec<u16>,
+    pub(super) names: BTreeMap<String, u16>,
+    pub(super) exports: Option<BTreeSet<u16>>,
+    pub(super) stubs: BTreeSet<u16>,
+    pub(super) version: EncodingVersion,
+    pub(super) source_map: Vec<(u16, usize)>,
+    pub(super) entry: Option<u16>,
+    pub(super) meta: BTreeMap<String, Vec<u8>>,
+    pub(super) relocs: Vec<Reloc>,
+    pub(super) arities: BTreeMap<u16, Arity>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_pairs"))]
+    pub(super) call_arities: BTreeMap<LibSite, Arity>,
+}
+
+/// Tag used in the [`CompiledLib::meta_commitment`] hash.
+pub const LIB_META_TAG: &str = "urn:ubideco:aluvm:lib-meta:v01#241020";
+
+/// Version of the bytecode encoding used by a compiled library.
+///
+/// The version is not a part of the [`Lib`] data (and thus doesn't affect the library id); it is
+/// recorded by the [`CompiledLib`] to prevent processing bytecode produced by the future
+/// versions of the encoding.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("v{0}")]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct EncodingVersion(u8);
+
+impl EncodingVersion {
+    /// The initial version of the bytecode encoding.
+    pub const V0: Self = Self(0);
+
+    /// The version adding the `trap`, `rjmp` and `rcall` instructions, which take the opcodes
+    /// decoded as reserved instructions by [`Self::V0`].
+    pub const V1: Self = Self(1);
+
+    /// The most recent version of the bytecode encoding supported by this crate.
+    pub const CURRENT: Self = Self::V1;
+
+    #[cfg(feature = "serde")]
+    fn initial() -> Self { Self::V0 }
+
+    /// Constructs version from its numeric value.
+    pub const fn with(version: u8) -> Self { Self(version) }
+
+    /// Returns numeric value of the version.
+    pub const fn to_u8(self) -> u8 { self.0 }
+
+    /// Checks whether the encoding of this version is supported by this crate.
+    pub fn is_supported(self) -> bool { self <= Self::CURRENT }
+}
+
+/// Defaults to the [current](EncodingVersion::CURRENT) version.
+impl Default for EncodingVersion {
+    fn default() -> Self { Self::CURRENT }
+}
+
+/// Routine exported by a library compiled with [`CompiledLib::compile_exported`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, From)]
+pub enum Export<'a> {
+    /// Routine with the given label.
+    #[from]
+    Label(&'a str),
+
+    /// Routine with the given number.
+    #[from]
+    Routine(u16),
+}
+
+/// Code offset used for the goto positions referencing [stub routines](CompiledLib::is_stub).
+///
+/// The offset lies outside of any code segment, thus making the VM to halt with a failure once a
+/// stub is called.
+pub const STUB_OFFSET: u16 = u16::MAX;
+
+/// Representation of the local goto positions in the code provided to the compiler.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub enum GotoMode {
+    /// Goto positions are routine numbers, i.e. the number of the goto targets preceding the
+    /// target instruction.
+    #[default]
+    Routine,
+
+    /// Goto positions are byte offsets of the target instructions in the code, as if all the
+    /// instructions were encoded one after another (i.e. before [`CompileOpts::strip_markers`] is
+    /// applied). The targets must land on the instruction boundaries, but not necessarily on the
+    /// goto targets.
+    Offset,
+}
+
+/// Options for the [`CompiledLib::compile_with`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct CompileOpts {
+    /// Allow gotos referencing routines absent in the code.
+    ///
+    /// Such gotos are resolved into [`STUB_OFFSET`], such that the program execution is halted
+    /// with a failure when it reaches them, and the referenced routines are recorded as stubs.
+    /// Applies only to the [`GotoMode::Routine`].
+    pub allow_dangling: bool,
+
+    /// Representation of the local goto positions in the code. The same representation must be
+    /// used by all of the gotos.
+    pub goto_mode: GotoMode,
+
+    /// Entry routine of the library, which is exposed via [`CompiledLib::entry`]. Compilation
+    /// fails if the routine is not defined in the code.
+    ///
+    /// If provided, the reachability of the rest of the routines is analyzed, and the routines
+    /// which can't be reached from the entry are reported in [`CompileReport::unreachable`].
+    pub entry: Option<u16>,
+
+    /// Version of the bytecode encoding to use.
+    pub version: EncodingVersion,
+
+    /// Fail compilation if some of the routines doesn't end with a terminating instruction.
+    ///
+    /// Otherwise, such routines are reported in [`CompileReport::unterminated`].
+    pub strict: bool,
+
+    /// Omit goto targets which are no-ops (see [`Instruction::is_nop`]) from the bytecode, making
+    /// the routine entry points to point to the instruction following them.
+    ///
+    /// Goto targets are kept if they are the last instruction of the code, or if they lie between
+    /// a [relative jump](Instruction::is_relative_jump) and its target (inclusive). If the
+    /// shift of some of the relative jumps is not known (see
+    /// [`Instruction::relative_jump_shift`]), all the goto targets are kept. Libraries
+    /// compiled with this option can't be [disassembled](CompiledLib::disassemble).
+    pub strip_markers: bool,
+
+    /// Fail compilation if a goto target immediately follows another goto target, which usually
+    /// indicates a code generation error.
+    pub deny_empty_routines: bool,
+
+    /// Fail compilation if the code defines a number of routines other than the expected one.
+    pub expected_routines: Option<u16>,
+
+    /// Maximal byte length of a routine (as given by [`CompiledLib::routine_range`]); larger
+    /// routines are reported in [`CompileReport::large_routines`].
+    pub max_routine_bytes: Option<u16>,
+
+    /// Fail compilation if some of the routines exceed [`Self::max_routine_bytes`].
+    pub deny_large_routines: bool,
+
+    /// Fail compilation if an instruction may read a register before it is written, i.e. if the
+    /// register is not written on all the code paths leading to the instruction.
+    ///
+    /// The paths are traced through the local gotos and the fall-through transitions between the
+    /// routines, starting from routine 0, the [entry](Self::entry) routine and the routines which
+    /// are not reached locally. These routines take their inputs from the external callers; the
+    /// compiler assumes they take no inputs, while the input registers can be declared with
+    /// [`CompiledLib::check_uninit_reads`]. Routines which are reached only from each other (like
+    /// the mutually recursive routines not called from elsewhere) are checked in the same way, as
+    /// taking their declared inputs. All registers are assumed to be written once a subroutine
+    /// call returns. Relative jumps are assumed to not leave the routine.
+    pub deny_uninit_reads: bool,
+
+    /// Drop routines which bytecode is identical to the bytecode of a preceding routine,
+    /// redirecting the gotos to the first of the identical routines.
+    ///
+    /// A routine is dropped only if the control can't fall into or out of it, i.e. if both it and
+    /// the routine preceding it end with a terminating instruction, and if it contains neither
+    /// relative jumps nor relocations. The rest of the routines are renumbered, and the labels of
+    /// the dropped routines are kept as aliases of their copies. Applies only to the
+    /// [`GotoMode::Routine`] and to the code without dangling gotos.
+    pub dedup_routines: bool,
+}
+
+/// Report produced by the compiler alongside the compiled library.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct CompileReport {
+    pub(super) unreachable: Vec<u16>,
+    pub(super) unterminated: Vec<u16>,
+    pub(super) large_routines: Vec<u16>,
+    pub(super) infinite_loops: Vec<u16>,
+}
+
+impl CompileReport {
+    /// Returns the numbers of the routines which can't be reached from the
+    /// [entry routine](CompileOpts::entry), in ascending order.
+    ///
+    /// Routines are considered reachable if they are referenced by some of the local gotos from a
+    /// reachable routine, or if the control flow of the preceding reachable routine passes to them.
+    /// Relative jumps are assumed to not leave the routine.
+    pub fn unreachable(&self) -> &[u16] { &self.unreachable }
+
+    /// Returns the numbers of the routines which last instruction is not a terminating one (see
+    /// [`Instruction::is_terminal`]), in ascending order.
+    ///
+    /// The execution of such routines falls through to the next routine, or halts once it reaches
+    /// the end of the code.
+    pub fn unterminated(&self) -> &[u16] { &self.unterminated }
+
+    /// Returns the numbers of the routines which byte length exceeds
+    /// [`CompileOpts::max_routine_bytes`], in ascending order.
+    pub fn large_routines(&self) -> &[u16] { &self.large_routines }
+
+    /// Returns the numbers of the routines forming trivial infinite loops, in ascending order.
+    ///
+    /// A loop is a cycle of routines, each of which contains no instructions other than no-ops and
+    /// [pure](crate::isa::RegAccess::pure) ones and passes the control flow to the next routine of
+    /// the cycle with an unconditional local goto or by falling through. Once such a cycle is
+    /// entered, the program runs until it exceeds the step or complexity limit. Loops with a
+    /// conditional exit, a call or any other effect are not reported.
+    pub fn infinite_loops(&self) -> &[u16] { &self.infinite_loops }
+}
+
+/// Reference which can't be resolved during the compilation, reported by [`CompiledLib::plan`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum UnresolvedRef {
+    /// instruction number {0} references routine {1}, which is not defined in the code.
+    Local(usize, u16),
+
+    /// instruction number {0} references offset {1:#x}, which is not an instruction boundary.
+    Offset(usize, u16),
+
+    /// instruction number {0} references offset {1:#x}, which lies out of reach of the relative
+    /// goto.
+    Relative(usize, u16),
+
+    /// instruction number {0} references library {1}, which is not provided as a dependency.
+    Lib(usize, LibId),
+
+    /// instruction number {0} references routine {2} of library {1}, which is not defined there.
+    Remote(usize, LibId, u16),
+
+    /// instruction number {0} references routine {2} of library {1}, which is not exported.
+    Private(usize, LibId, u16),
+}
+
+/// Layout of a library which would be produced by compiling some code, computed with
+/// [`CompiledLib::plan`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct CompilePlan {
+    pub(super) routines: Vec<usize>,
+    pub(super) code_len: usize,
+    pub(super) unresolved: Vec<UnresolvedRef>,
+}
+
+impl CompilePlan {
+    /// Returns code offsets of the routine entry points, indexed by the routine number.
+    ///
+    /// Offsets are not limited by the code segment size, such that they remain meaningful for the
+    /// code which is too large to be compiled.
+    pub fn routines(&self) -> &[usize] { &self.routines }
+
+    /// Returns the length of the code segment in bytes.
+    pub fn code_len(&self) -> usize { self.code_len }
+
+    /// Checks whether the code fits into the code segment.
+    pub fn fits_code_segment(&self) -> bool { self.code_len <= u16::MAX as usize }
+
+    /// Returns references which can't be resolved, in the order of the instructions containing
+    /// them.
+    pub fn unresolved(&self) -> &[UnresolvedRef] { &self.unresolved }
+}
+
+/// Error indicating that the library id doesn't match the library it is provided for.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display("library id {found} doesn't match the id of the library code {expected}.")]
+pub struct LibIdMismatch {
+    /// Actual id of the library.
+    pub expected: LibId,
+    /// Library id which was provided.
+    pub found: LibId,
+}
+
+/// Errors loading a serialized [`CompiledLib`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum LibLoadError {
+    /// {0}
+    #[from]
+    IdMismatch(LibIdMismatch),
+
+    /// library uses bytecode encoding version {0}, which is not supported.
+    UnsupportedVersion(EncodingVersion),
+
+    /// {0}
+    #[from]
+    Routines(FromPartsError),
+
+    /// {0} refers to routine {1}, which is not defined in the library.
+    UnknownRoutine(&'static str, u16),
+
+    /// relocation at offset {0:#x} lies outside of the code segment.
+    RelocOutOfCode(u16),
+}
+
+/// Errors constructing [`CompiledLib`] from its parts with [`CompiledLib::from_parts`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum FromPartsError {
+    /// {0}
+    #[from]
+    Verify(VerifyError),
+
+    /// routine {0} starts at offset {1:#x}, which lies outside of the code segment.
+    OutOfCode(u16, u16),
+
+    /// routine {0} starts at offset {1:#x}, which is not an instruction boundary.
+    Misaligned(u16, u16),
+
+    /// routine {0} starts before the routine preceding it.
+    Unordered(u16),
+}
+
+/// Checks that the routines are ordered and start inside the code segment and, if the instruction
+/// boundaries are known, that they start on an instruction boundary.
+fn check_routine_table(
+    routines: &[u16],
+    code_len: usize,
+    boundaries: Option<&BTreeSet<u16>>,
+) -> Result<(), FromPartsError> {
+    for (no, pos) in routines.iter().copied().enumerate() {
+        let no = no as u16;
+        if pos as usize >= code_len {
+            return Err(FromPartsError::OutOfCode(no, pos));
+        }
+        if boundaries.is_some_and(|boundaries| !boundaries.contains(&pos)) {
+            return Err(FromPartsError::Misaligned(no, pos));
+        }
+        if no > 0 && routines[no as usize - 1] > pos {
+            return Err(FromPartsError::Unordered(no));
+        }
+    }
+    Ok(())
+}
+
+/// Serialized representation of the [`CompiledLib`], which id is not verified yet.
+#[cfg(feature = "serde")]
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct CompiledLibUnchecked {
     id: LibId,
     lib: Lib,
     routines: Vec<u16>,
+    names: BTreeMap<String, u16>,
+    #[serde(default)]
+    exports: Option<BTreeSet<u16>>,
+    stubs: BTreeSet<u16>,
+    // Libraries serialized without the version precede the versioning of the encoding
+    #[serde(default = "EncodingVersion::initial")]
+    version: EncodingVersion,
+    #[serde(default)]
+    source_map: Vec<(u16, usize)>,
+    #[serde(default)]
+    entry: Option<u16>,
+    #[serde(default)]
+    meta: BTreeMap<String, Vec<u8>>,
+    #[serde(default)]
+    relocs: Vec<Reloc>,
+    #[serde(default)]
+    arities: BTreeMap<u16, Arity>,
+    #[serde(default)]
+    call_arities: Vec<(LibSite, Arity)>,
+}
+
+#[cfg(feature = "serde")]
+impl TryFrom<CompiledLibUnchecked> for CompiledLib {
+    type Error = LibLoadError;
+
+    fn try_from(unchecked: CompiledLibUnchecked) -> Result<Self, Self::Error> {
+        let CompiledLibUnchecked {
+            id,
+            lib,
+            routines,
+            names,
+            exports,
+            stubs,
+            version,
+            source_map,
+            entry,
+            meta,
+            relocs,
+            arities,
+            call_arities,
+        } = unchecked;
+        Self {
+            id,
+            lib,
+            routines,
+            names,
+            exports,
+            stubs,
+            version,
+            source_map,
+            entry,
+            meta,
+            relocs,
+            arities,
+            call_arities: call_arities.into_iter().collect(),
+        }
+        .validate()
+    }
+}
+
+/// Serializes map with non-string keys as a sequence of the key-value pairs, which is supported
+/// by all serde formats.
+#[cfg(feature = "serde")]
+fn serialize_pairs<K, V, S>(map: &BTreeMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    K: serde::Serialize,
+    V: serde::Serialize,
+    S: serde::Serializer,
+{
+    serializer.collect_seq(map)
+}
+
+impl From<CompiledLib> for Lib {
+    fn from(lib: CompiledLib) -> Self { lib.lib }
+}
+
+impl AsRef<Lib> for CompiledLib {
+    fn as_ref(&self) -> &Lib { &self.lib }
 }
 
 impl CompiledLib {
+    /// Maximal number of routines in a library, such that the routine count fits into `u16`.
+    pub const MAX_ROUTINES: usize = u16::MAX as usize;
+
     /// Compiles library from the provided instructions by resolving local call pointers first, and
     /// then assembling it into a bytecode by calling [`Self::assemble`].
     pub fn compile<Isa>(
+        code: impl AsMut<[Isa]>,
+        deps: &[&CompiledLib],
+    ) -> Result<Self, CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        Self::compile_labeled(code, &[], deps)
+    }
+
+    /// Compiles library in the same way as [`Self::compile`], taking ownership of the code and
+    /// returning it back with the goto positions resolved into byte offsets.
+    pub fn compile_owned<Isa>(
+        mut code: Vec<Isa>,
+        deps: &[&CompiledLib],
+    ) -> Result<(Self, Vec<Isa>), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let lib = Self::compile(&mut code, deps)?;
+        Ok((lib, code))
+    }
+
+    /// Compiles library in the same way as [`Self::compile`] from the instructions produced by an
+    /// iterator.
+    ///
+    /// Since the compilation requires two passes over the code, the instructions are collected
+    /// before the compilation.
+    pub fn compile_from_iter<Isa>(
+        code: impl IntoIterator<Item = Isa>,
+        deps: &[&CompiledLib],
+    ) -> Result<Self, CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        Self::compile(code.into_iter().collect::<Vec<_>>(), deps)
+    }
+
+    /// Compiles library in the same way as [`Self::compile`], additionally assigning names to the
+    /// routines.
+    ///
+    /// Labels are provided as a slice parallel to the instructions: a label at some position names
+    /// the routine started by the instruction at the same position, which must be a goto target.
+    /// The slice may be shorter than the code, in which case the rest of instructions are
+    /// considered unlabeled.
+    pub fn compile_labeled<Isa>(
+        code: impl AsMut<[Isa]>,
+        labels: &[Option<&str>],
+        deps: &[&CompiledLib],
+    ) -> Result<Self, CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        Self::compile_with(code, labels, deps, &default!())
+    }
+
+    /// Compiles library in the same way as [`Self::compile_labeled`], using the provided compiler
+    /// options.
+    pub fn compile_with<Isa>(
+        code: impl AsMut<[Isa]>,
+        labels: &[Option<&str>],
+        deps: &[&CompiledLib],
+        opts: &CompileOpts,
+    ) -> Result<Self, CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        Self::compile_reported(code, labels, deps, opts).map(|(lib, _)| lib)
+    }
+
+    /// Compiles library in the same way as [`Self::compile_with`], additionally returning the
+    /// compilation report.
+    pub fn compile_reported<Isa>(
+        mut code: impl AsMut<[Isa]>,
+        labels: &[Option<&str>],
+        deps: &[&CompiledLib],
+        opts: &CompileOpts,
+    ) -> Result<(Self, CompileReport), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        Self::compile_inner(code.as_mut(), labels, &[], deps, opts, |_, _| {})
+    }
+
+    /// Compiles library in the same way as [`Self::compile_reported`], reporting the compilation
+    /// progress.
+    ///
+    /// The `progress` callback is called after each of the instructions is processed by each of
+    /// the two compiler passes, with the number of the processed instructions and their total
+    /// number (which is twice the code length, accounting for both passes). The assembly of the
+    /// bytecode, which is performed after the second pass, is not reported.
+    pub fn compile_with_progress<Isa>(
         mut code: impl AsMut<[Isa]>,
+        labels: &[Option<&str>],
+        deps: &[&CompiledLib],
+        opts: &CompileOpts,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(Self, CompileReport), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        Self::compile_inner(code.as_mut(), labels, &[], deps, opts, progress)
+    }
+
+    /// Compiles library in the same way as [`Self::compile_with`], exporting only the provided
+    /// routines, given either by their labels or numbers (see [`Self::exports`]).
+    ///
+    /// The rest of the routines are private: they can't be found with [`Self::routine_by_name`],
+    /// and the compiler and the linker reject calls into them from other libraries.
+    pub fn compile_exported<'e, Isa>(
+        code: impl AsMut<[Isa]>,
+        labels: &[Option<&str>],
+        exports: &[impl Into<Export<'e>> + Copy],
         deps: &[&CompiledLib],
+        opts: &CompileOpts,
     ) -> Result<Self, CompilerError<Isa>>
     where
         Isa: Instruction<LibId>,
     {
-        let deps = deps
+        let mut lib = Self::compile_with(code, labels, deps, opts)?;
+        let exports = exports
             .iter()
-            .map(|lib| (lib.id, lib))
-            .collect::<BTreeMap<_, _>>();
+            .map(|export| match (*export).into() {
+                Export::Label(name) => lib
+                    .names
+                    .get(name)
+                    .copied()
+                    .ok_or_else(|| CompilerError::UnknownExport(name.to_string())),
+                Export::Routine(no) if no as usize >= lib.routines.len() => {
+                    Err(CompilerError::UnknownRoutine(no))
+                }
+                Export::Routine(no) => Ok(no),
+            })
+            .collect::<Result<_, _>>()?;
+        lib.exports = Some(exports);
+        Ok(lib)
+    }
+
+    /// Compiles library in the same way as [`Self::compile_with`], leaving some of the external
+    /// calls as named [relocations](Reloc), which are patched later with [`Self::apply_relocs`].
+    ///
+    /// Symbols are provided as a slice parallel to the instructions, in the same way as the labels:
+    /// a symbol at some position turns the external call at the same position into a relocation.
+    /// The call sites of such instructions are ignored, and the libraries they reference don't
+    /// have to be provided as dependencies.
+    pub fn compile_relocatable<Isa>(
+        mut code: impl AsMut<[Isa]>,
+        labels: &[Option<&str>],
+        symbols: &[Option<&str>],
+        deps: &[&CompiledLib],
+        opts: &CompileOpts,
+    ) -> Result<Self, CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        Self::compile_inner(code.as_mut(), labels, symbols, deps, opts, |_, _| {})
+            .map(|(lib, _)| lib)
+    }
+
+    /// Compiles library in the same way as [`Self::compile`], but instead of stopping on the first
+    /// goto reference which can't be resolved, reports all of them.
+    ///
+    /// # Errors
+    ///
+    /// Errors for all the local and external goto references which can't be resolved, in the
+    /// order of the instructions (one per instruction). If all the references are valid, the
+    /// error of the compilation, if any.
+    pub fn compile_collect_errors<Isa>(
+        mut code: impl AsMut<[Isa]>,
+        deps: &[&CompiledLib],
+    ) -> Result<Self, Vec<CompilerError<Isa>>>
+    where
+        Isa: Instruction<LibId>,
+    {
         let code = code.as_mut();
-        let mut routines = vec![];
-        let mut cursor = 0u16;
-        for instr in &*code {
-            if instr.is_goto_target() {
-                routines.push(cursor);
-            }
-            cursor += instr.code_byte_len();
+        let opts = CompileOpts::default();
+        let (layout, _) = layout(code, &[], &[], &opts, |_| {});
+        let errors = Resolver::new(&layout, deps, &opts)
+            .resolve_code(&mut code.to_vec(), &[], |_| {})
+            .into_iter()
+            .map(|err| err.into_compiler_error(&layout, opts.goto_mode))
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(errors);
         }
-        let mut cursor = 0u16;
-        for (no, instr) in code.iter_mut().enumerate() {
-            if let Some(goto_pos) = instr.local_goto_pos() {
-                let Some(pos) = routines.get(*goto_pos as usize) else {
-                    return Err(CompilerError::InvalidRef(instr.clone(), no, cursor, routines));
-                };
-                *goto_pos = *pos;
-            }
-            let cloned_instr = instr.clone();
-            if let Some(remote_pos) = instr.remote_goto_pos() {
-                let Some(lib) = deps.get(&remote_pos.prog_id) else {
-                    return Err(CompilerError::InvalidLib(
-                        cloned_instr,
-                        no,
-                        cursor,
-                        remote_pos.prog_id,
-                    ));
-                };
-                remote_pos.offset = lib.routine(remote_pos.offset).offset;
+        Self::compile(code, deps).map_err(|err| vec![err])
+    }
+
+    /// Computes the layout of the library which would be produced by [`Self::compile_relocatable`]
+    /// with the same arguments, without assembling the code into the bytecode and computing the
+    /// library id.
+    ///
+    /// Unlike the compilation, planning doesn't stop on the first error, and reports all the
+    /// references which can't be resolved with the given options. The code which doesn't fit into
+    /// the code segment is planned as well (see [`CompilePlan::fits_code_segment`]).
+    ///
+    /// # Errors
+    ///
+    /// Fails on the first of the compilation errors which are not related to the references (like
+    /// the repeated labels or an unsupported encoding version).
+    pub fn plan<Isa>(
+        code: &[Isa],
+        labels: &[Option<&str>],
+        symbols: &[Option<&str>],
+        deps: &[&CompiledLib],
+        opts: &CompileOpts,
+    ) -> Result<CompilePlan, CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        check_encoding(code, opts)?;
+        let (layout, errors) = layout(code, labels, symbols, opts, |_| {});
+        if let Some(err) = errors
+            .into_iter()
+            .find(|err| !matches!(err, CompilerError::CodeTooLarge { .. }))
+        {
+            return Err(err);
+        }
+        let mut unresolved = vec![];
+        let errors =
+            Resolver::new(&layout, deps, opts).resolve_code(&mut code.to_vec(), symbols, |_| {});
+        for err in errors {
+            match err.unresolved(opts.goto_mode) {
+                Some(unresolved_ref) => unresolved.push(unresolved_ref),
+                None => return Err(err.into_compiler_error(&layout, opts.goto_mode)),
             }
-            cursor += instr.code_byte_len();
         }
-        let lib = Lib::assemble(code)?;
-        let id = lib.lib_id();
-        Ok(Self { id, lib, routines })
+        Ok(CompilePlan {
+            routines: layout.routines,
+            code_len: layout.code_len,
+            unresolved,
+        })
     }
 
-    pub fn routines_count(&self) -> usize { self.routines.len() }
-
-    /// Returns code offset for the entry point of a given routine.
+    /// Checks that the code doesn't read registers before they are written, as described in
+    /// [`CompileOpts::deny_uninit_reads`], with the routines taking their arguments in the
+    /// registers declared by the calling conventions in `inputs`.
     ///
-    /// # Panics
+    /// The routines which are not listed in `inputs` are assumed to take no arguments. The code
+    /// is not compiled, and the rest of the options are used to interpret the local gotos.
     ///
-    /// Panics if the routine with the given number is not defined
-    pub fn routine(&self, no: u16) -> LibSite {
-        let pos = self.routines[no as usize];
-        LibSite::new(self.id, pos)
+    /// # Errors
+    ///
+    /// Fails with [`CompilerError::UninitRead`] on the first instruction which may read a
+    /// register before it is written, or with the structural errors of the code (like the empty
+    /// routines).
+    pub fn check_uninit_reads<Isa>(
+        code: &[Isa],
+        inputs: &BTreeMap<u16, CallConv<RegOf<Isa>>>,
+        opts: &CompileOpts,
+    ) -> Result<(), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let (layout, errors) = layout(code, &[], &[], opts, |_| {});
+        if let Some(err) = errors.into_iter().next() {
+            return Err(err);
+        }
+        let inputs = inputs
+            .iter()
+            .map(|(routine, conv)| (*routine, conv.args.iter().copied().collect()))
+            .collect();
+        let goto_routine = |pos: u16| layout.goto_routine(opts.goto_mode, pos);
+        let count = layout.routines.len();
+        let mut code = code.to_vec();
+        match uninit_read(&mut code, count, &inputs, opts.entry, goto_routine) {
+            Some((no, routine, reg)) => {
+                Err(CompilerError::UninitRead(code[no].clone(), no, routine, reg))
+            }
+            None => Ok(()),
+        }
     }
 
-    pub fn as_lib(&self) -> &Lib { &self.lib }
+    fn compile_inner<Isa>(
+        code: &mut [Isa],
+        labels: &[Option<&str>],
+        symbols: &[Option<&str>],
+        deps: &[&CompiledLib],
+        opts: &CompileOpts,
+        progress: impl FnMut(usize, usize),
+    ) -> Result<(Self, CompileReport), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        if !opts.dedup_routines || opts.goto_mode != GotoMode::Routine {
+            return Self::compile_pass(code, labels, symbols, deps, opts, progress);
+        }
+        let source = code.to_vec();
+        let (lib, report) = Self::compile_pass(code, labels, symbols, deps, opts, progress)?;
+        let duplicates = duplicate_routines(&source, symbols, &lib);
+        if duplicates.is_empty() || !lib.stubs.is_empty() {
+            return Ok((lib, report));
+        }
 
-    pub fn into_lib(self) -> Lib { self.lib }
+        // Routine numbers after dropping the duplicates
+        let renumber = |no: u16| {
+            let no = duplicates.get(&no).copied().unwrap_or(no);
+            no - duplicates.range(..no).count() as u16
+        };
+        let mut index = vec![];
+        let mut kept = vec![];
+        let mut routine = None::<u16>;
+        for (no, instr) in source.iter().enumerate() {
+            if instr.is_goto_target() {
+                routine = Some(routine.map_or(0, |no| no + 1));
+            }
+            if routine.is_some_and(|no| duplicates.contains_key(&no)) {
+                continue;
+            }
+            let mut instr = instr.clone();
+            if let Some(pos) = instr.local_goto_pos() {
+                *pos = renumber(*pos);
+            }
+            for pos in instr.local_goto_table() {
+                *pos = renumber(*pos);
+            }
+            index.push(no);
+            kept.push(instr);
+        }
+        let labels = index
+            .iter()
+            .map(|no| labels.get(*no).copied().flatten())
+            .collect::<Vec<_>>();
+        let symbols = index
+            .iter()
+            .map(|no| symbols.get(*no).copied().flatten())
+            .collect::<Vec<_>>();
+        let opts = CompileOpts {
+            entry: opts.entry.map(renumber),
+            expected_routines: None,
+            dedup_routines: false,
+            ..*opts
+        };
+        let (mut deduped, report) =
+            Self::compile_pass(&mut kept, &labels, &symbols, deps, &opts, |_, _| {})?;
+        for (name, no) in lib.names {
+            if duplicates.contains_key(&no) {
+                deduped.names.insert(name, renumber(no));
+            }
+        }
+        for (_, no) in &mut deduped.source_map {
+            *no = index[*no];
+        }
+        code.clone_from_slice(&source);
+        for (no, instr) in index.into_iter().zip(kept) {
+            code[no] = instr;
+        }
+        Ok((deduped, report))
+    }
+
+    fn compile_pass<Isa>(
+        code: &mut [Isa],
+        labels: &[Option<&str>],
+        symbols: &[Option<&str>],
+        deps: &[&CompiledLib],
+        opts: &CompileOpts,
+        mut progress: impl FnMut(usize, usize),
+    ) -> Result<(Self, CompileReport), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let len = code.len();
+        check_encoding(code, opts)?;
+        let (layout, errors) = layout(code, labels, symbols, opts, |done| progress(done, len * 2));
+        if let Some(err) = errors.into_iter().next() {
+            return Err(err);
+        }
+        let count = layout.routines.len();
+        if let Some(expected) = opts.expected_routines.filter(|n| *n as usize != count) {
+            return Err(CompilerError::RoutineCountMismatch { expected, found: count });
+        }
+        let goto_routine = |pos: u16| layout.goto_routine(opts.goto_mode, pos);
+        let unreachable = match opts.entry {
+            Some(entry) if entry as usize >= count => {
+                return Err(CompilerError::UnknownRoutine(entry));
+            }
+            Some(entry) => unreachable_routines(code, count, entry, goto_routine),
+            None => vec![],
+        };
+        let infinite_loops = infinite_loops(code, count, goto_routine);
+        if opts.deny_uninit_reads {
+            if let Some((no, routine, reg)) =
+                uninit_read(code, count, &none!(), opts.entry, goto_routine)
+            {
+                return Err(CompilerError::UninitRead(code[no].clone(), no, routine, reg));
+            }
+        }
+        let unterminated = unterminated_routines(code);
+        if let Some(no) = unterminated.first().filter(|_| opts.strict) {
+            return Err(CompilerError::UnterminatedRoutine(*no));
+        }
+        let source_map = layout
+            .offsets
+            .iter()
+            .enumerate()
+            .filter(|(no, _)| !layout.stripped[*no])
+            .map(|(no, offset)| (*offset as u16, no))
+            .collect();
+        let mut resolver = Resolver::new(&layout, deps, opts);
+        let errors = resolver.resolve_code(code, symbols, |done| progress(len + done, len * 2));
+        if let Some(err) = errors.into_iter().next() {
+            return Err(err.into_compiler_error(&layout, opts.goto_mode));
+        }
+        let Resolver { stubs, relocs, .. } = resolver;
+        let stripped = &layout.stripped;
+        let lib = if stripped.contains(&true) {
+            let (index, kept): (Vec<_>, Vec<_>) = code
+                .iter()
+                .enumerate()
+                .filter(|(no, _)| !stripped[*no])
+                .map(|(no, instr)| (no, instr.clone()))
+                .unzip();
+            Lib::assemble(&kept).map_err(|err| match err {
+                AssemblerError::Bytecode(no, offset, err) => {
+                    AssemblerError::Bytecode(index[no], offset, err)
+                }
+                err => err,
+            })?
+        } else {
+            Lib::assemble(code)?
+        };
+        let routines = layout.routine_offsets();
+        let mut large_routines = vec![];
+        if let Some(max) = opts.max_routine_bytes {
+            let ends = routines
+                .iter()
+                .skip(1)
+                .copied()
+                .chain([lib.code.len() as u16]);
+            for (no, (start, end)) in routines.iter().copied().zip(ends).enumerate() {
+                if end - start <= max {
+                    continue;
+                }
+                if opts.deny_large_routines {
+                    return Err(CompilerError::RoutineTooLarge(no as u16, end - start, max));
+                }
+                large_routines.push(no as u16);
+            }
+        }
+        let id = lib.lib_id();
+        let version = opts.version;
+        let entry = opts.entry;
+        let meta = BTreeMap::new();
+        Ok((
+            Self {
+                id,
+                lib,
+                routines,
+                names: layout.names,
+                exports: None,
+                stubs,
+                version,
+                source_map,
+                entry,
+                meta,
+                relocs,
+                arities: none!(),
+                call_arities: none!(),
+            },
+            CompileReport { unreachable, unterminated, large_routines, infinite_loops },
+        ))
+    }
+
+    /// Compiles several code chunks into a single library.
+    ///
+    /// Each of the chunks uses its own routine numbering for the local gotos, starting from zero.
+    /// The chunks are rebased (in place) into the routine space of the combined library, such that
+    /// the gotos of a chunk are offset by the number of routines defined in all the preceding
+    /// chunks, and then compiled as a single code.
+    ///
+    /// # Returns
+    ///
+    /// Compiled library and the ranges of the library routine numbers defined by each of the
+    /// chunks.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a chunk references a routine which it doesn't define, or if the combined code can't
+    /// be compiled.
+    pub fn compile_linked<Isa>(
+        chunks: &mut [&mut [Isa]],
+        deps: &[&CompiledLib],
+    ) -> Result<(Self, Vec<Range<u16>>), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let mut ranges = Vec::with_capacity(chunks.len());
+        let mut base = 0u16;
+        let mut instr_base = 0usize;
+        for (chunk_no, chunk) in chunks.iter_mut().enumerate() {
+            let count = chunk.iter().filter(|instr| instr.is_goto_target()).count();
+            if base as usize + count > Self::MAX_ROUTINES {
+                let no = chunk
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, instr)| instr.is_goto_target())
+                    .nth(Self::MAX_ROUTINES - base as usize)
+                    .map_or(0, |(no, _)| no);
+                return Err(CompilerError::TooManyRoutines(instr_base + no));
+            }
+            let count = count as u16;
+            for (no, instr) in chunk.iter_mut().enumerate() {
+                let cloned_instr = instr.clone();
+                let rebase = |goto_pos: &mut u16| {
+                    if *goto_pos >= count {
+                        return Err(*goto_pos);
+                    }
+                    *goto_pos += base;
+                    Ok(())
+                };
+                if let Some(goto_pos) = instr.local_goto_pos() {
+                    rebase(goto_pos).map_err(|routine| {
+                        CompilerError::InvalidChunkRef(cloned_instr.clone(), chunk_no, no, routine)
+                    })?;
+                }
+                for goto_pos in instr.local_goto_table() {
+                    rebase(goto_pos).map_err(|routine| {
+                        CompilerError::InvalidChunkRef(cloned_instr.clone(), chunk_no, no, routine)
+                    })?;
+                }
+            }
+            ranges.push(base..base + count);
+            base += count;
+            instr_base += chunk.len();
+        }
+        let code = chunks
+            .iter()
+            .flat_map(|chunk| chunk.iter().cloned())
+            .collect::<Vec<_>>();
+        let lib = Self::compile(code, deps)?;
+        Ok((lib, ranges))
+    }
+
+    /// Replaces the code of a single routine and recompiles the library.
+    ///
+    /// The library code is disassembled against the provided dependencies, the instructions of the
+    /// routine are replaced with the new code, and the code is compiled again. This shifts the
+    /// entry points of the subsequent routines by the change in the routine length and updates all
+    /// the gotos referencing them. Routine numbers, labels, exports, metadata and declared routine
+    /// arities are kept. Relocations outside of the replaced routine are kept and moved together
+    /// with their instructions; the declared [call arities](Self::with_call_arity) are kept only
+    /// for the routines which are still called by the library code.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the routine is not defined, or if the new code doesn't start with a goto target or
+    /// contains other goto targets (which would change the routine numbering). Libraries with
+    /// [stub](Self::is_stub) routines can't be recompiled, since the stub gotos can't be
+    /// disassembled.
+    pub fn recompile_routine<Isa>(
+        &mut self,
+        no: u16,
+        new_code: &[Isa],
+        deps: &[&CompiledLib],
+    ) -> Result<(), CompilerError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        if no as usize >= self.routines.len() {
+            return Err(CompilerError::UnknownRoutine(no));
+        }
+        let Some((first, rest)) = new_code.split_first() else {
+            return Err(CompilerError::RoutineMismatch(no));
+        };
+        if !first.is_goto_target() || rest.iter().any(Isa::is_goto_target) {
+            return Err(CompilerError::RoutineMismatch(no));
+        }
+
+        let mut code = self.disassemble_inner::<Isa>(deps, true)?;
+        let mut cursor = 0u16;
+        let mut symbols = Vec::with_capacity(code.len());
+        for instr in &code {
+            let reloc = self.relocs.iter().find(|reloc| reloc.site_offset == cursor);
+            symbols.push(reloc.map(|reloc| reloc.symbol.as_str()));
+            cursor += instr.code_byte_len();
+        }
+        let mut targets = code
+            .iter()
+            .enumerate()
+            .filter(|(_, instr)| instr.is_goto_target())
+            .map(|(index, _)| index)
+            .skip(no as usize);
+        let start = targets.next().expect("routine table matches the code");
+        let end = targets.next().unwrap_or(code.len());
+        code.splice(start..end, new_code.iter().cloned());
+        symbols.splice(start..end, new_code.iter().map(|_| None));
+
+        let opts = CompileOpts { entry: self.entry, version: self.version, ..default!() };
+        let (mut lib, _) =
+            CompiledLib::compile_inner(&mut code, &[], &symbols, deps, &opts, |_, _| {})?;
+        let callees = code
+            .iter_mut()
+            .filter_map(|instr| instr.remote_goto_pos().copied().map(LibSite::from))
+            .collect::<BTreeSet<_>>();
+        lib.names = core::mem::take(&mut self.names);
+        lib.exports = self.exports.take();
+        lib.meta = core::mem::take(&mut self.meta);
+        lib.arities = core::mem::take(&mut self.arities);
+        lib.call_arities = core::mem::take(&mut self.call_arities);
+        lib.call_arities
+            .retain(|callee, _| callees.contains(callee));
+        *self = lib;
+        Ok(())
+    }
+
+    /// Constructs compiled library from an existing library and a known routine table, without
+    /// re-running the compilation.
+    ///
+    /// The library id is computed from the library, and no routine names, exports, entry point or
+    /// other metadata are defined.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the library code doesn't pass the static verification (see [`Self::verify`]), or
+    /// if some of the routine offsets lie outside of the code segment, do not land on an
+    /// instruction boundary or are not ordered.
+    pub fn from_parts<Isa>(lib: Lib, routines: Vec<u16>) -> Result<Self, FromPartsError>
+    where Isa: Instruction<LibId> {
+        let boundaries = verify_code::<Isa>(
+            lib.code.as_slice(),
+            lib.data.as_slice(),
+            &lib.libs,
+            false,
+            EncodingVersion::CURRENT,
+        )?;
+        check_routine_table(&routines, lib.code.len(), Some(&boundaries))?;
+        Ok(Self {
+            id: lib.lib_id(),
+            lib,
+            routines,
+            names: none!(),
+            exports: None,
+            stubs: none!(),
+            version: EncodingVersion::CURRENT,
+            source_map: vec![],
+            entry: None,
+            meta: none!(),
+            relocs: vec![],
+            arities: none!(),
+            call_arities: none!(),
+        })
+    }
+
+    /// Checks the consistency of a library restored from its serialized form.
+    ///
+    /// Checks that the encoding version is supported, that the library id matches the library
+    /// code, that the routines are ordered and lie inside the code segment, that the entry point,
+    /// exports, stubs, labels and arities refer to the known routines, and that the relocations
+    /// lie inside the code segment. Since the instruction boundaries depend on the instruction
+    /// set, they are checked by [`Self::verify`].
+    #[cfg(feature = "serde")]
+    fn validate(self) -> Result<Self, LibLoadError> {
+        if !self.version.is_supported() {
+            return Err(LibLoadError::UnsupportedVersion(self.version));
+        }
+        let expected = self.lib.lib_id();
+        if self.id != expected {
+            return Err(LibIdMismatch { expected, found: self.id }.into());
+        }
+        let code_len = self.lib.code.len();
+        check_routine_table(&self.routines, code_len, None)?;
+        let count = self.routines.len();
+        let refs = self
+            .entry
+            .map(|no| ("entry point", no))
+            .into_iter()
+            .chain(self.exports.iter().flatten().map(|no| ("export", *no)))
+            .chain(self.stubs.iter().map(|no| ("stub", *no)))
+            .chain(self.names.values().map(|no| ("label", *no)))
+            .chain(self.arities.keys().map(|no| ("arity", *no)));
+        for (what, no) in refs {
+            if no as usize >= count {
+                return Err(LibLoadError::UnknownRoutine(what, no));
+            }
+        }
+        if let Some(reloc) = self
+            .relocs
+            .iter()
+            .find(|reloc| reloc.site_offset as usize >= code_len)
+        {
+            return Err(LibLoadError::RelocOutOfCode(reloc.site_offset));
+        }
+        Ok(self)
+    }
+
+    /// Disassembles library into a set of instructions, converting goto positions back from byte
+    /// offsets into routine numbers.
+    ///
+    /// The returned code, being compiled with [`Self::compile`] against the same dependencies,
+    /// produces exactly the same library.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the bytecode can't be decoded, if some of the local or external goto positions do
+    /// not point to a routine entry, or if some of the routine entries do not start with a goto
+    /// target.
+    pub fn disassemble<Isa>(&self, deps: &[&CompiledLib]) -> Result<Vec<Isa>, DisassembleError>
+    where Isa: Instruction<LibId> {
+        self.disassemble_inner(deps, false)
+    }
+
+    /// Disassembles library in the same way as [`Self::disassemble`], optionally keeping the
+    /// unpatched [relocations](Reloc) referencing [`LibId::RELOC`] as they are.
+    fn disassemble_inner<Isa>(
+        &self,
+        deps: &[&CompiledLib],
+        keep_relocs: bool,
+    ) -> Result<Vec<Isa>, DisassembleError>
+    where
+        Isa: Instruction<LibId>,
+    {
+        if !self.version.is_supported() {
+            return Err(DisassembleError::UnsupportedVersion(self.version));
+        }
+        let mut code = self.lib.disassemble::<Isa>()?;
+        let mut offset = 0u16;
+        for instr in &code {
+            if instr.encoding_version() > self.version {
+                return Err(DisassembleError::UnsupportedInstr(offset, self.version));
+            }
+            offset += instr.code_byte_len();
+        }
+        let mut targets = Vec::with_capacity(self.routines.len());
+        let mut cursor = 0u16;
+        for (no, instr) in code.iter_mut().enumerate() {
+            if instr.is_goto_target() {
+                targets.push(cursor);
+            }
+            let restore = |goto_pos: &mut u16| {
+                let Some(routine) = self.routines.iter().position(|pos| *pos == *goto_pos) else {
+                    return Err(DisassembleError::InvalidRef(no, cursor, *goto_pos));
+                };
+                *goto_pos = routine as u16;
+                Ok(())
+            };
+            let target = instr.local_goto_target(cursor);
+            if let Some(goto_pos) = instr.local_goto_pos() {
+                *goto_pos = target.ok_or(DisassembleError::InvalidRef(no, cursor, *goto_pos))?;
+                restore(goto_pos)?;
+            }
+            instr.local_goto_table().iter_mut().try_for_each(restore)?;
+            if let Some(remote_pos) = instr
+                .remote_goto_pos()
+                .filter(|pos| !keep_relocs || pos.prog_id != LibId::RELOC)
+            {
+                let Some(lib) = deps.iter().find(|lib| lib.id == remote_pos.prog_id) else {
+                    return Err(DisassembleError::InvalidLib(no, cursor, remote_pos.prog_id));
+                };
+                let Some(routine) = lib
+                    .routines
+                    .iter()
+                    .position(|pos| *pos == remote_pos.offset)
+                else {
+                    return Err(DisassembleError::InvalidRef(no, cursor, remote_pos.offset));
+                };
+                remote_pos.offset = routine as u16;
+            }
+            cursor += instr.code_byte_len();
+        }
+        if let Some(no) =
+            (0..self.routines.len()).find(|no| targets.get(*no) != self.routines.get(*no))
+        {
+            return Err(DisassembleError::MissingMarker(no as u16));
+        }
+        Ok(code)
+    }
+
+    /// Decodes the library code into a set of instructions, keeping goto positions resolved
+    /// into byte offsets.
+    ///
+    /// The returned code matches the instructions left by the compiler in the code buffer provided
+    /// to it, except the goto targets omitted with [`CompileOpts::strip_markers`]. Unlike
+    /// [`Self::disassemble`], the code doesn't require dependencies and can't be compiled back.
+    pub fn resolved_code<Isa>(&self) -> Result<Vec<Isa>, DisassembleError>
+    where Isa: Instruction<LibId> {
+        if !self.version.is_supported() {
+            return Err(DisassembleError::UnsupportedVersion(self.version));
+        }
+        self.lib.disassemble()
+    }
+
+    /// Iterates over the library code, lazily decoding the instructions together with their byte
+    /// offsets, in the order of the offsets.
+    ///
+    /// Once an instruction can't be decoded, the iterator yields the decoding error and stops.
+    pub fn iter_instructions<Isa>(
+        &self,
+    ) -> impl Iterator<Item = Result<(u16, Isa), DecodeError>> + '_
+    where Isa: Instruction<LibId> {
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        let mut failed = false;
+        core::iter::from_fn(move || {
+            if failed || reader.is_eof() {
+                return None;
+            }
+            let pos = reader.pos();
+            let instr = Isa::decode_instr(&mut reader).map_err(|_| DecodeError::Decode(pos));
+            failed = instr.is_err();
+            Some(instr.map(|instr| (pos, instr)))
+        })
+    }
+
+    /// Returns the location of the library entry point, if it was declared during the compilation
+    /// with [`CompileOpts::entry`].
+    pub fn entry(&self) -> Option<LibSite> { self.entry.and_then(|no| self.try_routine(no)) }
+
+    /// Returns version of the bytecode encoding used by the library.
+    pub fn version(&self) -> EncodingVersion { self.version }
+
+    /// Returns the index of the source instruction (in the code provided to the compiler) which
+    /// bytecode covers the given offset.
+    ///
+    /// Returns `None` if the offset lies outside the library code, or if the library doesn't carry
+    /// a source map (for instance, when it was deserialized from data produced without it).
+    pub fn source_index_at(&self, offset: u16) -> Option<usize> {
+        if offset as usize >= self.lib.code.len() {
+            return None;
+        }
+        let pos = self
+            .source_map
+            .partition_point(|(start, _)| *start <= offset);
+        pos.checked_sub(1).map(|pos| self.source_map[pos].1)
+    }
+
+    pub fn routines_count(&self) -> usize { self.routines.len() }
+
+    /// Returns code offsets for the entry points of all routines, indexed by the routine number.
+    pub fn routine_table(&self) -> &[u16] { &self.routines }
+
+    /// Returns an iterator over the routine numbers and their entry points.
+    pub fn routine_sites(&self) -> impl Iterator<Item = (u16, LibSite)> + '_ {
+        self.routines
+            .iter()
+            .enumerate()
+            .map(|(no, pos)| (no as u16, LibSite::new(self.id, *pos)))
+    }
+
+    /// Returns code offset for the entry point of a given routine.
+    ///
+    /// Must be used only with routine numbers known to be valid; for the routine numbers coming
+    /// from an untrusted input use [`Self::try_routine`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the routine with the given number is not defined
+    pub fn routine(&self, no: u16) -> LibSite {
+        self.try_routine(no)
+            .unwrap_or_else(|| panic!("routine {no} is not defined in the library {}", self.id))
+    }
+
+    /// Returns code offset for the entry point of a given routine, or `None` if the routine with
+    /// the given number is not defined.
+    pub fn try_routine(&self, no: u16) -> Option<LibSite> {
+        let pos = self.routines.get(no as usize)?;
+        Some(LibSite::new(self.id, *pos))
+    }
+
+    /// Checks whether the site points to an entry point of one of the library routines, and thus
+    /// can be used as a target of a call or as the entry point of a program.
+    pub fn site_is_valid_entry(&self, site: LibSite) -> bool {
+        site.lib_id == self.id && self.routines.contains(&site.offset)
+    }
+
+    /// Checks whether a routine with the given number is a stub, i.e. it was referenced by the code
+    /// compiled with [`CompileOpts::allow_dangling`], but was absent in it.
+    pub fn is_stub(&self, no: u16) -> bool { self.stubs.contains(&no) }
+
+    /// Returns byte range of the code segment occupied by a given routine.
+    ///
+    /// The routine spans up to the entry point of the next routine or, for the last routine, to
+    /// the end of the code segment. A routine which immediately falls through into the next one
+    /// spans only its goto target instruction.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the routine with the given number is not defined
+    pub fn routine_range(&self, no: u16) -> Range<u16> {
+        let start = self.routines[no as usize];
+        let end = self
+            .routines
+            .get(no as usize + 1)
+            .copied()
+            .unwrap_or(self.lib.code.len() as u16);
+        start..end
+    }
+
+    /// Returns the content hash of the bytecode of a given routine, as occupied by its
+    /// [range](Self::routine_range).
+    ///
+    /// Routines with the same id have the same bytecode, allowing clients to skip re-downloading
+    /// routines they already have when fetching an updated version of the library.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the routine with the given number is not defined
+    pub fn routine_id(&self, no: u16) -> RoutineId {
+        let range = self.routine_range(no);
+        RoutineId::from_code(&self.lib.code[range.start as usize..range.end as usize])
+    }
+
+    /// Returns the number of the routine which [range](Self::routine_range) contains the given
+    /// code offset, or `None` if the offset lies before the first routine or outside of the code
+    /// segment.
+    ///
+    /// The lookup performs a binary search over the routine table, relying on the routine offsets
+    /// being ordered. If several routines start at the same offset, the last of them is returned,
+    /// since the others have empty ranges.
+    pub fn routine_index_for_offset(&self, offset: u16) -> Option<u16> {
+        debug_assert!(
+            self.routines.windows(2).all(|pair| pair[0] <= pair[1]),
+            "routine offsets are not ordered"
+        );
+        if offset as usize >= self.lib.code.len() {
+            return None;
+        }
+        let no = self.routines.partition_point(|start| *start <= offset);
+        no.checked_sub(1).map(|no| no as u16)
+    }
+
+    /// Returns code offset for the entry point of a routine with a given label, if such routine
+    /// exists and is [exported](Self::is_exported).
+    pub fn routine_by_name(&self, name: &str) -> Option<LibSite> {
+        self.names
+            .get(name)
+            .filter(|no| self.is_exported(**no))
+            .map(|no| self.routine(*no))
+    }
+
+    /// Checks whether the routine with the given number is a part of the public library interface.
+    ///
+    /// Unless the library is compiled with [`Self::compile_exported`], all of its routines are
+    /// exported.
+    pub fn is_exported(&self, no: u16) -> bool {
+        self.exports
+            .as_ref()
+            .map_or(true, |exports| exports.contains(&no))
+    }
+
+    /// Iterates over the labels of the exported routines (see [`Self::is_exported`]) and their
+    /// entry points, in the alphabetical order of the labels.
+    pub fn exports(&self) -> impl Iterator<Item = (&str, LibSite)> + '_ {
+        self.names
+            .iter()
+            .filter(|(_, no)| self.is_exported(**no))
+            .map(|(name, no)| (name.as_str(), self.routine(*no)))
+    }
+
+    /// Attaches a metadata entry (like author, version or source code hash) to the library,
+    /// replacing the previous value under the same key.
+    ///
+    /// The metadata doesn't affect the code execution and is not a part of the [`Lib`], thus it
+    /// is not committed to by the [`LibId`]; use [`Self::meta_commitment`] to make it
+    /// tamper-evident.
+    pub fn with_meta(mut self, key: impl ToString, value: impl Into<Vec<u8>>) -> Self {
+        self.meta.insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Returns the value of the metadata entry with a given key, if present.
+    pub fn meta_get(&self, key: &str) -> Option<&[u8]> { self.meta.get(key).map(Vec::as_slice) }
+
+    /// Returns all the metadata entries of the library.
+    pub fn meta(&self) -> &BTreeMap<String, Vec<u8>> { &self.meta }
+
+    /// Computes the commitment to the library id together with its metadata, as a tagged SHA256
+    /// hash:
+    ///
+    /// ```text
+    /// tag = SHA256("urn:ubideco:aluvm:lib-meta:v01#241020")
+    /// commitment = SHA256(tag || tag || lib_id || n || (key_len || key || value_len || value)*)
+    /// ```
+    ///
+    /// where the number of entries `n` and the lengths are 32-bit little-endian integers and the
+    /// entries are ordered by their keys.
+    pub fn meta_commitment(&self) -> Bytes32 {
+        let tag = Sha256::digest(LIB_META_TAG.as_bytes());
+        let mut hasher = Sha256::new();
+        hasher.update(tag);
+        hasher.update(tag);
+        hasher.update(self.id.as_slice());
+        hasher.update((self.meta.len() as u32).to_le_bytes());
+        for (key, value) in &self.meta {
+            for item in [key.as_bytes(), value.as_slice()] {
+                hasher.update((item.len() as u32).to_le_bytes());
+                hasher.update(item);
+            }
+        }
+        Bytes32::from_byte_array(hasher.finalize())
+    }
+
+    /// Constructs a library from its segments, having a single routine starting at the beginning
+    /// of the code, which is the library entry point. The code is not verified.
+    pub(super) fn with_code_entry(lib: Lib) -> Self {
+        Self {
+            id: lib.lib_id(),
+            lib,
+            routines: vec![0],
+            names: none!(),
+            exports: None,
+            stubs: none!(),
+            version: EncodingVersion::CURRENT,
+            source_map: vec![],
+            entry: Some(0),
+            meta: none!(),
+            relocs: vec![],
+            arities: none!(),
+            call_arities: none!(),
+        }
+    }
+
+    pub fn as_lib(&self) -> &Lib { &self.lib }
+
+    /// Returns id of the library, computed once at the compilation or loading time.
+    pub fn lib_id(&self) -> LibId { self.id }
+
+    /// Returns the data segment of the library, containing the constants used by the code.
+    ///
+    /// The segment is not split into discrete constants: the instructions reference the data by
+    /// their offset and length (see [`Self::data_slice`]), and the same bytes may be shared by
+    /// several instructions.
+    pub fn data_segment(&self) -> &[u8] { self.lib.data.as_slice() }
+
+    /// Returns a slice of the data segment of a given length starting at the provided offset, as
+    /// referenced by the instructions, or `None` if the slice doesn't fit into the data segment.
+    pub fn data_slice(&self, offset: u16, len: u16) -> Option<&[u8]> {
+        let end = offset as usize + len as usize;
+        self.lib.data.as_slice().get(offset as usize..end)
+    }
+
+    /// Checks whether the library code doesn't reference other libraries, and thus can be run
+    /// without resolving any dependencies.
+    ///
+    /// The libs segment of the compiled library lists exactly the libraries referenced by the code
+    /// (including the placeholder of the unresolved [relocations](Self::relocs)), thus the check
+    /// doesn't require decoding the instructions.
+    pub fn is_self_contained(&self) -> bool { self.lib.libs.is_empty() }
+
+    pub fn into_lib(self) -> Lib { self.lib }
+}
+
+/// Checks that the encoding version is supported and defines all of the instructions.
+fn check_encoding<Isa>(code: &[Isa], opts: &CompileOpts) -> Result<(), CompilerError<Isa>>
+where Isa: Instruction<LibId> {
+    if !opts.version.is_supported() {
+        return Err(CompilerError::UnsupportedVersion(opts.version));
+    }
+    if let Some((no, instr)) = code
+        .iter()
+        .enumerate()
+        .find(|(_, instr)| instr.encoding_version() > opts.version)
+    {
+        let required = instr.encoding_version();
+        return Err(CompilerError::UnsupportedInstr(instr.clone(), no, required, opts.version));
+    }
+    Ok(())
+}
+
+/// Layout of the compiled code, computed by the first compilation pass before resolving the
+/// references.
+///
+/// Offsets are not limited by the code segment size, such that the layout can be computed for the
+/// code which is too large to be compiled.
+struct Layout {
+    /// Goto target markers which are stripped from the compiled code.
+    stripped: Vec<bool>,
+    /// Offsets of the instructions in the compiled code.
+    offsets: Vec<usize>,
+    /// Offsets of the routine entry points in the compiled code.
+    routines: Vec<usize>,
+    /// Numbers of the labeled routines.
+    names: BTreeMap<String, u16>,
+    /// Offsets of the instructions (and the routines) in the code, if it was encoded without
+    /// stripping, mapped to their offsets in the compiled code.
+    boundaries: BTreeMap<usize, usize>,
+    /// Offsets of the routine entry points in the code, if it was encoded without stripping.
+    src_routines: Vec<usize>,
+    /// Length of the compiled code.
+    code_len: usize,
+}
+
+impl Layout {
+    /// Maps a local goto position, which is not resolved into the code offset yet, into the
+    /// routine number.
+    fn goto_routine(&self, goto_mode: GotoMode, pos: u16) -> Option<u16> {
+        match goto_mode {
+            GotoMode::Routine => ((pos as usize) < self.routines.len()).then_some(pos),
+            GotoMode::Offset if self.boundaries.contains_key(&(pos as usize)) => self
+                .src_routines
+                .partition_point(|start| *start <= pos as usize)
+                .checked_sub(1)
+                .map(|no| no as u16),
+            GotoMode::Offset => None,
+        }
+    }
+
+    /// Returns offsets of the routine entry points, for the code fitting into the code segment.
+    fn routine_offsets(&self) -> Vec<u16> { self.routines.iter().map(|pos| *pos as u16).collect() }
+}
+
+/// Computes the layout of the code in the first compilation pass, collecting the errors in the
+/// code structure (like misplaced labels or the code exceeding the code segment) in the order of
+/// the instructions.
+fn layout<Isa>(
+    code: &[Isa],
+    labels: &[Option<&str>],
+    symbols: &[Option<&str>],
+    opts: &CompileOpts,
+    mut progress: impl FnMut(usize),
+) -> (Layout, Vec<CompilerError<Isa>>)
+where
+    Isa: Instruction<LibId>,
+{
+    let mut layout = Layout {
+        stripped: stripped_markers(code, opts.strip_markers),
+        offsets: Vec::with_capacity(code.len()),
+        routines: vec![],
+        names: none!(),
+        boundaries: none!(),
+        src_routines: vec![],
+        code_len: 0,
+    };
+    let mut errors = vec![];
+    let mut src_cursor = 0usize;
+    for (no, instr) in code.iter().enumerate() {
+        let label = labels.get(no).copied().flatten();
+        layout.boundaries.insert(src_cursor, layout.code_len);
+        layout.offsets.push(layout.code_len);
+        if instr.is_goto_target() {
+            let routine = layout.routines.len();
+            if opts.deny_empty_routines && no > 0 && code[no - 1].is_goto_target() {
+                errors.push(CompilerError::EmptyRoutine(no, routine as u16 - 1));
+            }
+            if routine == CompiledLib::MAX_ROUTINES {
+                errors.push(CompilerError::TooManyRoutines(no));
+            }
+            if let Some(label) = label.filter(|_| routine < CompiledLib::MAX_ROUTINES) {
+                if layout
+                    .names
+                    .insert(label.to_string(), routine as u16)
+                    .is_some()
+                {
+                    errors.push(CompilerError::RepeatedLabel(label.to_string()));
+                }
+            }
+            layout.src_routines.push(src_cursor);
+            layout.routines.push(layout.code_len);
+        } else if let Some(label) = label {
+            errors.push(CompilerError::LabelNotTarget(instr.clone(), no, label.to_string()));
+        }
+        if let Some(symbol) = symbols.get(no).copied().flatten() {
+            if instr.external_ref().is_none() {
+                errors.push(CompilerError::RelocNotCall(instr.clone(), no, symbol.to_string()));
+            }
+        }
+        if !layout.stripped[no] {
+            let end = layout.code_len + instr.code_byte_len() as usize;
+            if let Ok(overflow_at) = u16::try_from(layout.code_len) {
+                if end > u16::MAX as usize {
+                    errors.push(CompilerError::CodeTooLarge { at_instruction: no, overflow_at });
+                }
+            }
+            layout.code_len = end;
+        }
+        src_cursor += instr.code_byte_len() as usize;
+        progress(no + 1);
+    }
+    (layout, errors)
+}
+
+/// Reason of a failure to resolve a reference in the second compilation pass.
+enum RefFailure {
+    /// Local goto position which doesn't resolve into a routine or an instruction boundary.
+    Local(u16),
+    /// Resolved offset of a relative goto lying out of its reach.
+    Relative(u16),
+    /// Library which is not a dependency.
+    Lib(LibId),
+    /// Routine which is absent in the library.
+    Remote(LibId, u16),
+    /// Routine which is not exported by the library.
+    Private(LibId, u16),
+    /// Relocation symbol of an instruction which doesn't expose a patchable call site.
+    NotCall(String),
+}
+
+/// Reference which can't be resolved in the second compilation pass.
+struct RefError<Isa> {
+    /// Instruction before resolving its references.
+    instr: Isa,
+    no: usize,
+    cursor: u16,
+    failure: RefFailure,
+}
+
+impl<Isa: Instruction<LibId>> RefError<Isa> {
+    fn into_compiler_error(self, layout: &Layout, goto_mode: GotoMode) -> CompilerError<Isa> {
+        let Self { instr, no, cursor, failure } = self;
+        let count = layout.routines.len();
+        match failure {
+            RefFailure::Local(target) if goto_mode == GotoMode::Offset => {
+                CompilerError::InvalidOffsetRef(instr, no, cursor, target)
+            }
+            RefFailure::Local(target) if target as usize > count => {
+                CompilerError::GotoOutOfRange(instr, no, cursor, target, count)
+            }
+            RefFailure::Local(_) => {
+                let targets = GotoTargets {
+                    offsets: layout.routine_offsets(),
+                    labels: layout
+                        .names
+                        .iter()
+                        .map(|(name, no)| (*no, name.clone()))
+                        .collect(),
+                };
+                CompilerError::InvalidRef(
+                    instr,
+                    no,
+                    cursor,
+                    Isa::GOTO_TARGET_MNEMONIC,
+                    Box::new(targets),
+                )
+            }
+            RefFailure::Relative(pos) => {
+                CompilerError::RelativeGotoOutOfRange(instr, no, cursor, pos)
+            }
+            RefFailure::Lib(lib_id) => CompilerError::InvalidLib(instr, no, cursor, lib_id),
+            RefFailure::Remote(lib_id, routine) => {
+                CompilerError::InvalidRemoteRef(instr, no, cursor, lib_id, routine)
+            }
+            RefFailure::Private(lib_id, routine) => {
+                CompilerError::PrivateRemoteRef(instr, no, cursor, lib_id, routine)
+            }
+            RefFailure::NotCall(symbol) => CompilerError::RelocNotCall(instr, no, symbol),
+        }
+    }
+
+    /// Returns the unresolved reference reported by [`CompiledLib::plan`], or `None` if the
+    /// failure is not related to the reference target.
+    fn unresolved(&self, goto_mode: GotoMode) -> Option<UnresolvedRef> {
+        let no = self.no;
+        Some(match self.failure {
+            RefFailure::Local(target) if goto_mode == GotoMode::Offset => {
+                UnresolvedRef::Offset(no, target)
+            }
+            RefFailure::Local(target) => UnresolvedRef::Local(no, target),
+            RefFailure::Relative(pos) => UnresolvedRef::Relative(no, pos),
+            RefFailure::Lib(lib_id) => UnresolvedRef::Lib(no, lib_id),
+            RefFailure::Remote(lib_id, routine) => UnresolvedRef::Remote(no, lib_id, routine),
+            RefFailure::Private(lib_id, routine) => UnresolvedRef::Private(no, lib_id, routine),
+            RefFailure::NotCall(_) => return None,
+        })
+    }
+}
+
+/// Second compilation pass, resolving the local goto positions into the code offsets and the
+/// remote routine numbers into the routine offsets of the dependencies.
+struct Resolver<'a> {
+    layout: &'a Layout,
+    deps: BTreeMap<LibId, &'a CompiledLib>,
+    opts: &'a CompileOpts,
+    stubs: BTreeSet<u16>,
+    relocs: Vec<Reloc>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(layout: &'a Layout, deps: &[&'a CompiledLib], opts: &'a CompileOpts) -> Self {
+        let deps = deps.iter().map(|lib| (lib.id, *lib)).collect();
+        Self { layout, deps, opts, stubs: none!(), relocs: vec![] }
+    }
+
+    /// Resolves the references of all the instructions which are not stripped, collecting the
+    /// errors in the order of the instructions (one per instruction).
+    fn resolve_code<Isa>(
+        &mut self,
+        code: &mut [Isa],
+        symbols: &[Option<&str>],
+        mut progress: impl FnMut(usize),
+    ) -> Vec<RefError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let mut errors = vec![];
+        for (no, instr) in code.iter_mut().enumerate() {
+            if !self.layout.stripped[no] {
+                let cursor = u16::try_from(self.layout.offsets[no]).unwrap_or(u16::MAX);
+                let original = instr.clone();
+                let symbol = symbols.get(no).copied().flatten();
+                if let Err(failure) = self.resolve(instr, cursor, symbol) {
+                    errors.push(RefError { instr: original, no, cursor, failure });
+                }
+            }
+            progress(no + 1);
+        }
+        errors
+    }
+
+    fn resolve<Isa>(
+        &mut self,
+        instr: &mut Isa,
+        cursor: u16,
+        symbol: Option<&str>,
+    ) -> Result<(), RefFailure>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let Self { layout, deps, opts, stubs, relocs } = self;
+        let count = layout.routines.len();
+        let relative = instr.is_goto_relative();
+        let mut resolve = |goto_pos: &mut u16| {
+            let resolved = match opts.goto_mode {
+                // Stubs can't be reached by relative gotos, since they don't have an offset
+                GotoMode::Routine
+                    if opts.allow_dangling && !relative && *goto_pos as usize >= count =>
+                {
+                    stubs.insert(*goto_pos);
+                    Some(STUB_OFFSET)
+                }
+                GotoMode::Routine => layout
+                    .routines
+                    .get(*goto_pos as usize)
+                    .map(|pos| *pos as u16),
+                GotoMode::Offset => layout
+                    .boundaries
+                    .get(&(*goto_pos as usize))
+                    .map(|pos| *pos as u16),
+            };
+            *goto_pos = resolved.ok_or(RefFailure::Local(*goto_pos))?;
+            Ok(())
+        };
+        instr.local_goto_pos().map_or(Ok(()), &mut resolve)?;
+        instr.local_goto_table().iter_mut().try_for_each(resolve)?;
+        if let Some(goto_pos) = instr.local_goto_pos().filter(|_| relative) {
+            let Ok(delta) = i16::try_from(*goto_pos as i32 - cursor as i32) else {
+                return Err(RefFailure::Relative(*goto_pos));
+            };
+            *goto_pos = delta as u16;
+        }
+        if let Some(symbol) = symbol {
+            // Instructions may report external reference without exposing a patchable call site
+            let Some(remote_pos) = instr.remote_goto_pos() else {
+                return Err(RefFailure::NotCall(symbol.to_string()));
+            };
+            *remote_pos = Site::new(LibId::RELOC, STUB_OFFSET);
+            relocs.push(Reloc { site_offset: cursor, symbol: symbol.to_string() });
+        } else if let Some(remote_pos) = instr.remote_goto_pos() {
+            let (lib_id, routine) = (remote_pos.prog_id, remote_pos.offset);
+            let lib = deps.get(&lib_id).ok_or(RefFailure::Lib(lib_id))?;
+            let site = lib
+                .try_routine(routine)
+                .ok_or(RefFailure::Remote(lib_id, routine))?;
+            if !lib.is_exported(routine) {
+                return Err(RefFailure::Private(lib_id, routine));
+            }
+            remote_pos.offset = site.offset;
+        }
+        Ok(())
+    }
+}
+
+/// Computes routines which are not reachable from the entry routine, using the local goto
+/// positions, which must not be resolved into the code offsets yet and are mapped into the
+/// routine numbers with `goto_routine`.
+fn unreachable_routines<Isa>(
+    code: &mut [Isa],
+    count: usize,
+    entry: u16,
+    goto_routine: impl Fn(u16) -> Option<u16>,
+) -> Vec<u16>
+where
+    Isa: Instruction<LibId>,
+{
+    let mut edges = vec![BTreeSet::<u16>::new(); count];
+    let mut routine = None::<usize>;
+    let mut falls_through = false;
+    for instr in code {
+        if instr.is_goto_target() {
+            let next = routine.map_or(0, |no| no + 1);
+            if let Some(prev) = routine.filter(|_| falls_through) {
+                edges[prev].insert(next as u16);
+            }
+            routine = Some(next);
+        }
+        falls_through = !instr.is_terminal();
+        let Some(no) = routine else {
+            continue;
+        };
+        if let Some(pos) = instr.local_goto_pos() {
+            edges[no].extend(goto_routine(*pos));
+        }
+        for pos in instr.local_goto_table() {
+            edges[no].extend(goto_routine(*pos));
+        }
+    }
+
+    let mut reached = BTreeSet::new();
+    let mut queue = vec![entry];
+    while let Some(no) = queue.pop() {
+        if no as usize >= count || !reached.insert(no) {
+            continue;
+        }
+        queue.extend(edges[no as usize].iter().copied());
+    }
+    (0..count as u16)
+        .filter(|no| !reached.contains(no))
+        .collect()
+}
+
+/// Computes routines forming trivial infinite loops, as described in
+/// [`CompileReport::infinite_loops`], using the local goto positions, which must not be resolved
+/// into the code offsets yet and are mapped into the routine numbers with `goto_routine`.
+fn infinite_loops<Isa>(
+    code: &mut [Isa],
+    count: usize,
+    goto_routine: impl Fn(u16) -> Option<u16>,
+) -> Vec<u16>
+where
+    Isa: Instruction<LibId>,
+{
+    // The only routine to which the control flow of a routine which can't halt passes
+    let mut next = vec![None::<u16>; count];
+    let mut routine = None::<usize>;
+    // Whether the current routine so far has no effects and its end is not reached yet
+    let mut open = false;
+    for instr in code {
+        if instr.is_goto_target() {
+            let no = routine.map_or(0, |no| no + 1);
+            if let Some(prev) = routine.filter(|_| open) {
+                next[prev] = Some(no as u16);
+            }
+            routine = Some(no);
+            open = true;
+        }
+        let Some(no) = routine.filter(|_| open) else {
+            continue;
+        };
+        if instr.is_nop() || instr.reg_access().pure {
+            continue;
+        }
+        open = false;
+        if instr.is_terminal() && !instr.is_relative_jump() && instr.remote_goto_pos().is_none() {
+            next[no] = instr.local_goto_pos().and_then(|pos| goto_routine(*pos));
+        }
+    }
+
+    let mut looped = BTreeSet::new();
+    let mut visited = vec![false; count];
+    for start in 0..count {
+        let mut path = vec![];
+        let mut no = Some(start);
+        while let Some(cur) = no.filter(|cur| !visited[*cur]) {
+            visited[cur] = true;
+            path.push(cur);
+            no = next[cur].map(usize::from);
+        }
+        if let Some(pos) = no.and_then(|end| path.iter().position(|cur| *cur == end)) {
+            looped.extend(path[pos..].iter().map(|no| *no as u16));
+        }
+    }
+    looped.into_iter().collect()
+}
+
+/// Finds the first instruction which may read a register before it is written, as described in
+/// [`CompileOpts::deny_uninit_reads`], returning its number, its routine and the register name.
+///
+/// Routine 0, the entry routine and the routines which are not reached locally are the roots,
+/// which take the registers declared in `inputs`, even if they are also reached locally. Once
+/// all the routines reachable from the roots are traced, the first of the remaining routines
+/// becomes a root, until all the routines are traced. The local goto positions must not be
+/// resolved into the code offsets yet; they are mapped into the routine numbers with
+/// `goto_routine`.
+fn uninit_read<Isa>(
+    code: &mut [Isa],
+    count: usize,
+    inputs: &BTreeMap<u16, BTreeSet<RegOf<Isa>>>,
+    entry: Option<u16>,
+    goto_routine: impl Fn(u16) -> Option<u16>,
+) -> Option<(usize, u16, String)>
+where
+    Isa: Instruction<LibId>,
+{
+    // Registers which are written on all the paths; `None` stands for all the registers
+    type Defined<Isa> = Option<BTreeSet<RegOf<Isa>>>;
+
+    let mut starts = Vec::with_capacity(count);
+    for (no, instr) in code.iter().enumerate() {
+        if instr.is_goto_target() {
+            starts.push(no);
+        }
+    }
+    let len = code.len();
+    let range = |routine: usize| starts[routine]..starts.get(routine + 1).map_or(len, |no| *no);
+
+    let mut entries = vec![None::<Defined<Isa>>; count];
+    let mut reached = vec![false; count];
+    for (routine, start) in starts.iter().enumerate() {
+        if *start > 0 && !code[start - 1].is_terminal() {
+            reached[routine] = true;
+        }
+    }
+    for instr in code.iter_mut() {
+        let targets = instr.local_goto_table().to_vec();
+        for pos in targets
+            .into_iter()
+            .chain(instr.local_goto_pos().map(|pos| *pos))
+        {
+            if let Some(to) = goto_routine(pos) {
+                reached[to as usize] = true;
+            }
+        }
+    }
+    let mut roots = (0..count)
+        .map(|routine| !reached[routine] || routine == 0 || entry == Some(routine as u16))
+        .collect::<Vec<_>>();
+
+    let meet = |entry: &mut Option<Defined<Isa>>, defined: &Defined<Isa>| -> bool {
+        let met = match (entry.as_ref(), defined) {
+            (None, _) => defined.clone(),
+            (Some(None), _) => defined.clone(),
+            (Some(Some(prev)), None) => Some(prev.clone()),
+            (Some(Some(prev)), Some(defined)) => Some(prev & defined),
+        };
+        let changed = entry.as_ref() != Some(&met);
+        *entry = Some(met);
+        changed
+    };
+
+    // Traces the routine from its entry state, returning the first read of an undefined register
+    let mut first_read = None;
+    let mut trace =
+        |routine: usize, entries: &mut Vec<Option<Defined<Isa>>>, roots: &[bool], report: bool| {
+            let mut next = vec![];
+            let mut defined = entries[routine].clone().flatten();
+            let mut terminated = false;
+            for no in range(routine) {
+                let instr = &mut code[no];
+                let access = instr.reg_access();
+                if let Some(defined) = &defined {
+                    if let Some(reg) = access.reads.iter().find(|reg| !defined.contains(reg)) {
+                        if report && first_read.is_none() {
+                            first_read = Some((no, routine as u16, reg.to_string()));
+                        }
+                    }
+                }
+                if let Some(defined) = &mut defined {
+                    defined.extend(access.writes);
+                }
+                let targets = instr.local_goto_table().to_vec();
+                for pos in targets
+                    .into_iter()
+                    .chain(instr.local_goto_pos().map(|pos| *pos))
+                {
+                    let Some(to) = goto_routine(pos).map(usize::from) else {
+                        continue;
+                    };
+                    if !roots[to] && meet(&mut entries[to], &defined) {
+                        next.push(to);
+                    }
+                }
+                terminated = instr.is_terminal();
+                if instr.is_local_call() || (instr.remote_goto_pos().is_some() && !terminated) {
+                    defined = None;
+                }
+            }
+            if !terminated
+                && routine + 1 < count
+                && !roots[routine + 1]
+                && meet(&mut entries[routine + 1], &defined)
+            {
+                next.push(routine + 1);
+            }
+            next
+        };
+    let mut queue = (0..count)
+        .filter(|routine| roots[*routine])
+        .collect::<Vec<_>>();
+    loop {
+        for routine in queue.iter().copied() {
+            let declared = inputs.get(&(routine as u16)).cloned().unwrap_or_default();
+            entries[routine] = Some(Some(declared));
+        }
+        while let Some(routine) = queue.pop() {
+            queue.extend(trace(routine, &mut entries, &roots, false));
+        }
+        // Routines reachable only from each other, which are not traced from the roots yet
+        let Some(root) = (0..count).find(|routine| entries[*routine].is_none()) else {
+            break;
+        };
+        roots[root] = true;
+        queue.push(root);
+    }
+    for routine in 0..count {
+        trace(routine, &mut entries, &roots, true);
+    }
+    first_read
+}
+
+/// Marks goto targets which are omitted from the bytecode, as described in
+/// [`CompileOpts::strip_markers`].
+fn stripped_markers<Isa>(code: &[Isa], strip: bool) -> Vec<bool>
+where Isa: Instruction<LibId> {
+    if !strip {
+        return vec![false; code.len()];
+    }
+    let Some(spans) = relative_spans(code) else {
+        return vec![false; code.len()];
+    };
+    let mut cursor = 0i32;
+    code.iter()
+        .enumerate()
+        .map(|(no, instr)| {
+            let offset = cursor;
+            cursor += instr.code_byte_len() as i32;
+            instr.is_goto_target()
+                && instr.is_nop()
+                && no + 1 < code.len()
+                && !spans.iter().any(|span| span.contains(&offset))
+        })
+        .collect()
+}
+
+/// Finds routines which can be dropped by [`CompileOpts::dedup_routines`], mapping them to the
+/// first of the routines with the identical bytecode.
+fn duplicate_routines<Isa>(
+    code: &[Isa],
+    symbols: &[Option<&str>],
+    lib: &CompiledLib,
+) -> BTreeMap<u16, u16>
+where
+    Isa: Instruction<LibId>,
+{
+    let unterminated = unterminated_routines(code);
+    let mut droppable = vec![true; lib.routines.len()];
+    let mut routine = None::<usize>;
+    for (no, instr) in code.iter().enumerate() {
+        if instr.is_goto_target() {
+            routine = Some(routine.map_or(0, |no| no + 1));
+        }
+        let Some(routine) = routine else { continue };
+        // Relative jumps and gotos from the identical bytecode at a different offset reach
+        // different targets
+        if instr.is_relative_jump()
+            || instr.is_goto_relative()
+            || symbols.get(no).copied().flatten().is_some()
+        {
+            droppable[routine] = false;
+        }
+    }
+    for no in &unterminated {
+        droppable[*no as usize] = false;
+        if let Some(next) = droppable.get_mut(*no as usize + 1) {
+            *next = false;
+        }
+    }
+
+    let mut first = BTreeMap::<&[u8], u16>::new();
+    let mut duplicates = BTreeMap::new();
+    for no in 0..lib.routines.len() as u16 {
+        let range = lib.routine_range(no);
+        let bytes = &lib.lib.code.as_slice()[range.start as usize..range.end as usize];
+        let copy = *first.entry(bytes).or_insert(no);
+        if copy != no && droppable[no as usize] {
+            duplicates.insert(no, copy);
+        }
+    }
+    duplicates
+}
+
+fn unterminated_routines<Isa>(code: &[Isa]) -> Vec<u16>
+where Isa: Instruction<LibId> {
+    let mut unterminated = vec![];
+    let mut routine = None::<u16>;
+    let mut terminated = true;
+    for instr in code {
+        if instr.is_goto_target() {
+            if let Some(prev) = routine.filter(|_| !terminated) {
+                unterminated.push(prev);
+            }
+            routine = Some(routine.map_or(0, |no| no + 1));
+        }
+        terminated = instr.is_terminal();
+    }
+    if let Some(last) = routine.filter(|_| !terminated) {
+        unterminated.push(last);
+    }
+    unterminated
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{CtrlInstr, Instr};
+    use crate::regs::Status;
+    use crate::{aluasm, Site, Vm};
+
+    #[test]
+    fn named_routines() {
+        const MAIN: u16 = 0;
+        const HASH: u16 = 1;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            call        :HASH   ;
+            stop                ;
+            .routine    :HASH   ;
+            not         CO      ;
+            ret                 ;
+        };
+        let labels = [Some("main"), None, None, Some("hash")];
+        let lib = CompiledLib::compile_labeled(code, &labels, &[]).unwrap();
+        assert_eq!(lib.routine_by_name("main"), Some(lib.routine(MAIN)));
+        assert_eq!(lib.routine_by_name("hash"), Some(lib.routine(HASH)));
+        assert_eq!(lib.routine_by_name("absent"), None);
+    }
+
+    #[test]
+    fn disassemble_roundtrip() {
+        const MAIN: u16 = 0;
+        const LOOP: u16 = 1;
+        let dep = CompiledLib::compile(
+            aluasm! {
+                .routine    :MAIN   ;
+                ret                 ;
+            },
+            &[],
+        )
+        .unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        let code = aluasm! {
+            .routine    :MAIN       ;
+            call        :LOOP       ;
+            call        dep_id, :MAIN ;
+            stop                    ;
+            .routine    :LOOP       ;
+            jif         CO, :LOOP   ;
+            ret                     ;
+        };
+        let lib = CompiledLib::compile(code.clone(), &[&dep]).unwrap();
+        assert!(dep.is_self_contained());
+        assert!(!lib.is_self_contained());
+        let disassembled = lib.disassemble::<Instr<LibId>>(&[&dep]).unwrap();
+        assert_eq!(disassembled, code);
+        assert_eq!(CompiledLib::compile(disassembled, &[&dep]).unwrap(), lib);
+    }
+
+    #[test]
+    fn routine_range() {
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            nop                 ;
+            nop                 ;
+            jmp         +2      ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(CompiledLib::compile_from_iter(code, &[]).unwrap(), lib);
+        assert_eq!(lib.routine_range(0), 0..2);
+        assert_eq!(lib.routine_range(1), 2..3);
+        assert_eq!(lib.routine_range(2), 3..7);
+        assert_eq!(lib.routine_index_for_offset(0), Some(0));
+        assert_eq!(lib.routine_index_for_offset(1), Some(0));
+        assert_eq!(lib.routine_index_for_offset(2), Some(1));
+        assert_eq!(lib.routine_index_for_offset(6), Some(2));
+        assert_eq!(lib.routine_index_for_offset(7), None);
+    }
+
+    #[test]
+    fn routine_id() {
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            nop                 ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            chk         CO      ;
+            nop                 ;
+            stop                ;
+        };
+        let updated = CompiledLib::compile(code, &[]).unwrap();
+        assert_eq!(lib.routine_id(0), RoutineId::from_code(&lib.lib.code[..2]));
+        assert_ne!(lib.routine_id(0), updated.routine_id(0));
+        assert_eq!(lib.routine_id(1), updated.routine_id(1));
+        assert_ne!(lib.routine_id(0), lib.routine_id(1));
+    }
+
+    #[test]
+    fn from_parts() {
+        use amplify::confinement::SmallBlob;
+
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let from_parts =
+            |routines| CompiledLib::from_parts::<Instr<LibId>>(lib.lib.clone(), routines);
+        let parts = from_parts(lib.routines.clone()).unwrap();
+        assert_eq!(parts.routine_table(), lib.routine_table());
+        assert_eq!(parts.routine(FUNC), lib.routine(FUNC));
+        assert_eq!(parts.id, lib.id);
+
+        assert_eq!(from_parts(vec![0, 2]).unwrap_err(), FromPartsError::Misaligned(1, 2));
+        assert_eq!(from_parts(vec![0, 7]).unwrap_err(), FromPartsError::OutOfCode(1, 7));
+        assert_eq!(from_parts(vec![4, 0]).unwrap_err(), FromPartsError::Unordered(1));
+        let mut code = lib.lib.clone();
+        code.code = SmallBlob::from_checked(lib.lib.code[..2].to_vec());
+        assert_eq!(
+            CompiledLib::from_parts::<Instr<LibId>>(code, vec![0]).unwrap_err(),
+            FromPartsError::Verify(VerifyError::Decode(1))
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde_load() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let mut lib = CompiledLib::compile(code, &[])
+            .unwrap()
+            .with_meta("name", "test");
+        lib.entry = Some(0);
+        lib.arities.insert(FUNC, Arity { args: 1, returns: 0 });
+        let load = |lib: &CompiledLib| {
+            serde_json::from_value::<CompiledLib>(serde_json::to_value(lib).unwrap())
+                .map_err(|err| err.to_string())
+        };
+        assert_eq!(load(&lib).unwrap(), lib);
+
+        let check = |patch: fn(&mut CompiledLib), err: LibLoadError| {
+            let mut invalid = lib.clone();
+            patch(&mut invalid);
+            assert_eq!(load(&invalid).unwrap_err(), err.to_string());
+        };
+        let expected = lib.id;
+        let found = LibId::from([0xA5; 32]);
+        check(|lib| lib.id = LibId::from([0xA5; 32]), LibIdMismatch { expected, found }.into());
+        check(
+            |lib| lib.version = EncodingVersion::with(0xFF),
+            LibLoadError::UnsupportedVersion(EncodingVersion::with(0xFF)),
+        );
+        check(|lib| lib.routines = vec![0, 7], FromPartsError::OutOfCode(1, 7).into());
+        check(|lib| lib.routines = vec![4, 0], FromPartsError::Unordered(1).into());
+        check(|lib| lib.entry = Some(2), LibLoadError::UnknownRoutine("entry point", 2));
+        check(|lib| lib.exports = Some(bset![3]), LibLoadError::UnknownRoutine("export", 3));
+        check(
+            |lib| lib.arities = bmap! { 2 => Arity { args: 0, returns: 0 } },
+            LibLoadError::UnknownRoutine("arity", 2),
+        );
+        check(
+            |lib| lib.relocs = vec![Reloc { site_offset: 9, symbol: s!("f") }],
+            LibLoadError::RelocOutOfCode(9),
+        );
+    }
+
+    #[test]
+    fn try_routine() {
+        let code = aluasm! {
+            nop                 ;
+            stop                ;
+        };
+        let dep = CompiledLib::compile(code, &[]).unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        assert_eq!(dep.try_routine(0), Some(LibSite::new(dep_id, 0)));
+        assert_eq!(dep.try_routine(1), None);
+
+        let code: Vec<Instr<LibId>> = vec![CtrlInstr::Call { site: Site::new(dep_id, 1) }.into()];
+        let err = CompiledLib::compile(code, &[&dep]).unwrap_err();
+        assert!(matches!(err, CompilerError::InvalidRemoteRef(_, 0, 0, id, 1) if id == dep_id));
+    }
+
+    #[test]
+    fn site_is_valid_entry() {
+        let code = aluasm! {
+            nop                 ;
+            stop                ;
+            nop                 ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib_id = lib.as_lib().lib_id();
+        assert!(lib.site_is_valid_entry(LibSite::new(lib_id, 0)));
+        assert!(!lib.site_is_valid_entry(LibSite::new(lib_id, 1)));
+        assert!(lib.site_is_valid_entry(LibSite::new(lib_id, 2)));
+        assert!(!lib.site_is_valid_entry(LibSite::new(lib_id, 4)));
+        assert!(!lib.site_is_valid_entry(LibSite::new(LibId::RELOC, 0)));
+    }
+
+    #[test]
+    fn lib_conversions() {
+        let lib = CompiledLib::compile(aluasm! { nop; stop; }, &[]).unwrap();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.exec(lib.routine(0), &(), |_| Some(&lib)), Status::Ok);
+        assert_eq!(AsRef::<Lib>::as_ref(&lib), lib.as_lib());
+        assert_eq!(lib.lib_id(), lib.as_lib().lib_id());
+        assert_eq!(Lib::from(lib.clone()), lib.into_lib());
+    }
+
+    #[test]
+    fn recompile_routine() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 1;
+        const END: u16 = 2;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            call        :FUNC   ;
+            jmp         :END    ;
+            .routine    :FUNC   ;
+            ret                 ;
+            .routine    :END    ;
+            stop                ;
+        };
+        let mut lib =
+            CompiledLib::compile_labeled(code, &[Some("main"), None, None, Some("func")], &[])
+                .unwrap();
+        assert_eq!(lib.routine_table(), &[0, 7, 9]);
+
+        let new_code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            fail        CK      ;
+            ret                 ;
+        };
+        lib.recompile_routine(FUNC, &new_code, &[]).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 7, 11]);
+        assert_eq!(lib.routine_by_name("func"), Some(lib.routine(FUNC)));
+
+        let expected = aluasm! {
+            .routine    :MAIN   ;
+            call        :FUNC   ;
+            jmp         :END    ;
+            .routine    :FUNC   ;
+            not         CO      ;
+            fail        CK      ;
+            ret                 ;
+            .routine    :END    ;
+            stop                ;
+        };
+        assert_eq!(
+            lib,
+            CompiledLib::compile_labeled(expected, &[Some("main"), None, None, Some("func")], &[])
+                .unwrap()
+        );
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.exec(lib.routine(MAIN), &(), |_| Some(lib.as_lib())), Status::Fail);
+
+        assert!(matches!(
+            lib.recompile_routine(3, &new_code, &[]),
+            Err(CompilerError::UnknownRoutine(3))
+        ));
+        assert!(matches!(
+            lib.recompile_routine(END, &new_code[1..], &[]),
+            Err(CompilerError::RoutineMismatch(END))
+        ));
+    }
+
+    #[test]
+    fn recompile_relocatable() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 1;
+        const END: u16 = 2;
+        let dep = CompiledLib::compile(aluasm! { nop; ret; }, &[]).unwrap();
+        let dep_id = dep.lib_id();
+        let reloc_id = LibId::RELOC;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            call        :FUNC   ;
+            jmp         :END    ;
+            .routine    :FUNC   ;
+            call        dep_id, :MAIN ;
+            ret                 ;
+            .routine    :END    ;
+            call        reloc_id, :MAIN ;
+            stop                ;
+        };
+        let symbols = [None, None, None, None, None, None, None, Some("ext"), None];
+        let unary = Arity { args: 1, returns: 1 };
+        let mut lib = CompiledLib::compile_relocatable(code, &[], &symbols, &[&dep], &default!())
+            .unwrap()
+            .with_call_arity(dep.routine(MAIN), unary);
+        let end = lib.routine(END).offset;
+        assert_eq!(lib.relocs(), &[Reloc { site_offset: end + 1, symbol: s!("ext") }]);
+
+        lib.recompile_routine(FUNC, &aluasm! { nop; ret; }, &[&dep])
+            .unwrap();
+        let end = lib.routine(END).offset;
+        assert_eq!(lib.relocs(), &[Reloc { site_offset: end + 1, symbol: s!("ext") }]);
+        assert_eq!(lib.call_arity(dep.routine(MAIN)), None);
+
+        let resolved = bmap! { s!("ext") => dep.routine(MAIN) };
+        lib.apply_relocs::<Instr<LibId>>(&resolved, &[&dep])
+            .unwrap();
+        assert!(lib.relocs().is_empty());
+        lib.link::<Instr<LibId>>(&[&dep]).unwrap();
+    }
+
+    #[test]
+    fn compile_linked() {
+        let mut first = aluasm! {
+            nop                 ;
+            jif     CO, +3      ;
+            jmp     1           ;
+            nop                 ;
+            stop                ;
+        };
+        let mut second = aluasm! {
+            nop                 ;
+            call    0           ;
+            stop                ;
+        };
+        let (lib, ranges) =
+            CompiledLib::compile_linked(&mut [&mut first, &mut second], &[]).unwrap();
+        assert_eq!(ranges, vec![0..2, 2..3]);
+        assert_eq!(lib.routine_table(), &[0, 6, 8]);
+
+        let code = lib.disassemble::<Instr<LibId>>(&[]).unwrap();
+        assert_eq!(code[2], Instr::Ctrl(CtrlInstr::Jmp { pos: 1 }));
+        assert_eq!(code[6], Instr::Ctrl(CtrlInstr::Fn { pos: 2 }));
+        assert_eq!(second[1], Instr::Ctrl(CtrlInstr::Fn { pos: 2 }));
+    }
+
+    #[test]
+    fn compile_linked_invalid_ref() {
+        let mut first = aluasm! {
+            nop                 ;
+            stop                ;
+        };
+        let mut second = aluasm! {
+            nop                 ;
+            jmp     1           ;
+        };
+        assert!(matches!(
+            CompiledLib::compile_linked(&mut [&mut first, &mut second], &[]),
+            Err(CompilerError::InvalidChunkRef(_, 1, 1, 1))
+        ));
+    }
+
+    #[test]
+    fn source_map() {
+        let code = aluasm! {
+            nop                 ;
+            chk     CO          ;
+            jif     CO, +2      ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        assert_eq!(lib.source_index_at(0), Some(0));
+        assert_eq!(lib.source_index_at(1), Some(1));
+        assert_eq!(lib.source_index_at(2), Some(2));
+        assert_eq!(lib.source_index_at(3), Some(2));
+        assert_eq!(lib.source_index_at(4), Some(3));
+        assert_eq!(lib.source_index_at(5), None);
+    }
+
+    #[test]
+    fn encoding_version() {
+        let code = aluasm! {
+            nop                 ;
+            stop                ;
+        };
+        let mut lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(lib.version(), EncodingVersion::CURRENT);
+        assert!(lib.verify::<Instr<LibId>>().is_ok());
+
+        let future = EncodingVersion::with(EncodingVersion::CURRENT.to_u8() + 1);
+        let opts = CompileOpts { version: future, ..default!() };
+        assert!(matches!(
+            CompiledLib::compile_with(code, &[], &[], &opts),
+            Err(CompilerError::UnsupportedVersion(v)) if v == future
+        ));
+
+        lib.version = future;
+        assert_eq!(
+            lib.disassemble::<Instr<LibId>>(&[]),
+            Err(DisassembleError::UnsupportedVersion(future))
+        );
+        assert_eq!(
+            lib.verify::<Instr<LibId>>(),
+            Err(crate::VerifyError::UnsupportedVersion(future))
+        );
+    }
+
+    #[test]
+    fn encoding_version_instr() {
+        let code: Vec<Instr<LibId>> =
+            vec![CtrlInstr::Nop.into(), CtrlInstr::Stop.into(), CtrlInstr::Trap { code: 1 }.into()];
+        let opts = CompileOpts { version: EncodingVersion::V0, ..default!() };
+        assert_eq!(
+            CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap_err(),
+            CompilerError::UnsupportedInstr(
+                CtrlInstr::Trap { code: 1 }.into(),
+                2,
+                EncodingVersion::V1,
+                EncodingVersion::V0
+            )
+        );
+
+        let mut lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(lib.version(), EncodingVersion::V1);
+        assert_eq!(lib.disassemble::<Instr<LibId>>(&[]).unwrap(), code);
+        lib.version = EncodingVersion::V0;
+        assert_eq!(
+            lib.disassemble::<Instr<LibId>>(&[]),
+            Err(DisassembleError::UnsupportedInstr(2, EncodingVersion::V0))
+        );
+        assert_eq!(
+            lib.verify::<Instr<LibId>>(),
+            Err(crate::VerifyError::UnsupportedInstr(2, EncodingVersion::V0))
+        );
+
+        let code: Vec<Instr<LibId>> =
+            vec![CtrlInstr::Nop.into(), CtrlInstr::JmpRel { pos: 0xFFFF }.into()];
+        assert!(matches!(
+            CompiledLib::compile_with(code, &[], &[], &opts),
+            Err(CompilerError::UnsupportedInstr(_, 1, EncodingVersion::V1, EncodingVersion::V0))
+        ));
+    }
+
+    #[test]
+    fn meta() {
+        let code = aluasm! {
+            nop                 ;
+            stop                ;
+        };
+        let plain = CompiledLib::compile(code.clone(), &[]).unwrap();
+        let mut lib = CompiledLib::compile(code.clone(), &[])
+            .unwrap()
+            .with_meta("author", "Alice")
+            .with_meta("version", [1u8, 0]);
+        assert_eq!(lib.meta_get("author"), Some(&b"Alice"[..]));
+        assert_eq!(lib.meta_get("version"), Some(&[1u8, 0][..]));
+        assert_eq!(lib.meta_get("source"), None);
+        assert_eq!(lib.meta().len(), 2);
+
+        assert_eq!(lib.as_lib().lib_id(), plain.as_lib().lib_id());
+        assert_ne!(lib.meta_commitment(), plain.meta_commitment());
+        let commitment = lib.meta_commitment();
+        assert_ne!(lib.clone().with_meta("author", "Bob").meta_commitment(), commitment);
+
+        lib.recompile_routine(0, &code, &[]).unwrap();
+        assert_eq!(lib.meta_get("author"), Some(&b"Alice"[..]));
+        assert_eq!(lib.meta_commitment(), commitment);
+    }
+
+    #[test]
+    fn resolved_code() {
+        const FUNC: u16 = 1;
+        let mut code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let (owned, resolved) = CompiledLib::compile_owned(code.clone(), &[]).unwrap();
+        let lib = CompiledLib::compile(&mut code, &[]).unwrap();
+        assert_eq!(lib.resolved_code::<Instr<LibId>>().unwrap(), code);
+        assert_eq!(code[1], Instr::from(CtrlInstr::Fn { pos: 5 }));
+        assert_eq!(owned, lib);
+        assert_eq!(resolved, code);
+
+        let iterated = lib
+            .iter_instructions::<Instr<LibId>>()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let offsets = [0, 1, 4, 5, 6];
+        assert_eq!(iterated, offsets.into_iter().zip(code).collect::<Vec<_>>());
+
+        let mut lib = lib;
+        while lib.lib.code.len() > 3 {
+            lib.lib.code.pop();
+        }
+        let mut iter = lib.iter_instructions::<Instr<LibId>>();
+        assert!(matches!(iter.next(), Some(Ok((0, _)))));
+        assert_eq!(iter.next(), Some(Err(DecodeError::Decode(1))));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[test]
+    fn routine_validation() {
+        let code = aluasm! {
+            nop                 ;
+            nop                 ;
+            stop                ;
+        };
+        CompiledLib::compile(code.clone(), &[]).unwrap();
+
+        let opts = CompileOpts { deny_empty_routines: true, ..default!() };
+        assert!(matches!(
+            CompiledLib::compile_with(code.clone(), &[], &[], &opts),
+            Err(CompilerError::EmptyRoutine(1, 0))
+        ));
+
+        let opts = CompileOpts { expected_routines: Some(2), ..default!() };
+        CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap();
+        let opts = CompileOpts { expected_routines: Some(1), ..default!() };
+        let err = CompiledLib::compile_with(code, &[], &[], &opts).unwrap_err();
+        assert_eq!(err, CompilerError::RoutineCountMismatch { expected: 1, found: 2 });
+        assert_eq!(err.to_string(), "code defines 2 routines, while 1 routines were expected.");
+    }
+
+    #[test]
+    fn large_routines() {
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            not         CO      ;
+            stop                ;
+            nop                 ;
+            stop                ;
+        };
+        let (_, report) =
+            CompiledLib::compile_reported(code.clone(), &[], &[], &default!()).unwrap();
+        assert!(report.large_routines().is_empty());
+
+        let opts = CompileOpts { max_routine_bytes: Some(2), ..default!() };
+        let (_, report) = CompiledLib::compile_reported(code.clone(), &[], &[], &opts).unwrap();
+        assert_eq!(report.large_routines(), &[0]);
+
+        let opts = CompileOpts { deny_large_routines: true, ..opts };
+        let err = CompiledLib::compile_reported(code, &[], &[], &opts).unwrap_err();
+        assert_eq!(err, CompilerError::RoutineTooLarge(0, 4, 2));
+    }
+
+    #[test]
+    fn exports() {
+        const API: u16 = 0;
+        const HELPER: u16 = 1;
+        let code = aluasm! {
+            .routine    :API    ;
+            call        :HELPER ;
+            ret                 ;
+            .routine    :HELPER ;
+            ret                 ;
+        };
+        let labels = [Some("api"), None, None, Some("helper")];
+        let dep = CompiledLib::compile_with(code.clone(), &labels, &[], &default!()).unwrap();
+        assert!(dep.is_exported(HELPER));
+        assert_eq!(dep.exports().count(), 2);
+
+        let dep = CompiledLib::compile_exported(code.clone(), &labels, &["api"], &[], &default!())
+            .unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        assert!(dep.is_exported(API));
+        assert!(!dep.is_exported(HELPER));
+        assert_eq!(dep.exports().collect::<Vec<_>>(), vec![("api", dep.routine(API))]);
+        assert_eq!(dep.routine_by_name("api"), Some(dep.routine(API)));
+        assert_eq!(dep.routine_by_name("helper"), None);
+        let by_no =
+            CompiledLib::compile_exported(code.clone(), &labels, &[API], &[], &default!()).unwrap();
+        assert_eq!(by_no, dep);
+        let err =
+            CompiledLib::compile_exported(code.clone(), &labels, &["absent"], &[], &default!())
+                .unwrap_err();
+        assert_eq!(err, CompilerError::UnknownExport(s!("absent")));
+        let err =
+            CompiledLib::compile_exported(code, &labels, &[2u16], &[], &default!()).unwrap_err();
+        assert_eq!(err, CompilerError::UnknownRoutine(2));
+
+        let code = aluasm! { nop; call dep_id, :API; stop; };
+        assert!(CompiledLib::compile(code, &[&dep]).is_ok());
+        let code = aluasm! { nop; call dep_id, :HELPER; stop; };
+        let plan = CompiledLib::plan(&code, &[], &[], &[&dep], &default!()).unwrap();
+        assert_eq!(plan.unresolved(), &[UnresolvedRef::Private(1, dep_id, HELPER)]);
+        let err = CompiledLib::compile(code, &[&dep]).unwrap_err();
+        assert!(
+            matches!(err, CompilerError::PrivateRemoteRef(_, 1, 1, id, HELPER) if id == dep_id)
+        );
+    }
+
+    #[test]
+    fn plan() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 1;
+        const MISSING: u16 = 2;
+        let dep = CompiledLib::compile(
+            aluasm! {
+                .routine    :MAIN   ;
+                ret                 ;
+            },
+            &[],
+        )
+        .unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        let other_id = LibId::from([0xA5; 32]);
+        let code = aluasm! {
+            nop                     ;
+            call        :FUNC       ;
+            call        dep_id, :MAIN ;
+            stop                    ;
+            .routine    :FUNC       ;
+            jif         CO, :MISSING ;
+            call        dep_id, :FUNC ;
+            call        other_id, :MAIN ;
+            ret                     ;
+        };
+        let plan = CompiledLib::plan(&code, &[], &[], &[&dep], &default!()).unwrap();
+        assert_eq!(plan.routines(), &[0, 9]);
+        assert_eq!(plan.code_len(), 22);
+        assert!(plan.fits_code_segment());
+        assert_eq!(plan.unresolved(), &[
+            UnresolvedRef::Local(5, MISSING),
+            UnresolvedRef::Remote(6, dep_id, FUNC),
+            UnresolvedRef::Lib(7, other_id),
+        ]);
+
+        let plan = CompiledLib::plan(&code[..4], &[], &[], &[&dep], &default!()).unwrap();
+        assert_eq!(plan.unresolved(), &[UnresolvedRef::Local(1, FUNC)]);
+
+        let code = [&code[..5], &code[8..]].concat();
+        let plan = CompiledLib::plan(&code, &[], &[], &[&dep], &default!()).unwrap();
+        assert!(plan.unresolved().is_empty());
+        let lib = CompiledLib::compile(code, &[&dep]).unwrap();
+        let routines = plan
+            .routines()
+            .iter()
+            .map(|pos| *pos as u16)
+            .collect::<Vec<_>>();
+        assert_eq!(routines, lib.routines);
+        assert_eq!(plan.code_len(), lib.as_lib().code.len());
+
+        // Planning follows the compilation options
+        let code = aluasm! {
+            nop                     ;
+            call        :FUNC       ;
+            call        other_id, :MAIN ;
+            stop                    ;
+        };
+        let symbols = [None, None, Some("ext"), None];
+        let opts = CompileOpts { allow_dangling: true, strip_markers: true, ..default!() };
+        let plan = CompiledLib::plan(&code, &[], &symbols, &[], &opts).unwrap();
+        assert!(plan.unresolved().is_empty());
+        let lib =
+            CompiledLib::compile_relocatable(code.clone(), &[], &symbols, &[], &opts).unwrap();
+        assert_eq!(plan.routines(), &[0]);
+        assert_eq!(plan.code_len(), lib.as_lib().code.len());
+        assert_eq!(
+            CompiledLib::plan(&code, &[None, Some("func")], &symbols, &[], &opts),
+            Err(CompilerError::LabelNotTarget(code[1], 1, s!("func")))
+        );
+
+        let code = vec![
+            Instr::<LibId>::from(CtrlInstr::Nop),
+            CtrlInstr::Fn { pos: 2 }.into(),
+            CtrlInstr::Stop.into(),
+        ];
+        let opts = CompileOpts { goto_mode: GotoMode::Offset, ..default!() };
+        let plan = CompiledLib::plan(&code, &[], &[], &[], &opts).unwrap();
+        assert_eq!(plan.unresolved(), &[UnresolvedRef::Offset(1, 2)]);
+    }
+
+    #[test]
+    fn compile_progress() {
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            stop                ;
+        };
+        let mut reported = vec![];
+        let (lib, _) = CompiledLib::compile_with_progress(
+            code.clone(),
+            &[],
+            &[],
+            &default!(),
+            |done, total| reported.push((done, total)),
+        )
+        .unwrap();
+        assert_eq!(lib, CompiledLib::compile(code, &[]).unwrap());
+        assert_eq!(reported, (1..=6).map(|done| (done, 6)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn data_segment() {
+        let code = aluasm! {
+            nop                 ;
+            stop                ;
+        };
+        let mut lib = CompiledLib::compile(code, &[]).unwrap();
+        assert!(lib.data_segment().is_empty());
+        assert_eq!(lib.data_slice(0, 0), Some(&[][..]));
+        assert_eq!(lib.data_slice(0, 1), None);
+
+        lib.lib.data = small_blob![1, 2, 3, 4];
+        assert_eq!(lib.data_segment(), &[1, 2, 3, 4]);
+        assert_eq!(lib.data_slice(1, 2), Some(&[2, 3][..]));
+        assert_eq!(lib.data_slice(2, 2), Some(&[3, 4][..]));
+        assert_eq!(lib.data_slice(2, 3), None);
+        assert_eq!(lib.data_slice(u16::MAX, u16::MAX), None);
+    }
+
+    #[test]
+    fn routine_table() {
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            nop                 ;
+            nop                 ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let id = lib.as_lib().lib_id();
+        assert_eq!(lib.routine_table(), &[0, 2, 3]);
+        assert_eq!(lib.routine_sites().collect::<Vec<_>>(), vec![
+            (0, LibSite::new(id, 0)),
+            (1, LibSite::new(id, 2)),
+            (2, LibSite::new(id, 3))
+        ]);
+    }
+
+    #[test]
+    fn compile_collect_errors() {
+        const FUNC: u16 = 1;
+        let dep = CompiledLib::compile(aluasm! { nop; ret; }, &[]).unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        let other_id = LibId::from([0xA5; 32]);
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            jmp         5       ;
+            call    dep_id, 1   ;
+            call  other_id, 0   ;
+            jif         CO, 0   ;
+            stop                ;
+        };
+        let errors = CompiledLib::compile_collect_errors(code, &[&dep]).unwrap_err();
+        assert_eq!(errors.len(), 4);
+        assert!(matches!(errors[0], CompilerError::InvalidRef(_, 1, 1, "nop", _)));
+        assert!(matches!(errors[1], CompilerError::GotoOutOfRange(_, 2, 4, 5, 1)));
+        assert!(
+            matches!(errors[2], CompilerError::InvalidRemoteRef(_, 3, 7, id, 1) if id == dep_id)
+        );
+        assert!(matches!(errors[3], CompilerError::InvalidLib(_, 4, 11, id) if id == other_id));
+
+        let code = aluasm! {
+            nop                 ;
+            call    dep_id, 0   ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile_collect_errors(code.clone(), &[&dep]).unwrap();
+        assert_eq!(lib, CompiledLib::compile(code, &[&dep]).unwrap());
+    }
+
+    #[test]
+    fn uninit_reads() {
+        use RegInstr::*;
+
+        use crate::testing::isa::{RegInstr, XReg};
+
+        let opts = CompileOpts { deny_uninit_reads: true, ..default!() };
+        // Routine 2 is reached from routines 0 and 1, and only the latter writes `A`
+        let mut code = vec![
+            Nop,
+            Jmp(2),
+            Nop,
+            Put(XReg::A, 1),
+            Jmp(2),
+            Nop,
+            Mov(XReg::B, XReg::A),
+            Mov(XReg::A, XReg::B),
+        ];
+        assert_eq!(
+            CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap_err(),
+            CompilerError::UninitRead(Mov(XReg::B, XReg::A), 6, 2, s!("A"))
+        );
+        assert!(CompiledLib::compile_with(code.clone(), &[], &[], &default!()).is_ok());
+
+        // Registers read by the routine 0 must be declared as its inputs
+        code[1] = Mov(XReg::A, XReg::B);
+        code.insert(2, Jmp(2));
+        assert_eq!(
+            CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap_err(),
+            CompilerError::UninitRead(Mov(XReg::A, XReg::B), 1, 0, s!("B"))
+        );
+        let inputs = bmap! { 0 => CallConv::new([XReg::B], []) };
+        assert_eq!(CompiledLib::check_uninit_reads(&code, &inputs, &opts), Ok(()));
+        assert_eq!(
+            CompiledLib::check_uninit_reads(&code, &none!(), &opts),
+            Err(CompilerError::UninitRead(Mov(XReg::A, XReg::B), 1, 0, s!("B")))
+        );
+
+        // Routines reached only from each other are checked as well
+        let code = vec![Nop, Jmp(0), Nop, Mov(XReg::B, XReg::A), Jmp(2), Nop, Jmp(1)];
+        assert_eq!(
+            CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap_err(),
+            CompilerError::UninitRead(Mov(XReg::B, XReg::A), 3, 1, s!("A"))
+        );
+        let inputs = bmap! { 1 => CallConv::new([XReg::A], []) };
+        assert_eq!(CompiledLib::check_uninit_reads(&code, &inputs, &opts), Ok(()));
+
+        // Registers written by a routine are defined in the routine it falls through to
+        let code = vec![Nop, Jmp(1), Nop, Put(XReg::A, 1), Nop, Mov(XReg::B, XReg::A), Jmp(2)];
+        assert!(CompiledLib::compile_with(code, &[], &[], &opts).is_ok());
+        let code = vec![Nop, Jmp(2), Nop, Put(XReg::A, 1), Nop, Mov(XReg::B, XReg::A), Jmp(1)];
+        assert_eq!(
+            CompiledLib::compile_with(code, &[], &[], &opts).unwrap_err(),
+            CompilerError::UninitRead(Mov(XReg::B, XReg::A), 5, 2, s!("A"))
+        );
+    }
+
+    #[test]
+    fn relative_gotos() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 1;
+        const START: u16 = 2;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            rjmp        :START  ;
+            .routine    :FUNC   ;
+            not         CO      ;
+            ret                 ;
+            .routine    :START  ;
+            rcall       :FUNC   ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(&lib.lib.code[2..4], &[7 - 1, 0]);
+        assert_eq!(&lib.lib.code[9..11], &(4i16 - 8).to_le_bytes());
+        lib.verify::<Instr<LibId>>().unwrap();
+        assert_eq!(lib.disassemble::<Instr<LibId>>(&[]).unwrap(), code);
+        assert_eq!(lib.successors::<Instr<LibId>>(8).unwrap(), vec![4, 11]);
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.exec(lib.routine(MAIN), &(), |_| Some(lib.as_lib())), Status::Ok);
+        assert_eq!(vm.core.co(), Status::Fail);
+
+        let mut far =
+            vec![Instr::<LibId>::from(CtrlInstr::Nop), CtrlInstr::JmpRel { pos: 1 }.into()];
+        far.extend(vec![Instr::from(CtrlInstr::Stop); 0x8000]);
+        far.extend([Instr::from(CtrlInstr::Nop), CtrlInstr::Ret.into()]);
+        let err = CompiledLib::compile(far, &[]).unwrap_err();
+        assert!(matches!(err, CompilerError::RelativeGotoOutOfRange(_, 1, 1, 0x8004)));
+    }
+
+    #[test]
+    fn dangling() {
+        const STUB: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :STUB   ;
+            stop                ;
+        };
+        let err = CompiledLib::compile(code.clone(), &[]).unwrap_err();
+        assert!(matches!(err, CompilerError::InvalidRef(_, 1, 1, "nop", _)));
+        let far = aluasm! {
+            nop                 ;
+            call        5       ;
+            stop                ;
+        };
+        let err = CompiledLib::compile(far, &[]).unwrap_err();
+        assert!(matches!(err, CompilerError::GotoOutOfRange(_, 1, 1, 5, 1)));
+
+        let opts = CompileOpts { allow_dangling: true, ..default!() };
+        let lib = CompiledLib::compile_with(code, &[], &[], &opts).unwrap();
+        assert!(!lib.is_stub(0));
+        assert!(lib.is_stub(STUB));
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let status = vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib()));
+        assert_eq!(status, Status::Fail);
+    }
+
+    #[test]
+    fn unreachable() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 2;
+        const DEAD: u16 = 3;
+        const LOOP: u16 = 4;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            call        :FUNC   ;
+            nop                 ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+            .routine    :DEAD   ;
+            jmp         :LOOP   ;
+            .routine    :LOOP   ;
+            jmp         :DEAD   ;
+        };
+        let (_, report) =
+            CompiledLib::compile_reported(code.clone(), &[], &[], &default!()).unwrap();
+        assert!(report.unreachable().is_empty());
+
+        let opts = CompileOpts { entry: Some(MAIN), ..default!() };
+        let (_, report) = CompiledLib::compile_reported(code.clone(), &[], &[], &opts).unwrap();
+        assert_eq!(report.unreachable(), &[DEAD, LOOP]);
+
+        let opts = CompileOpts { entry: Some(DEAD), ..default!() };
+        let (_, report) = CompiledLib::compile_reported(code, &[], &[], &opts).unwrap();
+        assert_eq!(report.unreachable(), &[MAIN, 1, FUNC]);
+    }
+
+    #[test]
+    fn infinite_loops() {
+        const MAIN: u16 = 0;
+        const SPIN: u16 = 1;
+        const PING: u16 = 2;
+        const PONG: u16 = 3;
+        const WAIT: u16 = 4;
+        const DONE: u16 = 5;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            jif         CO, :WAIT;
+            jmp         :PING   ;
+            .routine    :SPIN   ;
+            jmp         :SPIN   ;
+            .routine    :PING   ;
+            .routine    :PONG   ;
+            jmp         :PING   ;
+            .routine    :WAIT   ;
+            chk         CK      ;
+            jmp         :WAIT   ;
+            .routine    :DONE   ;
+            jmp         :MAIN   ;
+        };
+        let (_, report) = CompiledLib::compile_reported(code, &[], &[], &default!()).unwrap();
+        assert_eq!(report.infinite_loops(), &[SPIN, PING, PONG]);
+        // Loops through a routine with a conditional exit are not reported
+        assert!(!report.infinite_loops().contains(&DONE));
+    }
+
+    #[test]
+    fn dedup_routines() {
+        const MAIN: u16 = 0;
+        const A: u16 = 1;
+        const B: u16 = 2;
+        const C: u16 = 3;
+        const D: u16 = 4;
+        const E: u16 = 5;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            call        :A      ;
+            call        :C      ;
+            stop                ;
+            .routine    :A      ;
+            not         CO      ;
+            ret                 ;
+            .routine    :B      ;
+            not         CO      ;
+            ret                 ;
+            .routine    :C      ;
+            call        :B      ;
+            ret                 ;
+            .routine    :D      ;
+            not         CO      ;
+            .routine    :E      ;
+            not         CO      ;
+            ret                 ;
+        };
+        let mut labels = vec![None; code.len()];
+        labels[4] = Some("a");
+        labels[7] = Some("b");
+        let opts = CompileOpts { dedup_routines: true, entry: Some(C), ..default!() };
+        let lib = CompiledLib::compile_with(code.clone(), &labels, &[], &opts).unwrap();
+        let orig = CompiledLib::compile_with(code, &labels, &[], &default!()).unwrap();
+
+        // `E` is kept since `D` falls through into it
+        assert_eq!(lib.routine_table(), &[0, 8, 11, 16, 18]);
+        assert_eq!(lib.routine_range(D - 1), 16..18);
+        assert_eq!(lib.routine_range(E - 1), 18..21);
+        assert_eq!(lib.as_lib().code.len(), orig.as_lib().code.len() - 3);
+        assert_eq!(lib.routine_by_name("b"), lib.routine_by_name("a"));
+        assert_eq!(lib.entry(), lib.try_routine(C - 1));
+        assert_eq!(lib.source_index_at(11), Some(10));
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.exec(lib.routine(MAIN), &(), |_| Some(lib.as_lib())), Status::Ok);
+        let mut orig_vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(orig_vm.exec(orig.routine(MAIN), &(), |_| Some(orig.as_lib())), Status::Ok);
+        assert_eq!(vm.steps(), orig_vm.steps());
+        assert_eq!(vm.core.co(), orig_vm.core.co());
+    }
+
+    #[test]
+    fn entry() {
+        const MAIN: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            fail    CK          ;
+            stop                ;
+            .routine    :MAIN   ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        assert_eq!(lib.entry(), None);
+
+        let opts = CompileOpts { entry: Some(MAIN), ..default!() };
+        let lib = CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap();
+        assert_eq!(lib.entry(), Some(lib.routine(MAIN)));
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let status = vm.exec(lib.entry().unwrap(), &(), |_| Some(lib.as_lib()));
+        assert_eq!(status, Status::Ok);
+
+        let opts = CompileOpts { entry: Some(2), ..default!() };
+        assert!(matches!(
+            CompiledLib::compile_with(code, &[], &[], &opts),
+            Err(CompilerError::UnknownRoutine(2))
+        ));
+    }
+
+    #[test]
+    fn unterminated() {
+        let code = aluasm! {
+            nop                 ;
+            chk     CO          ;
+            nop                 ;
+            ret                 ;
+            nop                 ;
+            jif     CO, +3      ;
+        };
+        let (_, report) =
+            CompiledLib::compile_reported(code.clone(), &[], &[], &default!()).unwrap();
+        assert_eq!(report.unterminated(), &[0, 2]);
+
+        let opts = CompileOpts { strict: true, ..default!() };
+        assert!(matches!(
+            CompiledLib::compile_reported(code, &[], &[], &opts),
+            Err(CompilerError::UnterminatedRoutine(0))
+        ));
+
+        let code = aluasm! {
+            nop                 ;
+            stop                ;
+        };
+        let opts = CompileOpts { strict: true, ..default!() };
+        let (_, report) = CompiledLib::compile_reported(code, &[], &[], &opts).unwrap();
+        assert!(report.unterminated().is_empty());
+    }
+
+    #[test]
+    fn strip_markers() {
+        let code = aluasm! {
+            nop                 ;
+            nop                 ;
+            call        2       ;
+            stop                ;
+            nop                 ;
+            jif     CO, +3      ;
+            ret                 ;
+            nop                 ;
+        };
+        let opts = CompileOpts { strip_markers: true, ..default!() };
+        let lib = CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 0, 4, 7]);
+        assert_eq!(lib.as_lib().code.len(), 8);
+        assert_eq!(lib.source_index_at(0), Some(2));
+        assert_eq!(lib.source_index_at(4), Some(5));
+        assert_eq!(lib.disassemble::<Instr<LibId>>(&[]), Err(DisassembleError::MissingMarker(0)));
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let status = vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib()));
+        assert_eq!(status, Status::Ok);
+
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 1, 6, 10]);
+        assert!(lib.disassemble::<Instr<LibId>>(&[]).is_ok());
+
+        // Markers spanned by relative jumps are kept
+        let code = aluasm! {
+            nop                 ;
+            jif     CO, +4      ;
+            ret                 ;
+            nop                 ;
+            ret                 ;
+            nop                 ;
+        };
+        let lib = CompiledLib::compile_with(code, &[], &[], &opts).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 3, 5]);
+    }
+
+    #[test]
+    fn goto_offsets() {
+        let code = vec![
+            Instr::<LibId>::from(CtrlInstr::Nop),
+            CtrlInstr::JiFail { pos: 7 }.into(),
+            CtrlInstr::Fn { pos: 8 }.into(),
+            CtrlInstr::Stop.into(),
+            CtrlInstr::Nop.into(),
+            CtrlInstr::Ret.into(),
+        ];
+        let opts = CompileOpts { goto_mode: GotoMode::Offset, entry: Some(0), ..default!() };
+        let (lib, report) = CompiledLib::compile_reported(code.clone(), &[], &[], &opts).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 8]);
+        assert_eq!(lib.as_lib().code.as_slice(), &[
+            0x00, 0x08, 0x07, 0x00, 0x0D, 0x08, 0x00, 0x10, 0x00, 0x0F
+        ]);
+        assert!(report.unreachable().is_empty());
+
+        let opts = CompileOpts {
+            goto_mode: GotoMode::Offset,
+            strip_markers: true,
+            ..default!()
+        };
+        let lib = CompiledLib::compile_with(code.clone(), &[], &[], &opts).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 7]);
+        assert_eq!(lib.as_lib().code.as_slice(), &[0x08, 0x06, 0x00, 0x0D, 0x07, 0x00, 0x10, 0x0F]);
+
+        // Byte offsets must land on instruction boundaries
+        let mut code = code;
+        code[2] = CtrlInstr::Fn { pos: 2 }.into();
+        let opts = CompileOpts { goto_mode: GotoMode::Offset, ..default!() };
+        assert_eq!(
+            CompiledLib::compile_with(code.clone(), &[], &[], &opts),
+            Err(CompilerError::InvalidOffsetRef(code[2], 2, 4, 2))
+        );
+    }
+
+    #[test]
+    fn too_large() {
+        let mut code = vec![Instr::<LibId>::from(CtrlInstr::Stop); Lib::MAX_CODE_LEN + 1];
+        code[0] = CtrlInstr::Nop.into();
+        assert_eq!(
+            CompiledLib::compile(code.clone(), &[]),
+            Err(CompilerError::CodeTooLarge {
+                at_instruction: Lib::MAX_CODE_LEN,
+                overflow_at: u16::MAX
+            })
+        );
+        code.pop();
+        assert!(CompiledLib::compile(code, &[]).is_ok());
+
+        let code = vec![Instr::<LibId>::from(CtrlInstr::Nop); CompiledLib::MAX_ROUTINES + 1];
+        assert_eq!(
+            CompiledLib::compile(code, &[]),
+            Err(CompilerError::TooManyRoutines(CompiledLib::MAX_ROUTINES))
+        );
+
+        let mut first = vec![Instr::<LibId>::from(CtrlInstr::Nop); CompiledLib::MAX_ROUTINES];
+        let mut second = vec![Instr::<LibId>::from(CtrlInstr::Stop), CtrlInstr::Nop.into()];
+        assert_eq!(
+            CompiledLib::compile_linked(&mut [&mut first, &mut second], &[]),
+            Err(CompilerError::TooManyRoutines(CompiledLib::MAX_ROUTINES + 1))
+        );
+    }
+
+    #[test]
+    fn label_not_target() {
+        let code = aluasm! {
+            nop     ;
+            stop    ;
+        };
+        let err = CompiledLib::compile_labeled(code, &[None, Some("stop")], &[]).unwrap_err();
+        assert_eq!(
+            err,
+            CompilerError::<Instr<LibId>>::LabelNotTarget(CtrlInstr::Stop.into(), 1, s!("stop"))
+        );
+    }
+
+    #[test]
+    fn invalid_ref_labels() {
+        const ABSENT: u16 = 1;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            call        :ABSENT ;
+        };
+        let err = CompiledLib::compile_labeled(code, &[Some("main")], &[]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "instruction number 1 `call    0001#h` (offset 0x1) references goto target absent in \
+             the code. Use `nop` instruction to mark the goto target.\nThe known goto targets \
+             are: 0@0x0 `main`"
+        );
+    }
 }
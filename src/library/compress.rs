@@ -0,0 +1,183 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Compressed storage format of the compiled libraries.
+//!
+//! The library with all its compiler-produced metadata is serialized with its serde
+//! representation (JSON), which is then compressed with zstd. The compressed data start with the
+//! [`COMPRESSED_MAGIC`] bytes, followed by the format version byte and the zstd frame.
+
+use std::io::Read;
+
+use super::compiler::CompiledLibUnchecked;
+use super::{CompiledLib, LibIdMismatch, LibLoadError};
+
+/// Maximal length of the decompressed data.
+pub const DECOMPRESSED_MAX_LEN: usize = 0x100_0000;
+
+/// Magic bytes starting the compressed data.
+pub const COMPRESSED_MAGIC: [u8; 4] = *b"ALUZ";
+
+/// Version of the compressed data format produced by [`CompiledLib::compress`].
+const FORMAT_VERSION: u8 = 1;
+
+/// Compression level used by zstd.
+const ZSTD_LEVEL: i32 = 19;
+
+/// Errors decompressing a library with [`CompiledLib::decompress`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum DecompressError {
+    /// data do not start with the magic bytes of a compressed library.
+    InvalidMagic,
+
+    /// compressed data format version {0} is not supported.
+    UnsupportedFormat(u8),
+
+    /// compressed data are truncated or corrupted.
+    Corrupted,
+
+    /// decompressed data exceed the maximal length.
+    TooLarge,
+
+    /// decompressed data are not a valid serialization of a compiled library.
+    InvalidLib,
+
+    /// {0}
+    #[from]
+    #[from(LibIdMismatch)]
+    Load(LibLoadError),
+}
+
+impl CompiledLib {
+    /// Serializes the library together with its routine table, labels, relocations and other
+    /// metadata, and compresses the result with zstd.
+    ///
+    /// The exact byte sequence is not a part of the API and may change between the crate and
+    /// zstd versions; only [`Self::decompress`] is guaranteed to restore the library.
+    pub fn compress(&self) -> Vec<u8> {
+        let data = serde_json::to_vec(self).expect("library serialization doesn't fail");
+        let mut compressed = Vec::with_capacity(data.len() / 4 + COMPRESSED_MAGIC.len() + 1);
+        compressed.extend(COMPRESSED_MAGIC);
+        compressed.push(FORMAT_VERSION);
+        compressed.extend(
+            zstd::bulk::compress(&data, ZSTD_LEVEL).expect("in-memory compression doesn't fail"),
+        );
+        compressed
+    }
+
+    /// Restores a library compressed with [`Self::compress`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the data are corrupted, or if the restored library is inconsistent (see
+    /// [`LibLoadError`]).
+    pub fn decompress(data: &[u8]) -> Result<Self, DecompressError> {
+        let payload = data
+            .strip_prefix(&COMPRESSED_MAGIC)
+            .ok_or(DecompressError::InvalidMagic)?;
+        let (version, frame) = payload.split_first().ok_or(DecompressError::Corrupted)?;
+        if *version != FORMAT_VERSION {
+            return Err(DecompressError::UnsupportedFormat(*version));
+        }
+        let mut data = Vec::new();
+        zstd::stream::read::Decoder::new(frame)
+            .and_then(|decoder| {
+                decoder
+                    .take(DECOMPRESSED_MAX_LEN as u64 + 1)
+                    .read_to_end(&mut data)
+            })
+            .map_err(|_| DecompressError::Corrupted)?;
+        if data.len() > DECOMPRESSED_MAX_LEN {
+            return Err(DecompressError::TooLarge);
+        }
+        let unchecked = serde_json::from_slice::<CompiledLibUnchecked>(&data)
+            .map_err(|_| DecompressError::InvalidLib)?;
+        Ok(CompiledLib::try_from(unchecked)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{aluasm, CompileOpts, LibId};
+
+    fn frame(data: &[u8]) -> Vec<u8> {
+        let mut compressed = COMPRESSED_MAGIC.to_vec();
+        compressed.push(FORMAT_VERSION);
+        compressed.extend(zstd::bulk::compress(data, ZSTD_LEVEL).unwrap());
+        compressed
+    }
+
+    #[test]
+    fn corrupted() {
+        let compressed = frame(b"{}");
+        assert_eq!(CompiledLib::decompress(&compressed[1..]), Err(DecompressError::InvalidMagic));
+        assert_eq!(CompiledLib::decompress(&compressed[..4]), Err(DecompressError::Corrupted));
+        let mut version = compressed.clone();
+        version[4] = 0;
+        assert_eq!(CompiledLib::decompress(&version), Err(DecompressError::UnsupportedFormat(0)));
+        assert_eq!(
+            CompiledLib::decompress(&compressed[..compressed.len() - 1]),
+            Err(DecompressError::Corrupted)
+        );
+        assert_eq!(CompiledLib::decompress(&compressed), Err(DecompressError::InvalidLib));
+        let too_large = frame(&vec![b' '; DECOMPRESSED_MAX_LEN + 1]);
+        assert_eq!(CompiledLib::decompress(&too_large), Err(DecompressError::TooLarge));
+    }
+
+    #[test]
+    fn lib() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 1;
+        let mut code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+        };
+        for _ in 0..100 {
+            code.extend(aluasm! { not CO; chk CO; });
+        }
+        code.extend(aluasm! { ret; });
+        let labels = [Some("main"), None, None, Some("func")];
+        let opts = CompileOpts { entry: Some(MAIN), ..default!() };
+        let (lib, _) = CompiledLib::compile_reported(code, &labels, &[], &opts).unwrap();
+        let lib = lib.with_meta("name", b"test".to_vec());
+        let compressed = lib.compress();
+        assert!(compressed.len() < serde_json::to_vec(&lib).unwrap().len() / 2);
+        let restored = CompiledLib::decompress(&compressed).unwrap();
+        assert_eq!(restored, lib);
+        assert_eq!(restored.as_lib().lib_id(), lib.as_lib().lib_id());
+        assert_eq!(restored.routine_by_name("func"), Some(lib.routine(FUNC)));
+
+        let mut invalid = lib.clone();
+        invalid.entry = Some(2);
+        let err = CompiledLib::decompress(&invalid.compress()).unwrap_err();
+        assert_eq!(err, LibLoadError::UnknownRoutine("entry point", 2).into());
+        invalid.id = LibId::from([0xA5; 32]);
+        let err = CompiledLib::decompress(&invalid.compress()).unwrap_err();
+        assert!(matches!(err, DecompressError::Load(LibLoadError::IdMismatch(_))));
+    }
+}
@@ -0,0 +1,134 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::vec::Vec;
+
+use super::{CompiledLib, LibId};
+
+/// Difference between two versions of a compiled library, as reported by [`CompiledLib::diff`].
+///
+/// Routines are matched by their numbers.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LibDiff {
+    /// Id of the original library.
+    pub old_id: LibId,
+    /// Id of the new library.
+    pub new_id: LibId,
+    /// Routines present only in the new library.
+    pub added: Vec<u16>,
+    /// Routines present only in the original library.
+    pub removed: Vec<u16>,
+    /// Routines present in both libraries, which bytecode differs.
+    pub changed: Vec<u16>,
+}
+
+impl LibDiff {
+    /// Checks whether the library id has changed.
+    pub fn id_changed(&self) -> bool { self.old_id != self.new_id }
+
+    /// Checks whether the routines are the same in both libraries.
+    ///
+    /// Libraries can still differ in their ids, for instance due to a change in the data segment,
+    /// or in the code preceding the first routine.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl CompiledLib {
+    /// Compares the routines of the library with the routines of its other version.
+    ///
+    /// Routine is considered changed if its bytecode differs. Since local gotos are encoded as
+    /// code offsets, resizing a routine also changes all routines jumping to the routines
+    /// following it.
+    pub fn diff(&self, other: &Self) -> LibDiff {
+        let old_count = self.routines.len() as u16;
+        let new_count = other.routines.len() as u16;
+        let changed = (0..old_count.min(new_count))
+            .filter(|no| {
+                let old = self.routine_range(*no);
+                let new = other.routine_range(*no);
+                self.lib.code[old.start as usize..old.end as usize]
+                    != other.lib.code[new.start as usize..new.end as usize]
+            })
+            .collect();
+        LibDiff {
+            old_id: self.id,
+            new_id: other.id,
+            added: (old_count..new_count).collect(),
+            removed: (new_count..old_count).collect(),
+            changed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::aluasm;
+
+    #[test]
+    fn diff() {
+        const FUNC: u16 = 1;
+        let old = CompiledLib::compile(
+            aluasm! {
+                nop                 ;
+                call        :FUNC   ;
+                stop                ;
+                .routine    :FUNC   ;
+                ret                 ;
+            },
+            &[],
+        )
+        .unwrap();
+        let diff = old.diff(&old);
+        assert!(diff.is_empty());
+        assert!(!diff.id_changed());
+
+        let new = CompiledLib::compile(
+            aluasm! {
+                nop                 ;
+                call        :FUNC   ;
+                stop                ;
+                .routine    :FUNC   ;
+                chk         CO      ;
+                ret                 ;
+                nop                 ;
+                stop                ;
+            },
+            &[],
+        )
+        .unwrap();
+        let diff = old.diff(&new);
+        assert!(diff.id_changed());
+        assert_eq!(diff.added, vec![2]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(diff.changed, vec![FUNC]);
+
+        let diff = new.diff(&old);
+        assert!(diff.added.is_empty());
+        assert_eq!(diff.removed, vec![2]);
+        assert_eq!(diff.changed, vec![FUNC]);
+    }
+}
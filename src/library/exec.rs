@@ -22,18 +22,21 @@
 // or implied. See the License for the specific language governing permissions and limitations under
 // the License.
 
+use alloc::vec::Vec;
+
 use amplify::num::u3;
-#[cfg(feature = "log")]
-use baid64::DisplayBaid64;
 
-use super::{Lib, Marshaller};
+#[cfg(feature = "log")]
+use super::trace::{Trace, COLORS};
+use super::{CompiledLib, Lib, LibSite, LibView, Marshaller};
 use crate::isa::{Bytecode, BytecodeRead, ExecStep, Instruction};
-use crate::{Core, LibId, Site, SiteId};
+use crate::vm::ExecError;
+use crate::{Core, LibId, RegisterSnapshot, Site, SiteId, Vm};
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 pub enum Jump<Id: SiteId> {
-    #[display("halt")]
-    Halt,
+    #[display("halt: {0}")]
+    Halt(HaltReason),
 
     #[display("={0}")]
     Instr(Site<Id>),
@@ -42,6 +45,85 @@ pub enum Jump<Id: SiteId> {
     Next(Site<Id>),
 }
 
+/// Reason for the program execution halt.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub enum HaltReason {
+    /// program was stopped by an instruction.
+    Stop,
+
+    /// instruction has set `CK` to a failed state while `CH` was set.
+    Fail,
+
+    /// program has reached the end of the code segment.
+    EndOfCode,
+
+    /// jump to offset {0:#06x}, which lies outside of the code segment.
+    InvalidJump(u16),
+
+    /// unable to decode instruction at offset {0:#06x}.
+    DecodeFailure(u16),
+
+    /// complexity limit is exceeded.
+    ComplexityExceeded,
+
+    /// program was aborted with trap code {0:#06x}.
+    Trapped(u16),
+
+    /// library {0} is not known to the VM.
+    UnknownLib(LibId),
+
+    /// {0}
+    Fault(ExecError),
+}
+
+impl HaltReason {
+    /// Checks whether the program has halted by itself, either with an explicit stop or by
+    /// reaching the end of the code, and not due to a failure or a fault.
+    pub fn is_completion(self) -> bool { matches!(self, HaltReason::Stop | HaltReason::EndOfCode) }
+}
+
+/// Reason for an [`ExecHook`] to interrupt the program execution before an instruction.
+#[derive(Copy, Clone, Eq, PartialEq, Debug, From)]
+pub(crate) enum HookBreak {
+    /// The program execution is halted with a VM fault.
+    #[from]
+    Fault(ExecError),
+
+    /// The program execution is paused, such that it can be resumed from the same instruction.
+    Pause,
+}
+
+/// Hook into the library code execution, which is called for each of the instructions.
+pub(crate) trait ExecHook<Instr: Instruction<LibId>> {
+    /// Called after an instruction is decoded and before it gets executed.
+    ///
+    /// Returning an error interrupts the program execution before the instruction is executed.
+    fn before_exec(
+        &mut self,
+        site: Site<LibId>,
+        instr: &Instr,
+        core: &Core<LibId, Instr::Core>,
+    ) -> Result<(), HookBreak>;
+
+    /// Called after an instruction has transferred the execution to an absolute code offset, i.e.
+    /// performed a non-relative jump or a call.
+    fn after_jump(&mut self, core: &mut Core<LibId, Instr::Core>) { let _ = core; }
+}
+
+impl<Instr: Instruction<LibId>> ExecHook<Instr> for () {
+    #[inline]
+    fn before_exec(
+        &mut self,
+        _: Site<LibId>,
+        _: &Instr,
+        _: &Core<LibId, Instr::Core>,
+    ) -> Result<(), HookBreak> {
+        Ok(())
+    }
+}
+
 impl Lib {
     /// Execute library code starting at entrypoint.
     ///
@@ -58,43 +140,94 @@ impl Lib {
     where
         Instr: Instruction<LibId> + Bytecode<LibId>,
     {
-        #[cfg(feature = "log")]
-        let (m, w, d, g, r, y, z) = (
-            "\x1B[0;35m",
-            "\x1B[1;1m",
-            "\x1B[0;37;2m",
-            "\x1B[0;32m",
-            "\x1B[0;31m",
-            "\x1B[0;33m",
-            "\x1B[0m",
-        );
-
-        let mut marshaller = Marshaller::with(&self.code, &self.data, &self.libs);
-        let lib_id = self.lib_id();
+        self.exec_hooked::<Instr, ()>(entrypoint, skip_first, core, context, &mut ())
+            .unwrap_or_else(|_| unreachable!("execution is never paused without a hook"))
+    }
 
+    /// Execute library code starting at entrypoint, calling the hook before each of the
+    /// instructions.
+    ///
+    /// # Returns
+    ///
+    /// Location for the external code jump, if any, or the site of the instruction before which
+    /// the hook has paused the execution.
+    pub(crate) fn exec_hooked<Instr, H>(
+        &self,
+        entrypoint: u16,
+        skip_first: bool,
+        core: &mut Core<LibId, Instr::Core>,
+        context: &Instr::Context<'_>,
+        hook: &mut H,
+    ) -> Result<Jump<LibId>, LibSite>
+    where
+        Instr: Instruction<LibId> + Bytecode<LibId>,
+        H: ExecHook<Instr>,
+    {
+        LibView::from(self).exec_hooked(entrypoint, skip_first, core, context, hook)
+    }
+}
+
+impl LibView<'_> {
+    /// Execute library code starting at entrypoint in the same way as [`Lib::exec`], reading the
+    /// bytecode from the borrowed segments.
+    ///
+    /// # Returns
+    ///
+    /// Location for the external code jump, if any.
+    pub fn exec<Instr>(
+        &self,
+        entrypoint: u16,
+        skip_first: bool,
+        core: &mut Core<LibId, Instr::Core>,
+        context: &Instr::Context<'_>,
+    ) -> Jump<LibId>
+    where
+        Instr: Instruction<LibId> + Bytecode<LibId>,
+    {
+        self.exec_hooked::<Instr, ()>(entrypoint, skip_first, core, context, &mut ())
+            .unwrap_or_else(|_| unreachable!("execution is never paused without a hook"))
+    }
+
+    /// Execute library code starting at entrypoint, calling the hook before each of the
+    /// instructions.
+    ///
+    /// # Returns
+    ///
+    /// Location for the external code jump, if any, or the site of the instruction before which
+    /// the hook has paused the execution.
+    pub(crate) fn exec_hooked<Instr, H>(
+        &self,
+        entrypoint: u16,
+        skip_first: bool,
+        core: &mut Core<LibId, Instr::Core>,
+        context: &Instr::Context<'_>,
+        hook: &mut H,
+    ) -> Result<Jump<LibId>, LibSite>
+    where
+        Instr: Instruction<LibId> + Bytecode<LibId>,
+        H: ExecHook<Instr>,
+    {
         #[cfg(feature = "log")]
-        let lib_mnemonic = lib_id.to_baid64_mnemonic();
-        #[cfg(feature = "log")]
-        let lib_ref = lib_mnemonic.split_at(5).0;
+        let (m, _, d, g, r, y, z) = COLORS;
+
+        let mut marshaller = Marshaller::with(self.code(), self.data(), self.libs());
+        let lib_id = self.lib_id();
 
         if marshaller.seek(entrypoint).is_err() {
             core.reset_ck();
             #[cfg(feature = "log")]
             eprintln!("jump to non-existing offset; halting, {y}CK{z} is set to {r}false{z}");
-            return Jump::Halt;
+            return Ok(Jump::Halt(HaltReason::InvalidJump(entrypoint)));
         }
 
         #[cfg(feature = "log")]
-        let mut ck0 = core.ck();
-        #[cfg(feature = "log")]
-        let mut co0 = core.co();
+        let mut trace = Trace::<Instr>::new(lib_id, core);
 
         if marshaller.is_eof() {
-            return Jump::Halt;
+            return Ok(Jump::Halt(HaltReason::EndOfCode));
         }
         // Skip instruction if required
         if skip_first {
-            #[cfg(feature = "log")]
             if Instr::decode_instr(&mut marshaller).is_err() {
                 #[cfg(feature = "log")]
                 {
@@ -103,7 +236,7 @@ impl Lib {
                         "; unable to decode instruction at byte pos {byte:06X}#h, bit pos {bit}",
                     );
                 }
-                return Jump::Halt;
+                return Ok(Jump::Halt(HaltReason::DecodeFailure(entrypoint)));
             };
             let next_pos = marshaller.offset();
             debug_assert_eq!(next_pos.1, u3::ZERO);
@@ -122,108 +255,36 @@ impl Lib {
                         "unable to decode instruction at byte pos {byte:06X}#h, bit pos {bit}",
                     );
                 }
-                return Jump::Halt;
+                return Ok(Jump::Halt(HaltReason::DecodeFailure(pos)));
             };
 
-            #[cfg(feature = "log")]
-            let mut prev = bmap![];
-
-            #[cfg(feature = "log")]
-            // Stupid compiler can't reason between `cfg` blocks
-            #[allow(unused_assignments)]
-            let mut src_empty = true;
-            #[cfg(feature = "log")]
-            {
-                for reg in instr.dst_regs() {
-                    prev.insert(reg, core.get(reg));
-                }
-                eprint!("{m}{}@{pos:06X}#h:{z} {: <32}; ", lib_ref, instr.to_string());
-                let src_regs = instr.src_regs();
-                src_empty = src_regs.is_empty();
-                let mut iter = src_regs.into_iter().peekable();
-                while let Some(reg) = iter.next() {
-                    eprint!("{d}{reg}{z} ");
-                    if let Some(val) = core.get(reg) {
-                        eprint!("{w}{}{z}", val);
-                    } else {
-                        eprint!("{d}~{z}");
-                    }
-                    if iter.peek().is_some() {
-                        eprint!(", ");
-                    }
+            match hook.before_exec(Site::new(lib_id, pos), &instr, core) {
+                Ok(()) => {}
+                Err(HookBreak::Fault(err)) => {
+                    #[cfg(feature = "log")]
+                    eprintln!("{m}{}@{pos:06X}#h:{z} {r}{err}{z}", trace.lib_ref());
+                    return Ok(Jump::Halt(HaltReason::Fault(err)));
                 }
+                Err(HookBreak::Pause) => return Err(LibSite::new(lib_id, pos)),
             }
 
-            let next = instr.exec(Site::new(lib_id, pos), core, context);
-
             #[cfg(feature = "log")]
-            {
-                if !src_empty {
-                    if !prev.is_empty() {
-                        eprint!(" => ");
-                    } else if ck0 != core.ck() || co0 != core.co() || next != ExecStep::Next {
-                        eprint!("; ");
-                    }
-                }
+            trace.before_exec(pos, &instr, core);
 
-                let mut iter = instr.dst_regs().into_iter().peekable();
-                while let Some(reg) = iter.next() {
-                    eprint!("{g}{reg}{z} ");
-                    if let Some(val) = prev.get(&reg).unwrap() {
-                        eprint!("{y}{}{z}", val);
-                    } else {
-                        eprint!("{d}~{z}");
-                    }
-                    eprint!(" -> ");
-                    if let Some(val) = core.get(reg) {
-                        eprint!("{y}{}{z}", val);
-                    } else {
-                        eprint!("{d}~{z}");
-                    }
-                    if iter.peek().is_some() {
-                        eprint!(", ");
-                    }
-                }
-                if !prev.is_empty() && (ck0 != core.ck() || co0 != core.co()) {
-                    eprint!(", ");
-                }
-                if ck0 != core.ck() {
-                    let p = if ck0.is_ok() { g } else { r };
-                    let c = if core.ck().is_ok() { g } else { r };
-                    eprint!("{y}CK{z} {p}{ck0}{z} -> {c}{}{z}", core.ck());
-                }
-                if ck0 != core.ck() && co0 != core.co() {
-                    eprint!(", ");
-                }
-                if co0 != core.co() {
-                    let p = if co0.is_ok() { g } else { r };
-                    let c = if core.co().is_ok() { g } else { r };
-                    eprint!("{y}CO{z} {p}{co0}{z} -> {c}{}{z}", core.co());
-                }
-                if (!prev.is_empty() || ck0 != core.ck() || co0 != core.co())
-                    && next != ExecStep::Next
-                {
-                    eprint!(", ");
-                }
+            let next = instr.exec(Site::new(lib_id, pos), core, context);
 
-                ck0 = core.ck();
-                co0 = core.co();
-            }
+            #[cfg(feature = "log")]
+            trace.after_exec(&instr, core, &next);
 
             if !core.acc_complexity(instr.complexity()) {
                 let _ = core.fail_ck();
                 #[cfg(feature = "log")]
-                {
-                    if !src_empty || !prev.is_empty() {
-                        eprint!(", ");
-                    }
-                    eprintln!("halting, complexity overflow");
-                }
-                return Jump::Halt;
+                trace.complexity_exceeded();
+                return Ok(Jump::Halt(HaltReason::ComplexityExceeded));
             }
             match next {
                 ExecStep::Stop => {
-                    return Jump::Halt;
+                    return Ok(Jump::Halt(HaltReason::Stop));
                 }
                 ExecStep::Fail => {
                     #[cfg(feature = "log")]
@@ -231,7 +292,7 @@ impl Lib {
                     if core.fail_ck() {
                         #[cfg(feature = "log")]
                         eprintln!(", {y}CH{z} is {g}true{z}: halting");
-                        return Jump::Halt;
+                        return Ok(Jump::Halt(HaltReason::Fail));
                     }
                     #[cfg(feature = "log")]
                     eprintln!(", {y}CH{z} is {r}false{z}: continuing");
@@ -252,22 +313,118 @@ impl Lib {
                             "jump to non-existing offset: unconditionally halting; {y}CK{z} is \
                              set to {r}fail{z}"
                         );
-                        return Jump::Halt;
+                        return Ok(Jump::Halt(HaltReason::InvalidJump(pos)));
+                    }
+                    if !instr.is_relative_jump() {
+                        hook.after_jump(core);
                     }
                 }
                 ExecStep::Call(site) => {
                     #[cfg(feature = "log")]
                     eprintln!("{d}calling{z} {m}{site}{z}");
-                    return Jump::Instr(site);
+                    hook.after_jump(core);
+                    return Ok(Jump::Instr(site));
                 }
                 ExecStep::Ret(site) => {
                     #[cfg(feature = "log")]
                     eprintln!("{d}returning to{z} {m}{site}{z}");
-                    return Jump::Next(site);
+                    return Ok(Jump::Next(site));
+                }
+                ExecStep::Trap(code) => {
+                    let _ = core.fail_ck();
+                    #[cfg(feature = "log")]
+                    eprintln!("{r}trap {code:#06x}{z}: halting; {y}CK{z} is set to {r}fail{z}");
+                    return Ok(Jump::Halt(HaltReason::Trapped(code)));
+                }
+                ExecStep::ReentrancyLimit => {
+                    #[cfg(feature = "log")]
+                    eprintln!("{r}reentrancy limit is exceeded{z}: halting");
+                    let site = LibSite::new(lib_id, pos);
+                    return Ok(Jump::Halt(HaltReason::Fault(ExecError::ReentrancyLimit(site))));
                 }
             }
         }
 
-        Jump::Halt
+        Ok(Jump::Halt(HaltReason::EndOfCode))
+    }
+}
+
+impl CompiledLib {
+    /// Runs the [entry routine](Self::entry) of the library once per each of the inputs, returning
+    /// the reasons of the program halts together with the final states of the registers.
+    ///
+    /// Each run is performed by a fresh VM, which registers are seeded with the input, and which
+    /// halts with [`ExecError::StepLimitExceeded`] after `step_limit` steps, if provided. External
+    /// calls are resolved against the provided dependencies.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the library doesn't define an entry routine.
+    #[allow(clippy::type_complexity)]
+    pub fn run_many<Isa>(
+        &self,
+        inputs: &[RegisterSnapshot<LibId, Isa::Core>],
+        step_limit: Option<u64>,
+        deps: &[&CompiledLib],
+        context: &Isa::Context<'_>,
+    ) -> Option<Vec<(HaltReason, RegisterSnapshot<LibId, Isa::Core>)>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let entry = self.entry()?;
+        let resolver = |id: LibId| {
+            core::iter::once(self)
+                .chain(deps.iter().copied())
+                .find(|lib| lib.id == id)
+                .map(|lib| &lib.lib)
+        };
+        let outputs = inputs
+            .iter()
+            .map(|input| {
+                let mut vm = Vm::<Isa>::new();
+                vm.restore(input);
+                vm.set_step_limit(step_limit);
+                let reason = vm.run_reported(entry, context, resolver);
+                (reason, vm.snapshot())
+            })
+            .collect();
+        Some(outputs)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::isa::{RegInstr, XReg};
+    use crate::CompileOpts;
+
+    #[test]
+    fn run_many() {
+        let code = [RegInstr::Nop, RegInstr::Mov(XReg::B, XReg::A)];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        assert!(lib.run_many::<RegInstr>(&[], None, &[], &()).is_none());
+
+        let opts = CompileOpts { entry: Some(0), ..default!() };
+        let lib = CompiledLib::compile_with(code, &[], &[], &opts).unwrap();
+        let inputs = (1..=3)
+            .map(|val| {
+                let mut vm = Vm::<RegInstr>::new();
+                vm.set_reg(XReg::A, val);
+                vm.snapshot()
+            })
+            .collect::<Vec<_>>();
+        let outputs = lib.run_many::<RegInstr>(&inputs, None, &[], &()).unwrap();
+        for (val, (reason, state)) in (1..=3).zip(outputs) {
+            assert_eq!(reason, HaltReason::EndOfCode);
+            let mut vm = Vm::<RegInstr>::new();
+            vm.restore(&state);
+            assert_eq!(vm.get_reg(XReg::B), Some(val));
+        }
+
+        let outputs = lib
+            .run_many::<RegInstr>(&inputs[..1], Some(1), &[], &())
+            .unwrap();
+        let site = crate::LibSite::new(lib.as_lib().lib_id(), 1);
+        assert_eq!(outputs[0].0, HaltReason::Fault(ExecError::StepLimitExceeded(site)));
     }
 }
@@ -0,0 +1,139 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::vec::Vec;
+
+use amplify::confinement::Confined;
+use strict_encoding::StrictDeserialize;
+
+use super::lib::LIB_MAX_LEN;
+use super::{CompiledLib, HaltReason, Lib, LibId};
+use crate::isa::Instr;
+use crate::Vm;
+
+/// Maximal number of execution steps performed by [`fuzz_execute`].
+pub const FUZZ_STEP_LIMIT: u64 = 0x1000;
+
+/// Fuzzing entry point, which decodes arbitrary bytes as a library, verifies it, and executes it
+/// with the base instruction set ([`Instr`]) under the [`FUZZ_STEP_LIMIT`].
+///
+/// The input is treated as a strict-serialized [`Lib`], which is compiled into a library with a
+/// single routine starting at the beginning of the code, being the library entry point. The
+/// function never panics, whatever the input is.
+///
+/// # Returns
+///
+/// `None` if the input is rejected, which happens when the input:
+/// - is not a valid strict serialization of a [`Lib`] (including inputs with trailing data);
+/// - contains code which fails the [static verification](CompiledLib::verify), for instance because
+///   of an undecodable instruction, a goto outside of the code or into the middle of an
+///   instruction, or an external call into a library not listed in the library segment;
+/// - contains an empty code segment.
+///
+/// Otherwise, the library is executed, and the reason of the program halt is returned. Calls into
+/// other libraries halt the program with [`HaltReason::UnknownLib`].
+pub fn fuzz_execute(code: &[u8]) -> Option<HaltReason> {
+    let data = Confined::<Vec<u8>, 0, LIB_MAX_LEN>::try_from(code.to_vec()).ok()?;
+    let lib = Lib::from_strict_serialized::<LIB_MAX_LEN>(data).ok()?;
+    let lib = CompiledLib::with_code_entry(lib);
+    lib.verify::<Instr<LibId>>().ok()?;
+
+    let mut vm = Vm::<Instr<LibId>>::new();
+    vm.set_step_limit(Some(FUZZ_STEP_LIMIT));
+    let entry = lib.entry()?;
+    Some(vm.run_reported(entry, &(), |id| (id == lib.id).then_some(&lib.lib)))
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictSerialize;
+
+    use super::*;
+    use crate::isa::Instruction;
+    use crate::vm::ExecError;
+    use crate::{aluasm, LibsSeg};
+
+    /// Deterministic pseudo-random byte generator (xorshift).
+    struct Bytes(u64);
+
+    impl Bytes {
+        fn next(&mut self) -> u8 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 >> 32) as u8
+        }
+
+        fn take(&mut self, len: usize) -> Vec<u8> { (0..len).map(|_| self.next()).collect() }
+    }
+
+    fn serialize(code: Vec<u8>, libs: LibsSeg) -> Vec<u8> {
+        let lib = Lib {
+            isae: Instr::<LibId>::isa_ext(),
+            code: Confined::try_from(code).unwrap(),
+            data: none!(),
+            libs,
+        };
+        lib.to_strict_serialized::<LIB_MAX_LEN>().unwrap().release()
+    }
+
+    #[test]
+    fn rejected() {
+        assert_eq!(fuzz_execute(&[]), None);
+        assert_eq!(fuzz_execute(&[0xFF; 16]), None);
+        assert_eq!(fuzz_execute(&serialize(vec![], none!())), None);
+
+        let mut data = serialize(vec![0x00], none!());
+        data.push(0);
+        assert_eq!(fuzz_execute(&data), None);
+    }
+
+    #[test]
+    fn executed() {
+        let lib = CompiledLib::compile(aluasm! { nop; stop; }, &[]).unwrap();
+        let data = lib.as_lib().to_strict_serialized::<LIB_MAX_LEN>().unwrap();
+        assert_eq!(fuzz_execute(&data), Some(HaltReason::Stop));
+
+        let lib = CompiledLib::compile(aluasm! { nop; jmp 0; }, &[]).unwrap();
+        let data = lib.as_lib().to_strict_serialized::<LIB_MAX_LEN>().unwrap();
+        let site = lib.routine(0);
+        assert_eq!(
+            fuzz_execute(&data),
+            Some(HaltReason::Fault(ExecError::StepLimitExceeded(site)))
+        );
+    }
+
+    #[test]
+    fn random() {
+        let mut bytes = Bytes(0x5EED_F00D);
+        let libs: LibsSeg = tiny_bset![LibId::from([0xA5u8; 32])];
+        for round in 0..2000 {
+            let len = bytes.next() as usize + 1;
+            let input = bytes.take(len);
+            let _ = fuzz_execute(&input);
+            let libs = if round % 2 == 0 { none!() } else { libs.clone() };
+            let _ = fuzz_execute(&serialize(input, libs));
+        }
+    }
+}
@@ -0,0 +1,271 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::ops::Range;
+
+use super::{CompiledLib, DisassembleError, LibId, Marshaller};
+use crate::isa::{BytecodeRead, Instruction, Operand};
+
+/// Node of a control flow graph, covering code of a single routine.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct BasicBlock {
+    /// Number of the routine; `None` for the code preceding the first routine.
+    pub routine: Option<u16>,
+    /// Byte span of the block in the code segment.
+    pub span: Range<u16>,
+}
+
+/// Kind of the control flow transition between basic blocks.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(lowercase)]
+pub enum EdgeKind {
+    /// Goto by an instruction which never passes the control to the next instruction.
+    Unconditional,
+
+    /// Goto by an instruction which may also pass the control to the next instruction, like
+    /// conditional jumps, indirect gotos or subroutine calls.
+    Conditional,
+
+    /// Passing control from the last instruction of a block to the following block.
+    FallThrough,
+}
+
+/// Edge of a control flow graph.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct CfgEdge {
+    /// Index of the source block.
+    pub from: usize,
+    /// Index of the destination block.
+    pub to: usize,
+    /// Kind of the transition.
+    pub kind: EdgeKind,
+}
+
+/// Control flow graph of a library, constructed with [`CompiledLib::cfg`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct Cfg {
+    blocks: Vec<BasicBlock>,
+    edges: BTreeSet<CfgEdge>,
+}
+
+impl Cfg {
+    /// Returns basic blocks, ordered by their position in the code.
+    pub fn blocks(&self) -> &[BasicBlock] { &self.blocks }
+
+    /// Returns edges between the basic blocks.
+    pub fn edges(&self) -> impl Iterator<Item = &CfgEdge> { self.edges.iter() }
+
+    /// Renders the graph in the DOT format.
+    ///
+    /// Unconditional edges are drawn with solid lines, conditional with dashed, and fall-through
+    /// edges with dotted lines.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph {\n");
+        for (no, block) in self.blocks.iter().enumerate() {
+            let name = match block.routine {
+                Some(routine) => format!("routine {routine}"),
+                None => s!("preamble"),
+            };
+            let Range { start, end } = block.span;
+            let _ = writeln!(dot, "    b{no} [label=\"{name}\\n{start:04X}#h..{end:04X}#h\"];");
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Unconditional => "solid",
+                EdgeKind::Conditional => "dashed",
+                EdgeKind::FallThrough => "dotted",
+            };
+            let _ = writeln!(dot, "    b{} -> b{} [style={style}];", edge.from, edge.to);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+impl CompiledLib {
+    /// Constructs control flow graph of the library code.
+    ///
+    /// The code is split into basic blocks at the routine entry points, and the blocks are
+    /// connected with the edges for local gotos and fall-through transitions. Gotos outside of the
+    /// library code (including gotos into [stubs](Self::is_stub)) are not represented.
+    pub fn cfg<Isa>(&self) -> Result<Cfg, DisassembleError>
+    where Isa: Instruction<LibId> {
+        let code_len = self.lib.code.len() as u16;
+        let mut starts = self.routines.iter().copied().map(Some).collect::<Vec<_>>();
+        if self.routines.first() != Some(&0) && code_len > 0 {
+            starts.insert(0, None);
+        }
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(index, start)| {
+                let routine = start.map(|_| (index - (starts[0].is_none() as usize)) as u16);
+                let start = start.unwrap_or_default();
+                let end = starts.get(index + 1).copied().flatten().unwrap_or(code_len);
+                BasicBlock { routine, span: start..end }
+            })
+            .collect::<Vec<_>>();
+        let block_at = |pos: u16| blocks.iter().position(|block| block.span.start == pos);
+
+        let mut edges = BTreeSet::new();
+        let mut block = 0usize;
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        while !reader.is_eof() {
+            let pos = reader.pos();
+            while blocks[block].span.end <= pos {
+                block += 1;
+            }
+            let mut instr =
+                Isa::decode_instr(&mut reader).map_err(|_| DisassembleError::Decode(pos))?;
+            let kind =
+                if instr.is_terminal() { EdgeKind::Unconditional } else { EdgeKind::Conditional };
+            let mut targets = instr.local_goto_table().to_vec();
+            targets.extend(instr.local_goto_target(pos));
+            for target in targets {
+                if let Some(to) = block_at(target) {
+                    edges.insert(CfgEdge { from: block, to, kind });
+                }
+            }
+            let next = reader.pos();
+            if !reader.is_eof() && next == blocks[block].span.end && !instr.is_terminal() {
+                edges.insert(CfgEdge { from: block, to: block + 1, kind: EdgeKind::FallThrough });
+            }
+        }
+
+        Ok(Cfg { blocks, edges })
+    }
+
+    /// Returns the code offsets of the instructions which may be executed right after the
+    /// instruction at the given `offset`, in ascending order.
+    ///
+    /// The successors include the next instruction (unless the instruction is
+    /// [terminal](Instruction::is_terminal) or is the last one in the code) and the targets of
+    /// local and relative gotos lying inside the code segment. Gotos outside of the library code
+    /// are not represented.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the instruction at the `offset` can't be decoded.
+    pub fn successors<Isa>(&self, offset: u16) -> Result<Vec<u16>, DisassembleError>
+    where Isa: Instruction<LibId> {
+        let code_len = self.lib.code.len() as u16;
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        reader
+            .seek(offset)
+            .map_err(|_| DisassembleError::Decode(offset))?;
+        let mut instr =
+            Isa::decode_instr(&mut reader).map_err(|_| DisassembleError::Decode(offset))?;
+
+        let mut successors = BTreeSet::new();
+        if !instr.is_terminal() && !reader.is_eof() {
+            successors.insert(reader.pos());
+        }
+        for operand in &instr.operands() {
+            if let Operand::Shift(shift) = operand {
+                successors.extend(offset.checked_add_signed(*shift));
+            }
+        }
+        successors.extend(instr.local_goto_table().iter().copied());
+        successors.extend(instr.local_goto_target(offset));
+        successors.retain(|pos| *pos < code_len);
+        Ok(successors.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::aluasm;
+    use crate::isa::{CtrlInstr, Instr};
+
+    #[test]
+    fn cfg() {
+        const LOOP: u16 = 0;
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            not         CO      ;
+            .routine    :LOOP   ;
+            call        :FUNC   ;
+            jif         CO, :LOOP ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let cfg = lib.cfg::<Instr<LibId>>().unwrap();
+        assert_eq!(cfg.blocks(), &[
+            BasicBlock { routine: None, span: 0..1 },
+            BasicBlock { routine: Some(0), span: 1..9 },
+            BasicBlock { routine: Some(1), span: 9..11 },
+        ]);
+        assert_eq!(cfg.edges().copied().collect::<Vec<_>>(), vec![
+            CfgEdge { from: 0, to: 1, kind: EdgeKind::FallThrough },
+            CfgEdge { from: 1, to: 1, kind: EdgeKind::Conditional },
+            CfgEdge { from: 1, to: 2, kind: EdgeKind::Conditional },
+        ]);
+        assert_eq!(
+            cfg.to_dot(),
+            "digraph {
+    b0 [label=\"preamble\\n0000#h..0001#h\"];
+    b1 [label=\"routine 0\\n0001#h..0009#h\"];
+    b2 [label=\"routine 1\\n0009#h..000B#h\"];
+    b0 -> b1 [style=dotted];
+    b1 -> b1 [style=dashed];
+    b1 -> b2 [style=dashed];
+}
+"
+        );
+    }
+
+    #[test]
+    fn successors() {
+        const LOOP: u16 = 0;
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            not         CO      ;
+            .routine    :LOOP   ;
+            call        :FUNC   ;
+            jif         CO, :LOOP ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let successors = |pos| lib.successors::<Instr<LibId>>(pos).unwrap();
+        assert_eq!(successors(0), vec![1]);
+        assert_eq!(successors(2), vec![5, 9]);
+        assert_eq!(successors(5), vec![1, 8]);
+        assert_eq!(successors(8), Vec::<u16>::new());
+        assert_eq!(successors(10), Vec::<u16>::new());
+        assert_eq!(lib.successors::<Instr<LibId>>(11), Err(DisassembleError::Decode(11)));
+
+        let code = vec![Instr::from(CtrlInstr::Nop), CtrlInstr::Sh { shift: -1 }.into()];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        assert_eq!(lib.successors::<Instr<LibId>>(1), Ok(vec![0]));
+    }
+}
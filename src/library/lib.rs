@@ -36,8 +36,16 @@ use crate::core::SiteId;
 use crate::{IsaId, Site, LIB_NAME_ALUVM};
 
 pub const LIB_ID_TAG: &str = "urn:ubideco:aluvm:lib:v01#241020";
+pub const ROUTINE_ID_TAG: &str = "urn:ubideco:aluvm:routine:v01#261014";
 
 /// Unique identifier for an AluVM library.
+///
+/// The canonical string representation of the id is Baid64 encoding of its 32 bytes, prefixed
+/// with `alu:` and followed by a mnemonic checksum, like
+/// `alu:uZkzX1J9-i5EvGTf-J1TB79p-OBvKq5x-1U2n4qd-8Nso3Ag#reunion-cable-tractor`. The prefix, the
+/// dashes and the checksum may be omitted when parsing; if the checksum is present, it is verified,
+/// such that a typo in the id results in [`Baid64ParseError::InvalidChecksum`], while an id of a
+/// wrong length results in [`Baid64ParseError::InvalidLen`].
 #[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Default, Debug, From)]
 #[wrapper(Deref, BorrowSlice, Hex, Index, RangeOps)]
 #[derive(StrictType, StrictEncode, StrictDecode)]
@@ -76,6 +84,103 @@ impl From<Sha256> for LibId {
     fn from(hash: Sha256) -> Self { Self(Bytes32::from_byte_array(hash.finalize())) }
 }
 
+/// Content hash of a single library routine bytecode, returned by
+/// [`super::CompiledLib::routine_id`].
+///
+/// The id commits only to the routine bytecode, and not to the data or library segment entries it
+/// references; thus routines with the same id may be reused across library versions only if they
+/// are checked to refer to the same data.
+#[derive(Wrapper, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Default, Debug, Display, From)]
+#[wrapper(Deref, BorrowSlice, Hex, Index, RangeOps)]
+#[display(inner)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct RoutineId(
+    #[from]
+    #[from([u8; 32])]
+    Bytes32,
+);
+
+impl RoutineId {
+    /// Computes the routine id from its bytecode as a tagged SHA256 hash:
+    ///
+    /// ```text
+    /// tag = SHA256("urn:ubideco:aluvm:routine:v01#261014")
+    /// id = SHA256(tag || tag || code)
+    /// ```
+    pub fn from_code(code: &[u8]) -> Self {
+        let tag = Sha256::digest(ROUTINE_ID_TAG.as_bytes());
+        let mut hasher = Sha256::new();
+        hasher.update(tag);
+        hasher.update(tag);
+        hasher.update(code);
+        Self(Bytes32::from_byte_array(hasher.finalize()))
+    }
+}
+
+impl LibId {
+    /// Placeholder id referenced by the external calls which are left by the compiler as
+    /// [relocations](super::Reloc), until they are patched with
+    /// [`super::CompiledLib::apply_relocs`].
+    pub const RELOC: LibId = LibId(Bytes32::zero());
+
+    /// Computes library id from the library segments without constructing [`Lib`].
+    ///
+    /// The id is the same as returned by [`Lib::lib_id`], and it commits to all library segments,
+    /// not just the code. It is computed as a tagged SHA256 hash:
+    ///
+    /// ```text
+    /// tag = SHA256("urn:ubideco:aluvm:lib:v01#241020")
+    /// id = SHA256(tag || tag || isae || code || data || libs)
+    /// ```
+    ///
+    /// where the segments are strict-encoded as follows:
+    /// - `isae`: a single byte with the number of ISA extensions, followed by each of the ISA
+    ///   extension ids in their lexicographic order, each prefixed with its byte length as a single
+    ///   byte;
+    /// - `code` and `data`: segment length as 16-bit little-endian integer, followed by the segment
+    ///   bytes;
+    /// - `libs`: a single byte with the number of library dependencies, followed by each of the
+    ///   32-byte library ids in their lexicographic order.
+    pub fn from_segments(
+        isae: &TinyOrdSet<IsaId>,
+        code: &SmallBlob,
+        data: &SmallBlob,
+        libs: &LibsSeg,
+    ) -> Self {
+        Self::from_slices(isae, code.as_slice(), data.as_slice(), libs)
+    }
+
+    /// Computes library id in the same way as [`Self::from_segments`], taking the code and data
+    /// segments as byte slices.
+    pub(super) fn from_slices(
+        isae: &TinyOrdSet<IsaId>,
+        code: &[u8],
+        data: &[u8],
+        libs: &LibsSeg,
+    ) -> Self {
+        let tag = Sha256::digest(LIB_ID_TAG.as_bytes());
+        let mut hasher = Sha256::new();
+        hasher.update(tag);
+        hasher.update(tag);
+
+        hasher.update([isae.len() as u8]);
+        for isa in isae {
+            hasher.update([isa.len() as u8]);
+            hasher.update(isa.as_bytes());
+        }
+        for segment in [code, data] {
+            hasher.update((segment.len() as u16).to_le_bytes());
+            hasher.update(segment);
+        }
+        hasher.update([libs.len() as u8]);
+        for lib in libs {
+            hasher.update(lib.as_slice());
+        }
+
+        hasher.into()
+    }
+}
+
 /// Location inside the instruction sequence which can be executed by the core.
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 #[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
@@ -90,13 +195,51 @@ impl From<Site<LibId>> for LibSite {
     fn from(site: Site<LibId>) -> Self { Self { lib_id: site.prog_id, offset: site.offset } }
 }
 
+impl Display for LibSite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}@{:04X}#h", self.lib_id, self.offset)
+    }
+}
+
 impl LibSite {
     #[inline]
     pub fn new(lib_id: LibId, offset: u16) -> Self { LibSite { lib_id, offset } }
+
+    /// Returns id of the library the site belongs to.
+    #[inline]
+    pub fn lib_id(&self) -> LibId { self.lib_id }
+
+    /// Returns code offset of the site within the library.
+    #[inline]
+    pub fn offset(&self) -> u16 { self.offset }
+
+    /// Computes a site within the same library which is `delta` bytes further in the code.
+    ///
+    /// Returns `None` if the resulting offset doesn't fit the 16-bit code segment.
+    #[inline]
+    pub fn checked_add(&self, delta: u16) -> Option<LibSite> {
+        self.offset
+            .checked_add(delta)
+            .map(|offset| LibSite::new(self.lib_id, offset))
+    }
+
+    /// Computes a site within the same library which is `delta` bytes back in the code.
+    ///
+    /// Returns `None` if the resulting offset is negative.
+    #[inline]
+    pub fn checked_sub(&self, delta: u16) -> Option<LibSite> {
+        self.offset
+            .checked_sub(delta)
+            .map(|offset| LibSite::new(self.lib_id, offset))
+    }
 }
 
 pub type LibsSeg = TinyOrdSet<LibId>;
 
+/// Upper bound for the length of a strict-serialized library (which consists of up to 255 ISA
+/// extension ids, two segments of up to 64kB each and up to 255 library ids).
+pub(super) const LIB_MAX_LEN: usize = 0x40000;
+
 #[derive(Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Debug)]
 #[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
 #[strict_type(lib = LIB_NAME_ALUVM)]
@@ -118,6 +261,18 @@ impl AsRef<Lib> for Lib {
 }
 
 impl Lib {
+    /// Maximal length of the code segment, in bytes.
+    pub const MAX_CODE_LEN: usize = u16::MAX as usize;
+
+    /// Maximal length of the data segment, in bytes.
+    pub const MAX_DATA_LEN: usize = u16::MAX as usize;
+
+    /// Maximal number of the libraries which can be called from the library code.
+    pub const MAX_LIBS: usize = u8::MAX as usize;
+
+    /// Maximal number of the ISA extensions used by the library.
+    pub const MAX_ISAE: usize = u8::MAX as usize;
+
     pub fn lib_id(&self) -> LibId { self.commit_id() }
 
     pub fn isae_string(&self) -> String {
@@ -171,6 +326,20 @@ mod test {
         assert_eq!(format!("{id:-#}"), "uZkzX1J9-i5EvGTf-J1TB79p-OBvKq5x-1U2n4qd-8Nso3Ag");
     }
 
+    #[test]
+    fn lib_id_from_segments() {
+        let lib = Lib::strict_dumb();
+        assert_eq!(LibId::from_segments(&lib.isae, &lib.code, &lib.data, &lib.libs), lib.lib_id());
+
+        let lib = Lib {
+            isae: tiny_bset![IsaId::from("ALU"), IsaId::from("BPDIGEST")],
+            code: small_blob![0x00, 0x01, 0x02, 0x03],
+            data: small_blob![0xFF; 48],
+            libs: tiny_bset![lib.lib_id(), LibId::from([0xA5u8; 32])],
+        };
+        assert_eq!(LibId::from_segments(&lib.isae, &lib.code, &lib.data, &lib.libs), lib.lib_id());
+    }
+
     #[test]
     fn lib_id_from_str() {
         let id = Lib::strict_dumb().lib_id();
@@ -191,5 +360,33 @@ mod test {
         );
 
         assert_eq!(id, LibId::from_str("uZkzX1J9i5EvGTfJ1TB79pOBvKq5x1U2n4qd8Nso3Ag").unwrap());
+        assert_eq!(id, LibId::from_str(&id.to_string()).unwrap());
+
+        assert!(matches!(
+            LibId::from_str(
+                "alu:uZkzX1J8-i5EvGTf-J1TB79p-OBvKq5x-1U2n4qd-8Nso3Ag#reunion-cable-tractor"
+            ),
+            Err(Baid64ParseError::InvalidChecksum(..))
+        ));
+        assert!(matches!(
+            LibId::from_str("alu:uZkzX1J9-i5EvGTf-J1TB79p-OBvKq5x-1U2n4qd"),
+            Err(Baid64ParseError::InvalidLen(_))
+        ));
+        assert!(matches!(
+            LibId::from_str("lib:uZkzX1J9-i5EvGTf-J1TB79p-OBvKq5x-1U2n4qd-8Nso3Ag"),
+            Err(Baid64ParseError::InvalidHri(..))
+        ));
+    }
+
+    #[test]
+    fn lib_site_arithmetics() {
+        let id = Lib::strict_dumb().lib_id();
+        let site = LibSite::new(id, 0xFFF0);
+        assert_eq!(site.lib_id(), id);
+        assert_eq!(site.offset(), 0xFFF0);
+        assert_eq!(site.checked_add(0x0F), Some(LibSite::new(id, 0xFFFF)));
+        assert_eq!(site.checked_add(0x10), None);
+        assert_eq!(site.checked_sub(0xFFF0), Some(LibSite::new(id, 0)));
+        assert_eq!(site.checked_sub(0xFFF1), None);
     }
 }
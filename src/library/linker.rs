@@ -0,0 +1,447 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::{self, Display, Formatter};
+
+use super::{AssemblerError, CompiledLib, Lib, LibId, LibSite, Marshaller};
+use crate::isa::{BytecodeRead, Instruction};
+use crate::Site;
+
+/// External call left by the compiler as a named relocation, which is patched with the actual
+/// call site by [`CompiledLib::apply_relocs`].
+///
+/// Until patched, the call references [`LibId::RELOC`] library, and its execution halts the VM
+/// with [`crate::ExecError::UnresolvedReloc`].
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{symbol}@{site_offset:#x}")]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub struct Reloc {
+    /// Offset of the external call instruction in the library code.
+    pub site_offset: u16,
+    /// Symbol naming the call target.
+    pub symbol: String,
+}
+
+/// Number of the argument and return registers of a routine calling convention (see
+/// [`crate::CallConv::arity`]), which is checked by the linker across the library boundaries.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{args}->{returns}")]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub struct Arity {
+    /// Number of the argument registers.
+    pub args: u16,
+    /// Number of the return registers.
+    pub returns: u16,
+}
+
+/// External reference which can't be linked to any of the dependencies.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display(doc_comments)]
+pub enum UnlinkedRef {
+    /// instruction at offset {0:#x} references library {1}, which is not provided as a
+    /// dependency.
+    Unresolved(u16, LibId),
+
+    /// instruction at offset {0:#x} references offset {2:#x} in library {1}, which is not an entry
+    /// point of any routine.
+    Misaligned(u16, LibId, u16),
+
+    /// instruction at offset {0:#x} references routine {2} of library {1}, which is not exported.
+    Private(u16, LibId, u16),
+}
+
+/// Errors happening during linking of a compiled library with its dependencies.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Error)]
+pub enum LinkError {
+    /// Unable to decode instruction at the provided offset.
+    Decode(u16),
+
+    /// Some of the external references can't be linked.
+    Unlinked(Vec<UnlinkedRef>),
+
+    /// Relocation at the provided offset doesn't point to an external call.
+    InvalidReloc(u16),
+
+    /// Unable to assemble the patched code.
+    Assemble(AssemblerError),
+
+    /// External call at the provided offset expects the callee to have a different arity than
+    /// declared by the callee library.
+    ArityMismatch {
+        /// Offset of the external call instruction.
+        offset: u16,
+        /// Arity declared by the callee library.
+        expected: Arity,
+        /// Arity expected by the caller.
+        found: Arity,
+    },
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            LinkError::Decode(pos) => write!(f, "unable to decode instruction at offset {pos:#x}"),
+            LinkError::InvalidReloc(pos) => {
+                write!(f, "relocation at offset {pos:#x} doesn't point to an external call")
+            }
+            LinkError::Assemble(err) => Display::fmt(err, f),
+            LinkError::ArityMismatch { offset, expected, found } => write!(
+                f,
+                "external call at offset {offset:#x} expects arity {found}, while the callee \
+                 routine has arity {expected}"
+            ),
+            LinkError::Unlinked(refs) => {
+                writeln!(f, "library has {} unlinked external references:", refs.len())?;
+                for r in refs {
+                    writeln!(f, "- {r}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl CompiledLib {
+    /// Checks that all external calls made by the library code land on the entry points of the
+    /// [exported](Self::is_exported) routines inside the provided dependencies.
+    ///
+    /// If the library [expects](Self::with_call_arity) the called routine to have some arity, and
+    /// the dependency [declares](Self::with_arity) the arity of the routine, the arities must
+    /// match.
+    ///
+    /// # Returns
+    ///
+    /// All the found external references which can't be linked, combined into
+    /// [`LinkError::Unlinked`], or the first [`LinkError::ArityMismatch`] if all the references
+    /// can be linked.
+    pub fn link<Isa>(&self, deps: &[&CompiledLib]) -> Result<(), LinkError>
+    where Isa: Instruction<LibId> {
+        let mut unlinked = vec![];
+        let mut mismatch = None;
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        while !reader.is_eof() {
+            let pos = reader.pos();
+            let mut instr = Isa::decode_instr(&mut reader).map_err(|_| LinkError::Decode(pos))?;
+            let Some(site) = instr.remote_goto_pos().copied().map(LibSite::from) else {
+                continue;
+            };
+            let (lib, no) = match callee(deps, pos, site) {
+                Ok(callee) => callee,
+                Err(unlinked_ref) => {
+                    unlinked.push(unlinked_ref);
+                    continue;
+                }
+            };
+            if let (Some(expected), Some(found)) = (lib.arity(no), self.call_arity(site)) {
+                if expected != found && mismatch.is_none() {
+                    mismatch = Some(LinkError::ArityMismatch { offset: pos, expected, found });
+                }
+            }
+        }
+        if !unlinked.is_empty() {
+            return Err(LinkError::Unlinked(unlinked));
+        }
+        mismatch.map_or(Ok(()), Err)
+    }
+
+    /// Declares the arity of the calling convention of a library routine, which is checked when
+    /// other libraries calling the routine are [linked](Self::link).
+    ///
+    /// The arity doesn't affect the code execution and is not a part of the [`Lib`].
+    pub fn with_arity(mut self, routine_no: u16, arity: Arity) -> Self {
+        self.arities.insert(routine_no, arity);
+        self
+    }
+
+    /// Returns the declared arity of a library routine, if any.
+    pub fn arity(&self, routine_no: u16) -> Option<Arity> { self.arities.get(&routine_no).copied() }
+
+    /// Declares the arity which the library code expects from an external routine it calls, which
+    /// is checked when the library is [linked](Self::link).
+    ///
+    /// The arity doesn't affect the code execution and is not a part of the [`Lib`].
+    pub fn with_call_arity(mut self, callee: LibSite, arity: Arity) -> Self {
+        self.call_arities.insert(callee, arity);
+        self
+    }
+
+    /// Returns the arity which the library code expects from an external routine, if declared.
+    pub fn call_arity(&self, callee: LibSite) -> Option<Arity> {
+        self.call_arities.get(&callee).copied()
+    }
+
+    /// Returns relocations which are not patched yet, ordered by their offsets.
+    pub fn relocs(&self) -> &[Reloc] { &self.relocs }
+
+    /// Patches the relocations with the call sites resolved for their symbols, re-assembling the
+    /// library (which changes its id).
+    ///
+    /// The resolved call sites must land on the entry points of the [exported](Self::is_exported)
+    /// routines of the provided dependencies; otherwise all such relocations are reported with
+    /// [`LinkError::Unlinked`] and the library is left unchanged. Relocations with symbols absent
+    /// in `resolved` are kept, and can be patched later.
+    pub fn apply_relocs<Isa>(
+        &mut self,
+        resolved: &BTreeMap<String, LibSite>,
+        deps: &[&CompiledLib],
+    ) -> Result<(), LinkError>
+    where
+        Isa: Instruction<LibId>,
+    {
+        if !self
+            .relocs
+            .iter()
+            .any(|reloc| resolved.contains_key(&reloc.symbol))
+        {
+            return Ok(());
+        }
+        let mut code = Vec::new();
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        let mut relocs = self.relocs.iter().peekable();
+        let mut unresolved = Vec::new();
+        let mut unlinked = Vec::new();
+        while !reader.is_eof() {
+            let pos = reader.pos();
+            let mut instr = Isa::decode_instr(&mut reader).map_err(|_| LinkError::Decode(pos))?;
+            while let Some(reloc) = relocs.next_if(|reloc| reloc.site_offset <= pos) {
+                if reloc.site_offset < pos {
+                    return Err(LinkError::InvalidReloc(reloc.site_offset));
+                }
+                let Some(site) = resolved.get(&reloc.symbol) else {
+                    unresolved.push(reloc.clone());
+                    continue;
+                };
+                let Some(remote_pos) = instr.remote_goto_pos() else {
+                    return Err(LinkError::InvalidReloc(pos));
+                };
+                if let Err(unlinked_ref) = callee(deps, pos, *site) {
+                    unlinked.push(unlinked_ref);
+                }
+                *remote_pos = Site::new(site.lib_id, site.offset);
+            }
+            code.push(instr);
+        }
+        if let Some(reloc) = relocs.next() {
+            return Err(LinkError::InvalidReloc(reloc.site_offset));
+        }
+        if !unlinked.is_empty() {
+            return Err(LinkError::Unlinked(unlinked));
+        }
+        self.lib = Lib::assemble(&code).map_err(LinkError::Assemble)?;
+        self.id = self.lib.lib_id();
+        self.relocs = unresolved;
+        Ok(())
+    }
+}
+
+/// Finds a dependency routine which entry point is called by the instruction at offset `pos`,
+/// returning the dependency and the routine number.
+fn callee<'lib>(
+    deps: &[&'lib CompiledLib],
+    pos: u16,
+    site: LibSite,
+) -> Result<(&'lib CompiledLib, u16), UnlinkedRef> {
+    let lib = deps
+        .iter()
+        .find(|lib| lib.id == site.lib_id)
+        .ok_or(UnlinkedRef::Unresolved(pos, site.lib_id))?;
+    let no = lib
+        .routines
+        .iter()
+        .position(|pos| *pos == site.offset)
+        .ok_or(UnlinkedRef::Misaligned(pos, site.lib_id, site.offset))? as u16;
+    if !lib.is_exported(no) {
+        return Err(UnlinkedRef::Private(pos, site.lib_id, no));
+    }
+    Ok((lib, no))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{CtrlInstr, Instr};
+    use crate::library::Lib;
+    use crate::{aluasm, Site};
+
+    #[test]
+    fn link() {
+        let dep = CompiledLib::compile(
+            aluasm! {
+                nop     ;
+                nop     ;
+                ret     ;
+            },
+            &[],
+        )
+        .unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        let code: Vec<Instr<LibId>> =
+            vec![CtrlInstr::Call { site: Site::new(dep_id, 1) }.into(), CtrlInstr::Stop.into()];
+        let lib = CompiledLib::compile(code, &[&dep]).unwrap();
+        lib.link::<Instr<LibId>>(&[&dep]).unwrap();
+        assert_eq!(
+            lib.link::<Instr<LibId>>(&[]).unwrap_err(),
+            LinkError::Unlinked(vec![UnlinkedRef::Unresolved(0, dep_id)])
+        );
+        let mut private = dep.clone();
+        private.exports = Some(none!());
+        assert_eq!(
+            lib.link::<Instr<LibId>>(&[&private]).unwrap_err(),
+            LinkError::Unlinked(vec![UnlinkedRef::Private(0, dep_id, 1)])
+        );
+
+        let code: Vec<Instr<LibId>> = vec![CtrlInstr::Exec { site: Site::new(dep_id, 2) }.into()];
+        let lib = Lib::assemble(&code).unwrap();
+        let lib = CompiledLib {
+            id: lib.lib_id(),
+            lib,
+            routines: vec![],
+            names: none!(),
+            exports: None,
+            stubs: none!(),
+            version: default!(),
+            source_map: vec![],
+            entry: None,
+            meta: none!(),
+            relocs: vec![],
+            arities: none!(),
+            call_arities: none!(),
+        };
+        assert_eq!(
+            lib.link::<Instr<LibId>>(&[&dep]).unwrap_err(),
+            LinkError::Unlinked(vec![UnlinkedRef::Misaligned(0, dep_id, 2)])
+        );
+    }
+
+    #[test]
+    fn arity() {
+        let unary = Arity { args: 1, returns: 1 };
+        let nullary = Arity { args: 0, returns: 1 };
+        let dep = CompiledLib::compile(aluasm! { nop; nop; ret; }, &[])
+            .unwrap()
+            .with_arity(1, unary);
+        assert_eq!(dep.arity(1), Some(unary));
+        assert_eq!(dep.arity(0), None);
+
+        let callee = dep.routine(1);
+        let code: Vec<Instr<LibId>> = vec![
+            CtrlInstr::Call { site: Site::new(callee.lib_id, callee.offset) }.into(),
+            CtrlInstr::Stop.into(),
+        ];
+        let lib = CompiledLib::compile(code, &[&dep]).unwrap();
+        lib.link::<Instr<LibId>>(&[&dep]).unwrap();
+
+        let lib = lib.with_call_arity(callee, nullary);
+        assert_eq!(lib.call_arity(callee), Some(nullary));
+        assert_eq!(lib.link::<Instr<LibId>>(&[&dep]).unwrap_err(), LinkError::ArityMismatch {
+            offset: 0,
+            expected: unary,
+            found: nullary
+        });
+        let lib = lib.with_call_arity(callee, unary);
+        lib.link::<Instr<LibId>>(&[&dep]).unwrap();
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&lib).unwrap();
+            assert_eq!(serde_json::from_str::<CompiledLib>(&json).unwrap(), lib);
+        }
+    }
+
+    #[test]
+    fn relocs() {
+        let dep = CompiledLib::compile(
+            aluasm! {
+                nop     ;
+                nop     ;
+                ret     ;
+            },
+            &[],
+        )
+        .unwrap();
+        let code: Vec<Instr<LibId>> = vec![
+            CtrlInstr::Nop.into(),
+            CtrlInstr::Call { site: Site::new(LibId::RELOC, 0) }.into(),
+            CtrlInstr::Stop.into(),
+        ];
+        let symbols = [None, Some("dep.ret")];
+        let mut lib =
+            CompiledLib::compile_relocatable(code.clone(), &[], &symbols, &[], &default!())
+                .unwrap();
+        assert_eq!(lib.relocs(), &[Reloc { site_offset: 1, symbol: s!("dep.ret") }]);
+
+        let resolver = |id: LibId| {
+            [&lib, &dep]
+                .into_iter()
+                .find(|l| l.id == id)
+                .map(|l| &l.lib)
+        };
+        let mut vm = crate::Vm::<Instr<LibId>>::new();
+        let call_site = LibSite::new(lib.id, 1);
+        assert_eq!(
+            vm.run(lib.routine(0), &(), resolver),
+            Err(crate::ExecError::UnresolvedReloc(call_site))
+        );
+
+        let mut resolved = BTreeMap::new();
+        lib.apply_relocs::<Instr<LibId>>(&resolved, &[&dep])
+            .unwrap();
+        assert_eq!(lib.relocs().len(), 1);
+        resolved.insert(s!("dep.ret"), dep.routine(1));
+        assert_eq!(
+            lib.apply_relocs::<Instr<LibId>>(&resolved, &[]),
+            Err(LinkError::Unlinked(vec![UnlinkedRef::Unresolved(1, dep.id)]))
+        );
+        let mut private = dep.clone();
+        private.exports = Some(bset![0]);
+        assert_eq!(
+            lib.apply_relocs::<Instr<LibId>>(&resolved, &[&private]),
+            Err(LinkError::Unlinked(vec![UnlinkedRef::Private(1, dep.id, 1)]))
+        );
+        assert_eq!(lib.relocs().len(), 1);
+        lib.apply_relocs::<Instr<LibId>>(&resolved, &[&dep])
+            .unwrap();
+        assert!(lib.relocs().is_empty());
+        lib.link::<Instr<LibId>>(&[&dep]).unwrap();
+
+        let resolver = |id: LibId| {
+            [&lib, &dep]
+                .into_iter()
+                .find(|l| l.id == id)
+                .map(|l| &l.lib)
+        };
+        let mut vm = crate::Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.run(lib.routine(0), &(), resolver), Ok(crate::regs::Status::Ok));
+        assert_eq!(vm.steps(), 5);
+
+        let symbols = [Some("dep.ret")];
+        assert!(matches!(
+            CompiledLib::compile_relocatable(code, &[], &symbols, &[], &default!()),
+            Err(crate::CompilerError::RelocNotCall(_, 0, _))
+        ));
+    }
+}
@@ -61,6 +61,8 @@ where
     bytecode: C,
     data: D,
     libs: &'a LibsSeg,
+    data_overflow: Option<u16>,
+    max_data_ref: u16,
 }
 
 impl<'a, C, D> Debug for Marshaller<'a, C, D>
@@ -76,6 +78,8 @@ where
             .field("bit_pos", &self.bit_pos)
             .field("data", &SmallBlob::from_slice_checked(self.data.as_ref()))
             .field("libs", &self.libs)
+            .field("data_overflow", &self.data_overflow)
+            .field("max_data_ref", &self.max_data_ref)
             .finish()
     }
 }
@@ -92,6 +96,8 @@ where Self: 'a
             bit_pos: u3::MIN,
             data: default!(),
             libs,
+            data_overflow: None,
+            max_data_ref: 0,
         }
     }
 
@@ -129,12 +135,28 @@ where
     /// If the length of the bytecode or data segment exceeds 0xFF.
     #[inline]
     pub fn with(bytecode: C, data: D, libs: &'a LibsSeg) -> Self {
-        Self { bytecode, byte_pos: 0, bit_pos: u3::MIN, data, libs }
+        Self {
+            bytecode,
+            byte_pos: 0,
+            bit_pos: u3::MIN,
+            data,
+            libs,
+            data_overflow: None,
+            max_data_ref: 0,
+        }
     }
 
     /// Returns the current offset of the marshaller
     pub const fn offset(&self) -> (u16, u3) { (self.byte_pos, self.bit_pos) }
 
+    /// Returns the offset of the first data reference read by the marshaller which spans past the
+    /// end of the data segment, if any.
+    pub const fn data_overflow(&self) -> Option<u16> { self.data_overflow }
+
+    /// Returns the length of the largest data reference (i.e. an immediate value stored in the
+    /// data segment) read by the marshaller so far.
+    pub const fn max_data_ref(&self) -> u16 { self.max_data_ref }
+
     fn read(&mut self, bit_count: u5) -> Result<u32, CodeEofError> {
         let mut ret = 0u32;
         let mut cnt = bit_count.to_u8();
@@ -330,7 +352,9 @@ where
     ) -> Result<N, CodeEofError> {
         let pos = self.read_word()? as usize;
         let end = pos + LEN;
+        self.max_data_ref = self.max_data_ref.max(LEN.min(u16::MAX as usize) as u16);
         if end > self.data.as_ref().len() {
+            self.data_overflow.get_or_insert(pos as u16);
             return Err(CodeEofError);
         }
         let mut buf = [0u8; LEN];
@@ -340,9 +364,16 @@ where
 
     fn read_bytes(&mut self) -> Result<(SmallBlob, bool), CodeEofError> {
         let pos = self.read_word()? as usize;
-        let end = pos + self.read_word()? as usize;
-        let ck = end >= self.data.as_ref().len();
-        let data = &self.data.as_ref()[pos.min(0xFF)..end.min(0xFF)];
+        let data_len = self.read_word()?;
+        self.max_data_ref = self.max_data_ref.max(data_len);
+        let end = pos + data_len as usize;
+        if end > self.data.as_ref().len() {
+            self.data_overflow.get_or_insert(pos as u16);
+        }
+        let len = self.data.as_ref().len();
+        let ck = end >= len;
+        // Out-of-bounds references are truncated to the end of the data segment
+        let data = &self.data.as_ref()[pos.min(0xFF).min(len)..end.min(0xFF).min(len)];
         Ok((SmallBlob::from_slice_checked(data), ck))
     }
 
@@ -493,6 +524,9 @@ mod tests {
         let number = 255u8;
         marshaller.write_fixed(255u8.to_le_bytes()).unwrap();
         let (code, data) = marshaller.finish();
+        // Bits are packed starting from the lowest bit; the word is little-endian
+        assert_eq!(code.as_slice(), &[0xF7, 0x7B, 0xDE, 0xAA, 0xF0, 0x00, 0x00]);
+        assert_eq!(data.as_slice(), &[0xFF]);
 
         let mut marshaller = Marshaller::with(code, data, &libseg);
         assert_eq!(marshaller.read_2bits().unwrap().to_u8(), 0b00000011);
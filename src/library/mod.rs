@@ -27,11 +27,59 @@ mod lib;
 pub mod armor;
 mod assembler;
 mod compiler;
+#[cfg(feature = "compress")]
+mod compress;
+mod batch;
+mod builder;
+#[cfg(feature = "std")]
+mod bundle;
+mod bytes;
+mod diff;
+#[cfg(feature = "fuzz")]
+mod fuzz;
+mod graph;
+pub(crate) mod optimizer;
+mod linker;
+mod patch;
+mod prepared;
+mod signed;
+mod stats;
+#[cfg(feature = "log")]
+mod trace;
+mod verifier;
+mod view;
 mod marshaller;
+#[cfg(feature = "std")]
+mod stream;
 mod exec;
 
-pub use assembler::AssemblerError;
-pub use compiler::{CompiledLib, CompilerError};
-pub use exec::Jump;
-pub use lib::{Lib, LibId, LibSite, LibsSeg};
+pub use assembler::{AssemblerError, DecodeError, DisassembleError};
+pub use batch::{CyclicDeps, DataPool, GlobalRoutineId, LibSet, LibSetError};
+pub use builder::{LibBuilder, RoutineRef};
+#[cfg(feature = "std")]
+pub use bundle::{BundleError, LibBundle};
+pub use bytes::{run_bytes, RegCodec, RunBytesError};
+pub use compiler::{
+    CompileOpts, CompilePlan, CompileReport, CompiledLib, CompilerError, EncodingVersion, Export,
+    FromPartsError, GotoMode, GotoTargets, LibIdMismatch, LibLoadError, UnresolvedRef, STUB_OFFSET,
+};
+#[cfg(feature = "compress")]
+pub use compress::{DecompressError, COMPRESSED_MAGIC, DECOMPRESSED_MAX_LEN};
+pub use diff::LibDiff;
+pub(crate) use exec::{ExecHook, HookBreak};
+pub use exec::{HaltReason, Jump};
+#[cfg(feature = "fuzz")]
+pub use fuzz::{fuzz_execute, FUZZ_STEP_LIMIT};
+pub use graph::{BasicBlock, Cfg, CfgEdge, EdgeKind};
+pub use lib::{Lib, LibId, LibSite, LibsSeg, RoutineId};
+pub use linker::{Arity, LinkError, Reloc, UnlinkedRef};
 pub use marshaller::{MarshallError, Marshaller};
+pub use optimizer::{canonicalize, eliminate_dead_stores, inline_routines, optimize, Inlined};
+pub use patch::{LibPatch, PatchApplyError, PatchError, SegmentDelta};
+pub use prepared::PreparedLib;
+pub use signed::{LibSigner, SigScheme, SignatureError, SignedLib};
+pub use stats::LibStats;
+#[cfg(feature = "std")]
+pub use stream::{InstructionReader, StreamError};
+pub use verifier::{VerifyError, VerifyLimits};
+pub use view::{LibView, LibViewError};
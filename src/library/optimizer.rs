@@ -0,0 +1,778 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use super::LibId;
+use crate::isa::{Instruction, RegAccess};
+
+/// Optimizes the code before its compilation, folding sequences of instructions which can be
+/// evaluated at compile time (see [`Instruction::try_const_eval`]).
+///
+/// Goto targets are never folded, thus routine numbers (which are used by the local gotos before
+/// the compilation) remain valid. Routines containing [relative
+/// jumps](Instruction::is_relative_jump), or spanned by them, are left intact, since the change in
+/// the code length may break them.
+///
+/// The pass is not run by the compiler, and must be explicitly applied to the code if required.
+///
+/// # Returns
+///
+/// The number of the removed instructions.
+pub fn optimize<Isa>(code: &mut Vec<Isa>) -> usize
+where Isa: Instruction<LibId> {
+    let (routines, relative) = routines(code);
+
+    let len = code.len();
+    let mut folded = Vec::<Isa>::with_capacity(len);
+    for (instr, routine) in code.drain(..).zip(routines) {
+        if !instr.is_goto_target() && !relative.contains(&routine) {
+            if let Some(prev) = folded.last_mut().filter(|prev| !prev.is_goto_target()) {
+                if let Some(instr) = prev
+                    .try_const_eval(&instr)
+                    .filter(|instr| !instr.is_goto_target())
+                {
+                    *prev = instr;
+                    continue;
+                }
+            }
+        }
+        folded.push(instr);
+    }
+    *code = folded;
+    len - code.len()
+}
+
+/// Removes pure instructions (see [`RegAccess::pure`]) which values written into the registers
+/// are overwritten before being read, as reported by [`Instruction::reg_access`].
+///
+/// The analysis is conservative: all registers are considered to be read by non-pure
+/// instructions (which may jump elsewhere), and to be live at the routine boundaries and at the
+/// end of the code. Thus, only the writes which are overwritten within the same sequence of pure
+/// instructions are eliminated. Like with [`optimize`], goto targets are never removed, and
+/// routines containing or spanned by [relative jumps](Instruction::is_relative_jump) are left
+/// intact.
+///
+/// # Returns
+///
+/// The number of the removed instructions.
+pub fn eliminate_dead_stores<Isa>(code: &mut Vec<Isa>) -> usize
+where Isa: Instruction<LibId> {
+    let (routines, relative) = routines(code);
+    // Registers which are overwritten by the subsequent instructions before being read
+    let mut killed = BTreeSet::new();
+    let mut dead = BTreeSet::new();
+    for (no, instr) in code.iter().enumerate().rev() {
+        let RegAccess { reads, writes, pure } = instr.reg_access();
+        if !pure || relative.contains(&routines[no]) {
+            killed.clear();
+            continue;
+        }
+        if !instr.is_goto_target() && !writes.is_empty() && writes.is_subset(&killed) {
+            dead.insert(no);
+            continue;
+        }
+        if instr.is_goto_target() {
+            killed.clear();
+            continue;
+        }
+        for reg in reads {
+            killed.remove(&reg);
+        }
+        killed.extend(writes);
+    }
+
+    let len = code.len();
+    let mut no = 0usize;
+    code.retain(|_| {
+        no += 1;
+        !dead.contains(&(no - 1))
+    });
+    len - code.len()
+}
+
+/// Report on the inlining of the routines produced by [`inline_routines`].
+#[derive(Clone, Eq, PartialEq, Debug, Default)]
+pub struct Inlined {
+    /// Number of the call sites replaced with the body of the called routine.
+    pub calls: usize,
+    /// New number for each of the original routines, or `None` if the routine was removed.
+    pub routines: Vec<Option<u16>>,
+}
+
+/// Replaces the calls of small leaf routines with the body of the called routine.
+///
+/// A routine is inlined if it consists of instructions which neither jump nor terminate the
+/// execution, followed by a single [return](Instruction::is_return) instruction, and the byte
+/// length of its instructions (not counting the goto target and the return) doesn't exceed
+/// `threshold`. The [local calls](Instruction::is_local_call) of such routines are replaced with a
+/// copy of the routine body, except for the calls from the routines containing or spanned by
+/// [relative jumps](Instruction::is_relative_jump).
+///
+/// Routines which are no longer referenced after all their calls were inlined are removed, unless
+/// they are listed in `keep` (which must include the entry point and the routines called from
+/// other libraries), the control flow may fall through into them from the preceding code, or they
+/// are spanned by relative jumps. The local gotos of the remaining code are renumbered
+/// accordingly.
+///
+/// Like [`optimize`], the pass must be applied to the code before the compilation, when the local
+/// gotos use routine numbers. It is not run by the compiler.
+pub fn inline_routines<Isa>(code: &mut Vec<Isa>, threshold: u16, keep: &[u16]) -> Inlined
+where Isa: Instruction<LibId> {
+    let targets = code
+        .iter()
+        .enumerate()
+        .filter(|(_, instr)| instr.is_goto_target())
+        .map(|(no, _)| no)
+        .collect::<Vec<_>>();
+    let count = targets.len();
+    let (routines, relative) = routines(code);
+
+    // Bodies of the routines which can be inlined, without the goto target and the return
+    let mut bodies = Vec::<Option<Vec<Isa>>>::with_capacity(count);
+    for (routine, start) in targets.iter().copied().enumerate() {
+        let end = targets.get(routine + 1).copied().unwrap_or(code.len());
+        let Some((last, body)) = code[start + 1..end].split_last() else {
+            bodies.push(None);
+            continue;
+        };
+        let mut len = 0u32;
+        let leaf = last.is_return()
+            && body.iter().all(|instr| {
+                len += instr.code_byte_len() as u32;
+                let mut instr = instr.clone();
+                !instr.is_terminal()
+                    && !instr.is_return()
+                    && !instr.is_local_call()
+                    && !instr.is_relative_jump()
+                    && instr.local_goto_pos().is_none()
+                    && instr.local_goto_table().is_empty()
+                    && instr.remote_goto_pos().is_none()
+            });
+        bodies.push((leaf && len <= threshold as u32).then(|| body.to_vec()));
+    }
+
+    let mut calls = 0usize;
+    let mut inlined = BTreeSet::new();
+    let mut result = Vec::<Isa>::with_capacity(code.len());
+    for (mut instr, routine) in code.drain(..).zip(routines) {
+        if instr.is_local_call() && !relative.contains(&routine) {
+            let pos = instr.local_goto_pos().map(|pos| *pos as usize);
+            if let Some(body) = pos.and_then(|pos| bodies.get(pos)).and_then(Option::as_ref) {
+                result.extend(body.iter().cloned());
+                inlined.extend(pos);
+                calls += 1;
+                continue;
+            }
+        }
+        result.push(instr);
+    }
+
+    // Routines which are still used by the remaining local gotos
+    let mut used = BTreeSet::new();
+    for instr in &mut result {
+        used.extend(instr.local_goto_pos().map(|pos| *pos as usize));
+        used.extend(instr.local_goto_table().iter().map(|pos| *pos as usize));
+    }
+    let mut removed = BTreeSet::new();
+    let mut routine = 0usize;
+    let mut falls_through = false;
+    for instr in &result {
+        if instr.is_goto_target() {
+            if !falls_through
+                && !relative.contains(&(routine + 1))
+                && inlined.contains(&routine)
+                && !used.contains(&routine)
+                && !keep.contains(&(routine as u16))
+            {
+                removed.insert(routine);
+            }
+            routine += 1;
+        }
+        falls_through = !instr.is_terminal();
+    }
+
+    let mut next = 0u16;
+    let renumbered = (0..count)
+        .map(|routine| {
+            if removed.contains(&routine) {
+                return None;
+            }
+            next += 1;
+            Some(next - 1)
+        })
+        .collect::<Vec<_>>();
+    let mut routine = None::<usize>;
+    result.retain_mut(|instr| {
+        if instr.is_goto_target() {
+            routine = Some(routine.map_or(0, |no| no + 1));
+        }
+        if routine.is_some_and(|no| removed.contains(&no)) {
+            return false;
+        }
+        if let Some(pos) = instr.local_goto_pos() {
+            *pos = renumbered
+                .get(*pos as usize)
+                .copied()
+                .flatten()
+                .unwrap_or(*pos);
+        }
+        for pos in instr.local_goto_table() {
+            *pos = renumbered
+                .get(*pos as usize)
+                .copied()
+                .flatten()
+                .unwrap_or(*pos);
+        }
+        true
+    });
+    *code = result;
+
+    Inlined { calls, routines: renumbered }
+}
+
+/// Brings the code into a canonical form, such that the code which differs only in the order of
+/// the routines or in the placement of the redundant goto targets produces the same library.
+///
+/// The canonical form is produced deterministically in two steps:
+/// 1. Goto targets of the routines which are not referenced by any of the local gotos, are not
+///    listed in `keep`, and into which the control flow falls through from the preceding routine
+///    are removed, merging the routine into the preceding one. The first routine and the routines
+///    containing or spanned by [relative jumps](Instruction::is_relative_jump) (or preceded by such
+///    routines) are never merged.
+/// 2. The remaining routines are grouped into chains, where each routine except the last one falls
+///    through into the next. The chain starting with the first routine is placed first, followed by
+///    the chains in the order of their first reference in a breadth-first traversal, where the
+///    references of a chain are visited in the order of the instructions. Chains which are not
+///    reachable from the first one follow in their original order, each being traversed in the same
+///    way. If the code contains relative jumps, the routines are not reordered, since that may move
+///    the jump targets.
+///
+/// The routines are renumbered according to their new order, and the local gotos are updated.
+/// Since the routine entry points change, libraries calling the routines of the canonicalized
+/// code must be compiled against it again.
+///
+/// Like [`optimize`], the pass must be applied to the code before the compilation, and is not run
+/// by the compiler.
+///
+/// # Returns
+///
+/// New number for each of the original routines, or `None` if the routine was merged into the
+/// preceding one.
+pub fn canonicalize<Isa>(code: &mut Vec<Isa>, keep: &[u16]) -> Vec<Option<u16>>
+where Isa: Instruction<LibId> {
+    let (routines, relative) = routines(code);
+    let count = routines.last().copied().unwrap_or_default();
+
+    let mut referenced = BTreeSet::new();
+    for instr in code.iter_mut() {
+        referenced.extend(instr.local_goto_pos().map(|pos| *pos as usize));
+        referenced.extend(instr.local_goto_table().iter().map(|pos| *pos as usize));
+    }
+    // Numbers of the routines which goto targets are removed
+    let mut merged = BTreeSet::new();
+    let mut falls_through = false;
+    for (instr, id) in code.iter().zip(&routines) {
+        if instr.is_goto_target() {
+            let routine = id - 1;
+            if falls_through
+                && routine > 0
+                && !referenced.contains(&routine)
+                && !keep.contains(&(routine as u16))
+                && !relative.contains(id)
+                && !relative.contains(&(id - 1))
+            {
+                merged.insert(routine);
+            }
+        }
+        falls_through = !instr.is_terminal();
+    }
+
+    // Code of each of the remaining routines, indexed by the routine number plus one, with the
+    // code before the first goto target at the zero index
+    let mut blocks = (0..=count).map(|_| Vec::<Isa>::new()).collect::<Vec<_>>();
+    let mut current = 0usize;
+    for (instr, id) in code.drain(..).zip(routines) {
+        if instr.is_goto_target() {
+            if merged.contains(&(id - 1)) {
+                continue;
+            }
+            current = id;
+        }
+        blocks[current].push(instr);
+    }
+
+    let mut chains = Vec::<Vec<usize>>::new();
+    let mut chain_of = vec![0usize; count];
+    let mut falls_through = false;
+    for id in (1..=count).filter(|id| !merged.contains(&(id - 1))) {
+        match chains.last_mut() {
+            Some(chain) if falls_through => chain.push(id),
+            _ => chains.push(vec![id]),
+        }
+        chain_of[id - 1] = chains.len() - 1;
+        falls_through = blocks[id].last().is_some_and(|instr| !instr.is_terminal());
+    }
+    let mut refs = Vec::<Vec<usize>>::with_capacity(chains.len());
+    for chain in &chains {
+        let mut targets = vec![];
+        for instr in chain.iter().flat_map(|id| &blocks[*id]) {
+            let mut instr = instr.clone();
+            targets.extend(instr.local_goto_pos().map(|pos| *pos as usize));
+            targets.extend(instr.local_goto_table().iter().map(|pos| *pos as usize));
+        }
+        refs.push(
+            targets
+                .into_iter()
+                .filter(|routine| *routine < count)
+                .map(|routine| chain_of[routine])
+                .collect(),
+        );
+    }
+
+    let mut order = Vec::with_capacity(chains.len());
+    let mut visited = vec![false; chains.len()];
+    let mut cursor = 0usize;
+    for root in 0..chains.len() {
+        if !relative.is_empty() {
+            order.push(root);
+            continue;
+        }
+        if visited[root] {
+            continue;
+        }
+        visited[root] = true;
+        order.push(root);
+        while let Some(chain) = order.get(cursor).copied() {
+            cursor += 1;
+            for target in &refs[chain] {
+                if !visited[*target] {
+                    visited[*target] = true;
+                    order.push(*target);
+                }
+            }
+        }
+    }
+
+    let mut renumbered = vec![None; count];
+    for (no, id) in order.iter().flat_map(|chain| &chains[*chain]).enumerate() {
+        renumbered[id - 1] = Some(no as u16);
+    }
+    let mut result = core::mem::take(&mut blocks[0]);
+    for id in order.iter().flat_map(|chain| &chains[*chain]) {
+        result.append(&mut blocks[*id]);
+    }
+    for instr in &mut result {
+        let renumber = |pos: &mut u16| {
+            *pos = renumbered
+                .get(*pos as usize)
+                .copied()
+                .flatten()
+                .unwrap_or(*pos)
+        };
+        instr.local_goto_pos().map(renumber);
+        instr.local_goto_table().iter_mut().for_each(renumber);
+    }
+    *code = result;
+
+    renumbered
+}
+
+/// Returns the routine number for each of the instructions (counting the code before the first
+/// goto target as a separate routine), and the set of routines containing relative jumps or
+/// overlapping with the code spanned by them (see [`relative_spans`]). If the shift of some of the
+/// relative jumps is not known, all the routines are included into the set.
+pub(super) fn routines<Isa>(code: &[Isa]) -> (Vec<usize>, BTreeSet<usize>)
+where Isa: Instruction<LibId> {
+    let spans = relative_spans(code);
+    let mut routine = 0usize;
+    let mut cursor = 0i32;
+    let mut routines = Vec::with_capacity(code.len());
+    let mut relative = BTreeSet::new();
+    for instr in code {
+        if instr.is_goto_target() {
+            routine += 1;
+        }
+        let len = instr.code_byte_len() as i32;
+        let spanned = spans.as_ref().map_or(true, |spans| {
+            spans
+                .iter()
+                .any(|span| cursor <= *span.end() && cursor + len > *span.start())
+        });
+        if instr.is_relative_jump() || spanned {
+            relative.insert(routine);
+        }
+        cursor += len;
+        routines.push(routine);
+    }
+    (routines, relative)
+}
+
+/// Returns the byte spans between each of the relative jumps and its target (inclusive), where
+/// the code length must be preserved, or `None` if the shift of some of the relative jumps is not
+/// known (see [`Instruction::relative_jump_shift`]).
+pub(super) fn relative_spans<Isa>(code: &[Isa]) -> Option<Vec<RangeInclusive<i32>>>
+where Isa: Instruction<LibId> {
+    let mut spans = vec![];
+    let mut cursor = 0i32;
+    for instr in code {
+        if instr.is_relative_jump() {
+            let target = cursor + instr.relative_jump_shift()? as i32;
+            spans.push(cursor.min(target)..=cursor.max(target));
+        }
+        cursor += instr.code_byte_len() as i32;
+    }
+    Some(spans)
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::collections::BTreeSet;
+    use core::ops::RangeInclusive;
+
+    use super::*;
+    use crate::core::{Core, Site};
+    use crate::isa::{
+        Bytecode, BytecodeRead, BytecodeWrite, CodeEofError, CtrlInstr, Either, ExecStep, Instr,
+    };
+    use crate::testing::isa::{RegInstr, XReg};
+    use crate::{CompiledLib, NoExt, NoRegs};
+
+    /// Extension instruction adding a constant to an accumulator (which is omitted in the test).
+    #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+    #[display("xadd {0}")]
+    struct XAdd(u8);
+
+    impl Bytecode<LibId> for XAdd {
+        fn op_range() -> RangeInclusive<u8> { 0x80..=0x80 }
+
+        fn opcode_byte(&self) -> u8 { 0x80 }
+
+        fn code_byte_len(&self) -> u16 { 2 }
+
+        fn external_ref(&self) -> Option<LibId> { None }
+
+        fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
+        where W: BytecodeWrite<LibId> {
+            writer.write_byte(self.0)
+        }
+
+        fn decode_operands<R>(reader: &mut R, _opcode: u8) -> Result<Self, CodeEofError>
+        where
+            Self: Sized,
+            R: BytecodeRead<LibId>,
+        {
+            reader.read_byte().map(XAdd)
+        }
+    }
+
+    impl Instruction<LibId> for XAdd {
+        const ISA_EXT: &'static [&'static str] = &["XADD"];
+
+        type Core = NoExt;
+        type Context<'ctx> = ();
+
+        fn mnemonic(&self) -> &'static str { "xadd" }
+
+        fn is_goto_target(&self) -> bool { false }
+
+        fn local_goto_pos(&mut self) -> Option<&mut u16> { None }
+
+        fn remote_goto_pos(&mut self) -> Option<&mut Site<LibId>> { None }
+
+        fn try_const_eval(&self, next: &Self) -> Option<Self> {
+            self.0.checked_add(next.0).map(XAdd)
+        }
+
+        fn src_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+        fn dst_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+        fn op_data_bytes(&self) -> u16 { 1 }
+
+        fn ext_data_bytes(&self) -> u16 { none!() }
+
+        fn exec(
+            &self,
+            _: Site<LibId>,
+            _: &mut Core<LibId, NoExt>,
+            _: &Self::Context<'_>,
+        ) -> ExecStep<Site<LibId>> {
+            ExecStep::Next
+        }
+    }
+
+    type Isa = Either<Instr<LibId>, XAdd>;
+
+    fn ctrl(instr: CtrlInstr<LibId>) -> Isa { Either::Base(instr.into()) }
+
+    #[test]
+    fn fold() {
+        let mut code: Vec<Isa> = vec![
+            ctrl(CtrlInstr::Nop),
+            Either::Ext(XAdd(1)),
+            Either::Ext(XAdd(2)),
+            Either::Ext(XAdd(255)),
+            ctrl(CtrlInstr::Jmp { pos: 1 }),
+            ctrl(CtrlInstr::Nop),
+            Either::Ext(XAdd(3)),
+            Either::Ext(XAdd(4)),
+            ctrl(CtrlInstr::Stop),
+        ];
+        assert_eq!(optimize(&mut code), 2);
+        assert_eq!(code, vec![
+            ctrl(CtrlInstr::Nop),
+            Either::Ext(XAdd(3)),
+            Either::Ext(XAdd(255)),
+            ctrl(CtrlInstr::Jmp { pos: 1 }),
+            ctrl(CtrlInstr::Nop),
+            Either::Ext(XAdd(7)),
+            ctrl(CtrlInstr::Stop),
+        ]);
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        assert_eq!(lib.routine_table(), &[0, 8]);
+    }
+
+    #[test]
+    fn relative_jumps() {
+        let mut code: Vec<Isa> = vec![
+            ctrl(CtrlInstr::Nop),
+            ctrl(CtrlInstr::Sh { shift: 4 }),
+            Either::Ext(XAdd(1)),
+            Either::Ext(XAdd(2)),
+            ctrl(CtrlInstr::Stop),
+        ];
+        let orig = code.clone();
+        assert_eq!(optimize(&mut code), 0);
+        assert_eq!(code, orig);
+    }
+
+    #[test]
+    fn dead_stores() {
+        use RegInstr::*;
+        use XReg::*;
+
+        let mut code = vec![
+            Nop,
+            Put(A, 1),
+            Put(B, 2),
+            Put(A, 3),
+            Mov(B, A),
+            Put(A, 5),
+            Jmp(1),
+            Nop,
+            Put(A, 6),
+            Nop,
+            Put(A, 7),
+        ];
+        assert_eq!(eliminate_dead_stores(&mut code), 2);
+        assert_eq!(code, vec![
+            Nop,
+            Put(A, 3),
+            Mov(B, A),
+            Put(A, 5),
+            Jmp(1),
+            Nop,
+            Put(A, 6),
+            Nop,
+            Put(A, 7),
+        ]);
+
+        let mut code = vec![Nop, Put(A, 1), Mov(B, A), Put(B, 2), Put(A, 3), Jmp(0)];
+        assert_eq!(eliminate_dead_stores(&mut code), 2);
+        assert_eq!(code, vec![Nop, Put(B, 2), Put(A, 3), Jmp(0)]);
+    }
+
+    #[test]
+    fn inline() {
+        use crate::isa::{CtrlInstr, Instr};
+        use crate::regs::Status;
+        use crate::{aluasm, CompiledLib, LibSite, Vm};
+
+        const FUNC: u16 = 1;
+        const BIG: u16 = 2;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            call        :FUNC   ;
+            call        :BIG    ;
+            stop                ;
+            .routine    :FUNC   ;
+            not         CO      ;
+            ret                 ;
+            .routine    :BIG    ;
+            not         CO      ;
+            not         CO      ;
+            not         CO      ;
+            ret                 ;
+        };
+        let run = |code: Vec<Instr<LibId>>| {
+            let lib = CompiledLib::compile(code, &[]).unwrap();
+            let lib = lib.as_lib();
+            let mut vm = Vm::<Instr<LibId>>::new();
+            assert_eq!(vm.exec(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib)), Status::Ok);
+            vm.core.co()
+        };
+        let expected = run(code.clone());
+
+        let mut inlined = code.clone();
+        let report = inline_routines(&mut inlined, 2, &[0]);
+        assert_eq!(report, Inlined { calls: 2, routines: vec![Some(0), None, Some(1)] });
+        assert_eq!(inlined[1], Instr::Ctrl(CtrlInstr::NotCo));
+        assert_eq!(inlined[2], Instr::Ctrl(CtrlInstr::NotCo));
+        assert_eq!(inlined[3], Instr::Ctrl(CtrlInstr::Fn { pos: 1 }));
+        assert_eq!(inlined.len(), code.len() - 3);
+        assert_eq!(run(inlined), expected);
+
+        let mut kept = code.clone();
+        let report = inline_routines(&mut kept, 2, &[0, FUNC]);
+        assert_eq!(report.routines, vec![Some(0), Some(1), Some(2)]);
+        assert_eq!(kept.len(), code.len());
+        assert_eq!(run(kept), expected);
+
+        let mut all = code.clone();
+        assert_eq!(inline_routines(&mut all, 3, &[0]).calls, 3);
+        assert_eq!(all.len(), code.len() - 6);
+        assert_eq!(run(all), expected);
+
+        let mut none = code.clone();
+        assert_eq!(inline_routines(&mut none, 0, &[0]), Inlined {
+            calls: 0,
+            routines: vec![Some(0), Some(1), Some(2)]
+        });
+        assert_eq!(none, code);
+
+        // Calls spanned by relative jumps are kept
+        let code = aluasm! {
+            nop                 ;
+            jif         CO, +7  ;
+            stop                ;
+            .routine    :FUNC   ;
+            call        :BIG    ;
+            ret                 ;
+            .routine    :BIG    ;
+            not         CO      ;
+            ret                 ;
+        };
+        let mut spanned = code.clone();
+        assert_eq!(inline_routines(&mut spanned, 2, &[0]), Inlined {
+            calls: 0,
+            routines: vec![Some(0), Some(1), Some(2)]
+        });
+        assert_eq!(spanned, code);
+    }
+
+    #[test]
+    fn canonicalize() {
+        use crate::isa::Instr;
+        use crate::{aluasm, CompiledLib};
+
+        let code = {
+            const A: u16 = 1;
+            const D: u16 = 2;
+            const B: u16 = 3;
+            aluasm! {
+                nop                 ;
+                call        :A      ;
+                call        :B      ;
+                stop                ;
+                .routine    :A      ;
+                not         CO      ;
+                .routine    :D      ;
+                ret                 ;
+                .routine    :B      ;
+                not         CO      ;
+                nop                 ;
+                jif         CO, :D  ;
+                ret                 ;
+            }
+        };
+        let reordered = {
+            const B: u16 = 1;
+            const A: u16 = 2;
+            const D: u16 = 3;
+            aluasm! {
+                nop                 ;
+                call        :A      ;
+                call        :B      ;
+                stop                ;
+                .routine    :B      ;
+                not         CO      ;
+                jif         CO, :D  ;
+                ret                 ;
+                .routine    :A      ;
+                not         CO      ;
+                .routine    :D      ;
+                ret                 ;
+            }
+        };
+
+        let mut canonical = code.clone();
+        let renumbered = super::canonicalize(&mut canonical, &[]);
+        assert_eq!(renumbered, vec![Some(0), Some(1), Some(2), Some(3), None]);
+        assert_eq!(canonical.len(), code.len() - 1);
+        let mut canonical_reordered = reordered.clone();
+        let renumbered = super::canonicalize(&mut canonical_reordered, &[]);
+        assert_eq!(renumbered, vec![Some(0), Some(3), Some(1), Some(2)]);
+        assert_eq!(canonical_reordered, canonical);
+
+        let lib = CompiledLib::compile(canonical.clone(), &[]).unwrap();
+        let disassembled = lib.disassemble::<Instr<LibId>>(&[]).unwrap();
+        assert_eq!(disassembled, canonical);
+        let mut twice = canonical.clone();
+        super::canonicalize(&mut twice, &[]);
+        assert_eq!(twice, canonical);
+
+        let mut kept = code.clone();
+        assert_eq!(super::canonicalize(&mut kept, &[4]).len(), 5);
+        assert_eq!(kept, code);
+
+        // Routines are not reordered if the code contains relative jumps
+        let relative = {
+            const B: u16 = 1;
+            const A: u16 = 2;
+            aluasm! {
+                nop                 ;
+                call        :A      ;
+                call        :B      ;
+                stop                ;
+                .routine    :B      ;
+                not         CO      ;
+                jif         CO, +2  ;
+                ret                 ;
+                .routine    :A      ;
+                not         CO      ;
+                nop                 ;
+                ret                 ;
+            }
+        };
+        let mut canonical = relative.clone();
+        let renumbered = super::canonicalize(&mut canonical, &[]);
+        assert_eq!(renumbered, vec![Some(0), Some(1), Some(2), None]);
+        assert_eq!(canonical.len(), relative.len() - 1);
+        assert_eq!(canonical[..9], relative[..9]);
+    }
+}
@@ -0,0 +1,343 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+use alloc::vec::Vec;
+
+use amplify::confinement::{self, SmallBlob};
+use strict_encoding::{StrictDeserialize, StrictSerialize};
+
+use super::{CompiledLib, CompilerError, Lib, LibId, LibIdMismatch};
+use crate::isa::Instruction;
+use crate::LIB_NAME_ALUVM;
+
+/// Replacement of a byte range in a library segment.
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[derive(StrictType, StrictEncode, StrictDecode)]
+#[strict_type(lib = LIB_NAME_ALUVM)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SegmentDelta {
+    /// Offset of the replaced range in the original segment.
+    pub offset: u16,
+    /// Length of the replaced range in the original segment.
+    pub removed: u16,
+    /// Bytes replacing the range.
+    pub inserted: SmallBlob,
+}
+
+impl SegmentDelta {
+    /// Constructs the smallest delta turning the `old` segment into the `new` one, by replacing
+    /// the bytes between their common prefix and suffix.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the replaced range or the inserted bytes don't fit the maximal segment length.
+    pub fn new(old: &[u8], new: &[u8]) -> Result<Self, confinement::Error> {
+        let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..]
+            .iter()
+            .rev()
+            .zip(new[prefix..].iter().rev())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let oversize =
+            |len: usize| confinement::Error::Oversize { len, max_len: u16::MAX as usize };
+        let removed = old.len() - prefix - suffix;
+        Ok(SegmentDelta {
+            offset: u16::try_from(prefix).map_err(|_| oversize(prefix))?,
+            removed: u16::try_from(removed).map_err(|_| oversize(removed))?,
+            inserted: SmallBlob::try_from(new[prefix..new.len() - suffix].to_vec())?,
+        })
+    }
+
+    /// Checks whether the delta leaves the segment unchanged.
+    pub fn is_empty(&self) -> bool { self.removed == 0 && self.inserted.is_empty() }
+
+    /// Applies the delta to a segment, returning `None` if the replaced range lies outside of the
+    /// segment or the resulting segment exceeds the maximal segment length.
+    pub fn apply(&self, segment: &[u8]) -> Option<SmallBlob> {
+        let start = self.offset as usize;
+        let end = start + self.removed as usize;
+        if end > segment.len() {
+            return None;
+        }
+        let mut patched = Vec::with_capacity(segment.len() - (end - start) + self.inserted.len());
+        patched.extend_from_slice(&segment[..start]);
+        patched.extend_from_slice(&self.inserted);
+        patched.extend_from_slice(&segment[end..]);
+        SmallBlob::try_from(patched).ok()
+    }
+}
+
+/// Binary patch replacing a single routine of a library, produced by
+/// [`CompiledLib::patch_routine`].
+///
+/// The patch can be distributed in its strict serialization.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+#[derive(StrictType, StrictDumb, StrictEncode, StrictDecode)]
+#[strict_type(lib = LIB_NAME_ALUVM)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub struct LibPatch {
+    /// Id of the original library.
+    pub old_id: LibId,
+    /// Id of the patched library.
+    pub new_id: LibId,
+    /// Number of the patched routine.
+    pub routine: u16,
+    /// Changes to the code segment.
+    pub code: SegmentDelta,
+    /// Changes to the data segment.
+    pub data: SegmentDelta,
+}
+
+impl StrictSerialize for LibPatch {}
+impl StrictDeserialize for LibPatch {}
+
+impl LibPatch {
+    /// Maximal length of the strict serialization of a patch, in bytes.
+    pub const MAX_SERIALIZED_LEN: usize = 32 * 2 + 2 + (2 * 2 + 2 + u16::MAX as usize) * 2;
+
+    /// Returns the number of bytes inserted by the patch into the library segments.
+    pub fn payload_len(&self) -> usize { self.code.inserted.len() + self.data.inserted.len() }
+
+    /// Applies the patch to the original library, reconstructing the patched one.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the library is not the one the patch was produced for, if the patch doesn't fit
+    /// the library segments, or if the id of the patched library doesn't match
+    /// [`Self::new_id`].
+    pub fn apply(&self, lib: &Lib) -> Result<Lib, PatchApplyError> {
+        let old_id = lib.lib_id();
+        if old_id != self.old_id {
+            return Err(PatchApplyError::WrongBase(LibIdMismatch {
+                expected: old_id,
+                found: self.old_id,
+            }));
+        }
+        let mut patched = lib.clone();
+        patched.code = self
+            .code
+            .apply(&lib.code)
+            .ok_or(PatchApplyError::OutOfBounds)?;
+        patched.data = self
+            .data
+            .apply(&lib.data)
+            .ok_or(PatchApplyError::OutOfBounds)?;
+        let new_id = patched.lib_id();
+        if new_id != self.new_id {
+            return Err(PatchApplyError::Corrupted(LibIdMismatch {
+                expected: new_id,
+                found: self.new_id,
+            }));
+        }
+        Ok(patched)
+    }
+}
+
+/// Errors producing a [`LibPatch`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum PatchError<Isa: Instruction<LibId>> {
+    /// {0}
+    #[from]
+    Compile(CompilerError<Isa>),
+
+    /// patching routine {0} changes the bytecode of other routines {1:?}.
+    Spread(u16, Vec<u16>),
+
+    /// patching routine {0} changes the set of the libraries referenced by the library.
+    LibsChanged(u16),
+
+    /// patch exceeds the maximal segment length: {0}
+    #[from]
+    Oversize(confinement::Error),
+}
+
+/// Errors applying a [`LibPatch`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum PatchApplyError {
+    /// patch is produced for a different library: {0}
+    WrongBase(LibIdMismatch),
+
+    /// patch doesn't fit the library segments.
+    OutOfBounds,
+
+    /// patched library is corrupted: {0}
+    Corrupted(LibIdMismatch),
+}
+
+impl CompiledLib {
+    /// Produces a patch replacing the code of a single routine, together with the patched
+    /// library.
+    ///
+    /// The library is recompiled with [`Self::recompile_routine`], and the patch contains the
+    /// minimal changes to the code and data segments turning the original library into the
+    /// patched one, which can be applied with [`LibPatch::apply`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the routine can't be recompiled, if the patch doesn't fit the maximal segment
+    /// length, or if the recompilation changes the bytecode of any other routine (see
+    /// [`Self::diff`]), for instance since the routine length change moves the entry points of
+    /// the routines referenced from the other code. The set of the referenced libraries must
+    /// also stay the same.
+    pub fn patch_routine<Isa>(
+        &self,
+        no: u16,
+        new_code: &[Isa],
+        deps: &[&CompiledLib],
+    ) -> Result<(CompiledLib, LibPatch), PatchError<Isa>>
+    where
+        Isa: Instruction<LibId>,
+    {
+        let mut patched = self.clone();
+        patched.recompile_routine(no, new_code, deps)?;
+
+        let diff = self.diff(&patched);
+        let mut spread = diff
+            .changed
+            .into_iter()
+            .chain(diff.added)
+            .chain(diff.removed)
+            .filter(|other| *other != no)
+            .collect::<Vec<_>>();
+        if !spread.is_empty() {
+            spread.sort_unstable();
+            return Err(PatchError::Spread(no, spread));
+        }
+        if self.lib.libs != patched.lib.libs {
+            return Err(PatchError::LibsChanged(no));
+        }
+
+        let patch = LibPatch {
+            old_id: self.id,
+            new_id: patched.id,
+            routine: no,
+            code: SegmentDelta::new(&self.lib.code, &patched.lib.code)?,
+            data: SegmentDelta::new(&self.lib.data, &patched.lib.data)?,
+        };
+        Ok((patched, patch))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::aluasm;
+    use crate::isa::Instr;
+
+    #[test]
+    fn segment_delta() {
+        let delta = SegmentDelta::new(&[1, 2, 3, 4], &[1, 5, 6, 4]).unwrap();
+        assert_eq!(delta.offset, 1);
+        assert_eq!(delta.removed, 2);
+        assert_eq!(delta.inserted.as_slice(), &[5, 6]);
+        assert_eq!(delta.apply(&[1, 2, 3, 4]).unwrap().as_slice(), &[1, 5, 6, 4]);
+        assert_eq!(delta.apply(&[1, 2]), None);
+
+        let delta = SegmentDelta::new(&[1, 2, 2], &[1, 2, 2, 2]).unwrap();
+        assert_eq!((delta.offset, delta.removed), (3, 0));
+        assert_eq!(delta.inserted.as_slice(), &[2]);
+        assert!(SegmentDelta::new(&[1, 2], &[1, 2]).unwrap().is_empty());
+
+        let large = vec![0u8; 0x10001];
+        assert_eq!(
+            SegmentDelta::new(&[], &large),
+            Err(confinement::Error::Oversize { len: 0x10001, max_len: 0xFFFF })
+        );
+        assert_eq!(
+            SegmentDelta::new(&large, &[]),
+            Err(confinement::Error::Oversize { len: 0x10001, max_len: 0xFFFF })
+        );
+        let mut changed = large.clone();
+        changed[0x10000] = 1;
+        assert_eq!(
+            SegmentDelta::new(&large, &changed),
+            Err(confinement::Error::Oversize { len: 0x10000, max_len: 0xFFFF })
+        );
+    }
+
+    #[test]
+    fn patch_routine() {
+        const MAIN: u16 = 0;
+        const FUNC: u16 = 1;
+        const LAST: u16 = 2;
+        let old = CompiledLib::compile(
+            aluasm! {
+                .routine    :MAIN   ;
+                call        :FUNC   ;
+                call        :LAST   ;
+                stop                ;
+                .routine    :FUNC   ;
+                not         CO      ;
+                ret                 ;
+                .routine    :LAST   ;
+                stop                ;
+            },
+            &[],
+        )
+        .unwrap();
+
+        let new_code = aluasm! {
+            .routine    :FUNC   ;
+            chk         CO      ;
+            ret                 ;
+        };
+        let (new, patch) = old.patch_routine(FUNC, &new_code, &[]).unwrap();
+        assert_eq!(patch.old_id, old.as_lib().lib_id());
+        assert_eq!(patch.new_id, new.as_lib().lib_id());
+        assert_ne!(patch.old_id, patch.new_id);
+        assert_eq!(patch.code.offset, old.routine_range(FUNC).start + 1);
+        assert_eq!(patch.payload_len(), 1);
+        assert!(patch.data.is_empty());
+        assert_eq!(patch.apply(old.as_lib()), Ok(new.as_lib().clone()));
+        let data = patch
+            .to_strict_serialized::<{ LibPatch::MAX_SERIALIZED_LEN }>()
+            .unwrap();
+        let decoded =
+            LibPatch::from_strict_serialized::<{ LibPatch::MAX_SERIALIZED_LEN }>(data).unwrap();
+        assert_eq!(decoded, patch);
+        assert_eq!(
+            patch.apply(new.as_lib()),
+            Err(PatchApplyError::WrongBase(LibIdMismatch {
+                expected: patch.new_id,
+                found: patch.old_id
+            }))
+        );
+        let mut corrupted = patch.clone();
+        corrupted.code.inserted = small_blob![0];
+        assert!(matches!(corrupted.apply(old.as_lib()), Err(PatchApplyError::Corrupted(_))));
+
+        let new_code = aluasm! {
+            .routine    :FUNC   ;
+            not         CO      ;
+            not         CO      ;
+            ret                 ;
+        };
+        assert_eq!(
+            old.patch_routine(FUNC, &new_code, &[]).unwrap_err(),
+            PatchError::<Instr<LibId>>::Spread(FUNC, vec![MAIN])
+        );
+    }
+}
@@ -0,0 +1,305 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+use alloc::vec::Vec;
+
+#[cfg(feature = "log")]
+use super::trace::{Trace, COLORS};
+use super::{CompiledLib, DecodeError, ExecHook, HaltReason, HookBreak, Jump, LibId, LibSite};
+use crate::isa::{ExecStep, Instruction};
+use crate::vm::ExecError;
+use crate::{Core, Site};
+
+/// Library code decoded into instructions once, such that it can be executed multiple times
+/// without decoding the bytecode on each run; see [`CompiledLib::prepare`].
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct PreparedLib<Isa: Instruction<LibId>> {
+    id: LibId,
+    code: Vec<Isa>,
+    offsets: Vec<u16>,
+    code_len: u16,
+    routines: Vec<u16>,
+}
+
+impl<Isa: Instruction<LibId>> PreparedLib<Isa> {
+    /// Returns id of the library.
+    pub fn lib_id(&self) -> LibId { self.id }
+
+    /// Returns the decoded instructions in the order of their offsets.
+    pub fn instructions(&self) -> &[Isa] { &self.code }
+
+    /// Returns the code offsets of the decoded instructions, parallel to
+    /// [`Self::instructions`].
+    pub fn offsets(&self) -> &[u16] { &self.offsets }
+
+    /// Returns the index of the instruction starting at the given code offset, if any.
+    pub fn position(&self, offset: u16) -> Option<usize> {
+        self.offsets.binary_search(&offset).ok()
+    }
+
+    /// Returns code offset for the entry point of a given routine, or `None` if the routine with
+    /// the given number is not defined.
+    pub fn try_routine(&self, no: u16) -> Option<LibSite> {
+        let pos = self.routines.get(no as usize)?;
+        Some(LibSite::new(self.id, *pos))
+    }
+
+    /// Execute library code starting at entrypoint, calling the hook before each of the
+    /// instructions, in the same way as [`crate::Lib::exec`].
+    ///
+    /// Jumps to offsets which lie inside the code segment, but are not instruction boundaries,
+    /// halt the execution with [`HaltReason::DecodeFailure`]. With the `log` feature, the
+    /// execution trace is printed in the same way as for the bytecode.
+    pub(crate) fn exec_hooked<H>(
+        &self,
+        entrypoint: u16,
+        skip_first: bool,
+        core: &mut Core<LibId, Isa::Core>,
+        context: &Isa::Context<'_>,
+        hook: &mut H,
+    ) -> Result<Jump<LibId>, LibSite>
+    where
+        H: ExecHook<Isa>,
+    {
+        #[cfg(feature = "log")]
+        let (m, _, d, g, r, y, z) = COLORS;
+
+        if entrypoint >= self.code_len {
+            core.reset_ck();
+            #[cfg(feature = "log")]
+            eprintln!("jump to non-existing offset; halting, {y}CK{z} is set to {r}false{z}");
+            return Ok(Jump::Halt(HaltReason::InvalidJump(entrypoint)));
+        }
+        let Some(mut no) = self.position(entrypoint) else {
+            #[cfg(feature = "log")]
+            eprintln!("; no instruction starts at byte pos {entrypoint:06X}#h");
+            return Ok(Jump::Halt(HaltReason::DecodeFailure(entrypoint)));
+        };
+        if skip_first {
+            no += 1;
+            #[cfg(feature = "log")]
+            if let Some(offset) = self.offsets.get(no) {
+                eprintln!("; return to the caller @{offset:06X}#h");
+            }
+        }
+
+        #[cfg(feature = "log")]
+        let mut trace = Trace::<Isa>::new(self.id, core);
+
+        while let Some(instr) = self.code.get(no) {
+            let pos = self.offsets[no];
+            let site = Site::new(self.id, pos);
+            match hook.before_exec(site, instr, core) {
+                Ok(()) => {}
+                Err(HookBreak::Fault(err)) => {
+                    #[cfg(feature = "log")]
+                    eprintln!("{m}{}@{pos:06X}#h:{z} {r}{err}{z}", trace.lib_ref());
+                    return Ok(Jump::Halt(HaltReason::Fault(err)));
+                }
+                Err(HookBreak::Pause) => return Err(site.into()),
+            }
+
+            #[cfg(feature = "log")]
+            trace.before_exec(pos, instr, core);
+
+            let next = instr.exec(site, core, context);
+
+            #[cfg(feature = "log")]
+            trace.after_exec(instr, core, &next);
+
+            if !core.acc_complexity(instr.complexity()) {
+                let _ = core.fail_ck();
+                #[cfg(feature = "log")]
+                trace.complexity_exceeded();
+                return Ok(Jump::Halt(HaltReason::ComplexityExceeded));
+            }
+            match next {
+                ExecStep::Stop => return Ok(Jump::Halt(HaltReason::Stop)),
+                ExecStep::Fail => {
+                    #[cfg(feature = "log")]
+                    eprint!("{y}CK{z} {g}success{z} -> {r}fail{z}");
+                    if core.fail_ck() {
+                        #[cfg(feature = "log")]
+                        eprintln!(", {y}CH{z} is {g}true{z}: halting");
+                        return Ok(Jump::Halt(HaltReason::Fail));
+                    }
+                    #[cfg(feature = "log")]
+                    eprintln!(", {y}CH{z} is {r}false{z}: continuing");
+                    no += 1;
+                }
+                ExecStep::Next => {
+                    #[cfg(feature = "log")]
+                    eprintln!();
+                    no += 1;
+                }
+                ExecStep::Jump(pos) if pos >= self.code_len => {
+                    let _ = core.fail_ck();
+                    #[cfg(feature = "log")]
+                    {
+                        eprintln!("{d}jumping{z} {m}@{pos:06X}{z}");
+                        eprintln!(
+                            "jump to non-existing offset: unconditionally halting; {y}CK{z} is \
+                             set to {r}fail{z}"
+                        );
+                    }
+                    return Ok(Jump::Halt(HaltReason::InvalidJump(pos)));
+                }
+                ExecStep::Jump(pos) => {
+                    #[cfg(feature = "log")]
+                    eprintln!("{d}jumping{z} {m}@{pos:06X}{z}");
+                    let Some(index) = self.position(pos) else {
+                        #[cfg(feature = "log")]
+                        eprintln!("; no instruction starts at byte pos {pos:06X}#h");
+                        return Ok(Jump::Halt(HaltReason::DecodeFailure(pos)));
+                    };
+                    no = index;
+                    if !instr.is_relative_jump() {
+                        hook.after_jump(core);
+                    }
+                }
+                ExecStep::Call(site) => {
+                    #[cfg(feature = "log")]
+                    eprintln!("{d}calling{z} {m}{site}{z}");
+                    hook.after_jump(core);
+                    return Ok(Jump::Instr(site));
+                }
+                ExecStep::Ret(site) => {
+                    #[cfg(feature = "log")]
+                    eprintln!("{d}returning to{z} {m}{site}{z}");
+                    return Ok(Jump::Next(site));
+                }
+                ExecStep::Trap(code) => {
+                    let _ = core.fail_ck();
+                    #[cfg(feature = "log")]
+                    eprintln!("{r}trap {code:#06x}{z}: halting; {y}CK{z} is set to {r}fail{z}");
+                    return Ok(Jump::Halt(HaltReason::Trapped(code)));
+                }
+                ExecStep::ReentrancyLimit => {
+                    #[cfg(feature = "log")]
+                    eprintln!("{r}reentrancy limit is exceeded{z}: halting");
+                    let site = site.into();
+                    return Ok(Jump::Halt(HaltReason::Fault(ExecError::ReentrancyLimit(site))));
+                }
+            }
+        }
+
+        Ok(Jump::Halt(HaltReason::EndOfCode))
+    }
+}
+
+impl CompiledLib {
+    /// Decodes the whole library code into instructions, which can be executed with
+    /// [`crate::Vm::run_prepared`] multiple times without decoding the bytecode again.
+    ///
+    /// # Errors
+    ///
+    /// Fails if some of the instructions can't be decoded, even if they are never executed.
+    pub fn prepare<Isa>(&self) -> Result<PreparedLib<Isa>, DecodeError>
+    where Isa: Instruction<LibId> {
+        let (offsets, code) = self.iter_instructions().collect::<Result<_, _>>()?;
+        Ok(PreparedLib {
+            id: self.id,
+            code,
+            offsets,
+            code_len: self.lib.code.len() as u16,
+            routines: self.routines.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::Instr;
+    use crate::{aluasm, Vm};
+
+    #[test]
+    fn prepare() {
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        let prepared = lib.prepare::<Instr<LibId>>().unwrap();
+        assert_eq!(prepared.lib_id(), lib.as_lib().lib_id());
+        assert_eq!(prepared.instructions(), code.as_slice());
+        assert_eq!(prepared.offsets(), &[0, 1, 2]);
+        assert_eq!(prepared.position(2), Some(2));
+        assert_eq!(prepared.position(3), None);
+        assert_eq!(prepared.try_routine(0), lib.try_routine(0));
+        assert_eq!(prepared.try_routine(1), None);
+    }
+
+    #[test]
+    fn run_prepared() {
+        const MAIN: u16 = 0;
+        const LOOP: u16 = 1;
+        const FUNC: u16 = 2;
+        let code = aluasm! {
+            .routine    :MAIN   ;
+            call        :LOOP   ;
+            fail        CK      ;
+            stop                ;
+            .routine    :LOOP   ;
+            call        :FUNC   ;
+            jif         CO, :LOOP ;
+            ret                 ;
+            .routine    :FUNC   ;
+            not         CO      ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let prepared = lib.prepare::<Instr<LibId>>().unwrap();
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let status = vm.run(lib.routine(MAIN), &(), |_| Some(lib.as_lib()));
+        let steps = vm.steps();
+
+        let mut prepared_vm = Vm::<Instr<LibId>>::new();
+        let prepared_status = prepared_vm.run_prepared(lib.routine(MAIN), &(), |_| Some(&prepared));
+        assert_eq!(prepared_status, status);
+        assert_eq!(prepared_vm.steps(), steps);
+        assert_eq!(prepared_vm.core, vm.core);
+    }
+
+    #[test]
+    fn misaligned_jump() {
+        let code = aluasm! {
+            nop                 ;
+            jmp         +1      ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let prepared = lib.prepare::<Instr<LibId>>().unwrap();
+        let mut core = Core::new();
+        assert_eq!(
+            prepared.exec_hooked(0, false, &mut core, &(), &mut ()),
+            Ok(Jump::Halt(HaltReason::DecodeFailure(2)))
+        );
+        assert_eq!(
+            prepared.exec_hooked(7, false, &mut core, &(), &mut ()),
+            Ok(Jump::Halt(HaltReason::InvalidJump(7)))
+        );
+    }
+}
@@ -0,0 +1,222 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Signed libraries, allowing to establish the provenance of a library before executing it.
+
+use core::fmt::Debug;
+
+use amplify::Bytes32;
+use commit_verify::{Digest, Sha256};
+
+use super::{CompiledLib, LibId};
+
+/// Tag used in the [`SignedLib::message`] hash.
+pub const LIB_SIG_TAG: &str = "urn:ubideco:aluvm:lib-sig:v01#261014";
+
+/// Signature scheme used to sign libraries.
+///
+/// The scheme is left to the crate users, such that the crate stays agnostic of the cryptography
+/// used; the signed message is always the 32-byte tagged hash of the library id (see
+/// [`SignedLib::message`]).
+pub trait SigScheme {
+    /// Public key verifying the signatures.
+    type PublicKey: Clone + Eq + Debug;
+
+    /// Signature over a library id.
+    type Signature: Clone + Eq + Debug;
+
+    /// Checks that the signature over the message is produced by the owner of the public key.
+    fn verify(pubkey: &Self::PublicKey, msg: Bytes32, sig: &Self::Signature) -> bool;
+}
+
+/// Signer of the libraries, holding the private key of the [`SigScheme`].
+pub trait LibSigner<S: SigScheme> {
+    /// Returns the public key corresponding to the private key of the signer.
+    fn public_key(&self) -> S::PublicKey;
+
+    /// Signs the message, which is produced by [`SignedLib::message`].
+    fn sign(&self, msg: Bytes32) -> S::Signature;
+}
+
+/// Errors verifying a [`SignedLib`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum SignatureError {
+    /// signature of the library {0} is invalid.
+    Invalid(LibId),
+
+    /// library {0} is signed by a key which is not trusted.
+    Untrusted(LibId),
+}
+
+/// Compiled library together with a signature over its id.
+///
+/// Since the library id commits to the whole library (including its code, data and dependencies),
+/// the signature over the id attests the library content.
+#[derive(Clone, Eq, PartialEq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(bound(
+        serialize = "S::PublicKey: serde::Serialize, S::Signature: serde::Serialize",
+        deserialize = "S::PublicKey: serde::Deserialize<'de>, S::Signature: \
+                       serde::Deserialize<'de>"
+    ))
+)]
+pub struct SignedLib<S: SigScheme> {
+    lib: CompiledLib,
+    sig: S::Signature,
+    pubkey: S::PublicKey,
+}
+
+impl<S: SigScheme> SignedLib<S> {
+    /// Constructs signed library from its parts, without verifying the signature.
+    pub fn new(lib: CompiledLib, sig: S::Signature, pubkey: S::PublicKey) -> Self {
+        Self { lib, sig, pubkey }
+    }
+
+    /// Computes the message signed for the library as a tagged SHA256 hash, such that the
+    /// signatures can't be confused with the signatures of the same key in other protocols:
+    ///
+    /// ```text
+    /// tag = SHA256("urn:ubideco:aluvm:lib-sig:v01#261014")
+    /// msg = SHA256(tag || tag || lib_id)
+    /// ```
+    pub fn message(lib_id: LibId) -> Bytes32 {
+        let tag = Sha256::digest(LIB_SIG_TAG.as_bytes());
+        let mut hasher = Sha256::new();
+        hasher.update(tag);
+        hasher.update(tag);
+        hasher.update(lib_id.as_slice());
+        Bytes32::from_byte_array(hasher.finalize())
+    }
+
+    /// Signs the library with the provided signer.
+    pub fn sign(lib: CompiledLib, signer: &impl LibSigner<S>) -> Self {
+        let sig = signer.sign(Self::message(lib.id));
+        Self { lib, sig, pubkey: signer.public_key() }
+    }
+
+    /// Returns the signed library.
+    pub fn lib(&self) -> &CompiledLib { &self.lib }
+
+    /// Returns the signature over the library [message](Self::message).
+    pub fn sig(&self) -> &S::Signature { &self.sig }
+
+    /// Returns the public key of the signer.
+    pub fn pubkey(&self) -> &S::PublicKey { &self.pubkey }
+
+    /// Verifies the signature over the library [message](Self::message) against the public key
+    /// of the signer.
+    ///
+    /// The check doesn't establish trust into the signer; use [`Self::verify_trusted`] to check
+    /// the signer against a known set of keys.
+    pub fn verify_signature(&self) -> Result<(), SignatureError> {
+        if !S::verify(&self.pubkey, Self::message(self.lib.id), &self.sig) {
+            return Err(SignatureError::Invalid(self.lib.id));
+        }
+        Ok(())
+    }
+
+    /// Verifies the signature (see [`Self::verify_signature`]) and checks that the library is
+    /// signed by one of the `trusted` keys, returning the library if both checks pass.
+    pub fn verify_trusted(self, trusted: &[S::PublicKey]) -> Result<CompiledLib, SignatureError> {
+        self.verify_signature()?;
+        if !trusted.contains(&self.pubkey) {
+            return Err(SignatureError::Untrusted(self.lib.id));
+        }
+        Ok(self.lib)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use commit_verify::{Digest, Sha256};
+
+    use super::*;
+    use crate::aluasm;
+
+    /// Keyed hash standing in for a signature scheme, where the public key equals to the private
+    /// one.
+    #[derive(Clone, Eq, PartialEq, Debug)]
+    struct Mac;
+
+    impl SigScheme for Mac {
+        type PublicKey = [u8; 32];
+        type Signature = [u8; 32];
+
+        fn verify(pubkey: &[u8; 32], msg: Bytes32, sig: &[u8; 32]) -> bool {
+            Key(*pubkey).sign(msg) == *sig
+        }
+    }
+
+    struct Key([u8; 32]);
+
+    impl LibSigner<Mac> for Key {
+        fn public_key(&self) -> [u8; 32] { self.0 }
+
+        fn sign(&self, msg: Bytes32) -> [u8; 32] {
+            let mut hasher = Sha256::new();
+            hasher.update(self.0);
+            hasher.update(msg.to_byte_array());
+            hasher.finalize().into()
+        }
+    }
+
+    #[test]
+    fn signed_lib() {
+        let lib = CompiledLib::compile(aluasm! { nop; stop; }, &[]).unwrap();
+        let id = lib.as_lib().lib_id();
+        let key = Key([7; 32]);
+        let signed = SignedLib::<Mac>::sign(lib.clone(), &key);
+        assert_eq!(signed.verify_signature(), Ok(()));
+        assert_ne!(SignedLib::<Mac>::message(id).to_byte_array(), id.to_byte_array());
+        let untagged = SignedLib::<Mac>::new(
+            lib.clone(),
+            key.sign(Bytes32::from(id.to_byte_array())),
+            [7; 32],
+        );
+        assert_eq!(untagged.verify_signature(), Err(SignatureError::Invalid(id)));
+        assert_eq!(signed.pubkey(), &[7; 32]);
+        assert_eq!(signed.clone().verify_trusted(&[[1; 32]]), Err(SignatureError::Untrusted(id)));
+        assert_eq!(signed.clone().verify_trusted(&[[1; 32], [7; 32]]), Ok(lib.clone()));
+
+        let other = CompiledLib::compile(aluasm! { nop; ret; }, &[]).unwrap();
+        let forged = SignedLib::<Mac>::new(other.clone(), *signed.sig(), key.public_key());
+        let other_id = other.as_lib().lib_id();
+        assert_eq!(forged.verify_signature(), Err(SignatureError::Invalid(other_id)));
+        assert_eq!(forged.verify_trusted(&[[7; 32]]), Err(SignatureError::Invalid(other_id)));
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() {
+        let lib = CompiledLib::compile(aluasm! { nop; stop; }, &[]).unwrap();
+        let signed = SignedLib::<Mac>::sign(lib, &Key([7; 32]));
+        let json = serde_json::to_string(&signed).unwrap();
+        let decoded = serde_json::from_str::<SignedLib<Mac>>(&json).unwrap();
+        assert_eq!(decoded, signed);
+        assert_eq!(decoded.verify_signature(), Ok(()));
+    }
+}
@@ -0,0 +1,164 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::BTreeMap;
+
+use super::{CompiledLib, DecodeError, LibId, Marshaller};
+use crate::isa::{BytecodeRead, Instruction};
+
+/// Size statistics of a compiled library, as reported by [`CompiledLib::stats`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct LibStats {
+    /// Length of the code segment, in bytes.
+    pub code_len: u16,
+    /// Length of the data segment, in bytes.
+    pub data_len: u16,
+    /// Number of the routines.
+    pub routines: u16,
+    /// Total length of all routines, in bytes. Differs from the code length if some code precedes
+    /// the first routine.
+    pub routines_len: u16,
+    /// Length of the largest routine, in bytes.
+    pub max_routine_len: u16,
+    /// Number of the instructions in the code segment.
+    pub instructions: u16,
+    /// Number of the goto target instructions in the code segment.
+    ///
+    /// May be less than the number of the routines for the libraries compiled with
+    /// [stripped markers](super::CompileOpts::strip_markers).
+    pub goto_targets: u16,
+}
+
+impl LibStats {
+    /// Returns the average length of a routine in bytes, rounded down, or zero if the library
+    /// doesn't have routines.
+    pub fn avg_routine_len(&self) -> u16 {
+        self.routines_len
+            .checked_div(self.routines)
+            .unwrap_or_default()
+    }
+}
+
+impl CompiledLib {
+    /// Computes size statistics of the library, decoding its code to count the instructions.
+    pub fn stats<Isa>(&self) -> Result<LibStats, DecodeError>
+    where Isa: Instruction<LibId> {
+        let mut instructions = 0u16;
+        let mut goto_targets = 0u16;
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        while !reader.is_eof() {
+            let pos = reader.pos();
+            let instr = Isa::decode_instr(&mut reader).map_err(|_| DecodeError::Decode(pos))?;
+            instructions += 1;
+            if instr.is_goto_target() {
+                goto_targets += 1;
+            }
+        }
+        let lens = (0..self.routines.len() as u16).map(|no| self.routine_range(no).len() as u16);
+        Ok(LibStats {
+            code_len: self.lib.code.len() as u16,
+            data_len: self.lib.data.len() as u16,
+            routines: self.routines.len() as u16,
+            routines_len: lens.clone().sum(),
+            max_routine_len: lens.max().unwrap_or_default(),
+            instructions,
+            goto_targets,
+        })
+    }
+
+    /// Counts how many times each instruction mnemonic appears in the library code.
+    ///
+    /// The count is static, i.e. it doesn't depend on how many times the instructions are
+    /// executed. Instructions are named by their [`Instruction::mnemonic`], thus the instruction
+    /// variants sharing a mnemonic, like `chk CO` and `chk CK`, are counted together.
+    pub fn opcode_histogram<Isa>(&self) -> Result<BTreeMap<&'static str, usize>, DecodeError>
+    where Isa: Instruction<LibId> {
+        let mut histogram = BTreeMap::<&'static str, usize>::new();
+        for item in self.iter_instructions::<Isa>() {
+            let (_, instr) = item?;
+            *histogram.entry(instr.mnemonic()).or_default() += 1;
+        }
+        Ok(histogram)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::Instr;
+    use crate::{aluasm, CompileOpts};
+
+    #[test]
+    fn stats() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            chk         CO      ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        let stats = lib.stats::<Instr<LibId>>().unwrap();
+        assert_eq!(stats, LibStats {
+            code_len: 8,
+            data_len: 0,
+            routines: 2,
+            routines_len: 8,
+            max_routine_len: 5,
+            instructions: 6,
+            goto_targets: 2,
+        });
+        assert_eq!(stats.avg_routine_len(), 4);
+
+        let opts = CompileOpts { strip_markers: true, ..default!() };
+        let lib = CompiledLib::compile_with(code, &[], &[], &opts).unwrap();
+        let stats = lib.stats::<Instr<LibId>>().unwrap();
+        assert_eq!(stats.code_len, 6);
+        assert_eq!(stats.instructions, 4);
+        assert_eq!(stats.goto_targets, 0);
+        assert_eq!(LibStats::default().avg_routine_len(), 0);
+    }
+
+    #[test]
+    fn opcode_histogram() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            chk         CK      ;
+            stop                ;
+            .routine    :FUNC   ;
+            chk         CO      ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let histogram = lib.opcode_histogram::<Instr<LibId>>().unwrap();
+        assert_eq!(
+            histogram,
+            [("call", 1), ("chk", 2), ("nop", 2), ("ret", 1), ("stop", 1)].into()
+        );
+    }
+}
@@ -0,0 +1,197 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::vec::Vec;
+
+use super::{LibId, LibsSeg, Marshaller};
+use crate::isa::{Bytecode, BytecodeRead};
+
+/// Number of bytes read from the stream at once.
+const CHUNK_LEN: usize = 64;
+
+/// Errors reading instructions from a bytecode stream.
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum StreamError {
+    /// unable to read the bytecode stream: {0}
+    #[from]
+    Io(io::Error),
+
+    /// unable to decode instruction at offset {0:#06x}.
+    Decode(u16),
+
+    /// bytecode stream exceeds the maximal size of the code segment.
+    TooLong,
+}
+
+/// Reader decoding instructions from a bytecode stream one by one, without loading the whole code
+/// segment into the memory.
+///
+/// Only the bytes of the instruction being decoded are buffered. The data and library segments,
+/// which are referenced by the instruction operands, must be provided in full.
+///
+/// The reader is an iterator over the decoded instructions and their offsets. It stops after the
+/// first error.
+pub struct InstructionReader<'a, R: Read, Isa> {
+    reader: R,
+    data: &'a [u8],
+    libs: &'a LibsSeg,
+    buf: Vec<u8>,
+    offset: u16,
+    eof: bool,
+    failed: bool,
+    phantom: PhantomData<Isa>,
+}
+
+impl<'a, R: Read, Isa> InstructionReader<'a, R, Isa>
+where Isa: Bytecode<LibId>
+{
+    /// Constructs reader over a stream of the library bytecode, using the provided data and
+    /// library segments.
+    pub fn new(reader: R, data: &'a [u8], libs: &'a LibsSeg) -> Self {
+        Self {
+            reader,
+            data,
+            libs,
+            buf: Vec::with_capacity(CHUNK_LEN),
+            offset: 0,
+            eof: false,
+            failed: false,
+            phantom: PhantomData,
+        }
+    }
+
+    /// Returns offset of the next instruction to read.
+    pub fn offset(&self) -> u16 { self.offset }
+
+    /// Reads more bytes from the stream into the buffer, returning whether anything was read.
+    fn fill(&mut self) -> Result<bool, StreamError> {
+        if self.eof {
+            return Ok(false);
+        }
+        let len = self.buf.len();
+        if self.offset as usize + len > u16::MAX as usize {
+            return Err(StreamError::TooLong);
+        }
+        self.buf.resize(len + CHUNK_LEN, 0);
+        let read = loop {
+            match self.reader.read(&mut self.buf[len..]) {
+                Ok(read) => break read,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => {
+                    self.buf.truncate(len);
+                    return Err(err.into());
+                }
+            }
+        };
+        self.buf.truncate(len + read);
+        self.eof = read == 0;
+        Ok(read > 0)
+    }
+
+    /// Reads the next instruction from the stream, returning its offset.
+    ///
+    /// Returns `Ok(None)` once the end of the stream is reached.
+    pub fn read_instr(&mut self) -> Result<Option<(u16, Isa)>, StreamError> {
+        if self.buf.is_empty() && !self.fill()? {
+            return Ok(None);
+        }
+        loop {
+            let mut marshaller = Marshaller::with(&self.buf, self.data, self.libs);
+            match Isa::decode_instr(&mut marshaller) {
+                Ok(instr) => {
+                    let len = marshaller.pos();
+                    let offset = self.offset;
+                    self.buf.drain(..len as usize);
+                    self.offset = self.offset.checked_add(len).ok_or(StreamError::TooLong)?;
+                    return Ok(Some((offset, instr)));
+                }
+                // The instruction may be incomplete, thus we retry with more data
+                Err(_) if self.fill()? => continue,
+                Err(_) => return Err(StreamError::Decode(self.offset)),
+            }
+        }
+    }
+}
+
+impl<R: Read, Isa> Iterator for InstructionReader<'_, R, Isa>
+where Isa: Bytecode<LibId>
+{
+    type Item = Result<(u16, Isa), StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let res = self.read_instr().transpose();
+        self.failed = matches!(res, Some(Err(_)));
+        res
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::Instr;
+    use crate::{aluasm, Lib};
+
+    #[test]
+    fn stream() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            jif         CO, +2  ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = Lib::assemble(&code).unwrap();
+        let reader = InstructionReader::<_, Instr<LibId>>::new(
+            lib.code.as_slice(),
+            lib.data.as_slice(),
+            &lib.libs,
+        );
+        let instrs = reader.collect::<Result<Vec<_>, _>>().unwrap();
+        let offsets = instrs.iter().map(|(pos, _)| *pos).collect::<Vec<_>>();
+        assert_eq!(offsets, vec![0, 1, 4, 6, 7, 8]);
+        assert_eq!(
+            instrs
+                .into_iter()
+                .map(|(_, instr)| instr)
+                .collect::<Vec<_>>(),
+            lib.disassemble::<Instr<LibId>>().unwrap()
+        );
+
+        let mut truncated = lib.code.to_vec();
+        truncated.truncate(3);
+        let mut reader =
+            InstructionReader::<_, Instr<LibId>>::new(truncated.as_slice(), &[], &lib.libs);
+        assert!(matches!(reader.next(), Some(Ok((0, _)))));
+        assert!(matches!(reader.next(), Some(Err(StreamError::Decode(1)))));
+        assert!(reader.next().is_none());
+    }
+}
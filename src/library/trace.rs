@@ -0,0 +1,166 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Execution trace printed to the standard error output if the `log` feature is enabled.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+
+use baid64::DisplayBaid64;
+
+use crate::core::{CoreExt, Register, Status};
+use crate::isa::{ExecStep, Instruction};
+use crate::{Core, LibId, Site};
+
+type Reg<Isa> = <<Isa as Instruction<LibId>>::Core as CoreExt>::Reg;
+type Value<Isa> = <Reg<Isa> as Register>::Value;
+
+/// Terminal colors used by the trace: magenta, white, dimmed, green, red, yellow and the reset.
+pub(super) const COLORS: (&str, &str, &str, &str, &str, &str, &str) = (
+    "\x1B[0;35m",
+    "\x1B[1;1m",
+    "\x1B[0;37;2m",
+    "\x1B[0;32m",
+    "\x1B[0;31m",
+    "\x1B[0;33m",
+    "\x1B[0m",
+);
+
+/// State of the trace, used to print the register changes made by each of the instructions.
+pub(super) struct Trace<Isa: Instruction<LibId>> {
+    lib_ref: String,
+    ck0: Status,
+    co0: Status,
+    prev: BTreeMap<Reg<Isa>, Option<Value<Isa>>>,
+    src_empty: bool,
+}
+
+impl<Isa: Instruction<LibId>> Trace<Isa> {
+    pub fn new(lib_id: LibId, core: &Core<LibId, Isa::Core>) -> Self {
+        Trace {
+            lib_ref: lib_id.to_baid64_mnemonic().split_at(5).0.to_string(),
+            ck0: core.ck(),
+            co0: core.co(),
+            prev: bmap![],
+            src_empty: true,
+        }
+    }
+
+    /// Short reference to the library, prefixing the instruction sites in the trace.
+    pub fn lib_ref(&self) -> &str { &self.lib_ref }
+
+    /// Prints the instruction with the values of its source registers.
+    pub fn before_exec(&mut self, pos: u16, instr: &Isa, core: &Core<LibId, Isa::Core>) {
+        let (m, w, d, _, _, _, z) = COLORS;
+
+        self.prev.clear();
+        for reg in instr.dst_regs() {
+            self.prev.insert(reg, core.get(reg));
+        }
+        eprint!("{m}{}@{pos:06X}#h:{z} {: <32}; ", self.lib_ref, instr.to_string());
+        let src_regs = instr.src_regs();
+        self.src_empty = src_regs.is_empty();
+        let mut iter = src_regs.into_iter().peekable();
+        while let Some(reg) = iter.next() {
+            eprint!("{d}{reg}{z} ");
+            if let Some(val) = core.get(reg) {
+                eprint!("{w}{}{z}", val);
+            } else {
+                eprint!("{d}~{z}");
+            }
+            if iter.peek().is_some() {
+                eprint!(", ");
+            }
+        }
+    }
+
+    /// Prints the changes of the destination registers and the flags made by the instruction.
+    pub fn after_exec(
+        &mut self,
+        instr: &Isa,
+        core: &Core<LibId, Isa::Core>,
+        next: &ExecStep<Site<LibId>>,
+    ) {
+        let (_, _, d, g, r, y, z) = COLORS;
+        let (ck0, co0) = (self.ck0, self.co0);
+        let prev = &self.prev;
+
+        if !self.src_empty {
+            if !prev.is_empty() {
+                eprint!(" => ");
+            } else if ck0 != core.ck() || co0 != core.co() || *next != ExecStep::Next {
+                eprint!("; ");
+            }
+        }
+
+        let mut iter = instr.dst_regs().into_iter().peekable();
+        while let Some(reg) = iter.next() {
+            eprint!("{g}{reg}{z} ");
+            if let Some(val) = prev.get(&reg).unwrap() {
+                eprint!("{y}{}{z}", val);
+            } else {
+                eprint!("{d}~{z}");
+            }
+            eprint!(" -> ");
+            if let Some(val) = core.get(reg) {
+                eprint!("{y}{}{z}", val);
+            } else {
+                eprint!("{d}~{z}");
+            }
+            if iter.peek().is_some() {
+                eprint!(", ");
+            }
+        }
+        if !prev.is_empty() && (ck0 != core.ck() || co0 != core.co()) {
+            eprint!(", ");
+        }
+        if ck0 != core.ck() {
+            let p = if ck0.is_ok() { g } else { r };
+            let c = if core.ck().is_ok() { g } else { r };
+            eprint!("{y}CK{z} {p}{ck0}{z} -> {c}{}{z}", core.ck());
+        }
+        if ck0 != core.ck() && co0 != core.co() {
+            eprint!(", ");
+        }
+        if co0 != core.co() {
+            let p = if co0.is_ok() { g } else { r };
+            let c = if core.co().is_ok() { g } else { r };
+            eprint!("{y}CO{z} {p}{co0}{z} -> {c}{}{z}", core.co());
+        }
+        if (!prev.is_empty() || ck0 != core.ck() || co0 != core.co()) && *next != ExecStep::Next {
+            eprint!(", ");
+        }
+
+        self.ck0 = core.ck();
+        self.co0 = core.co();
+    }
+
+    /// Prints the halt due to the complexity overflow.
+    pub fn complexity_exceeded(&self) {
+        if !self.src_empty || !self.prev.is_empty() {
+            eprint!(", ");
+        }
+        eprintln!("halting, complexity overflow");
+    }
+}
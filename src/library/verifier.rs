@@ -0,0 +1,438 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+
+use super::{CompiledLib, EncodingVersion, LibId, LibsSeg, Marshaller, STUB_OFFSET};
+use crate::isa::{BytecodeRead, Instruction};
+use crate::RegOf;
+
+/// Errors detected during static verification of a compiled library.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum VerifyError {
+    /// unable to decode instruction at offset {0:#x}.
+    Decode(u16),
+
+    /// instruction at offset {0:#x} is declared to take {1} bytes, but its bytecode takes {2}
+    /// bytes.
+    LenMismatch(u16, u16, u16),
+
+    /// instruction at offset {0:#x} jumps to offset {1:#x}, which lies outside of the code
+    /// segment.
+    OutOfCode(u16, u16),
+
+    /// instruction at offset {0:#x} jumps to offset {1:#x}, which is not an instruction boundary.
+    Misaligned(u16, u16),
+
+    /// instruction at offset {0:#x} references library {1}, which is absent in the library
+    /// segment.
+    UnlistedLib(u16, LibId),
+
+    /// routine {0} starts at offset {1:#x}, which is not an instruction boundary.
+    InvalidRoutine(u16, u16),
+
+    /// bytecode encoding version {0} is not supported.
+    UnsupportedVersion(EncodingVersion),
+
+    /// instruction at offset {0:#x} is not defined by the bytecode encoding version {1}.
+    UnsupportedInstr(u16, EncodingVersion),
+
+    /// entry point refers to routine {0}, which is not defined in the library.
+    InvalidEntry(u16),
+
+    /// instruction at offset {0:#x} uses a register which is not a part of the register profile.
+    OutOfProfile(u16),
+
+    /// instruction at offset {offset:#x} references data at offset {ref_target:#x}, which spans
+    /// past the end of the data segment.
+    DataRefOutOfBounds { offset: u16, ref_target: u16 },
+
+    /// data segment takes {len} bytes, exceeding the limit of {max} bytes.
+    DataTooLarge { len: u16, max: u16 },
+
+    /// instruction at offset {offset:#x} uses an immediate value of {len} bytes, exceeding the
+    /// limit of {max} bytes.
+    ImmediateTooLarge { offset: u16, len: u16, max: u16 },
+}
+
+/// Capacity limits of a target, checked by [`CompiledLib::verify_limits`].
+///
+/// Unset limits are not checked.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Default)]
+pub struct VerifyLimits {
+    /// Maximal length of the data segment, in bytes.
+    pub max_data_len: Option<u16>,
+    /// Maximal length of an immediate value stored in the data segment, in bytes.
+    pub max_immediate_len: Option<u16>,
+}
+
+impl CompiledLib {
+    /// Statically verifies the library bytecode without executing it.
+    ///
+    /// Checks that all of the instructions can be decoded and their declared lengths match the
+    /// bytecode (thus, the code segment length matches the sum of the instruction lengths), that
+    /// the data referenced by the instructions lies within the data segment, that local gotos and
+    /// routines land on instruction boundaries, that external references point to libraries
+    /// listed in the library segment, that the [entry point](Self::entry) is a known routine, and
+    /// that the bytecode encoding version is supported and defines all of the instructions. Gotos
+    /// into [stub](Self::is_stub) routines are allowed.
+    ///
+    /// Checking that external references land on the routines of the dependencies is done by
+    /// [`Self::link`].
+    ///
+    /// # Returns
+    ///
+    /// The first verification failure found.
+    pub fn verify<Isa>(&self) -> Result<(), VerifyError>
+    where Isa: Instruction<LibId> {
+        if !self.version.is_supported() {
+            return Err(VerifyError::UnsupportedVersion(self.version));
+        }
+        let boundaries = verify_code::<Isa>(
+            self.lib.code.as_slice(),
+            self.lib.data.as_slice(),
+            &self.lib.libs,
+            !self.stubs.is_empty(),
+            self.version,
+        )?;
+        for (no, pos) in self.routines.iter().enumerate() {
+            if !boundaries.contains(pos) {
+                return Err(VerifyError::InvalidRoutine(no as u16, *pos));
+            }
+        }
+        if let Some(entry) = self.entry.filter(|no| *no as usize >= self.routines.len()) {
+            return Err(VerifyError::InvalidEntry(entry));
+        }
+        Ok(())
+    }
+
+    /// Statically verifies the library bytecode in the same way as [`Self::verify`], additionally
+    /// checking that the instructions use only the registers allowed by a restricted register
+    /// profile.
+    ///
+    /// The profile is a predicate over the registers of the core extension, allowing targets with
+    /// a limited register file (like a few registers of each kind) to reject the code which doesn't
+    /// fit into it before the execution.
+    pub fn verify_for<Isa>(&self, profile: impl Fn(RegOf<Isa>) -> bool) -> Result<(), VerifyError>
+    where Isa: Instruction<LibId> {
+        self.verify::<Isa>()?;
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        while !reader.is_eof() {
+            let pos = reader.pos();
+            let instr = Isa::decode_instr(&mut reader).map_err(|_| VerifyError::Decode(pos))?;
+            if !instr
+                .src_regs()
+                .into_iter()
+                .chain(instr.dst_regs())
+                .all(&profile)
+            {
+                return Err(VerifyError::OutOfProfile(pos));
+            }
+        }
+        Ok(())
+    }
+
+    /// Statically verifies the library bytecode in the same way as [`Self::verify`], additionally
+    /// checking that the data segment and the immediate values used by the instructions fit into
+    /// the provided capacity limits.
+    ///
+    /// This allows the hosts with constrained resources to refuse oversized libraries before
+    /// allocating memory for them.
+    pub fn verify_limits<Isa>(&self, limits: VerifyLimits) -> Result<(), VerifyError>
+    where Isa: Instruction<LibId> {
+        self.verify::<Isa>()?;
+        let len = self.lib.data.len() as u16;
+        if let Some(max) = limits.max_data_len.filter(|max| len > *max) {
+            return Err(VerifyError::DataTooLarge { len, max });
+        }
+        let Some(max) = limits.max_immediate_len else {
+            return Ok(());
+        };
+        let mut reader = Marshaller::with(&self.lib.code, &self.lib.data, &self.lib.libs);
+        while !reader.is_eof() {
+            let offset = reader.pos();
+            Isa::decode_instr(&mut reader).map_err(|_| VerifyError::Decode(offset))?;
+            let len = reader.max_data_ref();
+            if len > max {
+                return Err(VerifyError::ImmediateTooLarge { offset, len, max });
+            }
+        }
+        Ok(())
+    }
+
+    /// Verifies a set of libraries (see [`Self::verify`]), returning verification results in the
+    /// order of the provided libraries.
+    ///
+    /// With `std` feature, the libraries are verified in parallel, using all the available CPU
+    /// cores; otherwise they are verified sequentially.
+    pub fn verify_all<Isa>(libs: &[CompiledLib]) -> Vec<Result<(), VerifyError>>
+    where Isa: Instruction<LibId> {
+        #[cfg(feature = "std")]
+        {
+            let threads = std::thread::available_parallelism().map_or(1, usize::from);
+            if threads > 1 && libs.len() > 1 {
+                let chunk_len = libs.len().div_ceil(threads);
+                return std::thread::scope(|scope| {
+                    let handles = libs
+                        .chunks(chunk_len)
+                        .map(|chunk| {
+                            scope.spawn(move || {
+                                chunk.iter().map(Self::verify::<Isa>).collect::<Vec<_>>()
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    handles
+                        .into_iter()
+                        .flat_map(|handle| {
+                            handle
+                                .join()
+                                .unwrap_or_else(|err| std::panic::resume_unwind(err))
+                        })
+                        .collect()
+                });
+            }
+        }
+        libs.iter().map(Self::verify::<Isa>).collect()
+    }
+}
+
+/// Verifies the code segment, returning the offsets of the instruction boundaries.
+///
+/// Gotos to [`STUB_OFFSET`] are allowed if `allow_stubs` is set.
+pub(super) fn verify_code<Isa>(
+    code: &[u8],
+    data: &[u8],
+    libs: &LibsSeg,
+    allow_stubs: bool,
+    version: EncodingVersion,
+) -> Result<BTreeSet<u16>, VerifyError>
+where
+    Isa: Instruction<LibId>,
+{
+    let mut boundaries = BTreeSet::new();
+    let mut gotos = vec![];
+    let mut reader = Marshaller::with(code, data, libs);
+    while !reader.is_eof() {
+        let pos = reader.pos();
+        boundaries.insert(pos);
+        let decoded = Isa::decode_instr(&mut reader);
+        if let Some(ref_target) = reader.data_overflow() {
+            return Err(VerifyError::DataRefOutOfBounds { offset: pos, ref_target });
+        }
+        let mut instr = decoded.map_err(|_| VerifyError::Decode(pos))?;
+        let len = reader.pos() - pos;
+        if len != instr.code_byte_len() {
+            return Err(VerifyError::LenMismatch(pos, instr.code_byte_len(), len));
+        }
+        if instr.encoding_version() > version {
+            return Err(VerifyError::UnsupportedInstr(pos, version));
+        }
+        let target = instr.local_goto_target(pos);
+        if let Some(goto_pos) = instr.local_goto_pos().copied() {
+            // Relative gotos pointing outside the addressable code are reported with their raw
+            // delta
+            let Some(target) = target else {
+                return Err(VerifyError::OutOfCode(pos, goto_pos));
+            };
+            gotos.push((pos, target));
+        }
+        gotos.extend(
+            instr
+                .local_goto_table()
+                .iter()
+                .map(|goto_pos| (pos, *goto_pos)),
+        );
+        if let Some(site) = instr.remote_goto_pos() {
+            if !libs.contains(&site.prog_id) {
+                return Err(VerifyError::UnlistedLib(pos, site.prog_id));
+            }
+        }
+    }
+
+    for (pos, goto_pos) in gotos {
+        if goto_pos == STUB_OFFSET && allow_stubs {
+            continue;
+        }
+        if goto_pos as usize >= code.len() {
+            return Err(VerifyError::OutOfCode(pos, goto_pos));
+        }
+        if !boundaries.contains(&goto_pos) {
+            return Err(VerifyError::Misaligned(pos, goto_pos));
+        }
+    }
+    Ok(boundaries)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{CtrlInstr, Instr};
+    use crate::library::Lib;
+    use crate::{aluasm, CompileOpts, Site};
+
+    fn uncompiled(code: &[Instr<LibId>]) -> CompiledLib {
+        let lib = Lib::assemble(code).unwrap();
+        CompiledLib {
+            id: lib.lib_id(),
+            lib,
+            routines: vec![],
+            names: none!(),
+            exports: None,
+            stubs: none!(),
+            version: default!(),
+            source_map: vec![],
+            entry: None,
+            meta: none!(),
+            relocs: vec![],
+            arities: none!(),
+            call_arities: none!(),
+        }
+    }
+
+    #[test]
+    fn verify() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code.clone(), &[]).unwrap();
+        lib.verify::<Instr<LibId>>().unwrap();
+
+        let opts = CompileOpts { allow_dangling: true, ..default!() };
+        let lib = CompiledLib::compile_with(&mut code[..3].to_vec(), &[], &[], &opts).unwrap();
+        lib.verify::<Instr<LibId>>().unwrap();
+
+        let lib = uncompiled(&[CtrlInstr::Nop.into(), CtrlInstr::Jmp { pos: 2 }.into()]);
+        assert_eq!(lib.verify::<Instr<LibId>>(), Err(VerifyError::Misaligned(1, 2)));
+
+        let lib = uncompiled(&[CtrlInstr::Fn { pos: 3 }.into()]);
+        assert_eq!(lib.verify::<Instr<LibId>>(), Err(VerifyError::OutOfCode(0, 3)));
+
+        let mut lib = uncompiled(&[CtrlInstr::Fn { pos: 0 }.into()]);
+        lib.lib.code.pop();
+        assert_eq!(lib.verify::<Instr<LibId>>(), Err(VerifyError::Decode(0)));
+
+        let lib_id = LibId::from([0xAB; 32]);
+        let mut lib = uncompiled(&[CtrlInstr::Call { site: Site::new(lib_id, 0) }.into()]);
+        lib.lib.libs.clear();
+        // References to absent library segment entries are decoded as a zero library id
+        assert_eq!(
+            lib.verify::<Instr<LibId>>(),
+            Err(VerifyError::UnlistedLib(0, LibId::default()))
+        );
+
+        let mut lib = uncompiled(&[CtrlInstr::Fn { pos: 0 }.into()]);
+        lib.routines.push(1);
+        assert_eq!(lib.verify::<Instr<LibId>>(), Err(VerifyError::InvalidRoutine(0, 1)));
+
+        let mut lib = uncompiled(&[CtrlInstr::Nop.into()]);
+        lib.entry = Some(0);
+        assert_eq!(lib.verify::<Instr<LibId>>(), Err(VerifyError::InvalidEntry(0)));
+    }
+
+    #[test]
+    fn data_refs() {
+        use crate::isa::Either;
+        use crate::testing::isa::XSwitch;
+
+        type Isa = Either<Instr<LibId>, XSwitch>;
+        let code: Vec<Isa> = vec![
+            Either::Base(CtrlInstr::Nop.into()),
+            Either::Ext(XSwitch(vec![0])),
+            Either::Base(CtrlInstr::Stop.into()),
+        ];
+        let mut lib = CompiledLib::compile(code, &[]).unwrap();
+        lib.verify::<Isa>().unwrap();
+        lib.lib.data.pop();
+        assert_eq!(
+            lib.verify::<Isa>(),
+            Err(VerifyError::DataRefOutOfBounds { offset: 1, ref_target: 0 })
+        );
+    }
+
+    #[test]
+    fn verify_limits() {
+        use crate::isa::Either;
+        use crate::testing::isa::XSwitch;
+
+        type Isa = Either<Instr<LibId>, XSwitch>;
+        let code: Vec<Isa> = vec![
+            Either::Base(CtrlInstr::Nop.into()),
+            Either::Ext(XSwitch(vec![0, 0, 0])),
+            Either::Base(CtrlInstr::Stop.into()),
+        ];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        assert_eq!(lib.verify_limits::<Isa>(default!()), Ok(()));
+        let limits = VerifyLimits { max_data_len: Some(6), max_immediate_len: Some(6) };
+        assert_eq!(lib.verify_limits::<Isa>(limits), Ok(()));
+        let limits = VerifyLimits { max_data_len: Some(5), max_immediate_len: None };
+        assert_eq!(
+            lib.verify_limits::<Isa>(limits),
+            Err(VerifyError::DataTooLarge { len: 6, max: 5 })
+        );
+        let limits = VerifyLimits { max_data_len: None, max_immediate_len: Some(4) };
+        assert_eq!(
+            lib.verify_limits::<Isa>(limits),
+            Err(VerifyError::ImmediateTooLarge { offset: 1, len: 6, max: 4 })
+        );
+    }
+
+    #[test]
+    fn verify_all() {
+        let libs = (0..100u16)
+            .map(|pos| uncompiled(&[CtrlInstr::Nop.into(), CtrlInstr::Jmp { pos }.into()]))
+            .collect::<Vec<_>>();
+        let expected = (0..100u16)
+            .map(|pos| match pos {
+                0 | 1 => Ok(()),
+                2 | 3 => Err(VerifyError::Misaligned(1, pos)),
+                _ => Err(VerifyError::OutOfCode(1, pos)),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(CompiledLib::verify_all::<Instr<LibId>>(&libs), expected);
+        assert!(CompiledLib::verify_all::<Instr<LibId>>(&[]).is_empty());
+    }
+
+    #[test]
+    fn verify_for() {
+        use crate::testing::isa::{RegInstr, XReg};
+
+        let lib = CompiledLib::compile(
+            [RegInstr::Nop, RegInstr::Put(XReg::A, 1), RegInstr::Mov(XReg::B, XReg::A)],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(lib.verify_for::<RegInstr>(|_| true), Ok(()));
+        assert_eq!(
+            lib.verify_for::<RegInstr>(|reg| reg == XReg::A),
+            Err(VerifyError::OutOfProfile(4))
+        );
+    }
+}
@@ -0,0 +1,267 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Zero-copy libraries borrowing their code and data segments.
+
+use alloc::borrow::Cow;
+use core::str::FromStr;
+
+use amplify::confinement::{SmallBlob, TinyOrdSet};
+
+use super::verifier::verify_code;
+use super::{DecodeError, EncodingVersion, Lib, LibId, LibsSeg, Marshaller, VerifyError};
+use crate::isa::{BytecodeRead, Instruction};
+use crate::IsaId;
+
+/// Errors parsing a library with [`Lib::from_mmap`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum LibViewError {
+    /// library data end unexpectedly at byte {0}.
+    UnexpectedEof(usize),
+
+    /// invalid ISA extension id at byte {0}.
+    InvalidIsa(usize),
+
+    /// ISA extension ids are not in a strict lexicographic order at byte {0}.
+    UnorderedIsae(usize),
+
+    /// library ids are not in a strict lexicographic order at byte {0}.
+    UnorderedLibs(usize),
+
+    /// library is followed by {0} bytes of unexpected data.
+    TrailingData(usize),
+}
+
+/// Library borrowing its code and data segments from an external buffer, like a memory-mapped
+/// file, such that it can be verified and executed without copying the bytecode.
+///
+/// Constructed with [`Lib::from_mmap`] from a strict-serialized library, or from a [`Lib`] with
+/// [`LibView::from`].
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct LibView<'a> {
+    id: LibId,
+    isae: Cow<'a, TinyOrdSet<IsaId>>,
+    code: &'a [u8],
+    data: &'a [u8],
+    libs: Cow<'a, LibsSeg>,
+}
+
+impl<'a> From<&'a Lib> for LibView<'a> {
+    fn from(lib: &'a Lib) -> Self {
+        LibView {
+            id: lib.lib_id(),
+            isae: Cow::Borrowed(&lib.isae),
+            code: lib.code.as_slice(),
+            data: lib.data.as_slice(),
+            libs: Cow::Borrowed(&lib.libs),
+        }
+    }
+}
+
+impl<'a> LibView<'a> {
+    /// Returns id of the library.
+    pub fn lib_id(&self) -> LibId { self.id }
+
+    /// Returns ISA extensions used by the library.
+    pub fn isae(&self) -> &TinyOrdSet<IsaId> { &self.isae }
+
+    /// Returns the code segment.
+    pub fn code(&self) -> &'a [u8] { self.code }
+
+    /// Returns the data segment.
+    pub fn data(&self) -> &'a [u8] { self.data }
+
+    /// Returns ids of the libraries called from the library code.
+    pub fn libs(&self) -> &LibsSeg { &self.libs }
+
+    /// Copies the library segments into an owned [`Lib`].
+    pub fn to_lib(&self) -> Lib {
+        Lib {
+            isae: self.isae.clone().into_owned(),
+            code: SmallBlob::from_checked(self.code.to_vec()),
+            data: SmallBlob::from_checked(self.data.to_vec()),
+            libs: self.libs.clone().into_owned(),
+        }
+    }
+
+    /// Iterates over the library code, lazily decoding the instructions together with their byte
+    /// offsets, in the same way as [`crate::CompiledLib::iter_instructions`].
+    pub fn iter_instructions<Isa>(
+        &self,
+    ) -> impl Iterator<Item = Result<(u16, Isa), DecodeError>> + '_
+    where Isa: Instruction<LibId> {
+        let mut reader = Marshaller::with(self.code, self.data, self.libs());
+        let mut failed = false;
+        core::iter::from_fn(move || {
+            if failed || reader.is_eof() {
+                return None;
+            }
+            let pos = reader.pos();
+            let instr = Isa::decode_instr(&mut reader).map_err(|_| DecodeError::Decode(pos));
+            failed = instr.is_err();
+            Some(instr.map(|instr| (pos, instr)))
+        })
+    }
+
+    /// Statically verifies the library bytecode in the same way as
+    /// [`crate::CompiledLib::verify`].
+    ///
+    /// Since the view doesn't know about the library routines, neither the routines nor the entry
+    /// point are checked, and gotos into stubs are not allowed. The instructions are checked
+    /// against the [current](EncodingVersion::CURRENT) bytecode encoding version.
+    ///
+    /// # Returns
+    ///
+    /// The first verification failure found.
+    pub fn verify<Isa>(&self) -> Result<(), VerifyError>
+    where Isa: Instruction<LibId> {
+        verify_code::<Isa>(self.code, self.data, &self.libs, false, EncodingVersion::CURRENT)
+            .map(|_| ())
+    }
+}
+
+impl Lib {
+    /// Parses a strict-serialized library (see [`LibId::from_segments`] for its layout) without
+    /// copying its code and data segments, which are borrowed from the provided buffer.
+    ///
+    /// This allows to load libraries from a memory-mapped file, paying only for the decoding of the
+    /// ISA extensions and library ids and for computing the library id.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the buffer doesn't contain exactly one valid strict-serialized library.
+    pub fn from_mmap(mmap: &[u8]) -> Result<LibView<'_>, LibViewError> {
+        let mut reader = Reader { bytes: mmap, pos: 0 };
+
+        let mut isae = TinyOrdSet::new();
+        for _ in 0..reader.byte()? {
+            let pos = reader.pos;
+            let len = reader.byte()?;
+            let isa = core::str::from_utf8(reader.take(len as usize)?)
+                .ok()
+                .and_then(|s| IsaId::from_str(s).ok())
+                .ok_or(LibViewError::InvalidIsa(pos))?;
+            if isae.last().is_some_and(|last| *last >= isa) {
+                return Err(LibViewError::UnorderedIsae(pos));
+            }
+            isae.push(isa)
+                .expect("the number of ISA extensions is limited by u8");
+        }
+        let code = reader.segment()?;
+        let data = reader.segment()?;
+        let mut libs = LibsSeg::new();
+        for _ in 0..reader.byte()? {
+            let pos = reader.pos;
+            let id = LibId::from(<[u8; 32]>::try_from(reader.take(32)?).expect("32 bytes"));
+            if libs.last().is_some_and(|last| *last >= id) {
+                return Err(LibViewError::UnorderedLibs(pos));
+            }
+            libs.push(id)
+                .expect("the number of libraries is limited by u8");
+        }
+        if reader.pos < mmap.len() {
+            return Err(LibViewError::TrailingData(mmap.len() - reader.pos));
+        }
+
+        Ok(LibView {
+            id: LibId::from_slices(&isae, code, data, &libs),
+            isae: Cow::Owned(isae),
+            code,
+            data,
+            libs: Cow::Owned(libs),
+        })
+    }
+}
+
+/// Cursor over the bytes of a strict-serialized library.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], LibViewError> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or(LibViewError::UnexpectedEof(self.bytes.len()))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn byte(&mut self) -> Result<u8, LibViewError> { Ok(self.take(1)?[0]) }
+
+    fn segment(&mut self) -> Result<&'a [u8], LibViewError> {
+        let len = u16::from_le_bytes([self.byte()?, self.byte()?]);
+        self.take(len as usize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use strict_encoding::StrictSerialize;
+
+    use super::*;
+    use crate::isa::Instr;
+    use crate::regs::Status;
+    use crate::{aluasm, CompiledLib, LibSite, Vm};
+
+    #[test]
+    fn from_mmap() {
+        const MAIN: u16 = 0;
+        let dep = CompiledLib::compile(aluasm! { .routine :MAIN; ret; }, &[]).unwrap();
+        let dep_id = dep.as_lib().lib_id();
+        let code = aluasm! {
+            not         CO      ;
+            call        dep_id, :MAIN ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[&dep]).unwrap();
+        let lib = lib.as_lib();
+        let bytes = lib.to_strict_serialized::<0x40000>().unwrap();
+
+        let view = Lib::from_mmap(&bytes).unwrap();
+        assert_eq!(view.lib_id(), lib.lib_id());
+        assert_eq!(view.code(), lib.code.as_slice());
+        assert_eq!(view.libs(), &lib.libs);
+        assert_eq!(&view.to_lib(), lib);
+        assert_eq!(view, LibView::from(lib));
+        assert_eq!(view.verify::<Instr<LibId>>(), Ok(()));
+        assert_eq!(view.iter_instructions::<Instr<LibId>>().count(), 3);
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let entry = LibSite::new(view.lib_id(), 0);
+        let dep = LibView::from(dep.as_lib());
+        let resolver = |id| [&view, &dep].into_iter().find(|lib| lib.lib_id() == id);
+        assert_eq!(vm.run_views(entry, &(), resolver), Ok(Status::Ok));
+        assert_eq!(vm.steps(), 5);
+
+        let truncated = &bytes[..bytes.len() - 1];
+        assert_eq!(Lib::from_mmap(truncated), Err(LibViewError::UnexpectedEof(truncated.len())));
+        let mut trailing = bytes.to_vec();
+        trailing.push(0);
+        assert_eq!(Lib::from_mmap(&trailing), Err(LibViewError::TrailingData(1)));
+    }
+}
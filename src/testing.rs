@@ -0,0 +1,149 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Helpers for testing ISA implementations, available with `tests` feature.
+
+use alloc::vec::Vec;
+
+use crate::isa::Instruction;
+use crate::{CompiledLib, LibId, Marshaller};
+
+#[cfg(test)]
+pub(crate) mod isa;
+
+/// Asserts that the code, being compiled, disassembled and compiled once again, produces exactly
+/// the same library, and that [`crate::isa::Bytecode::code_byte_len`] matches the length of the
+/// encoded bytecode for each of the instructions.
+///
+/// # Panics
+///
+/// If the code can't be compiled or any of the checks fail.
+pub fn assert_roundtrip<Isa>(code: &[Isa])
+where Isa: Instruction<LibId> {
+    assert_roundtrip_with(code, &[])
+}
+
+/// Performs the same checks as [`assert_roundtrip`] for the code referencing the provided
+/// dependencies.
+///
+/// # Panics
+///
+/// If the code can't be compiled or any of the checks fail.
+pub fn assert_roundtrip_with<Isa>(code: &[Isa], deps: &[&CompiledLib])
+where Isa: Instruction<LibId> {
+    let lib = CompiledLib::compile(code.to_vec(), deps)
+        .unwrap_or_else(|err| panic!("unable to compile the code: {err}"));
+    let libs = &lib.as_lib().libs;
+
+    for (no, instr) in code.iter().enumerate() {
+        let mut writer = Marshaller::new(libs);
+        instr
+            .encode_instr(&mut writer)
+            .unwrap_or_else(|err| panic!("unable to encode instruction #{no} `{instr}`: {err}"));
+        let (bytecode, _) = writer.finish();
+        assert_eq!(
+            bytecode.len(),
+            instr.code_byte_len() as usize,
+            "instruction #{no} `{instr}` declares code length different from its bytecode length"
+        );
+    }
+
+    let disassembled: Vec<Isa> = lib
+        .disassemble(deps)
+        .unwrap_or_else(|err| panic!("unable to disassemble the library: {err}"));
+    assert_eq!(disassembled, code, "disassembled code doesn't match the original one");
+    let recompiled = CompiledLib::compile(disassembled, deps)
+        .unwrap_or_else(|err| panic!("unable to compile the disassembled code: {err}"));
+    assert_eq!(
+        recompiled.as_lib().code,
+        lib.as_lib().code,
+        "recompiled library bytecode doesn't match the original one"
+    );
+    assert_eq!(recompiled, lib, "recompiled library doesn't match the original one");
+}
+
+/// Asserts that each of the local gotos in the code, not yet compiled, references an existing
+/// routine.
+///
+/// # Panics
+///
+/// If any of the local gotos references a routine number beyond the number of goto targets in the
+/// code.
+pub fn assert_goto_consistency<Isa>(code: &[Isa])
+where Isa: Instruction<LibId> {
+    let routines = code.iter().filter(|instr| instr.is_goto_target()).count();
+    for (no, instr) in code.iter().enumerate() {
+        let mut cloned = instr.clone();
+        let mut gotos = cloned.local_goto_table().to_vec();
+        gotos.extend(cloned.local_goto_pos().copied());
+        for pos in gotos {
+            assert!(
+                (pos as usize) < routines,
+                "instruction #{no} `{instr:?}` references routine {pos}, while the code has only \
+                 {routines} routines"
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::{CtrlInstr, Instr};
+    use crate::{aluasm, Site};
+
+    #[test]
+    fn roundtrip() {
+        const FUNC: u16 = 1;
+        let dep = CompiledLib::compile(
+            aluasm! {
+                nop     ;
+                ret     ;
+            },
+            &[],
+        )
+        .unwrap();
+        let mut code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            jif         CO, +2  ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        assert_goto_consistency(&code);
+        assert_roundtrip(&code);
+
+        code.push(CtrlInstr::Call { site: Site::new(dep.as_lib().lib_id(), 0) }.into());
+        assert_roundtrip_with(&code, &[&dep]);
+    }
+
+    #[test]
+    #[should_panic(expected = "references routine 2, while the code has only 2 routines")]
+    fn goto_consistency() {
+        let code: Vec<Instr<LibId>> =
+            vec![CtrlInstr::Nop.into(), CtrlInstr::Nop.into(), CtrlInstr::Jmp { pos: 2 }.into()];
+        assert_goto_consistency(&code);
+    }
+}
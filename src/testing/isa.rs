@@ -0,0 +1,304 @@
+// Reference rust implementation of AluVM (arithmetic logic unit virtual machine).
+// To find more on AluVM please check <https://aluvm.org>
+//
+// SPDX-License-Identifier: Apache-2.0
+//
+// Designed in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+// Written in 2021-2025 by Dr Maxim Orlovsky <orlovsky@ubideco.org>
+//
+// Copyright (C) 2021-2024 LNP/BP Standards Association, Switzerland.
+// Copyright (C) 2024-2025 Laboratories for Ubiquitous Deterministic Computing (UBIDECO),
+//                         Institute for Distributed and Cognitive Systems (InDCS), Switzerland.
+// Copyright (C) 2021-2025 Dr Maxim Orlovsky.
+// All rights under the above copyrights are reserved.
+//
+// Licensed under the Apache License, Version 2.0 (the "License"); you may not use this file except
+// in compliance with the License. You may obtain a copy of the License at
+//
+//        http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software distributed under the License
+// is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express
+// or implied. See the License for the specific language governing permissions and limitations under
+// the License.
+
+//! Instruction sets and core extensions used as fixtures by the tests across the crate.
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+
+use crate::core::{Core, CoreExt, Register, Site};
+use crate::isa::{
+    Bytecode, BytecodeRead, BytecodeWrite, CodeEofError, ExecStep, Instruction, RegAccess,
+};
+use crate::regs::Status;
+use crate::{LibId, NoExt, NoRegs};
+
+/// Register of the [`XCore`] extension.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub(crate) enum XReg {
+    #[display("A")]
+    A,
+    #[display("B")]
+    B,
+}
+
+impl Register for XReg {
+    type Value = u8;
+    fn bytes(self) -> u16 { 1 }
+}
+
+/// Core extension with two byte registers, `A` and `B`.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct XCore {
+    a: Option<u8>,
+    b: Option<u8>,
+}
+
+impl CoreExt for XCore {
+    type Reg = XReg;
+    type Config = ();
+
+    fn with(_config: Self::Config) -> Self { default!() }
+
+    fn get(&self, reg: Self::Reg) -> Option<u8> {
+        match reg {
+            XReg::A => self.a,
+            XReg::B => self.b,
+        }
+    }
+
+    fn clr(&mut self, reg: Self::Reg) { self.put(reg, None) }
+
+    fn put(&mut self, reg: Self::Reg, val: Option<u8>) {
+        match reg {
+            XReg::A => self.a = val,
+            XReg::B => self.b = val,
+        }
+    }
+
+    fn reset(&mut self) { *self = default!() }
+
+    #[cfg(feature = "zeroize")]
+    fn zeroize_regs(&mut self) {
+        zeroize::Zeroize::zeroize(&mut self.a);
+        zeroize::Zeroize::zeroize(&mut self.b);
+    }
+}
+
+/// Instruction set with registers, where `put` and `mov` are pure instructions.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Display)]
+pub(crate) enum RegInstr {
+    #[display("nop")]
+    Nop,
+    #[display("put {0}, {1}")]
+    Put(XReg, u8),
+    #[display("mov {0}, {1}")]
+    Mov(XReg, XReg),
+    #[display("jmp {0}")]
+    Jmp(u16),
+}
+
+impl Bytecode<LibId> for RegInstr {
+    fn op_range() -> RangeInclusive<u8> { 0..=3 }
+
+    fn opcode_byte(&self) -> u8 {
+        match self {
+            RegInstr::Nop => 0,
+            RegInstr::Put(..) => 1,
+            RegInstr::Mov(..) => 2,
+            RegInstr::Jmp(_) => 3,
+        }
+    }
+
+    fn code_byte_len(&self) -> u16 {
+        match self {
+            RegInstr::Nop => 1,
+            RegInstr::Put(..) | RegInstr::Mov(..) => 3,
+            RegInstr::Jmp(_) => 3,
+        }
+    }
+
+    fn external_ref(&self) -> Option<LibId> { None }
+
+    fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where W: BytecodeWrite<LibId> {
+        match *self {
+            RegInstr::Nop => Ok(()),
+            RegInstr::Put(reg, val) => {
+                writer.write_byte(reg as u8)?;
+                writer.write_byte(val)
+            }
+            RegInstr::Mov(dst, src) => {
+                writer.write_byte(dst as u8)?;
+                writer.write_byte(src as u8)
+            }
+            RegInstr::Jmp(pos) => writer.write_word(pos),
+        }
+    }
+
+    fn decode_operands<R>(reader: &mut R, opcode: u8) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: BytecodeRead<LibId>,
+    {
+        let reg = |byte: u8| if byte == 0 { XReg::A } else { XReg::B };
+        Ok(match opcode {
+            1 => RegInstr::Put(reg(reader.read_byte()?), reader.read_byte()?),
+            2 => RegInstr::Mov(reg(reader.read_byte()?), reg(reader.read_byte()?)),
+            3 => RegInstr::Jmp(reader.read_word()?),
+            _ => RegInstr::Nop,
+        })
+    }
+}
+
+impl Instruction<LibId> for RegInstr {
+    const ISA_EXT: &'static [&'static str] = &["XREG"];
+
+    type Core = XCore;
+    type Context<'ctx> = ();
+
+    fn mnemonic(&self) -> &'static str {
+        match self {
+            RegInstr::Nop => "nop",
+            RegInstr::Put(..) => "put",
+            RegInstr::Mov(..) => "mov",
+            RegInstr::Jmp(_) => "jmp",
+        }
+    }
+
+    fn is_goto_target(&self) -> bool { *self == RegInstr::Nop }
+
+    fn local_goto_pos(&mut self) -> Option<&mut u16> {
+        match self {
+            RegInstr::Jmp(pos) => Some(pos),
+            _ => None,
+        }
+    }
+
+    fn remote_goto_pos(&mut self) -> Option<&mut Site<LibId>> { None }
+
+    fn is_terminal(&self) -> bool { matches!(self, RegInstr::Jmp(_)) }
+
+    fn reg_access(&self) -> RegAccess<XReg> {
+        let pure = !matches!(self, RegInstr::Jmp(_));
+        RegAccess { reads: self.src_regs(), writes: self.dst_regs(), pure }
+    }
+
+    fn src_regs(&self) -> BTreeSet<XReg> {
+        match *self {
+            RegInstr::Mov(_, src) => bset![src],
+            _ => none!(),
+        }
+    }
+
+    fn dst_regs(&self) -> BTreeSet<XReg> {
+        match *self {
+            RegInstr::Put(dst, _) | RegInstr::Mov(dst, _) => bset![dst],
+            _ => none!(),
+        }
+    }
+
+    fn op_data_bytes(&self) -> u16 { none!() }
+
+    fn ext_data_bytes(&self) -> u16 { none!() }
+
+    fn exec(
+        &self,
+        _: Site<LibId>,
+        core: &mut Core<LibId, XCore>,
+        _: &Self::Context<'_>,
+    ) -> ExecStep<Site<LibId>> {
+        match *self {
+            RegInstr::Nop => ExecStep::Next,
+            RegInstr::Put(reg, val) => {
+                core.cx.set(reg, val);
+                ExecStep::Next
+            }
+            RegInstr::Mov(dst, src) => {
+                let val = core.cx.get(src);
+                core.cx.put(dst, val);
+                ExecStep::Next
+            }
+            RegInstr::Jmp(pos) => ExecStep::Jump(pos),
+        }
+    }
+}
+
+/// Extension instruction performing indirect goto to one of the jump table routines, using
+/// `CO` value as the table index.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Display)]
+#[display("xswitch CO, {0:?}")]
+pub(crate) struct XSwitch(pub Vec<u16>);
+
+impl Bytecode<LibId> for XSwitch {
+    fn op_range() -> RangeInclusive<u8> { 0x81..=0x81 }
+
+    fn opcode_byte(&self) -> u8 { 0x81 }
+
+    fn code_byte_len(&self) -> u16 { 5 }
+
+    fn external_ref(&self) -> Option<LibId> { None }
+
+    fn encode_operands<W>(&self, writer: &mut W) -> Result<(), W::Error>
+    where W: BytecodeWrite<LibId> {
+        let table = self
+            .0
+            .iter()
+            .flat_map(|pos| pos.to_le_bytes())
+            .collect::<Vec<_>>();
+        writer.write_bytes(&table)
+    }
+
+    fn decode_operands<R>(reader: &mut R, _opcode: u8) -> Result<Self, CodeEofError>
+    where
+        Self: Sized,
+        R: BytecodeRead<LibId>,
+    {
+        let (table, _) = reader.read_bytes()?;
+        let table = table
+            .chunks_exact(2)
+            .map(|pos| u16::from_le_bytes([pos[0], pos[1]]))
+            .collect();
+        Ok(XSwitch(table))
+    }
+}
+
+impl Instruction<LibId> for XSwitch {
+    const ISA_EXT: &'static [&'static str] = &["XSWITCH"];
+
+    type Core = NoExt;
+    type Context<'ctx> = ();
+
+    fn mnemonic(&self) -> &'static str { "xswitch" }
+
+    fn is_goto_target(&self) -> bool { false }
+
+    fn local_goto_pos(&mut self) -> Option<&mut u16> { None }
+
+    fn remote_goto_pos(&mut self) -> Option<&mut Site<LibId>> { None }
+
+    fn local_goto_table(&mut self) -> &mut [u16] { &mut self.0 }
+
+    fn src_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+    fn dst_regs(&self) -> BTreeSet<NoRegs> { none!() }
+
+    fn op_data_bytes(&self) -> u16 { none!() }
+
+    fn ext_data_bytes(&self) -> u16 { self.0.len() as u16 * 2 }
+
+    fn exec(
+        &self,
+        _: Site<LibId>,
+        core: &mut Core<LibId, NoExt>,
+        _: &Self::Context<'_>,
+    ) -> ExecStep<Site<LibId>> {
+        let index = (core.co() == Status::Fail) as usize;
+        match self.0.get(index) {
+            Some(pos) => ExecStep::Jump(*pos),
+            None => ExecStep::Fail,
+        }
+    }
+}
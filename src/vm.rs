@@ -24,61 +24,849 @@
 
 //! Alu virtual machine
 
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Display, Formatter};
 use core::marker::PhantomData;
 
-use crate::core::{Core, CoreConfig, CoreExt, Status};
+use crate::core::{Core, CoreConfig, CoreExt, OverflowMode, RegisterSnapshot, Status};
 use crate::isa::{Instr, Instruction};
-use crate::library::{Jump, Lib, LibId, LibSite};
+use crate::library::{
+    Arity, CompiledLib, ExecHook, HaltReason, HookBreak, Jump, Lib, LibId, LibSite, LibView,
+    PreparedLib,
+};
+use crate::{Register, Site};
+
+/// Errors which halt program execution by the virtual machine.
+///
+/// Each of the errors carries the site of the instruction at which the execution was halted; the
+/// index of the instruction in the source code can be found with
+/// [`CompiledLib::source_index_at`].
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub enum ExecError {
+    /// execution step limit is exceeded at {0}.
+    StepLimitExceeded(LibSite),
+
+    /// call stack depth limit is exceeded at {0}.
+    CallStackOverflow(LibSite),
+
+    /// external call at {0} to an unresolved relocation.
+    UnresolvedReloc(LibSite),
+
+    /// execution deadline is exceeded at {0}.
+    Timeout(LibSite),
+
+    /// jump or call to a code offset {0} which is not a routine entry point.
+    InvalidEntry(LibSite),
+
+    /// host call at {0} exceeds the limit of the nested host calls.
+    ReentrancyLimit(LibSite),
+}
+
+impl ExecError {
+    /// Returns the site at which the execution was halted.
+    ///
+    /// For [`Self::UnresolvedReloc`] this is the site of the external call; if the unresolved
+    /// relocation was the entry point of the program, the relocation site itself is returned.
+    pub fn site(&self) -> LibSite {
+        match *self {
+            ExecError::StepLimitExceeded(site)
+            | ExecError::CallStackOverflow(site)
+            | ExecError::UnresolvedReloc(site)
+            | ExecError::Timeout(site)
+            | ExecError::InvalidEntry(site)
+            | ExecError::ReentrancyLimit(site) => site,
+        }
+    }
+}
+
+/// Number of instructions executed between the checks of the [execution deadline](Vm::deadline).
+#[cfg(feature = "std")]
+pub const DEADLINE_CHECK_INTERVAL: u64 = 0x100;
+
+/// Register type of an instruction set.
+pub type RegOf<Isa> = <<Isa as Instruction<LibId>>::Core as CoreExt>::Reg;
 
 /// Alu virtual machine providing single-core execution environment
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug)]
 pub struct Vm<Isa = Instr<LibId>>
 where Isa: Instruction<LibId>
 {
     /// A set of registers
     pub core: Core<LibId, Isa::Core>,
 
+    /// Number of execution steps performed by the VM since its construction or the last reset.
+    steps: u64,
+
+    /// Maximum number of execution steps which can be performed by the VM.
+    step_limit: Option<u64>,
+
+    /// Function computing the number of steps taken by an instruction.
+    step_cost: fn(&Isa) -> u64,
+
+    /// Maximum depth of the call stack allowed during the execution.
+    max_call_depth: Option<u16>,
+
+    /// Whether jumps and calls are allowed only to the routine entry points.
+    strict_entries: bool,
+
+    /// Whether `CK` is reset each time a subroutine is called.
+    reset_status_on_call: bool,
+
+    /// Coverage of the code by the executed instructions, if it is recorded.
+    coverage: Option<Coverage>,
+
+    /// Registers which values are derived from the tainted inputs, if the taint is tracked.
+    taint: Option<BTreeSet<RegOf<Isa>>>,
+
+    /// Faults injected at the instruction sites for testing.
+    #[cfg(any(test, feature = "tests"))]
+    faults: BTreeMap<LibSite, fn(LibSite) -> ExecError>,
+
+    /// Site of the last instruction which execution was attempted by the VM.
+    last_site: Option<LibSite>,
+
+    /// Wall-clock time after which the execution is halted.
+    #[cfg(feature = "std")]
+    deadline: Option<std::time::Instant>,
+
     phantom: PhantomData<Isa>,
 }
 
+impl<Isa> Default for Vm<Isa>
+where Isa: Instruction<LibId>
+{
+    fn default() -> Self { Self::new() }
+}
+
 /// Runtime for program execution.
 impl<Isa> Vm<Isa>
 where Isa: Instruction<LibId>
 {
     /// Constructs new virtual machine instance with default core configuration.
-    pub fn new() -> Self { Self { core: Core::new(), phantom: Default::default() } }
+    pub fn new() -> Self { Self::with(default!(), default!()) }
 
     /// Constructs new virtual machine instance with default core configuration.
     pub fn with(config: CoreConfig, cx_config: <Isa::Core as CoreExt>::Config) -> Self {
         Self {
             core: Core::with(config, cx_config),
+            steps: 0,
+            step_limit: None,
+            step_cost: |_| 1,
+            max_call_depth: None,
+            strict_entries: false,
+            reset_status_on_call: false,
+            coverage: None,
+            taint: None,
+            #[cfg(any(test, feature = "tests"))]
+            faults: none!(),
+            last_site: None,
+            #[cfg(feature = "std")]
+            deadline: None,
             phantom: Default::default(),
         }
     }
 
     /// Resets all registers of the VM except those which were set up with the config object.
-    pub fn reset(&mut self) { self.core.reset(); }
+    ///
+    /// Resets the step counter, but keeps the step limit and step cost function.
+    pub fn reset(&mut self) {
+        self.core.reset();
+        self.steps = 0;
+        self.last_site = None;
+    }
+
+    /// Captures the state of all the VM registers.
+    ///
+    /// The step counter is not a part of the snapshot.
+    pub fn snapshot(&self) -> RegisterSnapshot<LibId, Isa::Core> { self.core.snapshot() }
+
+    /// Captures the state of the VM for a post-mortem debugging, including the registers (with the
+    /// call stack), the site of the last instruction executed (or attempted to be executed, if the
+    /// execution has faulted) and the step counter.
+    ///
+    /// Dumps of the VMs running the same program on the same inputs are equal.
+    pub fn dump(&self) -> VmDump<Isa::Core> {
+        VmDump {
+            core: self.core.clone(),
+            site: self.last_site,
+            steps: self.steps,
+        }
+    }
+
+    /// Returns the site of the last instruction which execution was attempted by the VM, if
+    /// any.
+    pub fn last_site(&self) -> Option<LibSite> { self.last_site }
+
+    /// Restores the state of all the VM registers from a snapshot taken with [`Self::snapshot`].
+    pub fn restore(&mut self, snapshot: &RegisterSnapshot<LibId, Isa::Core>) {
+        self.core.restore(snapshot)
+    }
+
+    /// Returns the value of an ISA register, or `None` if the register is not set.
+    ///
+    /// Used to read the program outputs after the execution.
+    pub fn get_reg(&self, reg: RegOf<Isa>) -> Option<<RegOf<Isa> as Register>::Value> {
+        self.core.get(reg)
+    }
+
+    /// Sets an ISA register to a value.
+    ///
+    /// Used to provide the program inputs before the execution. Since [`Self::reset`] clears the
+    /// registers, the inputs must be set after the reset.
+    pub fn set_reg(&mut self, reg: RegOf<Isa>, val: <RegOf<Isa> as Register>::Value) {
+        self.core.set(reg, val)
+    }
+
+    /// Returns the value of an ISA register converted into a Rust type, or `None` if the register
+    /// is not set.
+    ///
+    /// # Errors
+    ///
+    /// If the register value doesn't fit into the requested type.
+    pub fn get_reg_as<T>(&self, reg: RegOf<Isa>) -> Option<Result<T, T::Error>>
+    where T: TryFrom<<RegOf<Isa> as Register>::Value> {
+        self.core.get(reg).map(T::try_from)
+    }
+
+    /// Sets an ISA register to a value converted from a Rust type, like an integer or a byte array.
+    ///
+    /// # Errors
+    ///
+    /// If the value doesn't fit into the register, in which case the register is left unchanged.
+    pub fn set_reg_from<T>(
+        &mut self,
+        reg: RegOf<Isa>,
+        val: T,
+    ) -> Result<(), <<RegOf<Isa> as Register>::Value as TryFrom<T>>::Error>
+    where
+        <RegOf<Isa> as Register>::Value: TryFrom<T>,
+    {
+        self.core.set(reg, val.try_into()?);
+        Ok(())
+    }
+
+    /// Clears an ISA register, setting it to an undefined state.
+    pub fn clr_reg(&mut self, reg: RegOf<Isa>) { self.core.clr(reg) }
+
+    /// Returns the number of execution steps performed since the VM construction or the last
+    /// reset.
+    pub fn steps(&self) -> u64 { self.steps }
+
+    /// Returns the maximum number of execution steps the VM is allowed to perform.
+    pub fn step_limit(&self) -> Option<u64> { self.step_limit }
+
+    /// Sets the maximum number of execution steps the VM is allowed to perform. Once the limit is
+    /// reached, the execution halts with [`ExecError::StepLimitExceeded`].
+    pub fn set_step_limit(&mut self, limit: Option<u64>) { self.step_limit = limit; }
+
+    /// Sets the function computing the number of steps taken by each instruction.
+    ///
+    /// By default, each instruction takes a single step.
+    pub fn set_step_cost(&mut self, cost: fn(&Isa) -> u64) { self.step_cost = cost; }
+
+    /// Returns the wall-clock deadline of the execution, if set.
+    #[cfg(feature = "std")]
+    pub fn deadline(&self) -> Option<std::time::Instant> { self.deadline }
+
+    /// Sets the wall-clock deadline of the execution. Once the deadline passes, the execution
+    /// halts with [`ExecError::Timeout`].
+    ///
+    /// The deadline is checked before the first instruction and then once per
+    /// [`DEADLINE_CHECK_INTERVAL`] instructions, independently of the step limit.
+    #[cfg(feature = "std")]
+    pub fn set_deadline(&mut self, deadline: Option<std::time::Instant>) {
+        self.deadline = deadline;
+    }
+
+    /// Returns the overflow mode used by integer arithmetic operations.
+    pub fn overflow_mode(&self) -> OverflowMode { self.core.om() }
+
+    /// Sets the overflow mode used by integer arithmetic operations. The mode is kept on reset.
+    pub fn set_overflow_mode(&mut self, mode: OverflowMode) { self.core.set_om(mode); }
+
+    /// Returns the maximum depth of the call stack allowed during the execution.
+    pub fn max_call_depth(&self) -> Option<u16> { self.max_call_depth }
+
+    /// Sets the maximum depth of the call stack allowed during the execution. A call which would
+    /// exceed the limit is not performed, and the execution halts with
+    /// [`ExecError::CallStackOverflow`] reporting the site of the call instruction.
+    ///
+    /// Independently of the limit, calls exceeding [`crate::regs::CALL_STACK_SIZE_MAX`] fail,
+    /// setting `CK` to a failed state.
+    pub fn set_max_call_depth(&mut self, depth: Option<u16>) { self.max_call_depth = depth; }
+
+    /// Returns whether jumps and calls are allowed only to the routine entry points.
+    pub fn strict_entries(&self) -> bool { self.strict_entries }
+
+    /// Enables or disables the strict mode, in which any absolute jump or call must land on a
+    /// routine entry point, i.e. an instruction which [is a goto
+    /// target](Instruction::is_goto_target). Otherwise, the execution halts with
+    /// [`ExecError::InvalidEntry`].
+    ///
+    /// Relative jumps, returns from the calls and the entry point of the program are not checked;
+    /// use [`CompiledLib::site_is_valid_entry`] to validate the latter.
+    pub fn set_strict_entries(&mut self, strict: bool) { self.strict_entries = strict; }
+
+    /// Returns whether `CK` is reset each time a subroutine is called.
+    pub fn reset_status_on_call(&self) -> bool { self.reset_status_on_call }
+
+    /// Sets whether `CK` is reset to a non-failed state each time a subroutine is called, so the
+    /// subroutine starts with a clean status regardless of the failures in the caller code.
+    ///
+    /// By default, the flag is not set, and a failed `CK` is passed to the called subroutine, where
+    /// it may halt the execution (for instance, with `chk CK`). In both cases, the `CK` value set
+    /// by the subroutine is kept after it returns. The state of `CK` at the program entry point
+    /// is not affected by the flag.
+    pub fn set_reset_status_on_call(&mut self, reset: bool) { self.reset_status_on_call = reset; }
+
+    /// Enables or disables recording of the code coverage (see [`Self::coverage`]).
+    ///
+    /// Disabling the recording discards the coverage recorded so far.
+    pub fn set_coverage(&mut self, enabled: bool) {
+        match enabled {
+            true if self.coverage.is_none() => self.coverage = Some(none!()),
+            true => {}
+            false => self.coverage = None,
+        }
+    }
+
+    /// Returns the coverage of the code by the instructions executed since the recording was
+    /// enabled with [`Self::set_coverage`], or `None` if the coverage is not recorded.
+    ///
+    /// The coverage accumulates over multiple program runs, and is not cleared with
+    /// [`Self::reset`].
+    pub fn coverage(&self) -> Option<&Coverage> { self.coverage.as_ref() }
+
+    /// Enables tracking of the register taint, marking the `seeds` registers as tainted.
+    ///
+    /// During the execution, the registers written by an instruction become tainted if any of the
+    /// registers read by the instruction (see [`Instruction::reg_access`]) is tainted, and are
+    /// cleaned otherwise. Registers which may be only partially changed by the instruction keep
+    /// their taint. Implicit flows through the control registers and branching are not tracked.
+    ///
+    /// If the tracking is already enabled, the previously tainted registers are replaced with the
+    /// `seeds`.
+    pub fn set_taint(&mut self, seeds: impl IntoIterator<Item = RegOf<Isa>>) {
+        self.taint = Some(seeds.into_iter().collect());
+    }
+
+    /// Disables tracking of the register taint, discarding the taint information.
+    pub fn clear_taint(&mut self) { self.taint = None; }
+
+    /// Returns the set of the tainted registers, or `None` if the taint is not tracked.
+    ///
+    /// The taint persists over multiple program runs, and is not cleared with [`Self::reset`].
+    pub fn tainted(&self) -> Option<&BTreeSet<RegOf<Isa>>> { self.taint.as_ref() }
+
+    /// Checks whether the register value is derived from the tainted inputs.
+    ///
+    /// Always returns `false` if the taint is not tracked (see [`Self::set_taint`]).
+    pub fn is_tainted(&self, reg: RegOf<Isa>) -> bool {
+        self.taint
+            .as_ref()
+            .is_some_and(|taint| taint.contains(&reg))
+    }
+
+    /// Injects a fault at the instruction located at the given site, such that the program halts
+    /// with the error of the provided kind each time the execution reaches the instruction, before
+    /// the instruction is executed.
+    ///
+    /// This is a testing aid allowing to exercise error handling without crafting the code
+    /// causing the error, available with `tests` feature. Injected faults persist over multiple
+    /// program runs until [cleared](Self::clear_faults).
+    #[cfg(any(test, feature = "tests"))]
+    pub fn inject_fault_at(&mut self, site: LibSite, kind: fn(LibSite) -> ExecError) {
+        self.faults.insert(site, kind);
+    }
+
+    /// Removes all the faults injected with [`Self::inject_fault_at`].
+    #[cfg(any(test, feature = "tests"))]
+    pub fn clear_faults(&mut self) { self.faults.clear(); }
+
+    /// Returns the current depth of the call stack.
+    pub fn call_depth(&self) -> u16 { self.core.cp() }
+
+    /// Returns the return addresses from the call stack, starting from the outermost call.
+    pub fn call_stack(&self) -> Vec<LibSite> {
+        self.core.cs().iter().copied().map(LibSite::from).collect()
+    }
 
     /// Executes the program starting from the provided entry point.
     ///
     /// # Returns
     ///
-    /// Value of the `CK` register at the end of the program execution.
+    /// Value of the `CK` register at the end of the program execution. If the execution was halted
+    /// with an [`ExecError`], `CK` is set to a failed state.
     pub fn exec<L: AsRef<Lib>>(
         &mut self,
         entry_point: LibSite,
         context: &Isa::Context<'_>,
         lib_resolver: impl Fn(LibId) -> Option<L>,
     ) -> Status {
-        let mut call = Some(entry_point);
+        match self.run(entry_point, context, lib_resolver) {
+            Ok(status) => status,
+            Err(_) => {
+                let _ = self.core.fail_ck();
+                self.core.ck()
+            }
+        }
+    }
+
+    /// Runs the program starting from the provided entry point.
+    ///
+    /// # Returns
+    ///
+    /// Value of the `CK` register at the end of the program execution, or an error if the program
+    /// execution was halted by the VM.
+    pub fn run<L: AsRef<Lib>>(
+        &mut self,
+        entry_point: LibSite,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> Result<Status, ExecError> {
+        self.run_traced(entry_point, context, lib_resolver, |_, _, _| {})
+    }
+
+    /// Runs the program starting from the provided entry point in the same way as [`Self::run`],
+    /// calling `tracer` before the execution of each of the instructions.
+    ///
+    /// The tracer is provided with the location of the instruction, the decoded instruction itself
+    /// and the state of the registers before the instruction execution.
+    pub fn run_traced<L: AsRef<Lib>>(
+        &mut self,
+        entry_point: LibSite,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+        tracer: impl FnMut(LibSite, &Isa, &Core<LibId, Isa::Core>),
+    ) -> Result<Status, ExecError> {
+        let run =
+            self.run_hooked(ExecPoint::entry(entry_point), None, context, lib_resolver, tracer);
+        self.status(unfueled(run))
+    }
+
+    /// Runs the program starting from the provided entry point in the same way as [`Self::run`],
+    /// using libraries which code is already decoded with [`CompiledLib::prepare`].
+    ///
+    /// Since the instructions are not decoded during the execution, this is faster for the
+    /// libraries which are run multiple times.
+    pub fn run_prepared<'lib>(
+        &mut self,
+        entry_point: LibSite,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<&'lib PreparedLib<Isa>>,
+    ) -> Result<Status, ExecError>
+    where
+        Isa: 'lib,
+    {
+        let run = self.run_with(
+            ExecPoint::entry(entry_point),
+            None,
+            false,
+            |_, _, _| {},
+            |site, skip, core, hook| {
+                let lib = lib_resolver(site.lib_id)?;
+                Some(lib.exec_hooked(site.offset, skip, core, context, hook))
+            },
+        );
+        self.status(unfueled(run))
+    }
+
+    /// Runs a single routine of an already [prepared](CompiledLib::prepare) library, returning
+    /// the reason of the program halt.
+    ///
+    /// The routine is run on an empty call stack, such that the program halts with
+    /// [`HaltReason::Stop`] once the routine returns; the call stack of the caller is restored
+    /// afterwards. Registers are kept, so they can be used to
+    /// pass arguments to the routine. Calls to other libraries halt the program with
+    /// [`HaltReason::UnknownLib`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`CallError::NoRoutine`] if the library doesn't define routine `routine_no`.
+    pub fn run_routine(
+        &mut self,
+        lib: &PreparedLib<Isa>,
+        routine_no: u16,
+        context: &Isa::Context<'_>,
+    ) -> Result<HaltReason, CallError> {
+        let entry = lib
+            .try_routine(routine_no)
+            .ok_or(CallError::NoRoutine(routine_no))?;
+        let caller = self.core.cs().to_vec();
+        while self.core.pop_cs().is_some() {}
+        let run = self.run_with(
+            ExecPoint::entry(entry),
+            None,
+            false,
+            |_, _, _| {},
+            |site, skip, core, hook| {
+                (site.lib_id == lib.lib_id())
+                    .then(|| lib.exec_hooked(site.offset, skip, core, context, hook))
+            },
+        );
+        while self.core.pop_cs().is_some() {}
+        for frame in caller {
+            let _ = self.core.push_cs(frame);
+        }
+        Ok(unfueled(run))
+    }
+
+    /// Runs the program starting from the provided entry point in the same way as [`Self::run`],
+    /// using libraries which code is borrowed from an external buffer with [`Lib::from_mmap`].
+    pub fn run_views<'lib, 'code: 'lib>(
+        &mut self,
+        entry_point: LibSite,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<&'lib LibView<'code>>,
+    ) -> Result<Status, ExecError> {
+        let run = self.run_with(
+            ExecPoint::entry(entry_point),
+            None,
+            false,
+            |_, _, _| {},
+            |site, skip, core, hook| {
+                let lib = lib_resolver(site.lib_id)?;
+                Some(lib.exec_hooked(site.offset, skip, core, context, hook))
+            },
+        );
+        self.status(unfueled(run))
+    }
+
+    /// Runs the program starting from the provided entry point, returning the reason of the
+    /// program halt.
+    ///
+    /// Unless the program has completed (see [`HaltReason::is_completion`]), `CK` is set to a
+    /// failed state.
+    pub fn run_reported<L: AsRef<Lib>>(
+        &mut self,
+        entry_point: LibSite,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> HaltReason {
+        let run = self.run_hooked(
+            ExecPoint::entry(entry_point),
+            None,
+            context,
+            lib_resolver,
+            |_, _, _| {},
+        );
+        let reason = unfueled(run);
+        if !reason.is_completion() && self.core.ck().is_ok() {
+            let _ = self.core.fail_ck();
+        }
+        reason
+    }
+
+    /// Restores the registers from the `input` snapshot, runs the program starting from the
+    /// provided entry point and returns the snapshot of the registers once the program completes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the halt reason if the program hasn't completed (see [`HaltReason::is_completion`]),
+    /// including VM faults reported as [`HaltReason::Fault`]. A program which has completed with a
+    /// failed `CK` is reported as [`HaltReason::Fail`].
+    pub fn run_checked<L: AsRef<Lib>>(
+        &mut self,
+        entry_point: LibSite,
+        input: &RegisterSnapshot<LibId, Isa::Core>,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> Result<RegisterSnapshot<LibId, Isa::Core>, HaltReason> {
+        self.restore(input);
+        let reason = self.run_reported(entry_point, context, lib_resolver);
+        if !reason.is_completion() {
+            return Err(reason);
+        }
+        if !self.core.ck().is_ok() {
+            return Err(HaltReason::Fail);
+        }
+        Ok(self.snapshot())
+    }
+
+    /// Runs the program starting from the provided entry point, performing at most `fuel`
+    /// execution steps (as measured by the [step cost function](Self::set_step_cost)).
+    ///
+    /// If the program doesn't complete within the provided fuel, the execution is paused before
+    /// the next instruction, and the VM state required to continue the execution is returned as
+    /// [`RunOutcome::Paused`]. The state can be moved, serialized and later passed to
+    /// [`Self::resume`] of this or other VM with the same configuration. This allows running
+    /// multiple programs cooperatively by interleaving their execution.
+    ///
+    /// At least one instruction is executed with each call, even if its cost exceeds the fuel.
+    /// The [step limit](Self::set_step_limit) is applied independently of the fuel and halts the
+    /// program with an error.
+    pub fn run_fueled<L: AsRef<Lib>>(
+        &mut self,
+        entry_point: LibSite,
+        fuel: u64,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> Result<RunOutcome<Isa::Core>, ExecError> {
+        self.run_fueled_from(ExecPoint::entry(entry_point), fuel, context, lib_resolver)
+    }
+
+    fn run_fueled_from<L: AsRef<Lib>>(
+        &mut self,
+        start: ExecPoint,
+        fuel: u64,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> Result<RunOutcome<Isa::Core>, ExecError> {
+        Ok(match self.run_hooked(start, Some(fuel), context, lib_resolver, |_, _, _| {}) {
+            Ok(HaltReason::Fault(err)) => return Err(err),
+            Ok(_) => RunOutcome::Complete(self.core.ck()),
+            Err(ExecPoint { site, entering }) => RunOutcome::Paused(VmState {
+                core: self.core.clone(),
+                site,
+                entering,
+                steps: self.steps,
+            }),
+        })
+    }
+
+    /// Resumes the execution of a program paused by [`Self::run_fueled`], performing at most
+    /// `fuel` more execution steps.
+    ///
+    /// The registers and the step counter of the VM are replaced with the ones from the paused
+    /// state.
+    pub fn resume<L: AsRef<Lib>>(
+        &mut self,
+        state: VmState<Isa::Core>,
+        fuel: u64,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> Result<RunOutcome<Isa::Core>, ExecError> {
+        let VmState { core, site, entering, steps } = state;
+        self.core = core;
+        self.steps = steps;
+        self.run_fueled_from(ExecPoint { site, entering }, fuel, context, lib_resolver)
+    }
+
+    /// Runs the program starting from the provided entry point, recording the execution into a
+    /// log, which can be later [replayed](Self::replay) to check the execution determinism.
+    ///
+    /// The log contains the initial state of the registers and, for each of the executed
+    /// instructions, its location, opcode and the changes it made to the destination registers.
+    pub fn run_logged<L: AsRef<Lib>>(
+        &mut self,
+        entry_point: LibSite,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> ExecLog<Isa::Core>
+    where
+        <RegOf<Isa> as Register>::Value: PartialEq,
+    {
+        let initial = self.core.clone();
+        let start_steps = self.steps;
+        let mut steps = vec![];
+        let mut pending = None;
+        let tracer = |site, instr: &Isa, core: &Core<LibId, Isa::Core>| {
+            if let Some(step) = pending.take() {
+                steps.push(LogStep::complete(step, core));
+            }
+            let deltas = instr
+                .dst_regs()
+                .into_iter()
+                .map(|reg| (reg, core.get(reg)))
+                .collect();
+            pending = Some(LogStep {
+                site,
+                opcode: instr.opcode_byte(),
+                deltas,
+                co: core.co(),
+                ck: core.ck(),
+            });
+        };
+        let run =
+            self.run_hooked(ExecPoint::entry(entry_point), None, context, lib_resolver, tracer);
+        let halt = unfueled(run);
+        if let Some(step) = pending.take() {
+            steps.push(LogStep::complete(step, &self.core));
+        }
+        ExecLog { initial, entry: entry_point, start_steps, steps, halt }
+    }
+
+    /// Replays the execution recorded with [`Self::run_logged`], checking that each of the
+    /// executed instructions matches the log.
+    ///
+    /// The registers and the step counter of the VM are replaced with their state at the start of
+    /// the logged execution; the step limitations must be configured in the same way as for the
+    /// logged execution.
+    pub fn replay<L: AsRef<Lib>>(
+        &mut self,
+        log: &ExecLog<Isa::Core>,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+    ) -> Result<(), ReplayError>
+    where
+        <RegOf<Isa> as Register>::Value: PartialEq,
+    {
+        self.core = log.initial.clone();
+        self.steps = log.start_steps;
+        let replayed = self.run_logged(log.entry, context, lib_resolver);
+        if let Some(no) = (0..log.steps.len().max(replayed.steps.len()))
+            .find(|no| log.steps.get(*no) != replayed.steps.get(*no))
+        {
+            return Err(ReplayError::Diverged(no));
+        }
+        if replayed.halt != log.halt {
+            return Err(ReplayError::HaltMismatch { expected: log.halt, found: replayed.halt });
+        }
+        Ok(())
+    }
+
+    /// Calls a library routine as a function, passing the arguments and taking the returned values
+    /// through the registers defined by the calling convention.
+    ///
+    /// The arguments are loaded into the [argument registers](CallConv::args), and then the
+    /// routine is run until the program halts, resolving external calls against the library itself
+    /// and the provided dependencies. The rest of the registers are left intact, and must be reset
+    /// by the caller if required.
+    ///
+    /// # Returns
+    ///
+    /// Values of the [return registers](CallConv::returns) after the routine completion, which
+    /// are `None` for the registers left unset.
+    #[allow(clippy::type_complexity)]
+    pub fn call_routine(
+        &mut self,
+        lib: &CompiledLib,
+        routine_no: u16,
+        conv: &CallConv<RegOf<Isa>>,
+        args: &[<RegOf<Isa> as Register>::Value],
+        deps: &[&CompiledLib],
+        context: &Isa::Context<'_>,
+    ) -> Result<Vec<Option<<RegOf<Isa> as Register>::Value>>, CallError> {
+        let entry = lib
+            .try_routine(routine_no)
+            .ok_or(CallError::NoRoutine(routine_no))?;
+        if args.len() != conv.args.len() {
+            return Err(CallError::ArgCount { expected: conv.args.len(), found: args.len() });
+        }
+        for (reg, val) in conv.args.iter().zip(args) {
+            self.core.set(*reg, *val);
+        }
+        let resolver = |id: LibId| {
+            core::iter::once(lib)
+                .chain(deps.iter().copied())
+                .find(|lib| lib.lib_id() == id)
+                .map(CompiledLib::as_lib)
+        };
+        if !self.run(entry, context, resolver)?.is_ok() {
+            return Err(CallError::Failed);
+        }
+        Ok(conv.returns.iter().map(|reg| self.core.get(*reg)).collect())
+    }
+
+    /// Maps the reason of the program halt to the final value of `CK` register, or to the
+    /// execution error, if the program halted due to a fault.
+    fn status(&self, reason: HaltReason) -> Result<Status, ExecError> {
+        match reason {
+            HaltReason::Fault(err) => Err(err),
+            _ => Ok(self.core.ck()),
+        }
+    }
+
+    /// Runs the program, returning either the reason of the program halt or the point before which
+    /// the execution was paused due to the fuel exhaustion.
+    fn run_hooked<L: AsRef<Lib>>(
+        &mut self,
+        start: ExecPoint,
+        fuel: Option<u64>,
+        context: &Isa::Context<'_>,
+        lib_resolver: impl Fn(LibId) -> Option<L>,
+        tracer: impl FnMut(LibSite, &Isa, &Core<LibId, Isa::Core>),
+    ) -> Result<HaltReason, ExecPoint> {
+        self.run_with(start, fuel, false, tracer, |site, skip, core, hook| {
+            let lib = lib_resolver(site.lib_id)?;
+            Some(
+                lib.as_ref()
+                    .exec_hooked::<Isa, _>(site.offset, skip, core, context, hook),
+            )
+        })
+    }
+
+    /// Executes a single instruction of the program at the execution point, returning either the
+    /// reason of the program halt or the point of the next instruction to execute.
+    pub(crate) fn step_prepared(
+        &mut self,
+        point: ExecPoint,
+        context: &Isa::Context<'_>,
+        lib: &PreparedLib<Isa>,
+    ) -> Result<HaltReason, ExecPoint> {
+        self.run_with(
+            point,
+            None,
+            true,
+            |_, _, _| {},
+            |site, skip, core, hook| {
+                (site.lib_id == lib.lib_id())
+                    .then(|| lib.exec_hooked(site.offset, skip, core, context, hook))
+            },
+        )
+    }
+
+    /// Runs the program, executing the code of each of the libraries with `exec`, which returns
+    /// `None` if the library is not known.
+    ///
+    /// If `single_step` is set, the execution is paused before the second instruction.
+    fn run_with<T>(
+        &mut self,
+        start: ExecPoint,
+        fuel: Option<u64>,
+        single_step: bool,
+        tracer: T,
+        mut exec: impl FnMut(
+            LibSite,
+            bool,
+            &mut Core<LibId, Isa::Core>,
+            &mut VmHook<'_, Isa, T>,
+        ) -> Option<Result<Jump<LibId>, LibSite>>,
+    ) -> Result<HaltReason, ExecPoint>
+    where
+        T: FnMut(LibSite, &Isa, &Core<LibId, Isa::Core>),
+    {
+        let mut hook = VmHook {
+            steps: &mut self.steps,
+            limit: self.step_limit,
+            cost: self.step_cost,
+            max_call_depth: self.max_call_depth,
+            strict_entries: self.strict_entries,
+            entering: start.entering,
+            reset_status_on_call: self.reset_status_on_call,
+            call_depth: 0,
+            fuel,
+            single_step,
+            stepped: false,
+            burned: 0,
+            site: None,
+            coverage: self.coverage.as_mut(),
+            taint: self.taint.as_mut(),
+            #[cfg(any(test, feature = "tests"))]
+            faults: &self.faults,
+            #[cfg(feature = "std")]
+            deadline: self.deadline,
+            #[cfg(feature = "std")]
+            executed: 0,
+            tracer,
+        };
+        let mut site = start.site;
         let mut skip = false;
-        while let Some(ref mut site) = call {
-            if let Some(lib) = lib_resolver(site.lib_id) {
-                call = match lib
-                    .as_ref()
-                    .exec::<Isa>(site.offset, skip, &mut self.core, context)
-                {
-                    Jump::Halt => {
+        let res = loop {
+            if site.lib_id == LibId::RELOC {
+                let call_site = hook.site.unwrap_or(site);
+                break Ok(HaltReason::Fault(ExecError::UnresolvedReloc(call_site)));
+            }
+            if let Some(jump) = exec(site, skip, &mut self.core, &mut hook) {
+                let jump = match jump {
+                    Ok(jump) => jump,
+                    Err(site) => break Err(ExecPoint { site, entering: hook.entering }),
+                };
+                (site, skip) = match jump {
+                    Jump::Halt(reason) => {
                         #[cfg(feature = "log")]
                         {
                             let core = &self.core;
@@ -92,23 +880,1295 @@ where Isa: Instruction<LibId>
                                 core.co()
                             );
                         }
-                        None
-                    }
-                    Jump::Instr(site) => {
-                        skip = false;
-                        Some(site.into())
-                    }
-                    Jump::Next(site) => {
-                        skip = true;
-                        Some(site.into())
+                        break Ok(reason);
                     }
+                    Jump::Instr(site) => (site.into(), false),
+                    Jump::Next(site) => (site.into(), true),
                 };
             } else if let Some(pos) = site.offset.checked_add(1) {
                 site.offset = pos;
             } else {
-                call = None;
-            };
+                break Ok(HaltReason::UnknownLib(site.lib_id));
+            }
+        };
+        self.last_site = hook.site;
+        res
+    }
+}
+
+/// Coverage of the library code by the executed instructions, recorded by the [`Vm`] as a bitmap
+/// indexed by the instruction offset.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Coverage(BTreeMap<LibId, Vec<u64>>);
+
+impl Coverage {
+    /// Marks the instruction at a given site as executed.
+    pub fn insert(&mut self, site: LibSite) {
+        let bitmap = self.0.entry(site.lib_id).or_default();
+        let word = site.offset as usize / 64;
+        if bitmap.len() <= word {
+            bitmap.resize(word + 1, 0);
+        }
+        bitmap[word] |= 1 << (site.offset % 64);
+    }
+
+    /// Checks whether the instruction at a given site was executed.
+    pub fn contains(&self, site: LibSite) -> bool {
+        let word = self
+            .bitmap(site.lib_id)
+            .get(site.offset as usize / 64)
+            .copied()
+            .unwrap_or_default();
+        word & (1 << (site.offset % 64)) != 0
+    }
+
+    /// Returns the coverage bitmap of a library, where bit `offset % 64` of the word `offset / 64`
+    /// is set if the instruction at `offset` was executed. The bitmap is empty if none of the
+    /// library instructions were executed.
+    pub fn bitmap(&self, lib_id: LibId) -> &[u64] {
+        self.0.get(&lib_id).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns offsets of the executed instructions of a library, in the ascending order.
+    pub fn offsets(&self, lib_id: LibId) -> impl Iterator<Item = u16> + '_ {
+        self.bitmap(lib_id)
+            .iter()
+            .enumerate()
+            .flat_map(|(no, word)| {
+                (0..64u16)
+                    .filter(move |bit| word & (1 << bit) != 0)
+                    .map(move |bit| no as u16 * 64 + bit)
+            })
+    }
+
+    /// Returns ids of the libraries which code was executed.
+    pub fn libs(&self) -> impl Iterator<Item = LibId> + '_ { self.0.keys().copied() }
+
+    /// Returns the total number of the executed instructions.
+    pub fn len(&self) -> usize {
+        self.0
+            .values()
+            .flatten()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    /// Checks whether no instructions were executed.
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+}
+
+/// Outcome of a program execution with a limited fuel.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum RunOutcome<Cx: CoreExt> {
+    /// The program has completed with the provided value of the `CK` register.
+    Complete(Status),
+
+    /// The fuel was exhausted before the program completion; the execution can be continued with
+    /// [`Vm::resume`].
+    Paused(VmState<Cx>),
+}
+
+/// Point at which the program execution is started or paused.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub(crate) struct ExecPoint {
+    /// Location of the next instruction to execute.
+    site: LibSite,
+    /// Whether the instruction is reached by an absolute jump or a call.
+    entering: bool,
+}
+
+/// Extracts the reason of the halt of a program run without fuel limit, which can't be paused.
+fn unfueled(run: Result<HaltReason, ExecPoint>) -> HaltReason {
+    run.unwrap_or_else(|_| unreachable!("execution can't be paused without fuel limit"))
+}
+
+impl ExecPoint {
+    /// Point at the program entry, which is not checked to be a routine entry.
+    fn entry(site: LibSite) -> Self { Self { site, entering: false } }
+}
+
+/// State of a paused program execution, which can be resumed with [`Vm::resume`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub struct VmState<Cx: CoreExt> {
+    core: Core<LibId, Cx>,
+    site: LibSite,
+    /// Whether the instruction at the site is reached by an absolute jump or a call, such that it
+    /// is checked to be a routine entry in the [strict mode](Vm::set_strict_entries).
+    #[cfg_attr(feature = "serde", serde(default))]
+    entering: bool,
+    steps: u64,
+}
+
+impl<Cx: CoreExt> VmState<Cx> {
+    /// Returns the state of the VM registers at the moment of the pause.
+    pub fn core(&self) -> &Core<LibId, Cx> { &self.core }
+
+    /// Returns the location of the instruction from which the execution will be resumed.
+    pub fn site(&self) -> LibSite { self.site }
+
+    /// Returns the number of the execution steps performed by the VM before the pause.
+    pub fn steps(&self) -> u64 { self.steps }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Cx: CoreExt> zeroize::Zeroize for VmState<Cx> {
+    fn zeroize(&mut self) { self.core.zeroize() }
+}
+
+/// The state is zeroized on drop by the [`Core`] it contains.
+#[cfg(feature = "zeroize")]
+impl<Cx: CoreExt> zeroize::ZeroizeOnDrop for VmState<Cx> {}
+
+/// State of the VM captured for a post-mortem debugging with [`Vm::dump`].
+///
+/// The [`Display`] of the dump is a deterministic table of the register values.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(rename_all = "camelCase"))]
+pub struct VmDump<Cx: CoreExt> {
+    core: Core<LibId, Cx>,
+    site: Option<LibSite>,
+    steps: u64,
+}
+
+impl<Cx: CoreExt> VmDump<Cx> {
+    /// Returns the state of the VM registers.
+    pub fn core(&self) -> &Core<LibId, Cx> { &self.core }
+
+    /// Returns the return addresses from the call stack, starting from the outermost call.
+    pub fn call_stack(&self) -> Vec<LibSite> {
+        self.core.cs().iter().copied().map(LibSite::from).collect()
+    }
+
+    /// Returns the site of the last instruction which execution was attempted by the VM, if
+    /// any.
+    pub fn site(&self) -> Option<LibSite> { self.site }
+
+    /// Returns the number of the execution steps performed by the VM.
+    pub fn steps(&self) -> u64 { self.steps }
+}
+
+#[cfg(feature = "zeroize")]
+impl<Cx: CoreExt> zeroize::Zeroize for VmDump<Cx> {
+    fn zeroize(&mut self) { self.core.zeroize() }
+}
+
+/// The dump is zeroized on drop by the [`Core`] it contains.
+#[cfg(feature = "zeroize")]
+impl<Cx: CoreExt> zeroize::ZeroizeOnDrop for VmDump<Cx> {}
+
+impl<Cx: CoreExt> Display for VmDump<Cx> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.site {
+            Some(site) => writeln!(f, "PC {site}")?,
+            None => writeln!(f, "PC ~")?,
+        }
+        writeln!(f, "ST {}", self.steps)?;
+        Debug::fmt(&self.core, f)
+    }
+}
+
+/// Executor running a program from a [prepared library](PreparedLib) one instruction at a time,
+/// allowing to inspect and modify the VM state between the instructions.
+///
+/// All the limits and instrumentation set up for the VM apply to the execution. The program may
+/// call the routines of the same library only; calls to other libraries halt the execution with
+/// [`HaltReason::UnknownLib`].
+pub struct Stepper<'ctx, Isa: Instruction<LibId>> {
+    vm: Vm<Isa>,
+    lib: PreparedLib<Isa>,
+    context: Isa::Context<'ctx>,
+    point: ExecPoint,
+    halt: Option<HaltReason>,
+}
+
+impl<'ctx, Isa: Instruction<LibId>> Stepper<'ctx, Isa> {
+    /// Constructs a stepper which starts the execution from the given offset in the library code.
+    pub fn new(
+        vm: Vm<Isa>,
+        lib: PreparedLib<Isa>,
+        entry: u16,
+        context: Isa::Context<'ctx>,
+    ) -> Self {
+        let point = ExecPoint::entry(LibSite::new(lib.lib_id(), entry));
+        Self { vm, lib, context, point, halt: None }
+    }
+
+    /// Executes a single instruction.
+    ///
+    /// # Returns
+    ///
+    /// `None` if the program can be executed further, or the reason of the program halt otherwise.
+    /// Once the program has halted, the same reason is returned without executing anything.
+    pub fn step(&mut self) -> Option<HaltReason> {
+        if self.halt.is_none() {
+            match self.vm.step_prepared(self.point, &self.context, &self.lib) {
+                Ok(reason) => self.halt = Some(reason),
+                Err(point) => self.point = point,
+            }
+        }
+        self.halt
+    }
+
+    /// Returns the location of the next instruction to execute, or `None` if the program has
+    /// halted.
+    pub fn site(&self) -> Option<LibSite> { self.halt.is_none().then_some(self.point.site) }
+
+    /// Returns the next instruction to execute, or `None` if the program has halted or the next
+    /// instruction lies outside the library code.
+    pub fn instruction(&self) -> Option<&Isa> {
+        let no = self.lib.position(self.site()?.offset)?;
+        self.lib.instructions().get(no)
+    }
+
+    /// Returns the reason of the program halt, if it has halted.
+    pub fn halt_reason(&self) -> Option<HaltReason> { self.halt }
+
+    /// Returns the VM executing the program.
+    pub fn vm(&self) -> &Vm<Isa> { &self.vm }
+
+    /// Returns the VM executing the program, allowing to modify its registers.
+    pub fn vm_mut(&mut self) -> &mut Vm<Isa> { &mut self.vm }
+
+    /// Returns the library which code is executed.
+    pub fn lib(&self) -> &PreparedLib<Isa> { &self.lib }
+
+    /// Releases the VM executing the program.
+    pub fn into_vm(self) -> Vm<Isa> { self.vm }
+}
+
+/// Log of a program execution produced by [`Vm::run_logged`].
+///
+/// The log doesn't have a dedicated binary encoding, since the encoding of the register values
+/// is specific to the instruction set; with `serde` feature it can be stored with any of the
+/// serde formats, including the binary ones.
+#[derive(Clone, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        rename_all = "camelCase",
+        bound(
+            serialize = "Core<LibId, Cx>: serde::Serialize, LogStep<Cx::Reg>: serde::Serialize",
+            deserialize = "Core<LibId, Cx>: serde::Deserialize<'de>, LogStep<Cx::Reg>: \
+                           serde::Deserialize<'de>"
+        )
+    )
+)]
+pub struct ExecLog<Cx: CoreExt> {
+    initial: Core<LibId, Cx>,
+    entry: LibSite,
+    start_steps: u64,
+    steps: Vec<LogStep<Cx::Reg>>,
+    halt: HaltReason,
+}
+
+impl<Cx: CoreExt> PartialEq for ExecLog<Cx>
+where
+    Core<LibId, Cx>: PartialEq,
+    <Cx::Reg as Register>::Value: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.initial == other.initial
+            && self.entry == other.entry
+            && self.start_steps == other.start_steps
+            && self.steps == other.steps
+            && self.halt == other.halt
+    }
+}
+
+impl<Cx: CoreExt> Eq for ExecLog<Cx>
+where
+    Core<LibId, Cx>: Eq,
+    <Cx::Reg as Register>::Value: Eq,
+{
+}
+
+impl<Cx: CoreExt> ExecLog<Cx> {
+    /// Returns the state of the registers at the start of the execution.
+    pub fn initial(&self) -> &Core<LibId, Cx> { &self.initial }
+
+    /// Returns the entry point of the execution.
+    pub fn entry(&self) -> LibSite { self.entry }
+
+    /// Returns the executed instructions, in the order of their execution.
+    pub fn steps(&self) -> &[LogStep<Cx::Reg>] { &self.steps }
+
+    /// Returns the reason of the program halt.
+    pub fn halt(&self) -> HaltReason { self.halt }
+}
+
+/// Execution of a single instruction recorded in the [`ExecLog`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        rename_all = "camelCase",
+        bound(
+            serialize = "Reg: serde::Serialize, Reg::Value: serde::Serialize",
+            deserialize = "Reg: serde::Deserialize<'de>, Reg::Value: serde::Deserialize<'de>"
+        )
+    )
+)]
+pub struct LogStep<Reg: Register> {
+    /// Location of the instruction.
+    pub site: LibSite,
+    /// Opcode of the instruction.
+    pub opcode: u8,
+    /// Destination registers which values were changed by the instruction, with their new
+    /// values.
+    pub deltas: Vec<(Reg, Option<Reg::Value>)>,
+    /// Value of `CO` register after the instruction execution.
+    pub co: Status,
+    /// Value of `CK` register after the instruction execution.
+    pub ck: Status,
+}
+
+impl<Reg: Register> LogStep<Reg>
+where Reg::Value: PartialEq
+{
+    /// Replaces the values of the destination registers taken before the instruction execution
+    /// with the changes made by the instruction, recording the resulting values of `CO` and `CK`
+    /// registers.
+    fn complete<Cx: CoreExt<Reg = Reg>>(mut self, core: &Core<LibId, Cx>) -> Self {
+        self.co = core.co();
+        self.ck = core.ck();
+        self.deltas = self
+            .deltas
+            .into_iter()
+            .map(|(reg, val)| (reg, val, core.get(reg)))
+            .filter(|(_, before, after)| before != after)
+            .map(|(reg, _, after)| (reg, after))
+            .collect();
+        self
+    }
+}
+
+/// Mismatch between a replayed execution and its log.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ReplayError {
+    /// execution diverges from the log at step {0}.
+    Diverged(usize),
+
+    /// execution halts with `{found}`, while the log records halt with `{expected}`.
+    HaltMismatch {
+        expected: HaltReason,
+        found: HaltReason,
+    },
+}
+
+/// Calling convention of a routine, defining the registers used to pass the arguments and to
+/// return the values (see [`Vm::call_routine`]).
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(
+        rename_all = "camelCase",
+        bound(serialize = "Reg: serde::Serialize", deserialize = "Reg: serde::Deserialize<'de>")
+    )
+)]
+pub struct CallConv<Reg: Register> {
+    /// Registers receiving the routine arguments, in the order of the arguments.
+    pub args: Vec<Reg>,
+    /// Registers containing the values returned by the routine, in the order of the values.
+    pub returns: Vec<Reg>,
+}
+
+impl<Reg: Register> CallConv<Reg> {
+    /// Constructs the calling convention from the lists of argument and return registers.
+    pub fn new(
+        args: impl IntoIterator<Item = Reg>,
+        returns: impl IntoIterator<Item = Reg>,
+    ) -> Self {
+        Self {
+            args: args.into_iter().collect(),
+            returns: returns.into_iter().collect(),
+        }
+    }
+
+    /// Returns the number of the argument and return registers, which can be declared for a
+    /// library routine with [`CompiledLib::with_arity`].
+    pub fn arity(&self) -> Arity {
+        Arity {
+            args: self.args.len() as u16,
+            returns: self.returns.len() as u16,
+        }
+    }
+}
+
+/// Errors of a routine call with [`Vm::call_routine`].
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum CallError {
+    /// library doesn't have routine {0}.
+    NoRoutine(u16),
+
+    /// routine called with {found} arguments, while its calling convention requires {expected}.
+    ArgCount { expected: usize, found: usize },
+
+    /// routine execution has failed.
+    Failed,
+
+    /// routine execution was halted by the VM: {0}
+    #[from]
+    Exec(ExecError),
+}
+
+/// Execution hook accounting for the steps taken by each of the instructions and tracing their
+/// execution.
+struct VmHook<'vm, Isa, T>
+where Isa: Instruction<LibId>
+{
+    steps: &'vm mut u64,
+    limit: Option<u64>,
+    cost: fn(&Isa) -> u64,
+    max_call_depth: Option<u16>,
+    strict_entries: bool,
+    /// Whether the next instruction is reached by an absolute jump or a call.
+    entering: bool,
+    reset_status_on_call: bool,
+    /// Call stack depth before the execution of the last instruction.
+    call_depth: u16,
+    /// Number of steps which can be performed before the execution is paused.
+    fuel: Option<u64>,
+    /// Whether the execution is paused after a single instruction.
+    single_step: bool,
+    /// Whether at least one instruction was executed since the start or the resume.
+    stepped: bool,
+    /// Number of steps performed since the start or the resume of the execution.
+    burned: u64,
+    /// Site of the last instruction passed to the hook.
+    site: Option<LibSite>,
+    coverage: Option<&'vm mut Coverage>,
+    taint: Option<&'vm mut BTreeSet<RegOf<Isa>>>,
+    #[cfg(any(test, feature = "tests"))]
+    faults: &'vm BTreeMap<LibSite, fn(LibSite) -> ExecError>,
+    #[cfg(feature = "std")]
+    deadline: Option<std::time::Instant>,
+    /// Number of instructions executed since the start or the resume of the execution.
+    #[cfg(feature = "std")]
+    executed: u64,
+    tracer: T,
+}
+
+impl<Isa, T> ExecHook<Isa> for VmHook<'_, Isa, T>
+where
+    Isa: Instruction<LibId>,
+    T: FnMut(LibSite, &Isa, &Core<LibId, Isa::Core>),
+{
+    fn before_exec(
+        &mut self,
+        site: Site<LibId>,
+        instr: &Isa,
+        core: &Core<LibId, Isa::Core>,
+    ) -> Result<(), HookBreak> {
+        let site = LibSite::from(site);
+        self.site = Some(site);
+        if self.single_step && self.stepped {
+            return Err(HookBreak::Pause);
+        }
+        let cost = (self.cost)(instr);
+        let burned = self.burned.saturating_add(cost);
+        if self.burned > 0 && self.fuel.is_some_and(|fuel| burned > fuel) {
+            return Err(HookBreak::Pause);
+        }
+        #[cfg(any(test, feature = "tests"))]
+        if let Some(fault) = self.faults.get(&site) {
+            return Err(fault(site).into());
+        }
+        if core::mem::take(&mut self.entering) && !instr.is_goto_target() {
+            return Err(ExecError::InvalidEntry(site).into());
+        }
+        self.call_depth = core.cp();
+        if instr.is_call() && self.max_call_depth.is_some_and(|depth| core.cp() >= depth) {
+            return Err(ExecError::CallStackOverflow(site).into());
+        }
+        #[cfg(feature = "std")]
+        if let Some(deadline) = self.deadline {
+            if self.executed % DEADLINE_CHECK_INTERVAL == 0 && std::time::Instant::now() >= deadline
+            {
+                return Err(ExecError::Timeout(site).into());
+            }
+            self.executed += 1;
+        }
+        let steps = self.steps.saturating_add(cost);
+        if self.limit.is_some_and(|limit| steps > limit) {
+            return Err(ExecError::StepLimitExceeded(site).into());
+        }
+        self.burned = burned;
+        *self.steps = steps;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.insert(site);
+        }
+        if let Some(taint) = &mut self.taint {
+            let access = instr.reg_access();
+            let tainted = access.reads.iter().any(|reg| taint.contains(reg));
+            for reg in instr.dst_regs() {
+                if tainted {
+                    taint.insert(reg);
+                } else if access.writes.contains(&reg) {
+                    taint.remove(&reg);
+                }
+            }
+        }
+        (self.tracer)(site, instr, core);
+        self.stepped = true;
+        Ok(())
+    }
+
+    fn after_jump(&mut self, core: &mut Core<LibId, Isa::Core>) {
+        self.entering = self.strict_entries;
+        if self.reset_status_on_call && core.cp() > self.call_depth {
+            core.reset_ck();
         }
-        self.core.ck()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::isa::CtrlInstr;
+    use crate::regs::CALL_STACK_SIZE_MAX;
+    use crate::{aluasm, CompiledLib};
+
+    #[test]
+    fn step_limit() {
+        const LOOP: u16 = 0;
+        let code = aluasm! {
+            .routine    :LOOP   ;
+            jmp         :LOOP   ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.as_lib();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        vm.set_step_limit(Some(10));
+        let res = vm.run(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib));
+        assert_eq!(res, Err(ExecError::StepLimitExceeded(LibSite::new(lib.lib_id(), 0))));
+        assert_eq!(vm.steps(), 10);
+
+        vm.reset();
+        vm.set_step_cost(|instr| if instr.is_goto_target() { 0 } else { 3 });
+        assert_eq!(vm.exec(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib)), Status::Fail);
+        assert_eq!(vm.steps(), 9);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn deadline() {
+        use std::time::{Duration, Instant};
+
+        const LOOP: u16 = 0;
+        let code = aluasm! {
+            .routine    :LOOP   ;
+            jmp         :LOOP   ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.as_lib();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        vm.set_deadline(Some(Instant::now()));
+        let res = vm.run(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib));
+        assert_eq!(res, Err(ExecError::Timeout(LibSite::new(lib.lib_id(), 0))));
+        assert_eq!(vm.steps(), 0);
+
+        vm.reset();
+        vm.set_deadline(Some(Instant::now() + Duration::from_millis(20)));
+        let res = vm.run(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib));
+        assert!(matches!(res, Err(ExecError::Timeout(site)) if site.lib_id == lib.lib_id()));
+        assert!(vm.steps() > 0);
+        assert_eq!(vm.steps() % DEADLINE_CHECK_INTERVAL, 0);
+
+        vm.reset();
+        vm.set_step_limit(Some(10));
+        vm.set_deadline(Some(Instant::now() + Duration::from_secs(60)));
+        let res = vm.run(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib));
+        assert_eq!(res, Err(ExecError::StepLimitExceeded(LibSite::new(lib.lib_id(), 0))));
+    }
+
+    #[test]
+    fn run_logged() {
+        use crate::testing::isa::{RegInstr, XReg};
+
+        let code = [
+            RegInstr::Nop,
+            RegInstr::Put(XReg::A, 5),
+            RegInstr::Mov(XReg::B, XReg::A),
+            RegInstr::Put(XReg::A, 5),
+        ];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let resolver = |_| Some(lib.as_lib());
+        let mut vm = Vm::<RegInstr>::new();
+        let log = vm.run_logged(lib.routine(0), &(), resolver);
+        assert_eq!(log.halt(), HaltReason::EndOfCode);
+        assert_eq!(log.entry(), lib.routine(0));
+        let deltas = log
+            .steps()
+            .iter()
+            .map(|step| (step.site.offset, step.opcode, step.deltas.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(deltas, vec![
+            (0, 0, vec![]),
+            (1, 1, vec![(XReg::A, Some(5))]),
+            (4, 2, vec![(XReg::B, Some(5))]),
+            (7, 1, vec![]),
+        ]);
+
+        let mut vm = Vm::<RegInstr>::new();
+        vm.set_reg(XReg::A, 1);
+        assert_eq!(vm.replay(&log, &(), resolver), Ok(()));
+        assert_eq!(vm.get_reg(XReg::B), Some(5));
+
+        let mut forged = log.clone();
+        forged.steps[2].deltas[0].1 = Some(6);
+        assert_eq!(vm.replay(&forged, &(), resolver), Err(ReplayError::Diverged(2)));
+        let mut forged = log.clone();
+        forged.steps.pop();
+        assert_eq!(vm.replay(&forged, &(), resolver), Err(ReplayError::Diverged(3)));
+        let mut forged = log;
+        forged.halt = HaltReason::Stop;
+        assert_eq!(
+            vm.replay(&forged, &(), resolver),
+            Err(ReplayError::HaltMismatch {
+                expected: HaltReason::Stop,
+                found: HaltReason::EndOfCode
+            })
+        );
+
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            fail        CK      ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let resolver = |_| Some(lib.as_lib());
+        let config = CoreConfig { halt: false, complexity_lim: None };
+        let mut vm = Vm::<Instr<LibId>>::with(config, ());
+        let log = vm.run_logged(lib.routine(0), &(), resolver);
+        let flags = log
+            .steps()
+            .iter()
+            .map(|step| (step.co, step.ck))
+            .collect::<Vec<_>>();
+        assert_eq!(flags, vec![
+            (Status::Ok, Status::Ok),
+            (Status::Fail, Status::Ok),
+            (Status::Fail, Status::Fail),
+            (Status::Fail, Status::Fail)
+        ]);
+        let mut forged = log;
+        forged.steps[1].co = Status::Ok;
+        vm.reset();
+        assert_eq!(vm.replay(&forged, &(), resolver), Err(ReplayError::Diverged(1)));
+    }
+
+    #[test]
+    fn overflow_mode() {
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.overflow_mode(), OverflowMode::Trap);
+        assert_eq!(vm.core.overflow(0u8, u8::MAX), None);
+        assert_eq!(vm.core.co(), Status::Ok);
+
+        vm.set_overflow_mode(OverflowMode::Wrap);
+        vm.reset();
+        assert_eq!(vm.overflow_mode(), OverflowMode::Wrap);
+        assert_eq!(vm.core.overflow(0u8, u8::MAX), Some(0));
+        assert_eq!(vm.core.co(), Status::Fail);
+
+        vm.set_overflow_mode(OverflowMode::Saturate);
+        assert_eq!(vm.core.overflow(0u8, u8::MAX), Some(u8::MAX));
+    }
+
+    #[test]
+    fn coverage() {
+        const SKIP: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            jif     CO, :SKIP   ;
+            fail    CK          ;
+            .routine    :SKIP   ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib_id = lib.as_lib().lib_id();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib()));
+        assert_eq!(vm.coverage(), None);
+
+        vm.set_coverage(true);
+        vm.reset();
+        vm.core.set_co(Status::Fail);
+        assert_eq!(vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib())), Status::Ok);
+        let coverage = vm.coverage().unwrap();
+        assert_eq!(coverage.offsets(lib_id).collect::<Vec<_>>(), vec![0, 1, 5, 6]);
+        assert!(!coverage.contains(LibSite::new(lib_id, 4)));
+        assert_eq!(coverage.bitmap(lib_id), &[0b110_0011]);
+        assert_eq!(coverage.libs().collect::<Vec<_>>(), vec![lib_id]);
+
+        vm.reset();
+        vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib()));
+        let coverage = vm.coverage().unwrap();
+        assert_eq!(coverage.offsets(lib_id).collect::<Vec<_>>(), vec![0, 1, 4, 5, 6]);
+        assert_eq!(coverage.len(), 5);
+
+        vm.set_coverage(false);
+        assert_eq!(vm.coverage(), None);
+    }
+
+    #[test]
+    fn dump() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            call        :FUNC   ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib_id = lib.as_lib().lib_id();
+        let run = || {
+            let mut vm = Vm::<Instr<LibId>>::new();
+            vm.set_max_call_depth(Some(3));
+            assert!(vm.run(lib.routine(0), &(), |_| Some(lib.as_lib())).is_err());
+            vm
+        };
+        let vm = run();
+        let dump = vm.dump();
+        assert_eq!(dump, run().dump());
+        assert_eq!(dump.site(), Some(LibSite::new(lib_id, 6)));
+        assert_eq!(dump.site(), vm.last_site());
+        assert_eq!(dump.steps(), 7);
+        assert_eq!(dump.call_stack(), vm.call_stack());
+        assert_eq!(dump.call_stack().len(), 3);
+        assert_eq!(dump.core(), &vm.core);
+        let text = dump.to_string();
+        assert!(text.starts_with(&format!("PC {lib_id}@0006#h\nST 7\nC-regs:\n")));
+
+        let mut vm = vm;
+        vm.reset();
+        assert_eq!(vm.dump().site(), None);
+        assert!(vm.dump().to_string().starts_with("PC ~\nST 0\n"));
+    }
+
+    #[test]
+    fn inject_fault() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let resolver = |_| Some(lib.as_lib());
+        let mut vm = Vm::<Instr<LibId>>::new();
+        vm.inject_fault_at(lib.routine(FUNC), ExecError::Timeout);
+        let site = lib.routine(FUNC);
+        assert_eq!(vm.run(lib.routine(0), &(), resolver), Err(ExecError::Timeout(site)));
+        assert_eq!(ExecError::Timeout(site).site(), site);
+        assert_eq!(lib.source_index_at(site.offset), Some(3));
+        assert_eq!(vm.call_depth(), 1);
+        assert_eq!(vm.steps(), 2);
+
+        vm.reset();
+        assert_eq!(
+            vm.run_reported(lib.routine(0), &(), resolver),
+            HaltReason::Fault(ExecError::Timeout(site))
+        );
+
+        vm.clear_faults();
+        vm.reset();
+        assert_eq!(vm.run(lib.routine(0), &(), resolver), Ok(Status::Ok));
+    }
+
+    #[test]
+    #[cfg(feature = "zeroize")]
+    fn zeroize() {
+        use crate::testing::isa::{RegInstr, XCore, XReg};
+
+        let config = CoreConfig { halt: true, complexity_lim: Some(100) };
+        let mut vm = Vm::<RegInstr>::with(config, ());
+        vm.set_reg(XReg::A, 1);
+        vm.set_reg(XReg::B, 2);
+        let _ = vm.core.fail_ck();
+        let mut dump = vm.dump();
+        zeroize::Zeroize::zeroize(&mut dump);
+        assert_eq!(dump.core().get(XReg::A), None);
+        assert_eq!(dump.core().ck(), Status::Ok);
+        zeroize::Zeroize::zeroize(&mut vm.core);
+        assert_eq!(vm.get_reg(XReg::A), None);
+        assert_eq!(vm.core.ck(), Status::Ok);
+        assert_eq!(vm.core.cf(), 0);
+        assert_eq!(vm.core.cl(), Some(100));
+
+        let cx = &vm.core.cx;
+        // SAFETY: the pointer is derived from a valid reference, and the slice covers exactly the
+        // memory of the referenced value, which is not modified while the slice exists.
+        let bytes = unsafe {
+            core::slice::from_raw_parts(
+                (cx as *const XCore).cast::<u8>(),
+                core::mem::size_of_val(cx),
+            )
+        };
+        assert!(bytes.iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn taint() {
+        use crate::testing::isa::{RegInstr, XReg};
+
+        let code = [RegInstr::Nop, RegInstr::Mov(XReg::B, XReg::A), RegInstr::Put(XReg::A, 5)];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let resolver = |_| Some(lib.as_lib());
+        let mut vm = Vm::<RegInstr>::new();
+        vm.set_reg(XReg::A, 1);
+        vm.run(lib.routine(0), &(), resolver).unwrap();
+        assert_eq!(vm.tainted(), None);
+        assert!(!vm.is_tainted(XReg::B));
+
+        vm.set_taint([XReg::A]);
+        vm.reset();
+        vm.set_reg(XReg::A, 1);
+        vm.run(lib.routine(0), &(), resolver).unwrap();
+        assert!(vm.is_tainted(XReg::B));
+        assert!(!vm.is_tainted(XReg::A));
+
+        vm.reset();
+        vm.run(lib.routine(0), &(), resolver).unwrap();
+        assert_eq!(vm.tainted(), Some(&none!()));
+
+        vm.clear_taint();
+        assert_eq!(vm.tainted(), None);
+    }
+
+    #[test]
+    fn call_depth() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            call        :FUNC   ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.as_lib();
+        let lib_id = lib.lib_id();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        vm.set_max_call_depth(Some(3));
+        let res = vm.run(LibSite::new(lib_id, 0), &(), |_| Some(lib));
+        assert_eq!(res, Err(ExecError::CallStackOverflow(LibSite::new(lib_id, 6))));
+        assert_eq!(vm.call_depth(), 3);
+        assert_eq!(vm.call_stack(), vec![
+            LibSite::new(lib_id, 1),
+            LibSite::new(lib_id, 6),
+            LibSite::new(lib_id, 6)
+        ]);
+
+        vm.reset();
+        vm.set_max_call_depth(None);
+        assert_eq!(vm.run(LibSite::new(lib_id, 0), &(), |_| Some(lib)), Ok(Status::Fail));
+        assert_eq!(vm.call_depth(), CALL_STACK_SIZE_MAX);
+    }
+
+    #[test]
+    fn strict_entries() {
+        let code = aluasm! {
+            nop                 ;
+            jmp         5       ;
+            not         CO      ;
+            nop                 ;
+            call        10      ;
+            stop                ;
+            nop                 ;
+            ret                 ;
+        };
+        let lib = Lib::assemble::<Instr<LibId>>(&code).unwrap();
+        let lib_id = lib.lib_id();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        vm.set_strict_entries(true);
+        assert_eq!(vm.run(LibSite::new(lib_id, 0), &(), |_| Some(&lib)), Ok(Status::Ok));
+
+        let code = aluasm! {
+            nop                 ;
+            jmp         4       ;
+            not         CO      ;
+            stop                ;
+        };
+        let lib = Lib::assemble::<Instr<LibId>>(&code).unwrap();
+        let lib_id = lib.lib_id();
+        let res = vm.run(LibSite::new(lib_id, 0), &(), |_| Some(&lib));
+        assert_eq!(res, Err(ExecError::InvalidEntry(LibSite::new(lib_id, 4))));
+
+        vm.reset();
+        let outcome = vm.run_fueled(LibSite::new(lib_id, 0), 2, &(), |_| Some(&lib));
+        let Ok(RunOutcome::Paused(state)) = outcome else {
+            panic!("execution was not paused");
+        };
+        assert_eq!(state.site(), LibSite::new(lib_id, 4));
+        vm.reset();
+        let res = vm.resume(state, 2, &(), |_| Some(&lib));
+        assert_eq!(res, Err(ExecError::InvalidEntry(LibSite::new(lib_id, 4))));
+
+        let prepared = CompiledLib::from_parts::<Instr<LibId>>(lib.clone(), vec![0])
+            .unwrap()
+            .prepare::<Instr<LibId>>()
+            .unwrap();
+        let mut stepper = Stepper::new(vm.clone(), prepared, 0, ());
+        while stepper.step().is_none() {}
+        let err = ExecError::InvalidEntry(LibSite::new(lib_id, 4));
+        assert_eq!(stepper.halt_reason(), Some(HaltReason::Fault(err)));
+
+        vm.reset();
+        vm.set_strict_entries(false);
+        assert_eq!(vm.run(LibSite::new(lib_id, 0), &(), |_| Some(&lib)), Ok(Status::Ok));
+    }
+
+    #[test]
+    fn reset_status_on_call() {
+        const CHECK: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            fail        CK      ;
+            call        :CHECK  ;
+            stop                ;
+            .routine    :CHECK  ;
+            chk         CK      ;
+            not         CO      ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.as_lib();
+        let lib_id = lib.lib_id();
+        let config = CoreConfig { halt: false, complexity_lim: None };
+        let mut vm = Vm::<Instr<LibId>>::with(config, ());
+        assert!(!vm.reset_status_on_call());
+        assert_eq!(vm.run(LibSite::new(lib_id, 0), &(), |_| Some(lib)), Ok(Status::Fail));
+        assert_eq!(vm.core.co(), Status::Ok);
+
+        vm.reset();
+        vm.set_reset_status_on_call(true);
+        assert_eq!(vm.run(LibSite::new(lib_id, 0), &(), |_| Some(lib)), Ok(Status::Ok));
+        assert_eq!(vm.core.co(), Status::Fail);
+    }
+
+    #[test]
+    fn stepper() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            not         CO      ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib_id = lib.as_lib().lib_id();
+        let lib = lib.prepare::<Instr<LibId>>().unwrap();
+        let mut stepper = Stepper::new(Vm::new(), lib, 0, ());
+        assert_eq!(stepper.instruction(), Some(&CtrlInstr::Nop.into()));
+        let mut sites = vec![];
+        while stepper.step().is_none() {
+            sites.push(stepper.site().unwrap().offset);
+            if sites.len() == 1 {
+                assert_eq!(stepper.vm().core.co(), Status::Ok);
+            }
+        }
+        assert_eq!(sites, vec![1, 2, 6, 7, 5]);
+        assert_eq!(stepper.halt_reason(), Some(HaltReason::Stop));
+        assert_eq!(stepper.step(), Some(HaltReason::Stop));
+        assert_eq!(stepper.site(), None);
+        assert_eq!(stepper.instruction(), None);
+        assert_eq!(stepper.vm().core.co(), Status::Fail);
+        assert_eq!(stepper.vm().steps(), 6);
+        assert_eq!(stepper.lib().lib_id(), lib_id);
+    }
+
+    #[test]
+    fn snapshot() {
+        let code = aluasm! {
+            not         CO      ;
+            fail        CK      ;
+            stop                ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.as_lib();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let snapshot = vm.snapshot();
+        assert_eq!(vm.exec(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib)), Status::Fail);
+        assert_ne!(vm.snapshot(), snapshot);
+        let failed = vm.snapshot();
+
+        vm.restore(&snapshot);
+        assert_eq!(vm.snapshot(), snapshot);
+        assert_eq!(vm.core.ck(), Status::Ok);
+        assert_eq!(vm.core.co(), Status::Ok);
+
+        vm.exec(LibSite::new(lib.lib_id(), 0), &(), |_| Some(lib));
+        assert_eq!(vm.snapshot(), failed);
+    }
+
+    #[test]
+    fn fuel() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            not         CO      ;
+            stop                ;
+            .routine    :FUNC   ;
+            chk         CO      ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.as_lib();
+        let lib_id = lib.lib_id();
+
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let mut outcome = vm
+            .run_fueled(LibSite::new(lib_id, 0), 2, &(), |_| Some(lib))
+            .unwrap();
+        let mut pauses = vec![];
+        while let RunOutcome::Paused(state) = outcome {
+            pauses.push((state.site().offset, state.core().cp(), state.steps()));
+            // Other programs may run on the same VM in between
+            vm.reset();
+            outcome = vm.resume(state, 2, &(), |_| Some(lib)).unwrap();
+        }
+        assert_eq!(pauses, vec![(6, 1, 2), (8, 1, 4), (5, 0, 6)]);
+        assert_eq!(outcome, RunOutcome::Complete(Status::Ok));
+        assert_eq!(vm.steps(), 7);
+        assert_eq!(vm.core.co(), Status::Fail);
+
+        vm.reset();
+        vm.set_step_cost(|_| 5);
+        let RunOutcome::Paused(state) = vm
+            .run_fueled(LibSite::new(lib_id, 0), 2, &(), |_| Some(lib))
+            .unwrap()
+        else {
+            panic!("execution was not paused");
+        };
+        assert_eq!(state.site(), LibSite::new(lib_id, 1));
+    }
+
+    #[test]
+    fn call_routine() {
+        use crate::testing::isa::{RegInstr, XReg};
+
+        let code = vec![RegInstr::Nop, RegInstr::Mov(XReg::B, XReg::A)];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let conv = CallConv::new([XReg::A], [XReg::B]);
+        assert_eq!(conv.arity(), Arity { args: 1, returns: 1 });
+        let mut vm = Vm::<RegInstr>::new();
+        assert_eq!(vm.call_routine(&lib, 0, &conv, &[7], &[], &()), Ok(vec![Some(7)]));
+        assert_eq!(
+            vm.call_routine(&lib, 0, &conv, &[], &[], &()),
+            Err(CallError::ArgCount { expected: 1, found: 0 })
+        );
+        assert_eq!(vm.call_routine(&lib, 1, &conv, &[7], &[], &()), Err(CallError::NoRoutine(1)));
+
+        let conv = CallConv::new([], [XReg::A, XReg::B]);
+        vm.reset();
+        assert_eq!(vm.call_routine(&lib, 0, &conv, &[], &[], &()), Ok(vec![None, None]));
+    }
+
+    #[test]
+    fn run_routine() {
+        const FUNC: u16 = 1;
+        const FAIL: u16 = 2;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            not         CO      ;
+            ret                 ;
+            .routine    :FAIL   ;
+            fail        CK      ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.prepare::<Instr<LibId>>().unwrap();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.run_routine(&lib, FUNC, &()), Ok(HaltReason::Stop));
+        assert_eq!(vm.core.co(), Status::Fail);
+        assert_eq!(vm.steps(), 3);
+        assert_eq!(vm.call_depth(), 0);
+
+        vm.reset();
+        assert_eq!(vm.run_routine(&lib, 0, &()), Ok(HaltReason::Stop));
+        assert_eq!(vm.core.co(), Status::Fail);
+        assert_eq!(vm.run_routine(&lib, FAIL, &()), Ok(HaltReason::Stop));
+        assert_eq!(vm.core.ck(), Status::Fail);
+        assert_eq!(vm.steps(), 8);
+        assert_eq!(vm.run_routine(&lib, 3, &()), Err(CallError::NoRoutine(3)));
+
+        let frame = Site::new(lib.lib_id(), 1);
+        vm.core.push_cs(frame).unwrap();
+        assert_eq!(vm.run_routine(&lib, FUNC, &()), Ok(HaltReason::Stop));
+        assert_eq!(vm.core.cs(), &[frame]);
+    }
+
+    #[test]
+    fn run_checked() {
+        use crate::testing::isa::{RegInstr, XReg};
+
+        let code = vec![RegInstr::Nop, RegInstr::Mov(XReg::B, XReg::A)];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let entry = LibSite::new(lib.as_lib().lib_id(), 0);
+        let mut vm = Vm::<RegInstr>::new();
+        vm.set_reg(XReg::A, 5);
+        let input = vm.snapshot();
+        vm.reset();
+        let output = vm
+            .run_checked(entry, &input, &(), |_| Some(lib.as_lib()))
+            .unwrap();
+        vm.reset();
+        vm.restore(&output);
+        assert_eq!(vm.get_reg(XReg::B), Some(5));
+
+        let lib = CompiledLib::compile(aluasm! { nop; fail CK; }, &[]).unwrap();
+        let entry = LibSite::new(lib.as_lib().lib_id(), 0);
+        let mut vm = Vm::<Instr<LibId>>::new();
+        let input = vm.snapshot();
+        assert_eq!(
+            vm.run_checked(entry, &input, &(), |_| Some(lib.as_lib())),
+            Err(HaltReason::Fail)
+        );
+
+        let lib = CompiledLib::compile(aluasm! { nop; chk CO; }, &[]).unwrap();
+        let entry = LibSite::new(lib.as_lib().lib_id(), 0);
+        vm.core.set_co(Status::Fail);
+        let input = vm.snapshot();
+        assert_eq!(
+            vm.run_checked(entry, &input, &(), |_| Some(lib.as_lib())),
+            Err(HaltReason::Fail)
+        );
+        let alien = LibSite::new(LibId::from([0xAA; 32]), 0);
+        assert_eq!(
+            vm.run_checked(alien, &input, &(), |_| None::<&Lib>),
+            Err(HaltReason::UnknownLib(alien.lib_id))
+        );
+    }
+
+    #[test]
+    fn registers() {
+        use crate::testing::isa::{RegInstr, XReg};
+
+        let code = vec![RegInstr::Nop, RegInstr::Mov(XReg::B, XReg::A)];
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let mut vm = Vm::<RegInstr>::new();
+        assert_eq!(vm.get_reg(XReg::A), None);
+        vm.set_reg(XReg::A, 5);
+        assert_eq!(vm.exec(lib.routine(0), &(), |_| Some(lib.as_lib())), Status::Ok);
+        assert_eq!(vm.get_reg(XReg::A), Some(5));
+        assert_eq!(vm.get_reg(XReg::B), Some(5));
+        vm.clr_reg(XReg::A);
+        assert_eq!(vm.get_reg(XReg::A), None);
+        assert_eq!(vm.get_reg_as::<u128>(XReg::A), None);
+
+        assert_eq!(vm.set_reg_from(XReg::A, 200u128), Ok(()));
+        assert!(vm.set_reg_from(XReg::A, 0x100u16).is_err());
+        assert!(vm.set_reg_from(XReg::A, -1i64).is_err());
+        assert_eq!(vm.get_reg(XReg::A), Some(200));
+        assert_eq!(vm.get_reg_as::<u128>(XReg::A), Some(Ok(200)));
+        assert!(vm.get_reg_as::<i8>(XReg::A).unwrap().is_err());
+    }
+
+    #[test]
+    fn halt_reason() {
+        let lib = CompiledLib::compile(aluasm! { nop; stop; }, &[]).unwrap();
+        let lib = lib.as_lib();
+        let lib_id = lib.lib_id();
+        let mut vm = Vm::<Instr<LibId>>::new();
+        assert_eq!(vm.run_reported(LibSite::new(lib_id, 0), &(), |_| Some(lib)), HaltReason::Stop);
+        assert_eq!(vm.core.ck(), Status::Ok);
+
+        let lib = CompiledLib::compile(aluasm! { nop; chk CO; }, &[]).unwrap();
+        let lib = lib.as_lib();
+        let lib_id = lib.lib_id();
+        vm.reset();
+        assert_eq!(
+            vm.run_reported(LibSite::new(lib_id, 0), &(), |_| Some(lib)),
+            HaltReason::EndOfCode
+        );
+        assert_eq!(vm.core.ck(), Status::Ok);
+
+        vm.reset();
+        assert_eq!(
+            vm.run_reported(LibSite::new(lib_id, 5), &(), |_| Some(lib)),
+            HaltReason::InvalidJump(5)
+        );
+        assert_eq!(vm.core.ck(), Status::Fail);
+
+        let lib = CompiledLib::compile(aluasm! { nop; not CO; chk CO; stop; }, &[]).unwrap();
+        let lib = lib.as_lib();
+        let lib_id = lib.lib_id();
+        vm.reset();
+        assert_eq!(vm.run_reported(LibSite::new(lib_id, 0), &(), |_| Some(lib)), HaltReason::Fail);
+        assert_eq!(vm.core.ck(), Status::Fail);
+
+        vm.reset();
+        vm.set_step_limit(Some(1));
+        assert_eq!(
+            vm.run_reported(LibSite::new(lib_id, 0), &(), |_| Some(lib)),
+            HaltReason::Fault(ExecError::StepLimitExceeded(LibSite::new(lib_id, 1)))
+        );
+        assert_eq!(vm.core.ck(), Status::Fail);
+
+        let lib = CompiledLib::compile(aluasm! { nop; trap 0x2A; stop; }, &[]).unwrap();
+        let lib_id = lib.as_lib().lib_id();
+        vm.reset();
+        vm.set_step_limit(None);
+        assert_eq!(
+            vm.run_reported(LibSite::new(lib_id, 0), &(), |_| Some(lib.as_lib())),
+            HaltReason::Trapped(0x2A)
+        );
+        assert_eq!(vm.core.ck(), Status::Fail);
+        assert_eq!(
+            HaltReason::Trapped(0x2A).to_string(),
+            "program was aborted with trap code 0x002a."
+        );
+
+        let lib = lib.prepare::<Instr<LibId>>().unwrap();
+        vm.reset();
+        assert_eq!(vm.run_routine(&lib, 0, &()), Ok(HaltReason::Trapped(0x2A)));
+        assert_eq!(vm.core.ck(), Status::Fail);
+    }
+
+    #[test]
+    fn trace() {
+        const FUNC: u16 = 1;
+        let code = aluasm! {
+            nop                 ;
+            call        :FUNC   ;
+            stop                ;
+            .routine    :FUNC   ;
+            ret                 ;
+        };
+        let lib = CompiledLib::compile(code, &[]).unwrap();
+        let lib = lib.as_lib();
+        let lib_id = lib.lib_id();
+        let mut trace = vec![];
+        let mut vm = Vm::<Instr<LibId>>::new();
+        vm.run_traced(
+            LibSite::new(lib_id, 0),
+            &(),
+            |_| Some(lib),
+            |site, instr, core| trace.push((site.offset, instr.to_string(), core.cp())),
+        )
+        .unwrap();
+        assert_eq!(trace, vec![
+            (0, s!("nop"), 0),
+            (1, s!("call    0005#h"), 0),
+            (5, s!("nop"), 1),
+            (6, s!("ret"), 1),
+            (4, s!("stop"), 0),
+        ]);
     }
 }